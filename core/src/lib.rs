@@ -0,0 +1,186 @@
+/*!
+Mip chain layout math shared by [wgpu-mipmap](https://crates.io/crates/wgpu-mipmap) and offline
+asset tools.
+
+This crate has no dependencies and is `no_std`, so asset pipelines can compute the same level
+sizes, row pitches and filter weights as the GPU backends without pulling in wgpu or creating
+a device.
+
+```rust
+use wgpu_mipmap_core::*;
+let size = Extent::new(640, 480, 1);
+assert_eq!(mip_level_count(size), 10);
+assert_eq!(size.mip(3), Extent::new(80, 60, 1));
+assert_eq!(padded_bytes_per_row(80 * 4), 512);
+```
+*/
+#![no_std]
+
+use core::ops::Range;
+
+/// The alignment, in bytes, of each row of a buffer copied to or from a texture. Matches
+/// `wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`.
+pub const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+/// The size of a texture or one of its mip levels, in texels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Extent {
+    pub width: u32,
+    pub height: u32,
+    /// The depth of a 3D texture, halved by each level like the width and height. The array
+    /// layers of a 2D texture are never halved, so they're kept out of an `Extent`, or passed
+    /// as a depth of 1.
+    pub depth: u32,
+}
+
+impl Extent {
+    pub const fn new(width: u32, height: u32, depth: u32) -> Self {
+        Self {
+            width,
+            height,
+            depth,
+        }
+    }
+
+    /// Returns the size of mip level `level` of a texture of this size. Each dimension is
+    /// halved independently, so the shorter dimension of a rectangular texture stays at 1
+    /// while the longer one keeps shrinking.
+    pub fn mip(&self, level: u32) -> Self {
+        Self {
+            width: mip_size(self.width, level),
+            height: mip_size(self.height, level),
+            depth: mip_size(self.depth, level),
+        }
+    }
+
+    /// Returns the number of texels in a texture of this size.
+    pub fn texel_count(&self) -> u64 {
+        self.width as u64 * self.height as u64 * self.depth as u64
+    }
+}
+
+/// Returns the size of mip level `level` of a dimension of `size` texels, never less than 1.
+pub fn mip_size(size: u32, level: u32) -> u32 {
+    size.checked_shr(level).unwrap_or(0).max(1)
+}
+
+/// Returns the number of levels of a full mip chain of a 2D texture of size `size`, from
+/// level 0 down to 1x1. The depth is ignored, as array layers are never halved.
+pub fn mip_level_count(size: Extent) -> u32 {
+    32 - size.width.max(size.height).max(1).leading_zeros()
+}
+
+/// Returns the level of a 2D texture of size `size` whose width and height are those of
+/// `level_size`, or `None` if no level has them. The smallest such level is returned, as a
+/// 1xN level may keep its size for several levels. The depth is ignored.
+pub fn mip_level_of(size: Extent, level_size: Extent) -> Option<u32> {
+    (0..mip_level_count(size)).find(|&level| {
+        let mip = size.mip(level);
+        (mip.width, mip.height) == (level_size.width, level_size.height)
+    })
+}
+
+/// Returns the number of texels in `levels` of a texture of size `size`, halving the depth
+/// as for a 3D texture.
+pub fn texel_count(size: Extent, levels: Range<u32>) -> u64 {
+    levels.map(|level| size.mip(level).texel_count()).sum()
+}
+
+/// Returns `unpadded_bytes_per_row` rounded up to [`COPY_BYTES_PER_ROW_ALIGNMENT`], the
+/// pitch of each row of a level in a readback or upload buffer.
+pub fn padded_bytes_per_row(unpadded_bytes_per_row: u32) -> u32 {
+    let align = COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded_bytes_per_row + (align - unpadded_bytes_per_row % align) % align
+}
+
+/// Returns the weights of the source texels `2x`, `2x + 1` and `2x + 2` along one axis of a
+/// level `src_size` texels wide, as used by the box filter of every backend. Even sizes take
+/// the usual 2 texel average, and odd sizes the polyphase weights
+/// `(n - x, n, x + 1) / (2n + 1)`, where `n` is the destination size, so every source texel
+/// contributes equally. See "Non-Power-of-Two Mipmapping" from the NVIDIA SDK.
+pub fn box_weights(x: u32, src_size: u32) -> [f32; 3] {
+    if src_size <= 1 {
+        return [1.0, 0.0, 0.0];
+    }
+    if src_size % 2 == 0 {
+        return [0.5, 0.5, 0.0];
+    }
+    let n = (src_size / 2) as f32;
+    let x = x as f32;
+    let sum = 2.0 * n + 1.0;
+    [(n - x) / sum, n / sum, (x + 1.0) / sum]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_extent() {
+        let size = Extent::new(512, 64, 8);
+        assert_eq!(size.mip(0), size);
+        assert_eq!(size.mip(3), Extent::new(64, 8, 1));
+        assert_eq!(size.mip(8), Extent::new(2, 1, 1));
+        assert_eq!(size.mip(40), Extent::new(1, 1, 1));
+    }
+
+    #[test]
+    fn level_count() {
+        assert_eq!(mip_level_count(Extent::new(1, 1, 1)), 1);
+        assert_eq!(mip_level_count(Extent::new(512, 512, 1)), 10);
+        assert_eq!(mip_level_count(Extent::new(511, 3, 6)), 9);
+    }
+
+    #[test]
+    fn level_of() {
+        let size = Extent::new(8192, 4096, 1);
+        assert_eq!(mip_level_of(size, size), Some(0));
+        assert_eq!(mip_level_of(size, Extent::new(2048, 1024, 1)), Some(2));
+        assert_eq!(mip_level_of(size, Extent::new(1, 1, 1)), Some(13));
+        assert_eq!(mip_level_of(size, Extent::new(2048, 2048, 1)), None);
+        assert_eq!(mip_level_of(size, Extent::new(16384, 8192, 1)), None);
+        // Odd sizes round down
+        assert_eq!(
+            mip_level_of(Extent::new(1000, 600, 1), Extent::new(250, 150, 1)),
+            Some(2)
+        );
+        assert_eq!(
+            mip_level_of(Extent::new(4, 1, 1), Extent::new(2, 1, 1)),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn chain_texel_count() {
+        let size = Extent::new(4, 4, 1);
+        assert_eq!(texel_count(size, 0..3), 16 + 4 + 1);
+        assert_eq!(texel_count(size, 1..1), 0);
+    }
+
+    #[test]
+    fn row_pitch() {
+        assert_eq!(padded_bytes_per_row(4), 256);
+        assert_eq!(padded_bytes_per_row(256), 256);
+        assert_eq!(padded_bytes_per_row(257), 512);
+    }
+
+    #[test]
+    fn weights_sum_to_one() {
+        for src_size in 1..16 {
+            for x in 0..mip_size(src_size, 1) {
+                let sum: f32 = box_weights(x, src_size).iter().sum();
+                assert!((sum - 1.0).abs() < 1e-6, "{} {}", x, src_size);
+            }
+        }
+        // Each texel of an odd level contributes the same total weight
+        let mut contributions = [0.0f32; 5];
+        for x in 0..2 {
+            for (i, w) in box_weights(x, 5).iter().enumerate() {
+                contributions[2 * x as usize + i] += w;
+            }
+        }
+        for c in &contributions {
+            assert!((c - 0.4).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use wgpu_mipmap::{
+    context::{Context, ContextOptions},
+    util::{ktx2_file, MipReadback},
+    CompressedMipmapGenerator, MipmapGenerator, RecommendedMipmapGenerator, RenderMipmapGenerator,
+};
+
+// Bakes an asset the way an offline tool would: loads a png, generates its mipmaps, compresses
+// every level to BC7 on the GPU and writes both the uncompressed and the compressed levels to
+// KTX2 files
+fn main() {
+    env_logger::init();
+    futures::executor::block_on(async {
+        let context = Context::new(&ContextOptions::default())
+            .await
+            .expect("Failed to create a device");
+        let (device, queue) = (context.device, context.queue);
+        let cat_image = image::load_from_memory(include_bytes!("cat.png"))
+            .expect("this should work")
+            .into_rgba8();
+        let (width, height) = cat_image.dimensions();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count: 1 + (width.max(height) as f64).log2().floor() as u32,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // The render backend's usage includes the sampling the BC7 encoder needs
+            usage: RenderMipmapGenerator::required_usage()
+                | wgpu::TextureUsage::COPY_SRC
+                | wgpu::TextureUsage::COPY_DST,
+            label: None,
+        };
+        let texture = device.create_texture(&texture_descriptor);
+        queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &cat_image,
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: 4 * width,
+                rows_per_image: 0,
+            },
+            texture_descriptor.size,
+        );
+        let generator = RecommendedMipmapGenerator::new(&device);
+        let compressor = CompressedMipmapGenerator::new(&device);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        generator
+            .generate(&device, &mut encoder, &texture, &texture_descriptor)
+            .expect("shouldn't fail");
+        let uncompressed =
+            MipReadback::encode(&device, &mut encoder, &texture, &texture_descriptor);
+        let compressed = compressor
+            .encode_levels(
+                &device,
+                &mut encoder,
+                &texture,
+                &texture_descriptor,
+                wgpu::TextureFormat::Bc7RgbaUnormSrgb,
+            )
+            .expect("shouldn't fail");
+        queue.submit(std::iter::once(encoder.finish()));
+        let (uncompressed, compressed) = (uncompressed.read(), compressed.read());
+        device.poll(wgpu::Maintain::Wait);
+        let files = [
+            (
+                "cat-rgba8.ktx2",
+                texture_descriptor.format,
+                uncompressed.await,
+            ),
+            (
+                "cat-bc7.ktx2",
+                wgpu::TextureFormat::Bc7RgbaUnormSrgb,
+                compressed.await,
+            ),
+        ];
+        for (path, format, levels) in files {
+            let levels = levels.expect("shouldn't fail");
+            let file = ktx2_file(width, height, format, &levels).expect("shouldn't fail");
+            if cfg!(not(target_arch = "wasm32")) {
+                std::fs::write(path, &file).expect("failed to write the file");
+            }
+            println!("{}: {} levels, {} bytes", path, levels.len(), file.len());
+        }
+    });
+}
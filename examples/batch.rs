@@ -0,0 +1,89 @@
+use std::time::Instant;
+use wgpu_mipmap::{
+    context::{Context, ContextOptions},
+    BatchRecorder, ComputeMipmapGenerator, GenerationCost, MipmapGenerator,
+};
+
+const ITERATIONS: u32 = 20;
+const FORMATS: &[wgpu::TextureFormat] = &[
+    wgpu::TextureFormat::Rgba8Unorm,
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+];
+
+// Times encoding the mipmaps of many small textures one texture at a time and with a
+// `BatchRecorder`, which records the same level of every texture in one pass
+fn main() {
+    env_logger::init();
+    futures::executor::block_on(async {
+        let context = Context::new(&ContextOptions::default())
+            .await
+            .expect("Failed to create a device");
+        let (device, queue) = (context.device, context.queue);
+        let generator = ComputeMipmapGenerator::new_with_format_hints(&device, FORMATS);
+        println!("textures\tbatched\tpasses\tdispatches\tms encoding\tms total");
+        for &count in &[16usize, 256] {
+            // Alternate formats so the batch has to group dispatches by pipeline
+            let texture_descriptors = (0..count)
+                .map(|i| wgpu::TextureDescriptor {
+                    size: wgpu::Extent3d {
+                        width: 64,
+                        height: 64,
+                        depth: 1,
+                    },
+                    mip_level_count: 7,
+                    format: FORMATS[i % FORMATS.len()],
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    usage: ComputeMipmapGenerator::required_usage() | wgpu::TextureUsage::COPY_DST,
+                    label: None,
+                })
+                .collect::<Vec<_>>();
+            let textures = texture_descriptors
+                .iter()
+                .map(|d| device.create_texture(d))
+                .collect::<Vec<_>>();
+            for &batched in &[false, true] {
+                let (mut passes, mut dispatches) = (0, 0);
+                let mut encoding = 0.0;
+                let start = Instant::now();
+                for _ in 0..ITERATIONS {
+                    let encode_start = Instant::now();
+                    let mut encoder = device.create_command_encoder(&Default::default());
+                    let cost = if batched {
+                        let mut batch = BatchRecorder::new(&generator);
+                        for (texture, d) in textures.iter().zip(&texture_descriptors) {
+                            batch.push(texture, d).expect("shouldn't fail");
+                        }
+                        batch.encode(&device, &mut encoder).expect("shouldn't fail")
+                    } else {
+                        let mut cost = GenerationCost::default();
+                        for (texture, d) in textures.iter().zip(&texture_descriptors) {
+                            generator
+                                .generate(&device, &mut encoder, texture, d)
+                                .expect("shouldn't fail");
+                            let texture_cost = generator.estimate_cost(d).expect("shouldn't fail");
+                            cost.passes += texture_cost.passes;
+                            cost.dispatches += texture_cost.dispatches;
+                        }
+                        cost
+                    };
+                    let command_buffer = encoder.finish();
+                    encoding += encode_start.elapsed().as_secs_f64();
+                    passes = cost.passes;
+                    dispatches = cost.dispatches;
+                    queue.submit(std::iter::once(command_buffer));
+                    device.poll(wgpu::Maintain::Wait);
+                }
+                println!(
+                    "{}\t{}\t{}\t{}\t{:.3}\t{:.3}",
+                    count,
+                    batched,
+                    passes,
+                    dispatches,
+                    encoding * 1000.0 / ITERATIONS as f64,
+                    start.elapsed().as_secs_f64() * 1000.0 / ITERATIONS as f64
+                );
+            }
+        }
+    });
+}
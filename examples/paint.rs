@@ -0,0 +1,417 @@
+use std::time::Instant;
+use wgpu_mipmap::{
+    ComputeMipmapGenerator, GenerateOptions, MipRect, MipmapGenerator, PipelineCache,
+    RecommendedMipmapGenerator, RenderMipmapGenerator, WorkgroupSize,
+};
+use winit::{
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
+    event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::{Window, WindowBuilder},
+};
+
+const SIZE: u32 = 512;
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+const BRUSH_SIZE: u32 = 16;
+
+// Paints into level 0 of a texture with the mouse, and regenerates the levels computed from
+// the painted texels every frame. The left half of the window shows level 0 as it's painted,
+// and the right half tiles the texture on a receding plane, sampled with trilinear filtering.
+//
+// Keys 1, 2 and 3 switch between the recommended, compute and render backends, which are all
+// created from one pipeline cache, so each shader is compiled once.
+fn main() {
+    env_logger::init();
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("wgpu-mipmap paint")
+        .with_inner_size(LogicalSize::new(1280, 640))
+        .build(&event_loop)
+        .expect("Failed to create a window");
+    let mut state = futures::executor::block_on(State::new(&window));
+    let mut cursor = PhysicalPosition::new(0.0, 0.0);
+    let mut painting = false;
+    event_loop.run(move |event, _, control_flow| match event {
+        Event::WindowEvent { event, .. } => match event {
+            WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+            WindowEvent::Resized(size) => state.resize(size),
+            WindowEvent::CursorMoved { position, .. } => {
+                cursor = position;
+                if painting {
+                    state.paint(cursor);
+                }
+            }
+            WindowEvent::MouseInput {
+                state: button_state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                painting = button_state == ElementState::Pressed;
+                if painting {
+                    state.paint(cursor);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => {
+                let backend = match key {
+                    VirtualKeyCode::Key1 => Backend::Recommended,
+                    VirtualKeyCode::Key2 => Backend::Compute,
+                    VirtualKeyCode::Key3 => Backend::Render,
+                    _ => return,
+                };
+                state.backend = backend;
+                window.set_title(&format!("wgpu-mipmap paint ({:?})", backend));
+            }
+            _ => {}
+        },
+        Event::MainEventsCleared => window.request_redraw(),
+        Event::RedrawRequested(_) => state.render(),
+        _ => {}
+    });
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Backend {
+    Recommended,
+    Compute,
+    Render,
+}
+
+struct State {
+    surface: wgpu::Surface,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    swap_chain_descriptor: wgpu::SwapChainDescriptor,
+    swap_chain: wgpu::SwapChain,
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    texture: wgpu::Texture,
+    texture_descriptor: wgpu::TextureDescriptor<'static>,
+    recommended: RecommendedMipmapGenerator,
+    compute: ComputeMipmapGenerator,
+    render: RenderMipmapGenerator,
+    backend: Backend,
+    // The texels of level 0 painted since the mipmaps were last generated
+    dirty_rect: Option<MipRect>,
+    start: Instant,
+}
+
+impl State {
+    async fn new(window: &Window) -> Self {
+        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+        let surface = unsafe { instance.create_surface(window) };
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: Some(&surface),
+            })
+            .await
+            .expect("Failed to find an adapter");
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor::default(), None)
+            .await
+            .expect("Failed to create a device");
+        let size = window.inner_size();
+        let swap_chain_descriptor = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+            format: adapter.get_swap_chain_preferred_format(&surface),
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        let swap_chain = device.create_swap_chain(&surface, &swap_chain_descriptor);
+
+        // Every backend can generate the texture, so they can be switched between
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: SIZE,
+                height: SIZE,
+                depth: 1,
+            },
+            mip_level_count: 32 - SIZE.leading_zeros(),
+            format: FORMAT,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage()
+                | RenderMipmapGenerator::required_usage()
+                | wgpu::TextureUsage::COPY_DST,
+            label: Some("paint"),
+        };
+        let texture = device.create_texture(&texture_descriptor);
+        let data = wgpu_mipmap::util::checkerboard_rgba8(SIZE, SIZE, 16);
+        queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &data,
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: 4 * SIZE,
+                rows_per_image: 0,
+            },
+            texture_descriptor.size,
+        );
+
+        // The recommended generator creates the compute and render pipelines, which the
+        // other two generators reuse from the cache
+        let cache = PipelineCache::new();
+        let created = Instant::now();
+        let recommended = RecommendedMipmapGenerator::new_with_cache(
+            &device,
+            &[FORMAT],
+            WorkgroupSize::default(),
+            &cache,
+        );
+        println!("recommended generator created in {:?}", created.elapsed());
+        let created = Instant::now();
+        let compute = ComputeMipmapGenerator::new_with_cache(
+            &device,
+            &[FORMAT],
+            WorkgroupSize::default(),
+            &cache,
+        );
+        let render = RenderMipmapGenerator::new_with_cache(&device, &[FORMAT], &cache);
+        println!(
+            "compute and render generators created from the cache in {:?}",
+            created.elapsed()
+        );
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("paint"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("paint.wgsl").into()),
+            flags: wgpu::ShaderFlags::all(),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("paint"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                cull_mode: wgpu::CullMode::None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[swap_chain_descriptor.format.into()],
+            }),
+        });
+        // Trilinear filtering, repeated so the receding plane samples the smallest levels
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+        window.set_title(&format!("wgpu-mipmap paint ({:?})", Backend::Recommended));
+        Self {
+            surface,
+            device,
+            queue,
+            swap_chain_descriptor,
+            swap_chain,
+            pipeline,
+            bind_group,
+            texture,
+            texture_descriptor,
+            recommended,
+            compute,
+            render,
+            backend: Backend::Recommended,
+            // Every level is generated on the first frame
+            dirty_rect: Some(MipRect {
+                x: 0,
+                y: 0,
+                width: SIZE,
+                height: SIZE,
+            }),
+            start: Instant::now(),
+        }
+    }
+
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        self.swap_chain_descriptor.width = size.width;
+        self.swap_chain_descriptor.height = size.height;
+        self.swap_chain = self
+            .device
+            .create_swap_chain(&self.surface, &self.swap_chain_descriptor);
+    }
+
+    /// Writes a square of color to level 0 under `cursor`, if it's over the left half.
+    fn paint(&mut self, cursor: PhysicalPosition<f64>) {
+        let half_width = self.swap_chain_descriptor.width as f64 / 2.0;
+        let height = self.swap_chain_descriptor.height as f64;
+        if cursor.x < 0.0 || cursor.x >= half_width || cursor.y < 0.0 || cursor.y >= height {
+            return;
+        }
+        let x = (cursor.x / half_width * SIZE as f64) as u32;
+        let y = (cursor.y / height * SIZE as f64) as u32;
+        let rect = MipRect {
+            x: x.saturating_sub(BRUSH_SIZE / 2),
+            y: y.saturating_sub(BRUSH_SIZE / 2),
+            width: BRUSH_SIZE.min(SIZE - x.saturating_sub(BRUSH_SIZE / 2)),
+            height: BRUSH_SIZE.min(SIZE - y.saturating_sub(BRUSH_SIZE / 2)),
+        };
+        // The color cycles over time, so strokes show through the smaller levels
+        let t = self.start.elapsed().as_secs_f32();
+        let channel = |phase: f32| ((t + phase).sin() * 127.0 + 128.0) as u8;
+        let color = [channel(0.0), channel(2.1), channel(4.2), 255];
+        let data = color.repeat((rect.width * rect.height) as usize);
+        self.queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: rect.x,
+                    y: rect.y,
+                    z: 0,
+                },
+            },
+            &data,
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: 4 * rect.width,
+                rows_per_image: 0,
+            },
+            wgpu::Extent3d {
+                width: rect.width,
+                height: rect.height,
+                depth: 1,
+            },
+        );
+        self.dirty_rect = Some(match self.dirty_rect {
+            Some(dirty_rect) => union(&dirty_rect, &rect),
+            None => rect,
+        });
+    }
+
+    fn render(&mut self) {
+        let frame = match self.swap_chain.get_current_frame() {
+            Ok(frame) => frame,
+            Err(_) => {
+                self.swap_chain = self
+                    .device
+                    .create_swap_chain(&self.surface, &self.swap_chain_descriptor);
+                return;
+            }
+        };
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        // Only the render backend limits its passes to the dirty rect, the others regenerate
+        // whole levels
+        if let Some(dirty_rect) = self.dirty_rect.take() {
+            let generator: &dyn MipmapGenerator = match self.backend {
+                Backend::Recommended => &self.recommended,
+                Backend::Compute => &self.compute,
+                Backend::Render => &self.render,
+            };
+            let mut options = GenerateOptions::default();
+            options.dirty_rect = Some(dirty_rect);
+            generator
+                .generate_with_options(
+                    &self.device,
+                    &mut encoder,
+                    &self.texture,
+                    &self.texture_descriptor,
+                    &options,
+                )
+                .expect("Failed to generate mipmaps");
+        }
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: &frame.output.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            // Instance 0 is the flat texture, and instance 1 the receding plane
+            pass.draw(0..4, 0..2);
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+/// Returns the smallest rectangle containing `a` and `b`.
+fn union(a: &MipRect, b: &MipRect) -> MipRect {
+    let x = a.x.min(b.x);
+    let y = a.y.min(b.y);
+    MipRect {
+        x,
+        y,
+        width: (a.x + a.width).max(b.x + b.width) - x,
+        height: (a.y + a.height).max(b.y + b.height) - y,
+    }
+}
@@ -0,0 +1,32 @@
+// Draws the painted texture twice: flat on the left half of the window, where it's painted,
+// and tiled on a plane receding into the distance on the right half, where trilinear
+// filtering samples its mip levels.
+[[builtin(vertex_index)]] var<in> in_vertex_index: u32;
+[[builtin(instance_index)]] var<in> in_instance_index: u32;
+[[builtin(position)]] var<out> out_position: vec4<f32>;
+[[location(0)]] var<out> out_uv: vec2<f32>;
+
+[[stage(vertex)]]
+fn vs_main() {
+    // The corners of a quad, drawn as a triangle strip
+    const u: f32 = f32(in_vertex_index & 1u);
+    const v: f32 = f32((in_vertex_index & 2u) / 2u);
+    const plane: f32 = f32(in_instance_index);
+    const flat: vec4<f32> = vec4<f32>(u - 1.0, 1.0 - 2.0 * v, 0.0, 1.0);
+    // The far edge is 6 times as distant as the near one, and narrower
+    const near: vec4<f32> = vec4<f32>(u, -1.0, 0.0, 1.0);
+    const far: vec4<f32> = vec4<f32>((0.35 + 0.3 * u) * 6.0, 0.8 * 6.0, 0.0, 6.0);
+    const receding: vec4<f32> = near * v + far * (1.0 - v);
+    out_position = flat * (1.0 - plane) + receding * plane;
+    out_uv = vec2<f32>(u, v) * (1.0 + 7.0 * plane);
+}
+
+[[group(0), binding(0)]] var u_texture: texture_2d<f32>;
+[[group(0), binding(1)]] var u_sampler: sampler;
+[[location(0)]] var<in> in_uv: vec2<f32>;
+[[location(0)]] var<out> out_color: vec4<f32>;
+
+[[stage(fragment)]]
+fn fs_main() {
+    out_color = textureSample(u_texture, u_sampler, in_uv);
+}
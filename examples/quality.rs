@@ -0,0 +1,114 @@
+use wgpu_mipmap::{
+    context::{Context, ContextOptions},
+    metrics,
+    util::{MipBuffer, MipBufferDimensions},
+    RecommendedMipmapGenerator,
+};
+
+// A CPU box filter used as the reference every backend is compared against
+fn reference_chain(base: MipBuffer, mip_level_count: u32) -> Vec<MipBuffer> {
+    let mut levels = vec![base];
+    for level in 1..mip_level_count {
+        let prev = levels.last().unwrap();
+        let src = prev.dimensions;
+        let dims = MipBufferDimensions::new(src.width / 2, src.height / 2, src.bytes_per_channel);
+        let mut buffer = Vec::with_capacity(dims.unpadded_bytes_per_row * dims.height);
+        for y in 0..dims.height {
+            for x in 0..dims.width {
+                for c in 0..dims.bytes_per_channel {
+                    let texel = |dx: usize, dy: usize| {
+                        let sx = (2 * x + dx).min(src.width - 1);
+                        let sy = (2 * y + dy).min(src.height - 1);
+                        prev.buffer
+                            [sy * src.unpadded_bytes_per_row + sx * src.bytes_per_channel + c]
+                            as f32
+                    };
+                    let sum = texel(0, 0) + texel(1, 0) + texel(0, 1) + texel(1, 1);
+                    buffer.push((sum / 4.0).round() as u8);
+                }
+            }
+        }
+        levels.push(MipBuffer {
+            buffer,
+            dimensions: dims,
+            level,
+            format: prev.format,
+        });
+    }
+    levels
+}
+
+fn main() {
+    env_logger::init();
+
+    futures::executor::block_on(async {
+        let context = Context::new(&ContextOptions::default())
+            .await
+            .expect("Failed to create a device");
+        let (device, queue) = (context.device, context.queue);
+        // Generate texture data on the CPU
+        let cat_png_bytes = include_bytes!("cat.png");
+        let cat_image = image::load_from_memory(cat_png_bytes).expect("this should work");
+        let cat_image = cat_image.into_rgba8();
+        let width = cat_image.width();
+        let height = cat_image.height();
+        let mip_level_count = 1 + (width.max(height) as f64).log2().floor() as u32;
+        // Use a linear format so the reference box filter is exact
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let reference = reference_chain(
+            MipBuffer {
+                buffer: cat_image.to_vec(),
+                dimensions: MipBufferDimensions::new(width as usize, height as usize, 4),
+                level: 0,
+                format,
+            },
+            mip_level_count,
+        );
+        let backends = vec![
+            (
+                "compute",
+                wgpu_mipmap::ComputeMipmapGenerator::required_usage(),
+            ),
+            (
+                "render",
+                wgpu_mipmap::RenderMipmapGenerator::required_usage(),
+            ),
+            ("copy", wgpu_mipmap::CopyMipmapGenerator::required_usage()),
+        ];
+        let generator = RecommendedMipmapGenerator::new(&device);
+        println!("backend\tlevel\tpsnr (dB)\tssim");
+        for (usage_str, usage) in &backends {
+            let texture_descriptor = wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth: 1,
+                },
+                mip_level_count,
+                format,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                usage: *usage | wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::COPY_SRC,
+                label: None,
+            };
+            let mipmap_buffers = wgpu_mipmap::util::generate_and_copy_to_cpu(
+                &device,
+                &queue,
+                &generator,
+                &cat_image,
+                &texture_descriptor,
+            )
+            .await
+            .expect("shouldn't fail");
+            for (mip, expected) in mipmap_buffers.iter().zip(reference.iter()).skip(1) {
+                println!(
+                    "{}\t{}\t{:.2}\t{:.4}",
+                    usage_str,
+                    mip.level,
+                    metrics::psnr(mip, expected).unwrap(),
+                    metrics::ssim(mip, expected).unwrap()
+                );
+            }
+        }
+    });
+}
@@ -0,0 +1,72 @@
+use wgpu_mipmap::{
+    testing::TestContext, util::generate_and_copy_to_cpu, ComputeMipmapGenerator, PipelineCache,
+    SrgbStorageConversion, WorkgroupSize,
+};
+
+// Probes how the adapter selected by WGPU_MIPMAP_BACKEND converts srgb storage texels, then
+// checks that the compute shader selected from the probe averages an srgb texture correctly.
+// Prints a single tab separated line, and exits with an error if the average is wrong.
+fn main() {
+    env_logger::init();
+
+    futures::executor::block_on(async {
+        let context = match TestContext::new().await {
+            Some(context) => context,
+            None => {
+                println!("none\t-\t-\t-\tskipped");
+                return;
+            }
+        };
+        let info = context.adapter.get_info();
+        let conversion =
+            SrgbStorageConversion::probe(&context.adapter, &context.device, &context.queue)
+                .await
+                .expect("Failed to probe the srgb storage conversion");
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 2,
+                height: 2,
+                depth: 1,
+            },
+            mip_level_count: 2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage()
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        let generator = ComputeMipmapGenerator::new_with_srgb_conversion(
+            &context.device,
+            &[texture_descriptor.format],
+            WorkgroupSize::default(),
+            conversion,
+            &PipelineCache::new(),
+        );
+        // Two black and two white texels average to linear 0.5, encoded as 188
+        let data = [[0u8, 0, 0, 255], [255; 4], [255; 4], [0, 0, 0, 255]].concat();
+        let mip_buffers = generate_and_copy_to_cpu(
+            &context.device,
+            &context.queue,
+            &generator,
+            &data,
+            &texture_descriptor,
+        )
+        .await
+        .expect("Failed to generate mipmaps");
+        let average = mip_buffers[1].buffer[0];
+        let ok = (average as i32 - 188).abs() <= 2;
+        println!(
+            "{:?}\t{}\t{:?}\t{}\t{}",
+            info.backend,
+            info.name,
+            conversion,
+            average,
+            if ok { "ok" } else { "FAILED" }
+        );
+        if !ok {
+            std::process::exit(1);
+        }
+    });
+}
@@ -0,0 +1,68 @@
+use std::time::Instant;
+use wgpu_mipmap::{
+    context::{Context, ContextOptions},
+    GenerateOptions, MipmapGenerator, RenderMipmapGenerator,
+};
+
+const ITERATIONS: u32 = 100;
+
+// Times the render backend with and without `GenerateOptions::tail_atlas`
+fn main() {
+    env_logger::init();
+    futures::executor::block_on(async {
+        let context = Context::new(&ContextOptions::default())
+            .await
+            .expect("Failed to create a device");
+        let (device, queue) = (context.device, context.queue);
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let generator = RenderMipmapGenerator::new_with_format_hints(&device, &[format]);
+        println!("size\ttail_atlas\tpasses\tdraws\tbytes written\tms per texture");
+        for &size in &[128, 512, 2048] {
+            let texture_descriptor = wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth: 1,
+                },
+                mip_level_count: 1 + (size as f32).log2() as u32,
+                format,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                usage: RenderMipmapGenerator::required_usage() | wgpu::TextureUsage::COPY_DST,
+                label: None,
+            };
+            let texture = device.create_texture(&texture_descriptor);
+            for &tail_atlas in &[false, true] {
+                let mut options = GenerateOptions::default();
+                options.tail_atlas = tail_atlas;
+                let cost = generator
+                    .estimate_cost_with_options(&texture_descriptor, &options)
+                    .expect("shouldn't fail");
+                let start = Instant::now();
+                for _ in 0..ITERATIONS {
+                    let mut encoder = device.create_command_encoder(&Default::default());
+                    generator
+                        .generate_with_options(
+                            &device,
+                            &mut encoder,
+                            &texture,
+                            &texture_descriptor,
+                            &options,
+                        )
+                        .expect("shouldn't fail");
+                    queue.submit(std::iter::once(encoder.finish()));
+                    device.poll(wgpu::Maintain::Wait);
+                }
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{:.3}",
+                    size,
+                    tail_atlas,
+                    cost.passes,
+                    cost.dispatches,
+                    cost.bytes_written,
+                    start.elapsed().as_secs_f64() * 1000.0 / ITERATIONS as f64
+                );
+            }
+        }
+    });
+}
@@ -0,0 +1,506 @@
+use wgpu::{
+    CommandEncoder, Device, Extent3d, Origin3d, Texture, TextureCopyView, TextureDescriptor,
+    TextureDimension, TextureUsage,
+};
+
+use crate::{
+    backends::RenderMipmapGenerator,
+    core::*,
+    trace::span,
+    util::{get_mip_extent, to_core_extent},
+};
+
+/// How [`AtlasMipmapGenerator`] arranges the levels of a mip chain in an atlas. Level 0 is
+/// always at the top-left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MipAtlasPacking {
+    /// Levels 1 and up are stacked in a column to the right of level 0, so the atlas is
+    /// 1.5 times as wide as level 0.
+    #[default]
+    Column,
+    /// Levels 1 and up are laid out in a row below level 0, so the atlas is 1.5 times as
+    /// tall as level 0.
+    Row,
+}
+
+/// Where each level of a mip chain is placed in an atlas texture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MipAtlasLayout {
+    /// The smallest atlas that holds every level.
+    pub size: Extent3d,
+    /// The texels of each level, from level 0 down to 1x1.
+    pub rects: Vec<MipRect>,
+}
+
+impl MipAtlasLayout {
+    /// Returns the layout of the full mip chain of a texture of `size`, down to 1x1, packed
+    /// with `packing`.
+    pub fn new(size: &Extent3d, packing: MipAtlasPacking) -> Self {
+        let mip_level_count = wgpu_mipmap_core::mip_level_count(to_core_extent(size));
+        let mut rects = vec![MipRect {
+            x: 0,
+            y: 0,
+            width: size.width,
+            height: size.height,
+        }];
+        let mut atlas_width = size.width;
+        let mut atlas_height = size.height;
+        // The offset of the next level along the column or row
+        let mut offset = 0;
+        for mip_level in 1..mip_level_count {
+            let extent = get_mip_extent(size, mip_level);
+            let rect = match packing {
+                MipAtlasPacking::Column => MipRect {
+                    x: size.width,
+                    y: offset,
+                    width: extent.width,
+                    height: extent.height,
+                },
+                MipAtlasPacking::Row => MipRect {
+                    x: offset,
+                    y: size.height,
+                    width: extent.width,
+                    height: extent.height,
+                },
+            };
+            offset += match packing {
+                MipAtlasPacking::Column => extent.height,
+                MipAtlasPacking::Row => extent.width,
+            };
+            atlas_width = atlas_width.max(rect.x + rect.width);
+            atlas_height = atlas_height.max(rect.y + rect.height);
+            rects.push(rect);
+        }
+        Self {
+            size: Extent3d {
+                width: atlas_width,
+                height: atlas_height,
+                depth: 1,
+            },
+            rects,
+        }
+    }
+
+    /// Returns the texture coordinates of `mip_level` in the atlas as `[u_min, v_min, u_max,
+    /// v_max]`, or `None` if the chain has no such level.
+    pub fn uv_rect(&self, mip_level: u32) -> Option<[f32; 4]> {
+        let rect = self.rects.get(mip_level as usize)?;
+        let width = self.size.width as f32;
+        let height = self.size.height as f32;
+        Some([
+            rect.x as f32 / width,
+            rect.y as f32 / height,
+            (rect.x + rect.width) as f32 / width,
+            (rect.y + rect.height) as f32 / height,
+        ])
+    }
+}
+
+/// Writes level 0 of a texture and its generated mip chain into a single non-mipmapped atlas
+/// texture, for passes that would rather sample one texture than bind a view per level.
+#[derive(Debug)]
+pub struct AtlasMipmapGenerator<'a> {
+    generator: &'a RenderMipmapGenerator,
+}
+
+impl<'a> AtlasMipmapGenerator<'a> {
+    /// Creates a new `AtlasMipmapGenerator` from an existing `RenderMipmapGenerator`.
+    /// Once created, it can be used repeatedly to generate atlases for any
+    /// texture supported by the render generator.
+    pub fn new(generator: &'a RenderMipmapGenerator) -> Self {
+        Self { generator }
+    }
+
+    /// Returns the texture usage `AtlasMipmapGenerator` requires of the source texture.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::SAMPLED | TextureUsage::COPY_SRC
+    }
+
+    /// Returns a descriptor for the atlas of a texture described by `texture_descriptor`,
+    /// packed with `packing`.
+    pub fn atlas_descriptor(
+        texture_descriptor: &TextureDescriptor,
+        packing: MipAtlasPacking,
+    ) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-atlas"),
+            size: MipAtlasLayout::new(&texture_descriptor.size, packing).size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: texture_descriptor.format,
+            usage: TextureUsage::SAMPLED | TextureUsage::COPY_DST,
+        }
+    }
+
+    /// Encodes commands to box filter the full mip chain of level 0 of `texture`, down to
+    /// 1x1, and copy every level, level 0 included, into `atlas` as laid out by `packing`.
+    /// Returns the layout, whose `uv_rect` gives the texture coordinates of each level.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`.
+    ///   Levels after 0 are neither read nor written.
+    /// - `atlas_descriptor` should be the same descriptor used to create the `atlas`, with
+    ///   the format of `texture`, usage containing `TextureUsage::COPY_DST`, and at least
+    ///   the size returned by [`AtlasMipmapGenerator::atlas_descriptor`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        atlas: &Texture,
+        atlas_descriptor: &TextureDescriptor,
+        packing: MipAtlasPacking,
+    ) -> Result<MipAtlasLayout, Error> {
+        let _span = span!("generate_mipmaps", {
+            backend = "atlas",
+            format = texture_descriptor.format,
+            levels = texture_descriptor.mip_level_count,
+            filter = MipFilter::Box,
+        });
+        if !texture_descriptor.usage.contains(Self::required_usage()) {
+            return Err(Error::UnsupportedUsage(texture_descriptor.usage));
+        }
+        if !atlas_descriptor.usage.contains(TextureUsage::COPY_DST) {
+            return Err(Error::UnsupportedUsage(atlas_descriptor.usage));
+        }
+        if atlas_descriptor.format != texture_descriptor.format {
+            return Err(Error::UnsupportedFormat(atlas_descriptor.format));
+        }
+        let layout = MipAtlasLayout::new(&texture_descriptor.size, packing);
+        if atlas_descriptor.size.width < layout.size.width
+            || atlas_descriptor.size.height < layout.size.height
+        {
+            return Err(Error::ExtentMismatch);
+        }
+        let mip_level_count = layout.rects.len() as u32;
+        // Render the chain into a temporary texture the size of level 1, then copy each of
+        // its levels into the atlas
+        let tmp_texture = if mip_level_count > 1 {
+            let tmp_descriptor = TextureDescriptor {
+                label: None,
+                size: Extent3d {
+                    depth: 1,
+                    ..get_mip_extent(&texture_descriptor.size, 1)
+                },
+                mip_level_count: mip_level_count - 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: texture_descriptor.format,
+                usage: RenderMipmapGenerator::required_usage() | TextureUsage::COPY_SRC,
+            };
+            let tmp_texture = device.create_texture(&tmp_descriptor);
+            self.generator.generate_src_dst(
+                device,
+                encoder,
+                texture,
+                &tmp_texture,
+                texture_descriptor,
+                &tmp_descriptor,
+                1,
+                0,
+                0,
+                1..mip_level_count,
+                MipFilter::Box,
+                BoxRounding::default(),
+                None,
+            )?;
+            Some(tmp_texture)
+        } else {
+            None
+        };
+        for (mip_level, rect) in layout.rects.iter().enumerate() {
+            let src = match mip_level {
+                0 => TextureCopyView {
+                    texture,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                },
+                _ => TextureCopyView {
+                    texture: tmp_texture.as_ref().unwrap(),
+                    mip_level: mip_level as u32 - 1,
+                    origin: Origin3d::ZERO,
+                },
+            };
+            encoder.copy_texture_to_texture(
+                src,
+                TextureCopyView {
+                    texture: atlas,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: rect.x,
+                        y: rect.y,
+                        z: 0,
+                    },
+                },
+                Extent3d {
+                    width: rect.width,
+                    height: rect.height,
+                    depth: 1,
+                },
+            );
+        }
+        Ok(layout)
+    }
+
+    /// Encodes commands to copy the levels packed in `atlas` as laid out by `layout` into
+    /// the mip levels of `texture`, the reverse of [`AtlasMipmapGenerator::generate`]. This
+    /// lets a chain drawn into an atlas in a single pass be scattered into a real mip chain.
+    /// Levels past the end of the shorter of the two chains are not copied.
+    ///
+    /// Expectations:
+    /// - `atlas_descriptor` should be the same descriptor used to create the `atlas`, with
+    ///   usage containing `TextureUsage::COPY_SRC`.
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`, with
+    ///   the format of `atlas`, usage containing `TextureUsage::COPY_DST`, and level 0 the
+    ///   size of the first rect of `layout`.
+    pub fn unpack(
+        encoder: &mut CommandEncoder,
+        atlas: &Texture,
+        atlas_descriptor: &TextureDescriptor,
+        layout: &MipAtlasLayout,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+    ) -> Result<(), Error> {
+        if !atlas_descriptor.usage.contains(TextureUsage::COPY_SRC) {
+            return Err(Error::UnsupportedUsage(atlas_descriptor.usage));
+        }
+        if !texture_descriptor.usage.contains(TextureUsage::COPY_DST) {
+            return Err(Error::UnsupportedUsage(texture_descriptor.usage));
+        }
+        if texture_descriptor.format != atlas_descriptor.format {
+            return Err(Error::UnsupportedFormat(texture_descriptor.format));
+        }
+        let fits = layout.rects.iter().all(|rect| {
+            rect.x + rect.width <= atlas_descriptor.size.width
+                && rect.y + rect.height <= atlas_descriptor.size.height
+        });
+        let base_matches = layout.rects.first().is_some_and(|rect| {
+            rect.width == texture_descriptor.size.width
+                && rect.height == texture_descriptor.size.height
+        });
+        if !fits || !base_matches {
+            return Err(Error::ExtentMismatch);
+        }
+        let level_count = texture_descriptor.mip_level_count as usize;
+        for (mip_level, rect) in layout.rects.iter().take(level_count).enumerate() {
+            encoder.copy_texture_to_texture(
+                TextureCopyView {
+                    texture: atlas,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: rect.x,
+                        y: rect.y,
+                        z: 0,
+                    },
+                },
+                TextureCopyView {
+                    texture,
+                    mip_level: mip_level as u32,
+                    origin: Origin3d::ZERO,
+                },
+                Extent3d {
+                    width: rect.width,
+                    height: rect.height,
+                    depth: 1,
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    #[test]
+    fn layout() {
+        let size = Extent3d {
+            width: 16,
+            height: 8,
+            depth: 1,
+        };
+        let column = MipAtlasLayout::new(&size, MipAtlasPacking::Column);
+        assert_eq!(column.rects.len(), 5);
+        // Levels 1 to 4 are 8x4, 4x2, 2x1 and 1x1
+        assert_eq!(
+            column.rects[3],
+            MipRect {
+                x: 16,
+                y: 6,
+                width: 2,
+                height: 1
+            }
+        );
+        assert_eq!((column.size.width, column.size.height), (24, 8));
+        assert_eq!(column.uv_rect(1), Some([16.0 / 24.0, 0.0, 1.0, 0.5]));
+        assert_eq!(column.uv_rect(5), None);
+
+        let row = MipAtlasLayout::new(&size, MipAtlasPacking::Row);
+        assert_eq!(
+            row.rects[4],
+            MipRect {
+                x: 14,
+                y: 8,
+                width: 1,
+                height: 1
+            }
+        );
+        assert_eq!((row.size.width, row.size.height), (16, 12));
+
+        // The 1-texel-tall levels of a wide texture overflow the column
+        let wide = MipAtlasLayout::new(
+            &Extent3d {
+                width: 8,
+                height: 1,
+                depth: 1,
+            },
+            MipAtlasPacking::Column,
+        );
+        assert_eq!((wide.size.width, wide.size.height), (12, 3));
+    }
+
+    #[test]
+    fn generate_and_unpack_atlas() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let (width, height) = (16, 8);
+        let texture_descriptor = TextureDescriptor {
+            size: Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count: 5,
+            format,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: RenderMipmapGenerator::required_usage()
+                | TextureUsage::COPY_SRC
+                | TextureUsage::COPY_DST,
+            label: None,
+        };
+        let data = checkerboard_rgba8(width, height, 2);
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = RenderMipmapGenerator::new_with_format_hints(&device, &[format]);
+            let expected =
+                generate_and_copy_to_cpu(&device, &queue, &generator, &data, &texture_descriptor)
+                    .await
+                    .unwrap();
+            // The atlas is generated from a texture with a single level
+            let src_descriptor = TextureDescriptor {
+                mip_level_count: 1,
+                ..texture_descriptor.clone()
+            };
+            let src = device.create_texture(&src_descriptor);
+            queue.write_texture(
+                TextureCopyView {
+                    texture: &src,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                },
+                &data,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * width,
+                    rows_per_image: height,
+                },
+                src_descriptor.size,
+            );
+            let atlas_descriptor = TextureDescriptor {
+                usage: TextureUsage::COPY_DST | TextureUsage::COPY_SRC,
+                ..AtlasMipmapGenerator::atlas_descriptor(&src_descriptor, MipAtlasPacking::Row)
+            };
+            let atlas = device.create_texture(&atlas_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let layout = AtlasMipmapGenerator::new(&generator)
+                .generate(
+                    &device,
+                    &mut encoder,
+                    &src,
+                    &src_descriptor,
+                    &atlas,
+                    &atlas_descriptor,
+                    MipAtlasPacking::Row,
+                )
+                .unwrap();
+            let readback = MipReadback::encode(&device, &mut encoder, &atlas, &atlas_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let actual = readback.read();
+            device.poll(wgpu::Maintain::Wait);
+            let actual = actual.await.unwrap();
+            let atlas_texels = actual[0].as_slice::<[u8; 4]>();
+            for (expected, rect) in expected.iter().zip(layout.rects.iter()) {
+                let expected_texels = expected.as_slice::<[u8; 4]>();
+                for y in 0..rect.height {
+                    let atlas_row = ((rect.y + y) * layout.size.width + rect.x) as usize;
+                    let expected_row = (y * rect.width) as usize;
+                    assert_eq!(
+                        atlas_texels[atlas_row..atlas_row + rect.width as usize],
+                        expected_texels[expected_row..expected_row + rect.width as usize],
+                        "level {}",
+                        expected.level
+                    );
+                }
+            }
+
+            // Scattering the atlas back into a mip chain gives the generated levels
+            let dst_descriptor = TextureDescriptor {
+                usage: TextureUsage::COPY_DST | TextureUsage::COPY_SRC,
+                ..texture_descriptor.clone()
+            };
+            let dst = device.create_texture(&dst_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            AtlasMipmapGenerator::unpack(
+                &mut encoder,
+                &atlas,
+                &atlas_descriptor,
+                &layout,
+                &dst,
+                &dst_descriptor,
+            )
+            .unwrap();
+            let readback = MipReadback::encode(&device, &mut encoder, &dst, &dst_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let actual = readback.read();
+            device.poll(wgpu::Maintain::Wait);
+            let actual = actual.await.unwrap();
+            for (expected, actual) in expected.iter().zip(actual.iter()) {
+                assert_eq!(expected.buffer, actual.buffer, "level {}", expected.level);
+            }
+            // The base level must match the layout
+            let small_descriptor = TextureDescriptor {
+                size: Extent3d {
+                    width: width / 2,
+                    ..dst_descriptor.size
+                },
+                mip_level_count: 1,
+                ..dst_descriptor.clone()
+            };
+            let small = device.create_texture(&small_descriptor);
+            assert_eq!(
+                AtlasMipmapGenerator::unpack(
+                    &mut device.create_command_encoder(&Default::default()),
+                    &atlas,
+                    &atlas_descriptor,
+                    &layout,
+                    &small,
+                    &small_descriptor,
+                ),
+                Err(Error::ExtentMismatch)
+            );
+        });
+    }
+}
@@ -0,0 +1,270 @@
+use crate::{
+    backends::compute::{storage_views, ComputeMipmapGenerator, Dispatch},
+    core::*,
+    trace::span,
+};
+use wgpu::{
+    CommandEncoder, ComputePassDescriptor, ComputePipeline, Device, Texture, TextureDescriptor,
+};
+
+/// Records the mipmap generation of many textures with a `ComputeMipmapGenerator` in as few
+/// passes and pipeline binds as possible.
+///
+/// Generating each texture on its own records a pass per level and binds the pipeline of its
+/// format in each of them. `BatchRecorder` instead records the `n`th dispatch of every texture
+/// in one pass, grouped by pipeline, so a batch of textures sharing a format takes one pass
+/// and one pipeline bind per level of the largest texture. The dispatches of a pass only read
+/// levels written by earlier passes, so the result is the same as generating each texture.
+///
+/// ```no_run
+/// # use wgpu_mipmap::*;
+/// # fn example(device: &wgpu::Device, generator: &ComputeMipmapGenerator,
+/// #     textures: &[(wgpu::Texture, wgpu::TextureDescriptor)]) -> Result<(), Error> {
+/// let mut batch = BatchRecorder::new(generator);
+/// for (texture, texture_descriptor) in textures {
+///     batch.push(texture, texture_descriptor)?;
+/// }
+/// let mut encoder = device.create_command_encoder(&Default::default());
+/// batch.encode(device, &mut encoder)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct BatchRecorder<'a> {
+    generator: &'a ComputeMipmapGenerator,
+    textures: Vec<BatchTexture<'a>>,
+}
+
+#[derive(Debug)]
+struct BatchTexture<'a> {
+    texture: &'a Texture,
+    texture_descriptor: &'a TextureDescriptor<'a>,
+    options: GenerateOptions,
+    bytes_written: u64,
+}
+
+impl<'a> BatchRecorder<'a> {
+    /// Creates an empty batch recorded with the pipelines of `generator`.
+    pub fn new(generator: &'a ComputeMipmapGenerator) -> Self {
+        Self {
+            generator,
+            textures: Vec::new(),
+        }
+    }
+
+    /// Adds the generation of every level of `texture` to the batch.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`.
+    pub fn push(
+        &mut self,
+        texture: &'a Texture,
+        texture_descriptor: &'a TextureDescriptor<'a>,
+    ) -> Result<(), Error> {
+        self.push_with_options(texture, texture_descriptor, GenerateOptions::default())
+    }
+
+    /// Adds the generation of the levels of `texture` selected by `options` to the batch.
+    /// Returns the error `generate_with_options` would, without recording anything, if the
+    /// generator can't generate the texture.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`.
+    pub fn push_with_options(
+        &mut self,
+        texture: &'a Texture,
+        texture_descriptor: &'a TextureDescriptor<'a>,
+        options: GenerateOptions,
+    ) -> Result<(), Error> {
+        let cost = self
+            .generator
+            .estimate_cost_with_options(texture_descriptor, &options)?;
+        self.textures.push(BatchTexture {
+            texture,
+            texture_descriptor,
+            options,
+            bytes_written: cost.bytes_written,
+        });
+        Ok(())
+    }
+
+    /// Returns the number of textures in the batch.
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    /// Returns true if no texture was added to the batch.
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    /// Encodes commands to generate the mipmaps of every texture in the batch, and returns
+    /// the work encoded.
+    pub fn encode(
+        self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+    ) -> Result<GenerationCost, Error> {
+        let _span = span!("generate_mipmaps", {
+            backend = "batch",
+            textures = self.textures.len(),
+        });
+        let views = self
+            .textures
+            .iter()
+            .map(|batch_texture| {
+                storage_views(
+                    batch_texture.texture,
+                    batch_texture.texture_descriptor.mip_level_count,
+                    batch_texture.texture_descriptor.format,
+                    batch_texture.options.array_layer,
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut texture_dispatches = Vec::with_capacity(self.textures.len());
+        for (batch_texture, views) in self.textures.iter().zip(&views) {
+            let texture_descriptor = batch_texture.texture_descriptor;
+            // A single level texture has no levels to generate
+            if texture_descriptor.mip_level_count == 1 {
+                continue;
+            }
+            let views = views.iter().collect::<Vec<_>>();
+            texture_dispatches.push(self.generator.record_dispatches(
+                device,
+                texture_descriptor,
+                &batch_texture.options,
+                texture_descriptor.format,
+                &views,
+            )?);
+        }
+        let steps = texture_dispatches.iter().map(Vec::len).max().unwrap_or(0);
+        let mut cost = GenerationCost {
+            passes: steps as u32,
+            dispatches: 0,
+            bytes_written: self.textures.iter().map(|t| t.bytes_written).sum(),
+        };
+        for step in 0..steps {
+            let mut dispatches = texture_dispatches
+                .iter()
+                .filter_map(|dispatches| dispatches.get(step))
+                .collect::<Vec<&Dispatch>>();
+            // Textures sharing a pipeline are dispatched one after another, so it's bound once
+            dispatches.sort_by_key(|(pipeline, _, _)| *pipeline as *const ComputePipeline);
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+            let mut bound: Option<&ComputePipeline> = None;
+            for (pipeline, bind_group, (x, y)) in dispatches {
+                if !bound.is_some_and(|bound| std::ptr::eq(bound, *pipeline)) {
+                    pass.set_pipeline(pipeline);
+                    bound = Some(pipeline);
+                }
+                pass.set_bind_group(0, bind_group, &[]);
+                pass.dispatch(*x, *y, 1);
+                cost.dispatches += 1;
+            }
+        }
+        Ok(cost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    #[test]
+    fn matches_generate() {
+        if !adapter_available() {
+            return;
+        }
+        let descriptor = |size: u32, format| wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 32 - size.leading_zeros(),
+            format,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage()
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        let descriptors = [
+            descriptor(32, wgpu::TextureFormat::Rgba8Unorm),
+            descriptor(8, wgpu::TextureFormat::R8Unorm),
+            descriptor(16, wgpu::TextureFormat::Rgba8Unorm),
+            descriptor(1, wgpu::TextureFormat::Rgba8Unorm),
+        ];
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = ComputeMipmapGenerator::new_with_format_hints(
+                &device,
+                &[
+                    wgpu::TextureFormat::Rgba8Unorm,
+                    wgpu::TextureFormat::R8Unorm,
+                ],
+            );
+            let data = descriptors
+                .iter()
+                .map(|d| {
+                    let size = d.size.width;
+                    match d.format {
+                        wgpu::TextureFormat::R8Unorm => checkerboard_r8(size, size, 2),
+                        _ => checkerboard_rgba8(size, size, 2),
+                    }
+                })
+                .collect::<Vec<_>>();
+            let textures = descriptors
+                .iter()
+                .zip(&data)
+                .map(|(d, data)| {
+                    let texture = device.create_texture(d);
+                    queue.write_texture(
+                        wgpu::TextureCopyView {
+                            texture: &texture,
+                            mip_level: 0,
+                            origin: wgpu::Origin3d::ZERO,
+                        },
+                        data,
+                        wgpu::TextureDataLayout {
+                            offset: 0,
+                            bytes_per_row: data.len() as u32 / d.size.height,
+                            rows_per_image: 0,
+                        },
+                        d.size,
+                    );
+                    texture
+                })
+                .collect::<Vec<_>>();
+            let mut batch = BatchRecorder::new(&generator);
+            for (texture, d) in textures.iter().zip(&descriptors) {
+                batch.push(texture, d).unwrap();
+            }
+            assert_eq!(batch.len(), 4);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let cost = batch.encode(&device, &mut encoder).unwrap();
+            // One pass per level of the 32x32 texture, one dispatch per level written
+            assert_eq!(cost.passes, 5);
+            assert_eq!(cost.dispatches, 5 + 3 + 4);
+            let readbacks = textures
+                .iter()
+                .zip(&descriptors)
+                .map(|(texture, d)| MipReadback::encode(&device, &mut encoder, texture, d))
+                .collect::<Vec<_>>();
+            queue.submit(std::iter::once(encoder.finish()));
+            let reads = readbacks.iter().map(|r| r.read()).collect::<Vec<_>>();
+            device.poll(wgpu::Maintain::Wait);
+            for ((read, d), data) in reads.into_iter().zip(&descriptors).zip(&data) {
+                let batched = read.await.unwrap();
+                let expected = generate_and_copy_to_cpu(&device, &queue, &generator, data, d)
+                    .await
+                    .unwrap();
+                for (a, b) in batched.iter().zip(&expected) {
+                    assert_eq!(a.buffer, b.buffer, "{:?} level {}", d.format, a.level);
+                }
+            }
+        });
+    }
+}
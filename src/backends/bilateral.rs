@@ -0,0 +1,421 @@
+use crate::{
+    core::*,
+    trace::event,
+    util::{create_mip_level_view, get_mip_extent},
+};
+use std::collections::HashMap;
+use wgpu::{
+    util::{make_spirv, BufferInitDescriptor, DeviceExt},
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsage,
+    CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
+    PipelineLayoutDescriptor, ShaderFlags, ShaderModule, ShaderModuleDescriptor, ShaderStage,
+    StorageTextureAccess, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsage, TextureViewDimension,
+};
+
+/// The format of the linear depth textures that guide [`BilateralMipmapGenerator`]. Depth
+/// buffers can't be bound as storage textures, so the depth is expected to be copied or
+/// linearized into one first, e.g. by the pass that writes view-space depth for SSAO.
+pub const LINEAR_DEPTH_FORMAT: TextureFormat = TextureFormat::R32Float;
+
+// Must match the local_size_x and local_size_y values in bilateral.comp
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Generates mipmaps of G-buffer data, such as normals or half resolution SSAO and SSR
+/// inputs, that don't average foreground and background texels across depth
+/// discontinuities.
+///
+/// Each level of a [`LINEAR_DEPTH_FORMAT`] depth texture is written with the nearest
+/// (smallest) depth of the 2x2 block it covers. Each texel of the texture is the average of
+/// its block, with each texel weighted by `exp(-t * t)`, where `t` is the difference between
+/// its depth and the nearest depth divided by `depth_sigma` times the nearest depth. Texels
+/// of the nearest surface dominate, and blocks of a single surface are box filtered.
+#[derive(Debug)]
+pub struct BilateralMipmapGenerator {
+    layout_cache: HashMap<TextureFormat, BindGroupLayout>,
+    pipeline_cache: HashMap<TextureFormat, ComputePipeline>,
+    depth_sigma: f32,
+}
+
+impl BilateralMipmapGenerator {
+    /// Returns the texture usage `BilateralMipmapGenerator` requires of both the texture and
+    /// its depth.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::STORAGE
+    }
+
+    /// Creates a new `BilateralMipmapGenerator`. Once created, it can be used repeatedly to
+    /// generate mipmaps for any texture with format specified in `format_hints`.
+    pub fn new_with_format_hints(device: &Device, format_hints: &[TextureFormat]) -> Self {
+        let mut layout_cache = HashMap::new();
+        let mut pipeline_cache = HashMap::new();
+        for &format in format_hints {
+            if let Some(module) = shader_for_format(device, format) {
+                let layout = bind_group_layout(device, format);
+                let pipeline = create_pipeline(device, &module, &layout, format);
+                layout_cache.insert(format, layout);
+                pipeline_cache.insert(format, pipeline);
+            } else {
+                event!(
+                    WARN,
+                    { format = format, backend = "bilateral" },
+                    "unsupported format"
+                );
+            }
+        }
+        Self {
+            layout_cache,
+            pipeline_cache,
+            depth_sigma: 0.1,
+        }
+    }
+
+    /// Returns the depth difference, relative to the nearest depth of a block, at which a
+    /// texel's weight falls to `1 / e`. Defaults to 0.1.
+    pub fn depth_sigma(&self) -> f32 {
+        self.depth_sigma
+    }
+
+    /// Sets the depth difference, relative to the nearest depth of a block, at which a
+    /// texel's weight falls to `1 / e`. Smaller values keep surfaces apart more strictly,
+    /// larger ones average more of a sloped surface. Must be greater than 0.
+    pub fn set_depth_sigma(&mut self, depth_sigma: f32) {
+        self.depth_sigma = depth_sigma;
+    }
+
+    /// Returns a descriptor for a depth texture matching `texture_descriptor`: same size and
+    /// mip level count, [`LINEAR_DEPTH_FORMAT`], and usage suitable for uploading, generation
+    /// and sampling.
+    pub fn depth_descriptor(texture_descriptor: &TextureDescriptor) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-linear-depth"),
+            size: texture_descriptor.size,
+            mip_level_count: texture_descriptor.mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: LINEAR_DEPTH_FORMAT,
+            usage: Self::required_usage()
+                | TextureUsage::SAMPLED
+                | TextureUsage::COPY_SRC
+                | TextureUsage::COPY_DST,
+        }
+    }
+
+    /// Encodes commands to generate the levels of `texture` and `depth_texture` selected by
+    /// `options`, from the base level of each.
+    ///
+    /// Expectations:
+    /// - `texture` has a power of two size, `TextureUsage::STORAGE` and a floating-point
+    ///   format specified in `format_hints`.
+    /// - `depth_texture` must match the descriptor returned by
+    ///   [`BilateralMipmapGenerator::depth_descriptor`], up to usage flags, and its base level
+    ///   should already hold the linear depth of each texel.
+    /// - `options.filter` is ignored.
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate_with_depth(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        depth_texture: &Texture,
+        depth_texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<(), Error> {
+        for descriptor in &[texture_descriptor, depth_texture_descriptor] {
+            if descriptor.dimension != TextureDimension::D2 {
+                return Err(Error::UnsupportedDimension(descriptor.dimension));
+            }
+            if !descriptor.usage.contains(Self::required_usage()) {
+                return Err(Error::UnsupportedUsage(descriptor.usage));
+            }
+        }
+        if !texture_descriptor.size.width.is_power_of_two()
+            || !texture_descriptor.size.height.is_power_of_two()
+        {
+            return Err(Error::NpotTexture);
+        }
+        if depth_texture_descriptor.format != LINEAR_DEPTH_FORMAT {
+            return Err(Error::UnsupportedFormat(depth_texture_descriptor.format));
+        }
+        if texture_descriptor.size != depth_texture_descriptor.size
+            || texture_descriptor.mip_level_count != depth_texture_descriptor.mip_level_count
+        {
+            return Err(Error::ExtentMismatch);
+        }
+        let format = texture_descriptor.format;
+        let layout = self
+            .layout_cache
+            .get(&format)
+            .ok_or(Error::UnknownFormat(format))?;
+        let pipeline = self
+            .pipeline_cache
+            .get(&format)
+            .ok_or(Error::UnknownFormat(format))?;
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("wgpu-mipmap-bilateral-params"),
+            contents: bytemuck::cast_slice(&[self.depth_sigma, 0.0, 0.0, 0.0]),
+            usage: BufferUsage::UNIFORM,
+        });
+        for mip in mip_levels {
+            event!(
+                TRACE,
+                {
+                    backend = "bilateral",
+                    format = format,
+                    level = mip,
+                },
+                "dispatching level"
+            );
+            let views = [
+                create_mip_level_view(texture, mip - 1),
+                create_mip_level_view(depth_texture, mip - 1),
+                create_mip_level_view(texture, mip),
+                create_mip_level_view(depth_texture, mip),
+            ];
+            let mut entries = (0..)
+                .zip(&views)
+                .map(|(binding, view)| BindGroupEntry {
+                    binding,
+                    resource: BindingResource::TextureView(view),
+                })
+                .collect::<Vec<_>>();
+            entries.push(BindGroupEntry {
+                binding: 4,
+                resource: BindingResource::Buffer {
+                    buffer: &params,
+                    offset: 0,
+                    size: None,
+                },
+            });
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout,
+                entries: &entries,
+            });
+            let extent = get_mip_extent(&texture_descriptor.size, mip);
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch(
+                extent.width.div_ceil(WORKGROUP_SIZE),
+                extent.height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        Ok(())
+    }
+}
+
+fn shader_for_format(device: &Device, format: TextureFormat) -> Option<ShaderModule> {
+    let s = |d| {
+        Some(device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(d),
+            flags: ShaderFlags::empty(),
+        }))
+    };
+    match format {
+        TextureFormat::R8Unorm => s(include_bytes!("shaders/bilateral_r8.comp.spv")),
+        TextureFormat::R8Snorm => s(include_bytes!("shaders/bilateral_r8_snorm.comp.spv")),
+        TextureFormat::R16Float => s(include_bytes!("shaders/bilateral_r16f.comp.spv")),
+        TextureFormat::Rg8Unorm => s(include_bytes!("shaders/bilateral_rg8.comp.spv")),
+        TextureFormat::Rg8Snorm => s(include_bytes!("shaders/bilateral_rg8_snorm.comp.spv")),
+        TextureFormat::R32Float => s(include_bytes!("shaders/bilateral_r32f.comp.spv")),
+        TextureFormat::Rg16Float => s(include_bytes!("shaders/bilateral_rg16f.comp.spv")),
+        TextureFormat::Rgba8Unorm => s(include_bytes!("shaders/bilateral_rgba8.comp.spv")),
+        TextureFormat::Rgba8Snorm => s(include_bytes!("shaders/bilateral_rgba8_snorm.comp.spv")),
+        TextureFormat::Bgra8Unorm => s(include_bytes!("shaders/bilateral_rgba8.comp.spv")),
+        TextureFormat::Rgb10a2Unorm => s(include_bytes!("shaders/bilateral_rgb10_a2.comp.spv")),
+        TextureFormat::Rg11b10Float => {
+            s(include_bytes!("shaders/bilateral_r11f_g11f_b10f.comp.spv"))
+        }
+        TextureFormat::Rg32Float => s(include_bytes!("shaders/bilateral_rg32f.comp.spv")),
+        TextureFormat::Rgba16Float => s(include_bytes!("shaders/bilateral_rgba16f.comp.spv")),
+        TextureFormat::Rgba32Float => s(include_bytes!("shaders/bilateral_rgba32f.comp.spv")),
+        _ => None,
+    }
+}
+
+fn bind_group_layout(device: &Device, format: TextureFormat) -> BindGroupLayout {
+    let textures = [
+        (StorageTextureAccess::ReadOnly, format),
+        (StorageTextureAccess::ReadOnly, LINEAR_DEPTH_FORMAT),
+        (StorageTextureAccess::WriteOnly, format),
+        (StorageTextureAccess::WriteOnly, LINEAR_DEPTH_FORMAT),
+    ];
+    let mut entries = (0..)
+        .zip(&textures)
+        .map(|(binding, &(access, format))| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStage::COMPUTE,
+            ty: BindingType::StorageTexture {
+                access,
+                format,
+                view_dimension: TextureViewDimension::D2,
+            },
+            count: None,
+        })
+        .collect::<Vec<_>>();
+    entries.push(BindGroupLayoutEntry {
+        binding: 4,
+        visibility: ShaderStage::COMPUTE,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    });
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &entries,
+    })
+}
+
+fn create_pipeline(
+    device: &Device,
+    module: &ShaderModule,
+    bind_group_layout: &BindGroupLayout,
+    format: TextureFormat,
+) -> ComputePipeline {
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some(&format!("wgpu-mipmap-bilateral-pipeline-{:?}", format)),
+        layout: Some(&pipeline_layout),
+        module,
+        entry_point: "main",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn descriptor(size: u32) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format: TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: TextureUsage::STORAGE | TextureUsage::COPY_SRC | TextureUsage::COPY_DST,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn depth_descriptor() {
+        let texture_descriptor = descriptor(64);
+        let depth = BilateralMipmapGenerator::depth_descriptor(&texture_descriptor);
+        assert_eq!(depth.size, texture_descriptor.size);
+        assert_eq!(depth.mip_level_count, texture_descriptor.mip_level_count);
+        assert_eq!(depth.format, LINEAR_DEPTH_FORMAT);
+        assert!(depth
+            .usage
+            .contains(BilateralMipmapGenerator::required_usage()));
+    }
+
+    // A red foreground at depth 1 covers the left half of a green background at depth 10
+    #[test]
+    fn keeps_surfaces_apart() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 16;
+        let texture_descriptor = descriptor(size);
+        let data = (0..size * size)
+            .flat_map(|id| {
+                if id % size < size / 2 {
+                    vec![255u8, 0, 0, 255]
+                } else {
+                    vec![0u8, 255, 0, 255]
+                }
+            })
+            .collect::<Vec<_>>();
+        let depth_data = (0..size * size)
+            .map(|id| if id % size < size / 2 { 1.0f32 } else { 10.0 })
+            .collect::<Vec<_>>();
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = BilateralMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let texture = device.create_texture(&texture_descriptor);
+            let depth_descriptor = BilateralMipmapGenerator::depth_descriptor(&texture_descriptor);
+            let depth = device.create_texture(&depth_descriptor);
+            for (texture, descriptor, data) in &[
+                (&texture, &texture_descriptor, &data[..]),
+                (&depth, &depth_descriptor, bytemuck::cast_slice(&depth_data)),
+            ] {
+                queue.write_texture(
+                    wgpu::TextureCopyView {
+                        texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d::ZERO,
+                    },
+                    data,
+                    wgpu::TextureDataLayout {
+                        offset: 0,
+                        bytes_per_row: 4 * size,
+                        rows_per_image: size,
+                    },
+                    descriptor.size,
+                );
+            }
+            let mut encoder = device.create_command_encoder(&Default::default());
+            generator
+                .generate_with_depth(
+                    &device,
+                    &mut encoder,
+                    &texture,
+                    &texture_descriptor,
+                    &depth,
+                    &depth_descriptor,
+                    &Default::default(),
+                )
+                .unwrap();
+            let readback =
+                MipReadback::encode(&device, &mut encoder, &texture, &texture_descriptor);
+            let depth_readback =
+                MipReadback::encode(&device, &mut encoder, &depth, &depth_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let (mips, depth_mips) = (readback.read(), depth_readback.read());
+            device.poll(wgpu::Maintain::Wait);
+            let (mips, depth_mips) = (mips.await.unwrap(), depth_mips.await.unwrap());
+            // Blocks straddling the edge keep the foreground's color and depth instead of
+            // a blend of the two
+            for (mip, depth_mip) in mips.iter().zip(depth_mips.iter()).skip(1) {
+                let width = mip.width() as usize;
+                let texels = mip.as_slice::<[u8; 4]>();
+                let depths = depth_mip.as_slice::<f32>();
+                for (i, (texel, depth)) in texels.iter().zip(depths).enumerate() {
+                    if i % width < width.div_ceil(2) {
+                        assert_eq!(*texel, [255, 0, 0, 255], "level {}", mip.level);
+                        assert_eq!(*depth, 1.0, "level {}", mip.level);
+                    } else {
+                        assert_eq!(*texel, [0, 255, 0, 255], "level {}", mip.level);
+                        assert_eq!(*depth, 10.0, "level {}", mip.level);
+                    }
+                }
+            }
+        });
+    }
+}
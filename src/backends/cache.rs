@@ -0,0 +1,119 @@
+use crate::{
+    backends::{
+        render::{FormatPipelines, RenderResources},
+        ComputeMipmapGenerator, SrgbStorageConversion, WorkgroupSize,
+    },
+    util::par_map,
+};
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+use wgpu::{Device, TextureFormat};
+
+/// Shader modules, samplers and pipelines shared between the generators created with it.
+///
+/// Generators created with `new_with_cache` only create what isn't already in the cache, and
+/// add it to the cache, so the render and compute backends of several
+/// [`RecommendedMipmapGenerator`](crate::RecommendedMipmapGenerator)s, e.g. one per thread,
+/// share their compiled pipelines. A cache must only be used with the device it was first
+/// used with.
+///
+/// The cache only lives in memory. wgpu 0.7 has no API to read back or seed the driver's
+/// compiled pipeline data, so pipelines can't be persisted to disk across runs; drivers that
+/// keep their own on-disk shader cache still skip recompiling them.
+#[derive(Debug, Default)]
+pub struct PipelineCache {
+    render_resources: Mutex<Option<Arc<RenderResources>>>,
+    // `None` for formats the render backend can't generate
+    render_pipelines: Mutex<HashMap<TextureFormat, Option<Arc<FormatPipelines>>>>,
+    // A generator with the pipelines of a single format
+    compute: Mutex<
+        HashMap<(TextureFormat, WorkgroupSize, SrgbStorageConversion), ComputeMipmapGenerator>,
+    >,
+}
+
+impl PipelineCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn render_resources(&self, device: &Device) -> Arc<RenderResources> {
+        self.render_resources
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| Arc::new(RenderResources::new(device)))
+            .clone()
+    }
+
+    /// Returns the render pipelines of each format in `formats`, in order.
+    pub(crate) fn render_pipelines(
+        &self,
+        device: &Device,
+        resources: &RenderResources,
+        formats: &[TextureFormat],
+    ) -> Vec<Option<Arc<FormatPipelines>>> {
+        get_or_create(&self.render_pipelines, formats, |&format| {
+            FormatPipelines::new(device, resources, format).map(Arc::new)
+        })
+    }
+
+    /// Returns one generator per format in `formats`, in order, each with the pipelines of
+    /// its format.
+    pub(crate) fn compute_generators(
+        &self,
+        device: &Device,
+        formats: &[TextureFormat],
+        workgroup_size: WorkgroupSize,
+        srgb_conversion: SrgbStorageConversion,
+    ) -> Vec<ComputeMipmapGenerator> {
+        let keys: Vec<_> = formats
+            .iter()
+            .map(|&format| (format, workgroup_size, srgb_conversion))
+            .collect();
+        get_or_create(
+            &self.compute,
+            &keys,
+            |&(format, workgroup_size, srgb_conversion)| {
+                ComputeMipmapGenerator::new_for_formats(
+                    device,
+                    &[format],
+                    workgroup_size,
+                    srgb_conversion,
+                )
+            },
+        )
+    }
+}
+
+/// Returns the value of each key in `keys`, in order, creating the values missing from `map`
+/// with `create`. The lock isn't held while creating values, so generators created
+/// concurrently may both create a value; the first one inserted is kept.
+fn get_or_create<K, V>(
+    map: &Mutex<HashMap<K, V>>,
+    keys: &[K],
+    create: impl Fn(&K) -> V + Sync,
+) -> Vec<V>
+where
+    K: Copy + Eq + Hash + Sync,
+    V: Clone + Send,
+{
+    let missing = {
+        let map = map.lock().unwrap();
+        let mut missing: Vec<K> = Vec::new();
+        for key in keys {
+            if !map.contains_key(key) && !missing.contains(key) {
+                missing.push(*key);
+            }
+        }
+        missing
+    };
+    let created = par_map(&missing, create);
+    let mut map = map.lock().unwrap();
+    for (key, value) in missing.into_iter().zip(created) {
+        map.entry(key).or_insert(value);
+    }
+    keys.iter().map(|key| map[key].clone()).collect()
+}
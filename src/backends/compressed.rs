@@ -0,0 +1,821 @@
+#[cfg(feature = "unstable")]
+use crate::util::MipReadback;
+use crate::{
+    backends::{ColorConversion, RenderMipmapGenerator},
+    core::*,
+    trace::{event, span},
+    util::{create_mip_level_view, get_mip_extent, MipBufferDimensions, MipReadbackLevel},
+};
+use std::num::NonZeroU32;
+use wgpu::{
+    util::{make_spirv, BufferInitDescriptor, DeviceExt},
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferCopyView,
+    BufferDescriptor, BufferSize, BufferUsage, CommandEncoder, ComputePassDescriptor,
+    ComputePipeline, ComputePipelineDescriptor, Device, Extent3d, Origin3d,
+    PipelineLayoutDescriptor, ShaderFlags, ShaderModuleDescriptor, ShaderStage, Texture,
+    TextureAspect, TextureCopyView, TextureDataLayout, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureSampleType, TextureUsage, TextureView, TextureViewDescriptor,
+    TextureViewDimension,
+};
+
+// Must match the local_size_x and local_size_y values in bc.comp
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Generates mipmaps for BC1, BC3 and BC7 textures that only have their first level, e.g. DDS
+/// files streamed without mipmaps.
+///
+/// The first level is decoded by drawing it into a temporary `Rgba8Unorm` (or
+/// `Rgba8UnormSrgb`) texture, whose levels are generated with the render backend. Each level
+/// is then encoded into BC blocks by a compute shader and copied into the compressed texture.
+/// The encoder picks the endpoints of each block from its bounding box, which is fast but
+/// lower quality than offline compressors, always writes opaque BC1 blocks and only writes
+/// mode 6 BC7 blocks.
+///
+/// With the `unstable` feature, `CompressedMipmapGenerator::encode_levels` encodes the levels
+/// of an uncompressed texture into blocks read back to the CPU instead, for baking assets
+/// offline.
+#[derive(Debug)]
+pub struct CompressedMipmapGenerator {
+    decoded: RenderMipmapGenerator,
+    layout: BindGroupLayout,
+    bc1_pipeline: ComputePipeline,
+    bc3_pipeline: ComputePipeline,
+    bc7_pipeline: ComputePipeline,
+}
+
+impl CompressedMipmapGenerator {
+    /// Returns the texture usage `CompressedMipmapGenerator` requires of the compressed
+    /// texture.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::SAMPLED | TextureUsage::COPY_DST
+    }
+
+    /// Creates a new `CompressedMipmapGenerator`. Once created, it can be used repeatedly to
+    /// generate mipmaps for any `Bc1RgbaUnorm`, `Bc3RgbaUnorm`, `Bc7RgbaUnorm` or sRGB variant
+    /// texture.
+    pub fn new(device: &Device) -> Self {
+        let decoded = RenderMipmapGenerator::new_with_format_hints(
+            device,
+            &[TextureFormat::Rgba8Unorm, TextureFormat::Rgba8UnormSrgb],
+        );
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("wgpu-mipmap-bc-bg-layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStage::COMPUTE,
+                    ty: BindingType::Texture {
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStage::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStage::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |source, name| {
+            let module = device.create_shader_module(&ShaderModuleDescriptor {
+                label: None,
+                source: make_spirv(source),
+                flags: ShaderFlags::empty(),
+            });
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some(name),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point: "main",
+            })
+        };
+        let bc1_pipeline = pipeline(
+            include_bytes!("shaders/bc1.comp.spv"),
+            "wgpu-mipmap-bc1-pipeline",
+        );
+        let bc3_pipeline = pipeline(
+            include_bytes!("shaders/bc3.comp.spv"),
+            "wgpu-mipmap-bc3-pipeline",
+        );
+        let bc7_pipeline = pipeline(
+            include_bytes!("shaders/bc7.comp.spv"),
+            "wgpu-mipmap-bc7-pipeline",
+        );
+        Self {
+            decoded,
+            layout,
+            bc1_pipeline,
+            bc3_pipeline,
+            bc7_pipeline,
+        }
+    }
+
+    /// Returns the format levels are decoded to, the encoding pipeline and the size of a
+    /// block in bytes for `format`, or `None` if it isn't a supported BC format.
+    fn encoding(&self, format: TextureFormat) -> Option<(TextureFormat, &ComputePipeline, u32)> {
+        match format {
+            TextureFormat::Bc1RgbaUnorm => Some((TextureFormat::Rgba8Unorm, &self.bc1_pipeline, 8)),
+            TextureFormat::Bc1RgbaUnormSrgb => {
+                Some((TextureFormat::Rgba8UnormSrgb, &self.bc1_pipeline, 8))
+            }
+            TextureFormat::Bc3RgbaUnorm => {
+                Some((TextureFormat::Rgba8Unorm, &self.bc3_pipeline, 16))
+            }
+            TextureFormat::Bc3RgbaUnormSrgb => {
+                Some((TextureFormat::Rgba8UnormSrgb, &self.bc3_pipeline, 16))
+            }
+            TextureFormat::Bc7RgbaUnorm => {
+                Some((TextureFormat::Rgba8Unorm, &self.bc7_pipeline, 16))
+            }
+            TextureFormat::Bc7RgbaUnormSrgb => {
+                Some((TextureFormat::Rgba8UnormSrgb, &self.bc7_pipeline, 16))
+            }
+            _ => None,
+        }
+    }
+
+    /// Encodes commands to compress every level of `texture`, an `Rgba8Unorm` or
+    /// `Rgba8UnormSrgb` texture whose mipmaps are already generated, into blocks of `format`,
+    /// and to copy them to a buffer read back with [`MipReadback::read`]. The levels read
+    /// back are measured in blocks: their width and height are the number of blocks, and
+    /// their texel size is the size of a block. They can be written to a KTX2 file with
+    /// `util::ktx2_file`. Nothing is sampled from a compressed texture, so the device doesn't
+    /// need `Features::TEXTURE_COMPRESSION_BC`.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`,
+    ///   which must have `TextureUsage::SAMPLED`. Only its first array layer is encoded.
+    /// - `format` is a BC1, BC3 or BC7 format, and is sRGB if and only if `texture` is.
+    ///
+    /// Needs the `unstable` feature, as the shape of [`MipReadback`] may still change.
+    #[cfg(feature = "unstable")]
+    pub fn encode_levels(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        format: TextureFormat,
+    ) -> Result<MipReadback, Error> {
+        if texture_descriptor.dimension != TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(texture_descriptor.dimension));
+        }
+        if !texture_descriptor.usage.contains(TextureUsage::SAMPLED) {
+            return Err(Error::UnsupportedUsage(texture_descriptor.usage));
+        }
+        let (decoded_format, pipeline, block_size) = self
+            .encoding(format)
+            .ok_or(Error::UnsupportedFormat(format))?;
+        if texture_descriptor.format != decoded_format {
+            return Err(Error::UnsupportedFormat(texture_descriptor.format));
+        }
+        let mut size = 0;
+        let levels = (0..texture_descriptor.mip_level_count)
+            .map(|mip| {
+                let (blocks_x, blocks_y) =
+                    block_count(&get_mip_extent(&texture_descriptor.size, mip));
+                let dimensions = MipBufferDimensions::new(
+                    blocks_x as usize,
+                    blocks_y as usize,
+                    block_size as usize,
+                );
+                let level = MipReadbackLevel {
+                    offset: size,
+                    dimensions,
+                };
+                // Padded rows keep every level aligned for binding
+                size += (dimensions.height * dimensions.padded_bytes_per_row) as u64;
+                level
+            })
+            .collect::<Vec<_>>();
+        let blocks = device.create_buffer(&BufferDescriptor {
+            label: Some("wgpu-mipmap-bc-blocks"),
+            size,
+            usage: BufferUsage::STORAGE | BufferUsage::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let srgb = decoded_format == TextureFormat::Rgba8UnormSrgb;
+        for (mip, level) in levels.iter().enumerate() {
+            let src_view = create_mip_level_view(texture, mip as u32);
+            self.encode_blocks(device, encoder, pipeline, &src_view, srgb, &blocks, level);
+        }
+        // Storage buffers can't be mapped, so the blocks are copied to a readback buffer
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: Some("wgpu-mipmap-readback"),
+            size,
+            usage: BufferUsage::COPY_DST | BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_buffer_to_buffer(&blocks, 0, &readback, 0, size);
+        Ok(MipReadback::from_levels(readback, levels, format))
+    }
+
+    /// Encodes a compute pass writing the blocks of `src_view` to `blocks` at the offset and
+    /// with the row padding of `level`, whose dimensions are measured in blocks.
+    #[allow(clippy::too_many_arguments)]
+    fn encode_blocks(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        pipeline: &ComputePipeline,
+        src_view: &TextureView,
+        srgb: bool,
+        blocks: &Buffer,
+        level: &MipReadbackLevel,
+    ) {
+        let dims = &level.dimensions;
+        let block_size = dims.bytes_per_channel as u32;
+        // Padded to 16 bytes, the size of the std140 block
+        let params = [
+            dims.padded_bytes_per_row as u32 / block_size,
+            srgb as u32,
+            0,
+            0,
+        ];
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("wgpu-mipmap-bc-params"),
+            contents: bytemuck::cast_slice(&params),
+            usage: BufferUsage::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(src_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Buffer {
+                        buffer: &params,
+                        offset: 0,
+                        size: None,
+                    },
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Buffer {
+                        buffer: blocks,
+                        offset: level.offset,
+                        size: BufferSize::new((dims.height * dims.padded_bytes_per_row) as u64),
+                    },
+                },
+            ],
+        });
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch(
+            (dims.width as u32).div_ceil(WORKGROUP_SIZE),
+            (dims.height as u32).div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+    }
+
+    /// Returns the format levels are decoded to, the encoding pipeline and the size of a
+    /// block in bytes for textures described by `texture_descriptor`.
+    fn validate(
+        &self,
+        texture_descriptor: &TextureDescriptor,
+    ) -> Result<(TextureFormat, &ComputePipeline, u32), Error> {
+        if texture_descriptor.dimension != TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(texture_descriptor.dimension));
+        }
+        if !texture_descriptor.usage.contains(Self::required_usage()) {
+            return Err(Error::UnsupportedUsage(texture_descriptor.usage));
+        }
+        self.encoding(texture_descriptor.format)
+            .ok_or(Error::UnsupportedFormat(texture_descriptor.format))
+    }
+
+    /// Draws `src_view` into `dst_view`, a texture of `format` the same size. Each texel is
+    /// fetched and written unchanged by the identity conversion.
+    fn decode(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_view: &TextureView,
+        dst_view: &TextureView,
+        format: TextureFormat,
+    ) -> Result<(), Error> {
+        self.decoded.convert_level(
+            device,
+            encoder,
+            src_view,
+            dst_view,
+            format,
+            &ColorConversion::IDENTITY,
+        )
+    }
+
+    /// Returns the temporary texture levels are decoded to and generated in.
+    fn decoded_descriptor(
+        texture_descriptor: &TextureDescriptor,
+        format: TextureFormat,
+        base_mip_level: u32,
+        mip_level_count: u32,
+    ) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-bc-decoded"),
+            size: Extent3d {
+                depth: 1,
+                ..get_mip_extent(&texture_descriptor.size, base_mip_level)
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: RenderMipmapGenerator::required_usage(),
+        }
+    }
+}
+
+/// Returns the number of blocks along each axis of a level of size `extent`.
+fn block_count(extent: &Extent3d) -> (u32, u32) {
+    (extent.width.div_ceil(4), extent.height.div_ceil(4))
+}
+
+impl MipmapGenerator for CompressedMipmapGenerator {
+    fn generate_with_options(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<(), Error> {
+        let _span = span!("generate_mipmaps", {
+            backend = "compressed",
+            format = texture_descriptor.format,
+            levels = texture_descriptor.mip_level_count,
+            filter = options.filter,
+        });
+        // A single level texture has no levels to generate
+        if texture_descriptor.mip_level_count == 1 {
+            return Ok(());
+        }
+        let (format, pipeline, block_size) = self.validate(texture_descriptor)?;
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        if mip_levels.start == mip_levels.end {
+            return Ok(());
+        }
+        let base_mip_level = mip_levels.start - 1;
+        let decoded_descriptor = Self::decoded_descriptor(
+            texture_descriptor,
+            format,
+            base_mip_level,
+            mip_levels.end - base_mip_level,
+        );
+        let decoded = device.create_texture(&decoded_descriptor);
+        let src_view = texture.create_view(&TextureViewDescriptor {
+            label: None,
+            format: None,
+            dimension: Some(TextureViewDimension::D2),
+            aspect: TextureAspect::All,
+            base_mip_level,
+            level_count: NonZeroU32::new(1),
+            base_array_layer: options.array_layer,
+            array_layer_count: NonZeroU32::new(1),
+        });
+        self.decode(
+            device,
+            encoder,
+            &src_view,
+            &create_mip_level_view(&decoded, 0),
+            format,
+        )?;
+        self.decoded.generate_with_options(
+            device,
+            encoder,
+            &decoded,
+            &decoded_descriptor,
+            &GenerateOptions {
+                filter: options.filter,
+                ..GenerateOptions::default()
+            },
+        )?;
+        let srgb = format == TextureFormat::Rgba8UnormSrgb;
+        for mip in mip_levels {
+            event!(
+                TRACE,
+                { backend = "compressed", format = texture_descriptor.format, level = mip },
+                "encoding level"
+            );
+            let decoded_level = mip - base_mip_level;
+            let (blocks_x, blocks_y) =
+                block_count(&get_mip_extent(&decoded_descriptor.size, decoded_level));
+            // Rows of blocks are copied to the texture, so they are padded to the copy alignment
+            let level = MipReadbackLevel {
+                offset: 0,
+                dimensions: MipBufferDimensions::new(
+                    blocks_x as usize,
+                    blocks_y as usize,
+                    block_size as usize,
+                ),
+            };
+            let bytes_per_row = level.dimensions.padded_bytes_per_row as u32;
+            let blocks = device.create_buffer(&BufferDescriptor {
+                label: Some("wgpu-mipmap-bc-blocks"),
+                size: (bytes_per_row * blocks_y) as u64,
+                usage: BufferUsage::STORAGE | BufferUsage::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let src_view = create_mip_level_view(&decoded, decoded_level);
+            self.encode_blocks(device, encoder, pipeline, &src_view, srgb, &blocks, &level);
+            // Levels smaller than a block are copied as a whole block
+            encoder.copy_buffer_to_texture(
+                BufferCopyView {
+                    buffer: &blocks,
+                    layout: TextureDataLayout {
+                        offset: 0,
+                        bytes_per_row,
+                        rows_per_image: 0,
+                    },
+                },
+                TextureCopyView {
+                    texture,
+                    mip_level: mip,
+                    origin: Origin3d {
+                        z: options.array_layer,
+                        ..Origin3d::ZERO
+                    },
+                },
+                Extent3d {
+                    width: blocks_x * 4,
+                    height: blocks_y * 4,
+                    depth: 1,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn estimate_cost_with_options(
+        &self,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<GenerationCost, Error> {
+        // A single level texture has no levels to generate
+        if texture_descriptor.mip_level_count == 1 {
+            return Ok(GenerationCost::default());
+        }
+        let (format, _, block_size) = self.validate(texture_descriptor)?;
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        if mip_levels.start == mip_levels.end {
+            return Ok(GenerationCost::default());
+        }
+        let base_mip_level = mip_levels.start - 1;
+        let decoded_descriptor = Self::decoded_descriptor(
+            texture_descriptor,
+            format,
+            base_mip_level,
+            mip_levels.end - base_mip_level,
+        );
+        let cost = self.decoded.estimate_cost_with_options(
+            &decoded_descriptor,
+            &GenerateOptions {
+                filter: options.filter,
+                ..GenerateOptions::default()
+            },
+        )?;
+        // One pass decodes the base level, then each level is encoded in its own pass
+        let levels = mip_levels.end - mip_levels.start;
+        let decoded_bytes = crate::util::level_bytes(&decoded_descriptor, 0..1);
+        let encoded_bytes: u64 = mip_levels
+            .map(|mip| {
+                let (blocks_x, blocks_y) = block_count(&get_mip_extent(
+                    &decoded_descriptor.size,
+                    mip - base_mip_level,
+                ));
+                (blocks_x * blocks_y * block_size) as u64
+            })
+            .sum();
+        Ok(GenerationCost {
+            passes: cost.passes + 1 + levels,
+            dispatches: cost.dispatches + 1 + levels,
+            bytes_written: cost.bytes_written + decoded_bytes + encoded_bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn descriptor(format: TextureFormat) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            size: Extent3d {
+                width: 16,
+                height: 16,
+                depth: 1,
+            },
+            mip_level_count: 5,
+            format,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: CompressedMipmapGenerator::required_usage() | TextureUsage::COPY_SRC,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn unsupported_format() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let generator = CompressedMipmapGenerator::new(&device);
+            let texture_descriptor = descriptor(TextureFormat::Rgba8Unorm);
+            assert_eq!(
+                generator.estimate_cost(&texture_descriptor),
+                Err(Error::UnsupportedFormat(TextureFormat::Rgba8Unorm))
+            );
+        });
+    }
+
+    // Decodes a mode 6 BC7 block into its 16 texels
+    fn decode_bc7_mode6(block: &[u8]) -> Vec<[u8; 4]> {
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(block);
+        let bits = u128::from_le_bytes(bytes);
+        let get = |pos: u32, n: u32| ((bits >> pos) & ((1 << n) - 1)) as u32;
+        assert_eq!(get(0, 7), 1 << 6, "not a mode 6 block");
+        let (p0, p1) = (get(63, 1), get(64, 1));
+        const WEIGHTS: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+        (0..16)
+            .map(|i| {
+                let index = if i == 0 {
+                    get(65, 3)
+                } else {
+                    get(64 + 4 * i, 4)
+                };
+                let w = WEIGHTS[index as usize];
+                let mut texel = [0; 4];
+                for (c, t) in texel.iter_mut().enumerate() {
+                    let c = c as u32;
+                    let e0 = get(7 + 14 * c, 7) << 1 | p0;
+                    let e1 = get(14 + 14 * c, 7) << 1 | p1;
+                    *t = (((64 - w) * e0 + w * e1 + 32) >> 6) as u8;
+                }
+                texel
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encodes_bc7_levels() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let texture_descriptor = TextureDescriptor {
+            size: Extent3d {
+                width: 8,
+                height: 8,
+                depth: 1,
+            },
+            mip_level_count: 4,
+            format: TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: TextureUsage::SAMPLED | TextureUsage::COPY_DST,
+            label: None,
+        };
+        // A diagonal gradient, so the texels of every block lie on a line
+        let data: Vec<u8> = (0..64u32)
+            .flat_map(|i| {
+                let t = (i % 8 + i / 8) as u8;
+                vec![t * 16, 255 - t * 16, 128, 255 - t * 8]
+            })
+            .collect();
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = CompressedMipmapGenerator::new(&device);
+            let texture = device.create_texture(&texture_descriptor);
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                },
+                &data,
+                TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 8 * 4,
+                    rows_per_image: 0,
+                },
+                texture_descriptor.size,
+            );
+            let mut encoder = device.create_command_encoder(&Default::default());
+            assert_eq!(
+                generator
+                    .encode_levels(
+                        &device,
+                        &mut encoder,
+                        &texture,
+                        &texture_descriptor,
+                        TextureFormat::Bc7RgbaUnormSrgb
+                    )
+                    .err(),
+                Some(Error::UnsupportedFormat(TextureFormat::Rgba8Unorm))
+            );
+            let readback = generator
+                .encode_levels(
+                    &device,
+                    &mut encoder,
+                    &texture,
+                    &texture_descriptor,
+                    TextureFormat::Bc7RgbaUnorm,
+                )
+                .unwrap();
+            queue.submit(std::iter::once(encoder.finish()));
+            let read = readback.read();
+            device.poll(wgpu::Maintain::Wait);
+            let levels = read.await.unwrap();
+            let blocks: Vec<_> = levels.iter().map(|l| (l.width(), l.height())).collect();
+            assert_eq!(blocks, vec![(2, 2), (1, 1), (1, 1), (1, 1)]);
+            assert_eq!(levels[0].bytes_per_texel(), 16);
+            for (b, block) in levels[0].buffer.chunks(16).enumerate() {
+                for (i, texel) in decode_bc7_mode6(block).iter().enumerate() {
+                    let x = (b % 2) * 4 + i % 4;
+                    let y = (b / 2) * 4 + i / 4;
+                    let expected = &data[(y * 8 + x) * 4..][..4];
+                    for (&a, &e) in texel.iter().zip(expected) {
+                        assert!(
+                            (a as i32 - e as i32).abs() <= 8,
+                            "texel ({}, {}): {:?} != {:?}",
+                            x,
+                            y,
+                            texel,
+                            expected
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn regenerates_bc1_levels() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let options = crate::context::ContextOptions {
+                features: wgpu::Features::TEXTURE_COMPRESSION_BC,
+                ..Default::default()
+            };
+            // Adapters without BC support can't create the texture
+            let (device, queue) = match crate::context::Context::new(&options).await {
+                Ok(context) => (context.device, context.queue),
+                Err(_) => return,
+            };
+            let generator = CompressedMipmapGenerator::new(&device);
+            let texture_descriptor = descriptor(TextureFormat::Bc1RgbaUnorm);
+            let texture = device.create_texture(&texture_descriptor);
+            // Every block of the first level is solid white: both endpoints are 0xffff
+            let block = [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0];
+            let data: Vec<u8> = block.iter().copied().cycle().take(4 * 4 * 8).collect();
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                },
+                &data,
+                TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * 8,
+                    rows_per_image: 0,
+                },
+                texture_descriptor.size,
+            );
+            let mut encoder = device.create_command_encoder(&Default::default());
+            generator
+                .generate(&device, &mut encoder, &texture, &texture_descriptor)
+                .unwrap();
+            queue.submit(std::iter::once(encoder.finish()));
+            device.poll(wgpu::Maintain::Wait);
+        });
+    }
+    #[test]
+    fn decodes_bc1_level() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let options = crate::context::ContextOptions {
+                features: wgpu::Features::TEXTURE_COMPRESSION_BC,
+                ..Default::default()
+            };
+            // Adapters without BC support can't create the texture
+            let (device, queue) = match crate::context::Context::new(&options).await {
+                Ok(context) => (context.device, context.queue),
+                Err(_) => return,
+            };
+            let generator = CompressedMipmapGenerator::new(&device);
+            let mut texture_descriptor = descriptor(TextureFormat::Bc1RgbaUnorm);
+            texture_descriptor.mip_level_count = 1;
+            let texture = device.create_texture(&texture_descriptor);
+            // Every block is solid, with a different red and green per block, so a decode
+            // that samples the wrong texel reads the color of another block
+            let block_color = |x: u32, y: u32| ((x * 8 + 4) << 11 | (y * 16 + 8) << 5) as u16;
+            let data: Vec<u8> = (0..16u32)
+                .flat_map(|b| {
+                    let color = block_color(b % 4, b / 4).to_le_bytes();
+                    vec![color[0], color[1], color[0], color[1], 0, 0, 0, 0]
+                })
+                .collect();
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                },
+                &data,
+                TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * 8,
+                    rows_per_image: 0,
+                },
+                texture_descriptor.size,
+            );
+            let decoded_descriptor = TextureDescriptor {
+                usage: RenderMipmapGenerator::required_usage() | TextureUsage::COPY_SRC,
+                ..CompressedMipmapGenerator::decoded_descriptor(
+                    &texture_descriptor,
+                    TextureFormat::Rgba8Unorm,
+                    0,
+                    1,
+                )
+            };
+            let decoded = device.create_texture(&decoded_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            generator
+                .decode(
+                    &device,
+                    &mut encoder,
+                    &create_mip_level_view(&texture, 0),
+                    &create_mip_level_view(&decoded, 0),
+                    TextureFormat::Rgba8Unorm,
+                )
+                .unwrap();
+            let readback =
+                MipReadback::encode(&device, &mut encoder, &decoded, &decoded_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let read = readback.read();
+            device.poll(wgpu::Maintain::Wait);
+            let levels = read.await.unwrap();
+            for (i, texel) in levels[0].buffer.chunks(4).enumerate() {
+                let (x, y) = (i as u32 % 16, i as u32 / 16);
+                let color = block_color(x / 4, y / 4);
+                let expand =
+                    |c: u16, bits: u32| (c as u32 * 255 + (1 << bits) / 2) / ((1 << bits) - 1);
+                let expected = [expand(color >> 11, 5), expand(color >> 5 & 0x3f, 6), 0, 255];
+                for (&a, &e) in texel.iter().zip(&expected) {
+                    assert!(
+                        (a as i32 - e as i32).abs() <= 2,
+                        "texel ({}, {}): {:?} != {:?}",
+                        x,
+                        y,
+                        texel,
+                        expected
+                    );
+                }
+            }
+        });
+    }
+}
@@ -1,62 +1,851 @@
-use crate::{core::*, util::get_mip_extent};
-use std::{collections::HashMap, num::NonZeroU32};
+// With some format features disabled, the shader selection functions have no arm using
+// their arguments
+#![cfg_attr(not(feature = "all-formats"), allow(unused_variables, unused_macros))]
+
+use crate::{
+    backends::{render::to_sample_type, DeviceBehavior, PipelineCache, SrgbStorageConversion},
+    core::*,
+    trace::{event, span},
+    util::{
+        create_stochastic_params, format_bytes_per_channel, get_mip_extent, level_bytes,
+        texel_count, MipExtent,
+    },
+};
+use std::{collections::HashMap, num::NonZeroU32, ops::Range, sync::Arc};
 use wgpu::{
-    util::make_spirv, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
-    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, CommandEncoder,
-    ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
-    PipelineLayoutDescriptor, ShaderFlags, ShaderModule, ShaderModuleDescriptor, ShaderStage,
-    StorageTextureAccess, Texture, TextureAspect, TextureDescriptor, TextureDimension,
-    TextureFormat, TextureUsage, TextureViewDescriptor, TextureViewDimension,
+    util::make_spirv, Adapter, AdapterInfo, BindGroup, BindGroupDescriptor, BindGroupEntry,
+    BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
+    BufferBindingType, CommandEncoder, ComputePassDescriptor, ComputePipeline,
+    ComputePipelineDescriptor, Device, DeviceType, Extent3d, Features, Limits,
+    PipelineLayoutDescriptor, Queue, Sampler, SamplerDescriptor, ShaderFlags, ShaderModule,
+    ShaderModuleDescriptor, ShaderStage, StorageTextureAccess, Texture, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsage,
+    TextureView, TextureViewDescriptor, TextureViewDimension,
 };
 
+/// A compute pipeline, its bind group and the number of workgroups to dispatch in x and y.
+pub(crate) type Dispatch<'a> = (&'a ComputePipeline, BindGroup, (u32, u32));
+
+// Must match the local_size_x and local_size_y values in box2.comp
+const TWO_LEVEL_WORKGROUP_SIZE: u32 = 8;
+
+// The number of destination levels in tail.comp, and the largest
+// source level its single workgroup can reduce
+const TAIL_LEVEL_COUNT: u32 = 6;
+const TAIL_MAX_SIZE: u32 = 64;
+
+// Must match the local_size_x and local_size_y values in nearest.comp, stochastic.comp,
+// dpid_guidance.comp, dpid.comp and equirect.comp
+const FILTER_WORKGROUP_SIZE: u32 = 8;
+
+// The format of the guidance levels written by dpid_guidance.comp
+const DPID_GUIDANCE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// Controls how `ComputeMipmapGenerator` separates the dispatches of consecutive mip levels.
+///
+/// Each level reads the level written by the previous dispatch, so the previous level must
+/// transition from a storage write to a storage read in between. wgpu inserts these
+/// transitions from the usage of the bound views; the modes differ in where.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ComputeSynchronization {
+    /// Records each mip level in its own compute pass, so transitions happen at pass
+    /// boundaries. This is the default.
+    #[default]
+    PassPerLevel,
+    /// Records every mip level in a single compute pass and relies on wgpu transitioning
+    /// the bound views between dispatches. Fewer passes, but more reliant on the backend's
+    /// barrier handling within a pass.
+    SinglePass,
+}
+
+/// The workgroup size of the compute shaders used by `ComputeMipmapGenerator`.
+///
+/// Larger workgroups need fewer dispatches on big desktop GPUs but can exceed the
+/// invocation limits, or hurt occupancy, on mobile and integrated GPUs. The default,
+/// 16x16, is the largest size within the 256 invocations every adapter supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum WorkgroupSize {
+    /// 8x8 invocations.
+    X8,
+    /// 16x16 invocations. This is the default.
+    #[default]
+    X16,
+    /// 32x32 invocations. Exceeds the invocation limit of some mobile and integrated GPUs.
+    X32,
+}
+
+impl WorkgroupSize {
+    /// Returns the width and height of the workgroup.
+    pub fn dimension(self) -> u32 {
+        match self {
+            WorkgroupSize::X8 => 8,
+            WorkgroupSize::X16 => 16,
+            WorkgroupSize::X32 => 32,
+        }
+    }
+
+    /// Picks a workgroup size for an adapter from a table of vendor heuristics.
+    pub fn for_adapter(adapter_info: &AdapterInfo) -> Self {
+        // PCI vendor IDs
+        const AMD: usize = 0x1002;
+        const APPLE: usize = 0x106b;
+        const ARM: usize = 0x13b5;
+        const IMG_TEC: usize = 0x1010;
+        const INTEL: usize = 0x8086;
+        const NVIDIA: usize = 0x10de;
+        const QUALCOMM: usize = 0x5143;
+        match adapter_info.vendor {
+            NVIDIA => WorkgroupSize::X16,
+            AMD => WorkgroupSize::X16,
+            APPLE => WorkgroupSize::X16,
+            INTEL => WorkgroupSize::X8,
+            ARM | IMG_TEC | QUALCOMM => WorkgroupSize::X8,
+            // Software rasterizers and unknown vendors
+            _ => match adapter_info.device_type {
+                DeviceType::DiscreteGpu => WorkgroupSize::X16,
+                _ => WorkgroupSize::X8,
+            },
+        }
+    }
+}
+
+/// How a device supports binding textures of a format as storage textures, queried with
+/// [`StorageSupport::query`] or recorded per format by [`ComputeMipmapGenerator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StorageSupport {
+    /// WebGPU guarantees storage usage for the format on every device, e.g. `Rgba8Unorm`.
+    Guaranteed,
+    /// Storage usage depends on the adapter, and the device was created with
+    /// `Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`. Generators created without an
+    /// adapter assume the adapter supports it.
+    AdapterSpecific,
+    /// Textures of the format can't be storage textures on the device.
+    Unsupported,
+}
+
+impl StorageSupport {
+    /// Returns how `device`, created from `adapter`, supports storage textures of `format`.
+    pub fn query(adapter: &Adapter, device: &Device, format: TextureFormat) -> Self {
+        match Self::for_device(device, format) {
+            StorageSupport::AdapterSpecific
+                if !adapter
+                    .get_texture_format_features(format)
+                    .allowed_usages
+                    .contains(TextureUsage::STORAGE) =>
+            {
+                StorageSupport::Unsupported
+            }
+            support => support,
+        }
+    }
+
+    /// Returns how `device` supports storage textures of `format`, as far as can be told
+    /// without its adapter.
+    fn for_device(device: &Device, format: TextureFormat) -> Self {
+        if format
+            .describe()
+            .guaranteed_format_features
+            .allowed_usages
+            .contains(TextureUsage::STORAGE)
+        {
+            StorageSupport::Guaranteed
+        } else if device
+            .features()
+            .contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        {
+            StorageSupport::AdapterSpecific
+        } else {
+            StorageSupport::Unsupported
+        }
+    }
+}
+
+/// Returns true if the backends wgpu targets commonly lack storage writes of `format`, so
+/// creating its pipelines on a device that doesn't support it may fail.
+///
+/// The other formats the compute shaders declare, such as `r8` and `rg16f`, are writable on
+/// practically every device, so their pipelines are created regardless.
+fn optional_storage_writes(format: TextureFormat) -> bool {
+    format == TextureFormat::Rg11b10Float
+}
+
+/// Identifies one of the pipelines `ComputeMipmapGenerator` creates for each format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PipelineKind {
+    Box(BoxRounding),
+    // The box filter of textures that are also sampled, see gather_shader_for_format
+    Gather(BoxRounding),
+    TwoLevel,
+    Tail,
+    Nearest,
+    Stochastic,
+    DpidGuidance,
+    Dpid,
+    Equirect,
+    Mode,
+    MaxMagnitude,
+    // The box filter of storage views of the texture with this format
+    View(TextureFormat),
+}
+
+/// A pipeline of a [`PipelineKind`] and its bind group layout, which the box filters of both
+/// roundings share.
+#[derive(Debug, Clone)]
+struct FormatPipeline {
+    layout: Arc<BindGroupLayout>,
+    pipeline: Arc<ComputePipeline>,
+}
+
+impl FormatPipeline {
+    fn new(layout: BindGroupLayout, pipeline: ComputePipeline) -> Self {
+        Self {
+            layout: Arc::new(layout),
+            pipeline: Arc::new(pipeline),
+        }
+    }
+}
+
 /// Generates mipmaps for textures with storage usage.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ComputeMipmapGenerator {
-    layout_cache: HashMap<TextureFormat, BindGroupLayout>,
-    pipeline_cache: HashMap<TextureFormat, ComputePipeline>,
+    pipelines: HashMap<(PipelineKind, TextureFormat), FormatPipeline>,
+    gather_sampler: Option<Arc<Sampler>>,
+    storage_support: HashMap<TextureFormat, StorageSupport>,
+    synchronization: ComputeSynchronization,
+    workgroup_size: WorkgroupSize,
+    device_behavior: Option<DeviceBehavior>,
 }
 
 impl ComputeMipmapGenerator {
     /// Returns the texture usage `ComputeMipmapGenerator` requires for mipmap generation.
+    ///
+    /// `Rgba8Unorm` textures that also have `TextureUsage::SAMPLED`, as most textures with
+    /// mipmaps do, are box filtered by a shader that gathers the source level instead of
+    /// loading it as a storage texture.
     pub fn required_usage() -> TextureUsage {
         TextureUsage::STORAGE
     }
 
+    /// Returns the device features `ComputeMipmapGenerator` requires to generate mipmaps for
+    /// textures with formats in `format_hints`. WebGPU only guarantees storage usage for a few
+    /// formats, such as `Rgba8Unorm` and `Rgba16Float`. The others, such as `R8Unorm` and
+    /// `Bgra8Unorm`, need `Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`, and an adapter
+    /// that supports storage for them.
+    pub fn required_features(format_hints: &[TextureFormat]) -> Features {
+        let adapter_specific = format_hints.iter().any(|format| {
+            !format
+                .describe()
+                .guaranteed_format_features
+                .allowed_usages
+                .contains(Self::required_usage())
+        });
+        if adapter_specific {
+            Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+        } else {
+            Features::empty()
+        }
+    }
+
+    /// Returns the device limits `ComputeMipmapGenerator` requires to use every code path.
+    /// `GenerateOptions::tail_combine` binds the source and 6 destination levels as storage
+    /// textures, more than the default limit of 4; it is skipped on devices created with
+    /// lower limits.
+    pub fn required_limits() -> Limits {
+        Limits {
+            max_storage_textures_per_shader_stage: 1 + TAIL_LEVEL_COUNT,
+            ..Default::default()
+        }
+    }
+
     /// Creates a new `ComputeMipmapGenerator`. Once created, it can be used repeatedly to
     /// generate mipmaps for any texture with format specified in `format_hints`.
     pub fn new_with_format_hints(device: &Device, format_hints: &[TextureFormat]) -> Self {
-        let mut layout_cache = HashMap::new();
-        let mut pipeline_cache = HashMap::new();
+        Self::new_with_workgroup_size(device, format_hints, WorkgroupSize::default())
+    }
+
+    /// Creates a new `ComputeMipmapGenerator` whose shaders use `workgroup_size`,
+    /// e.g. as chosen by [`WorkgroupSize::for_adapter`].
+    pub fn new_with_workgroup_size(
+        device: &Device,
+        format_hints: &[TextureFormat],
+        workgroup_size: WorkgroupSize,
+    ) -> Self {
+        Self::new_with_cache(device, format_hints, workgroup_size, &PipelineCache::new())
+    }
+
+    /// Like [`ComputeMipmapGenerator::new_with_workgroup_size`], but reuses the pipelines in
+    /// `cache`, and adds the ones it creates to it.
+    ///
+    /// With the `parallel` feature, the shaders and pipelines of each format are created on
+    /// their own thread.
+    pub fn new_with_cache(
+        device: &Device,
+        format_hints: &[TextureFormat],
+        workgroup_size: WorkgroupSize,
+        cache: &PipelineCache,
+    ) -> Self {
+        Self::new_with_srgb_conversion(
+            device,
+            format_hints,
+            workgroup_size,
+            SrgbStorageConversion::default(),
+            cache,
+        )
+    }
+
+    /// Like [`ComputeMipmapGenerator::new_with_cache`], but generates `Rgba8UnormSrgb` and
+    /// `Bgra8UnormSrgb` textures with the shader matching `srgb_conversion`, e.g. as measured
+    /// by [`SrgbStorageConversion::probe`], instead of the one guessed from the target OS.
+    pub fn new_with_srgb_conversion(
+        device: &Device,
+        format_hints: &[TextureFormat],
+        workgroup_size: WorkgroupSize,
+        srgb_conversion: SrgbStorageConversion,
+        cache: &PipelineCache,
+    ) -> Self {
+        // No layout or pipeline is shared between formats, so the cache holds a generator
+        // per format, merged into the first
+        let mut generators = cache
+            .compute_generators(device, format_hints, workgroup_size, srgb_conversion)
+            .into_iter();
+        let mut generator = generators
+            .next()
+            .unwrap_or_else(|| Self::new_for_formats(device, &[], workgroup_size, srgb_conversion));
+        for other in generators {
+            generator.merge(other);
+        }
+        generator
+    }
+
+    /// Like [`ComputeMipmapGenerator::new_with_cache`], but queries the [`StorageSupport`] of
+    /// each format from `adapter` rather than guessing it from the features of `device`.
+    /// Formats whose storage writes are optional, such as `Rg11b10Float`, get no pipelines
+    /// when the adapter doesn't support them, so `generate` returns
+    /// `Error::UnsupportedStorageFormat` and `RecommendedMipmapGenerator` uses another
+    /// backend.
+    pub fn new_with_adapter(
+        adapter: &Adapter,
+        device: &Device,
+        format_hints: &[TextureFormat],
+        workgroup_size: WorkgroupSize,
+        cache: &PipelineCache,
+    ) -> Self {
+        Self::new_with_storage_support(adapter, device, format_hints, |format_hints| {
+            Self::new_with_cache(device, format_hints, workgroup_size, cache)
+        })
+    }
+
+    /// Creates a generator with `new` for the formats of `format_hints` `adapter` can write,
+    /// and records the storage support of every format.
+    fn new_with_storage_support(
+        adapter: &Adapter,
+        device: &Device,
+        format_hints: &[TextureFormat],
+        new: impl FnOnce(&[TextureFormat]) -> Self,
+    ) -> Self {
+        let storage_support = format_hints
+            .iter()
+            .map(|&format| (format, StorageSupport::query(adapter, device, format)))
+            .collect::<HashMap<_, _>>();
+        let writable = format_hints
+            .iter()
+            .copied()
+            .filter(|format| {
+                let unsupported = storage_support[format] == StorageSupport::Unsupported;
+                if unsupported && optional_storage_writes(*format) {
+                    event!(
+                        DEBUG,
+                        { format = format, backend = "compute" },
+                        "storage writes unsupported by the adapter, skipping"
+                    );
+                    return false;
+                }
+                true
+            })
+            .collect::<Vec<_>>();
+        let mut generator = new(&writable);
+        generator.storage_support.extend(storage_support);
+        generator
+    }
+
+    /// Probes the implementation-defined behavior of `device` with [`DeviceBehavior::probe`],
+    /// then creates a generator like [`ComputeMipmapGenerator::new_with_srgb_conversion`] with
+    /// the probed srgb conversion. The behavior is recorded in the generator, see
+    /// [`ComputeMipmapGenerator::device_behavior`].
+    ///
+    /// The probe submits a few tiny dispatches to `queue` and waits for them, the first time
+    /// it's called for an adapter.
+    pub async fn new_with_probe(
+        adapter: &Adapter,
+        device: &Device,
+        queue: &Queue,
+        format_hints: &[TextureFormat],
+        workgroup_size: WorkgroupSize,
+        cache: &PipelineCache,
+    ) -> Result<Self, Error> {
+        let behavior = DeviceBehavior::probe(adapter, device, queue).await?;
+        let mut generator =
+            Self::new_with_storage_support(adapter, device, format_hints, |format_hints| {
+                Self::new_with_srgb_conversion(
+                    device,
+                    format_hints,
+                    workgroup_size,
+                    behavior.srgb_conversion,
+                    cache,
+                )
+            });
+        generator.device_behavior = Some(behavior);
+        Ok(generator)
+    }
+
+    /// Moves the layouts and pipelines of `other` into `self`.
+    fn merge(&mut self, other: Self) {
+        self.pipelines.extend(other.pipelines);
+        self.gather_sampler = self.gather_sampler.take().or(other.gather_sampler);
+        self.storage_support.extend(other.storage_support);
+    }
+
+    /// Creates the layouts and pipelines of `format_hints` on the calling thread.
+    pub(crate) fn new_for_formats(
+        device: &Device,
+        format_hints: &[TextureFormat],
+        workgroup_size: WorkgroupSize,
+        srgb_conversion: SrgbStorageConversion,
+    ) -> Self {
+        let mut pipelines = HashMap::new();
+        let mut storage_support = HashMap::new();
+        let tail_supported = device.limits().max_storage_textures_per_shader_stage
+            >= Self::required_limits().max_storage_textures_per_shader_stage;
         for &format in format_hints {
-            if let Some(module) = shader_for_format(device, format) {
+            let support = StorageSupport::for_device(device, format);
+            storage_support.insert(format, support);
+            if support == StorageSupport::Unsupported && optional_storage_writes(format) {
+                event!(
+                    DEBUG,
+                    { format = format, backend = "compute" },
+                    "storage writes unsupported by the device, skipping"
+                );
+                continue;
+            }
+            if let Some(view_format) = linear_view_format(format) {
+                if let Some(module) =
+                    reinterpreted_shader_for_format(device, format, view_format, workgroup_size)
+                {
+                    let bind_group_layout = bind_group_layout_for_format(device, view_format);
+                    let pipeline = compute_pipeline_for_format(
+                        device,
+                        &module,
+                        &bind_group_layout,
+                        view_format,
+                    );
+                    pipelines.insert(
+                        (PipelineKind::View(view_format), format),
+                        FormatPipeline::new(bind_group_layout, pipeline),
+                    );
+                }
+            }
+            if let Some(module) = two_level_shader_for_format(device, format) {
+                let bind_group_layout = multi_level_bind_group_layout_for_format(device, format, 2);
+                let pipeline =
+                    compute_pipeline_for_format(device, &module, &bind_group_layout, format);
+                pipelines.insert(
+                    (PipelineKind::TwoLevel, format),
+                    FormatPipeline::new(bind_group_layout, pipeline),
+                );
+            }
+            if let Some(module) = tail_shader_for_format(device, format).filter(|_| tail_supported)
+            {
+                let bind_group_layout =
+                    multi_level_bind_group_layout_for_format(device, format, TAIL_LEVEL_COUNT);
+                let pipeline =
+                    compute_pipeline_for_format(device, &module, &bind_group_layout, format);
+                pipelines.insert(
+                    (PipelineKind::Tail, format),
+                    FormatPipeline::new(bind_group_layout, pipeline),
+                );
+            }
+            if let Some(module) = equirect_shader_for_format(device, format) {
+                let bind_group_layout = bind_group_layout_for_format(device, format);
+                let pipeline =
+                    compute_pipeline_for_format(device, &module, &bind_group_layout, format);
+                pipelines.insert(
+                    (PipelineKind::Equirect, format),
+                    FormatPipeline::new(bind_group_layout, pipeline),
+                );
+            }
+            if let Some(module) = mode_shader_for_format(device, format) {
                 let bind_group_layout = bind_group_layout_for_format(device, format);
                 let pipeline =
                     compute_pipeline_for_format(device, &module, &bind_group_layout, format);
-                layout_cache.insert(format, bind_group_layout);
-                pipeline_cache.insert(format, pipeline);
+                pipelines.insert(
+                    (PipelineKind::Mode, format),
+                    FormatPipeline::new(bind_group_layout, pipeline),
+                );
+            }
+            if let Some(module) = max_magnitude_shader_for_format(device, format) {
+                let bind_group_layout = bind_group_layout_for_format(device, format);
+                let pipeline =
+                    compute_pipeline_for_format(device, &module, &bind_group_layout, format);
+                pipelines.insert(
+                    (PipelineKind::MaxMagnitude, format),
+                    FormatPipeline::new(bind_group_layout, pipeline),
+                );
+            }
+            if let Some((guidance_module, module)) = dpid_shaders_for_format(device, format) {
+                let guidance_layout = storage_bind_group_layout(
+                    device,
+                    &[
+                        (StorageTextureAccess::ReadOnly, format),
+                        (StorageTextureAccess::WriteOnly, DPID_GUIDANCE_FORMAT),
+                    ],
+                );
+                let layout = storage_bind_group_layout(
+                    device,
+                    &[
+                        (StorageTextureAccess::ReadOnly, format),
+                        (StorageTextureAccess::ReadOnly, DPID_GUIDANCE_FORMAT),
+                        (StorageTextureAccess::WriteOnly, format),
+                    ],
+                );
+                let guidance_pipeline =
+                    compute_pipeline_for_format(device, &guidance_module, &guidance_layout, format);
+                let pipeline = compute_pipeline_for_format(device, &module, &layout, format);
+                pipelines.insert(
+                    (PipelineKind::DpidGuidance, format),
+                    FormatPipeline::new(guidance_layout, guidance_pipeline),
+                );
+                pipelines.insert(
+                    (PipelineKind::Dpid, format),
+                    FormatPipeline::new(layout, pipeline),
+                );
+            }
+            if let Some(module) = point_shader_for_format(device, format, true) {
+                let bind_group_layout = stochastic_bind_group_layout_for_format(device, format);
+                let pipeline =
+                    compute_pipeline_for_format(device, &module, &bind_group_layout, format);
+                pipelines.insert(
+                    (PipelineKind::Stochastic, format),
+                    FormatPipeline::new(bind_group_layout, pipeline),
+                );
+            }
+            if let Some(module) = point_shader_for_format(device, format, false) {
+                let bind_group_layout = bind_group_layout_for_format(device, format);
+                let pipeline =
+                    compute_pipeline_for_format(device, &module, &bind_group_layout, format);
+                pipelines.insert(
+                    (PipelineKind::Nearest, format),
+                    FormatPipeline::new(bind_group_layout, pipeline),
+                );
             } else {
-                log::warn!(
-                    "ComputeMipmapGenerator does not support requested format {:?}",
-                    format
+                event!(
+                    WARN,
+                    { format = format, backend = "compute" },
+                    "unsupported format"
                 );
                 continue;
             }
+            if let Some(module) = shader_for_format(device, format, workgroup_size, srgb_conversion)
+            {
+                let bind_group_layout = Arc::new(bind_group_layout_for_format(device, format));
+                let pipeline =
+                    compute_pipeline_for_format(device, &module, &bind_group_layout, format);
+                if let Some(module) =
+                    unorm8_shader_for_format(device, format, workgroup_size, BoxRounding::HalfUp)
+                {
+                    let pipeline =
+                        compute_pipeline_for_format(device, &module, &bind_group_layout, format);
+                    let half_up = FormatPipeline {
+                        layout: bind_group_layout.clone(),
+                        pipeline: Arc::new(pipeline),
+                    };
+                    pipelines.insert((PipelineKind::Box(BoxRounding::HalfUp), format), half_up);
+                }
+                let half_even = FormatPipeline {
+                    layout: bind_group_layout,
+                    pipeline: Arc::new(pipeline),
+                };
+                pipelines.insert(
+                    (PipelineKind::Box(BoxRounding::HalfEven), format),
+                    half_even,
+                );
+            }
+            if let Some(module) =
+                gather_shader_for_format(device, format, workgroup_size, BoxRounding::HalfEven)
+            {
+                let bind_group_layout =
+                    Arc::new(gather_bind_group_layout_for_format(device, format));
+                let pipeline =
+                    compute_pipeline_for_format(device, &module, &bind_group_layout, format);
+                if let Some(module) =
+                    gather_shader_for_format(device, format, workgroup_size, BoxRounding::HalfUp)
+                {
+                    let pipeline =
+                        compute_pipeline_for_format(device, &module, &bind_group_layout, format);
+                    let half_up = FormatPipeline {
+                        layout: bind_group_layout.clone(),
+                        pipeline: Arc::new(pipeline),
+                    };
+                    pipelines.insert((PipelineKind::Gather(BoxRounding::HalfUp), format), half_up);
+                }
+                let half_even = FormatPipeline {
+                    layout: bind_group_layout,
+                    pipeline: Arc::new(pipeline),
+                };
+                pipelines.insert(
+                    (PipelineKind::Gather(BoxRounding::HalfEven), format),
+                    half_even,
+                );
+            }
         }
+        // Gather needs a sampler, even though it doesn't filter
+        let gather = pipelines
+            .keys()
+            .any(|(kind, _)| matches!(kind, PipelineKind::Gather(_)));
+        let gather_sampler = if gather {
+            Some(Arc::new(device.create_sampler(&SamplerDescriptor {
+                label: Some("wgpu-mipmap-gather-sampler"),
+                ..Default::default()
+            })))
+        } else {
+            None
+        };
         Self {
-            layout_cache,
-            pipeline_cache,
+            pipelines,
+            gather_sampler,
+            storage_support,
+            synchronization: ComputeSynchronization::default(),
+            workgroup_size,
+            device_behavior: None,
         }
     }
+
+    /// Encodes commands to generate mipmaps for a texture, binding its mip levels as
+    /// storage views of `storage_view_format` instead of the texture's own format.
+    ///
+    /// This lets an `Rgba8UnormSrgb` or `Bgra8UnormSrgb` texture be generated through
+    /// a view of its linear counterpart, which never converts on load or store, instead
+    /// of relying on how each platform treats srgb storage textures. The format must be
+    /// specified in `format_hints` when creating the generator.
+    ///
+    /// wgpu 0.7 has no `view_formats` on `TextureDescriptor`, so the reinterpretation
+    /// is not declared up front and relies on the backend accepting views whose format
+    /// differs from the texture's.
+    pub fn generate_with_view_formats(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        storage_view_format: TextureFormat,
+    ) -> Result<(), Error> {
+        self.generate_views(
+            device,
+            encoder,
+            texture,
+            texture_descriptor,
+            &GenerateOptions::default(),
+            storage_view_format,
+        )
+    }
+
+    /// Encodes commands to generate the mip levels selected by `options`, reading and writing
+    /// the levels through `level_views` instead of creating views of the texture, e.g. views
+    /// already retained by a material system.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the descriptor used to create the texture viewed.
+    /// - `level_views[n]` should be a 2D view of mip level `n` of array layer
+    ///   `options.array_layer`, with the texture's format and a single level and layer.
+    ///
+    /// Returns `Error::LevelViewCount` if there isn't exactly one view per mip level.
+    pub fn generate_with_level_views(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture_descriptor: &TextureDescriptor,
+        level_views: &[&TextureView],
+        options: &GenerateOptions,
+    ) -> Result<(), Error> {
+        if level_views.len() != texture_descriptor.mip_level_count as usize {
+            return Err(Error::LevelViewCount(
+                level_views.len(),
+                texture_descriptor.mip_level_count,
+            ));
+        }
+        // A single level texture has no levels to generate
+        if texture_descriptor.mip_level_count == 1 {
+            return Ok(());
+        }
+        self.dispatch_levels(
+            device,
+            encoder,
+            texture_descriptor,
+            options,
+            texture_descriptor.format,
+            level_views,
+        )
+    }
+
+    /// Returns the workgroup size of the single level compute shaders.
+    pub fn workgroup_size(&self) -> WorkgroupSize {
+        self.workgroup_size
+    }
+
+    /// Returns the storage support recorded for `format` when the generator was created, or
+    /// `None` if `format` wasn't in `format_hints`. Without an adapter, e.g. with
+    /// [`ComputeMipmapGenerator::new_with_cache`], it's guessed from the device features.
+    pub fn storage_support(&self, format: TextureFormat) -> Option<StorageSupport> {
+        self.storage_support.get(&format).copied()
+    }
+
+    /// Returns true if no pipelines were created for `format` because the device can't write
+    /// it from a compute shader.
+    pub(crate) fn skipped_for_storage(&self, format: TextureFormat) -> bool {
+        optional_storage_writes(format)
+            && self.storage_support(format) == Some(StorageSupport::Unsupported)
+    }
+
+    /// Returns the device behavior the generator was created with by
+    /// [`ComputeMipmapGenerator::new_with_probe`], or `None` if it wasn't probed.
+    pub fn device_behavior(&self) -> Option<DeviceBehavior> {
+        self.device_behavior
+    }
+
+    /// Sets how the dispatches of consecutive mip levels are separated.
+    pub fn set_synchronization(&mut self, synchronization: ComputeSynchronization) {
+        self.synchronization = synchronization;
+    }
+
+    /// Returns the pipeline `generate` dispatches to write one level of a texture with
+    /// `format`, or `None` if `format` wasn't specified when creating the generator, for
+    /// recording the generator's dispatches in your own compute passes.
+    ///
+    /// Bind the level to read as a read-only storage texture at binding 0, and the level to
+    /// write as a write-only storage texture at binding 1, both with `format`. Dispatch
+    /// `ceil(width / n) x ceil(height / n)` workgroups for a level of `width x height`,
+    /// where `n` is [`ComputeMipmapGenerator::workgroup_size`], or 8 for integer formats.
+    pub fn compute_pipeline_for(&self, format: TextureFormat) -> Option<&ComputePipeline> {
+        self.default_pipeline(format)
+            .map(|(_, pipeline)| pipeline.as_ref())
+    }
+
+    /// Returns the bind group layout of [`ComputeMipmapGenerator::compute_pipeline_for`].
+    pub fn bind_group_layout_for(&self, format: TextureFormat) -> Option<&BindGroupLayout> {
+        self.default_pipeline(format)
+            .map(|(layout, _)| layout.as_ref())
+    }
+
+    /// Returns the layout and pipeline of `kind` for `format`, if they were created.
+    fn pipeline(
+        &self,
+        kind: PipelineKind,
+        format: TextureFormat,
+    ) -> Option<(&Arc<BindGroupLayout>, &Arc<ComputePipeline>)> {
+        self.pipelines
+            .get(&(kind, format))
+            .map(|entry| (&entry.layout, &entry.pipeline))
+    }
+
+    // Integer formats are point sampled by default
+    fn default_pipeline(
+        &self,
+        format: TextureFormat,
+    ) -> Option<(&Arc<BindGroupLayout>, &Arc<ComputePipeline>)> {
+        let kind = match to_sample_type(format) {
+            TextureSampleType::Uint | TextureSampleType::Sint => PipelineKind::Nearest,
+            _ => PipelineKind::Box(BoxRounding::HalfEven),
+        };
+        self.pipeline(kind, format)
+    }
 }
 
 impl MipmapGenerator for ComputeMipmapGenerator {
-    fn generate(
+    fn generate_with_options(
         &self,
         device: &Device,
         encoder: &mut CommandEncoder,
         texture: &Texture,
         texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
     ) -> Result<(), Error> {
+        let _span = span!("generate_mipmaps", {
+            backend = "compute",
+            format = texture_descriptor.format,
+            levels = texture_descriptor.mip_level_count,
+            filter = options.filter,
+        });
+        self.generate_views(
+            device,
+            encoder,
+            texture,
+            texture_descriptor,
+            options,
+            texture_descriptor.format,
+        )
+    }
+
+    fn estimate_cost_with_options(
+        &self,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<GenerationCost, Error> {
+        // A single level texture has no levels to generate
+        if texture_descriptor.mip_level_count == 1 {
+            return Ok(GenerationCost::default());
+        }
+        let format = texture_descriptor.format;
+        let (_, _, filter) = self.select_pipeline(texture_descriptor, options, format)?;
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        let box_filter = filter == MipFilter::Box;
+        let two_level = options.two_levels_per_pass
+            && box_filter
+            && self.pipeline(PipelineKind::TwoLevel, format).is_some();
+        let tail = options.tail_combine
+            && box_filter
+            && self.pipeline(PipelineKind::Tail, format).is_some();
+        let tail_start = if tail {
+            tail_start(texture_descriptor, &mip_levels)
+        } else {
+            None
+        };
+        let loop_levels = tail_start.unwrap_or(mip_levels.end) - mip_levels.start;
+        let mut dispatches = match filter {
+            _ if two_level => loop_levels.div_ceil(2),
+            // Each DPID level is preceded by a dispatch writing its guidance
+            MipFilter::Dpid => 2 * loop_levels,
+            _ => loop_levels,
+        };
+        if tail_start.is_some() {
+            dispatches += 1;
+        }
+        let mut bytes_written = level_bytes(texture_descriptor, mip_levels.clone());
+        if filter == MipFilter::Dpid {
+            bytes_written += texel_count(
+                &MipExtent::of(texture_descriptor).single_layer(),
+                mip_levels,
+            ) * format_bytes_per_channel(&DPID_GUIDANCE_FORMAT) as u64;
+        }
+        let passes = match self.synchronization {
+            ComputeSynchronization::PassPerLevel => dispatches,
+            ComputeSynchronization::SinglePass => 1,
+        };
+        Ok(GenerationCost {
+            passes,
+            dispatches,
+            bytes_written,
+        })
+    }
+}
+
+impl ComputeMipmapGenerator {
+    /// Validates `texture_descriptor` and returns the bind group layout, pipeline and filter
+    /// used to generate its levels through views of `view_format`.
+    fn select_pipeline(
+        &self,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+        view_format: TextureFormat,
+    ) -> Result<(&BindGroupLayout, &ComputePipeline, MipFilter), Error> {
         // Texture width and height must be a power of 2
         if !texture_descriptor.size.width.is_power_of_two()
             || !texture_descriptor.size.height.is_power_of_two()
@@ -71,69 +860,415 @@ impl MipmapGenerator for ComputeMipmapGenerator {
             return Err(Error::UnsupportedUsage(texture_descriptor.usage));
         }
 
-        let layout = self
-            .layout_cache
-            .get(&texture_descriptor.format)
-            .ok_or(Error::UnknownFormat(texture_descriptor.format))?;
-        let pipeline = self
-            .pipeline_cache
-            .get(&texture_descriptor.format)
-            .ok_or(Error::UnknownFormat(texture_descriptor.format))?;
-
-        let mip_count = texture_descriptor.mip_level_count;
-        // TODO: Can we create the views every call?
-        let views = (0..mip_count)
-            .map(|base_mip_level| {
-                texture.create_view(&TextureViewDescriptor {
-                    label: None,
-                    format: None,
-                    dimension: None,
-                    aspect: TextureAspect::All,
-                    base_mip_level,
-                    level_count: NonZeroU32::new(1),
-                    array_layer_count: None,
-                    base_array_layer: 0,
-                })
-            })
-            .collect::<Vec<_>>();
+        let format = texture_descriptor.format;
+        let reinterpret = view_format != format;
+        // Integer texels can't be averaged, so integer formats are point sampled by default
+        let filter = match options.filter {
+            MipFilter::Box
+                if matches!(
+                    to_sample_type(format),
+                    TextureSampleType::Uint | TextureSampleType::Sint
+                ) =>
+            {
+                MipFilter::Nearest
+            }
+            filter => filter,
+        };
+        let (layout, pipeline) = if reinterpret {
+            if linear_view_format(format) != Some(view_format) {
+                return Err(Error::IncompatibleViewFormat(format, view_format));
+            }
+            self.pipeline(PipelineKind::View(view_format), format)
+                .ok_or(Error::UnknownFormat(format))?
+        } else {
+            let kind = match filter {
+                MipFilter::Box => PipelineKind::Box(BoxRounding::HalfEven),
+                MipFilter::Nearest => PipelineKind::Nearest,
+                MipFilter::Stochastic { .. } => PipelineKind::Stochastic,
+                MipFilter::Dpid => PipelineKind::Dpid,
+                MipFilter::Equirectangular => PipelineKind::Equirect,
+                MipFilter::Mode => PipelineKind::Mode,
+                MipFilter::MaxMagnitude => PipelineKind::MaxMagnitude,
+            };
+            self.pipeline(kind, format)
+                .ok_or(if self.skipped_for_storage(format) {
+                    Error::UnsupportedStorageFormat(format)
+                } else {
+                    Error::UnknownFormat(format)
+                })?
+        };
+        let pipeline = match self.pipeline(PipelineKind::Box(BoxRounding::HalfUp), format) {
+            Some((_, half_up))
+                if !reinterpret
+                    && filter == MipFilter::Box
+                    && options.rounding == BoxRounding::HalfUp =>
+            {
+                half_up
+            }
+            _ => pipeline,
+        };
+        Ok((layout, pipeline, filter))
+    }
+
+    fn generate_views(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+        view_format: TextureFormat,
+    ) -> Result<(), Error> {
+        // A single level texture has no levels to generate
+        if texture_descriptor.mip_level_count == 1 {
+            return Ok(());
+        }
+        // Validate before creating any views
+        self.select_pipeline(texture_descriptor, options, view_format)?;
+        let views = storage_views(
+            texture,
+            texture_descriptor.mip_level_count,
+            view_format,
+            options.array_layer,
+        );
+        let views = views.iter().collect::<Vec<_>>();
+        self.dispatch_levels(
+            device,
+            encoder,
+            texture_descriptor,
+            options,
+            view_format,
+            &views,
+        )
+    }
+
+    /// Encodes the dispatches writing the levels selected by `options`, where `views[n]` is
+    /// a storage view of level `n` with `view_format`.
+    fn dispatch_levels(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+        view_format: TextureFormat,
+        views: &[&TextureView],
+    ) -> Result<(), Error> {
+        let dispatches =
+            self.record_dispatches(device, texture_descriptor, options, view_format, views)?;
+        match self.synchronization {
+            ComputeSynchronization::PassPerLevel => {
+                for (pipeline, bind_group, (x, y)) in &dispatches {
+                    let mut pass =
+                        encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+                    pass.set_pipeline(pipeline);
+                    pass.set_bind_group(0, bind_group, &[]);
+                    pass.dispatch(*x, *y, 1);
+                }
+            }
+            ComputeSynchronization::SinglePass => {
+                let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+                for (pipeline, bind_group, (x, y)) in &dispatches {
+                    pass.set_pipeline(pipeline);
+                    pass.set_bind_group(0, bind_group, &[]);
+                    pass.dispatch(*x, *y, 1);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the dispatches writing the levels selected by `options`, in the order they must
+    /// be encoded, where `views[n]` is a storage view of level `n` with `view_format`. Each
+    /// dispatch only reads levels written by the dispatches before it.
+    pub(crate) fn record_dispatches(
+        &self,
+        device: &Device,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+        view_format: TextureFormat,
+        views: &[&TextureView],
+    ) -> Result<Vec<Dispatch<'_>>, Error> {
+        let (layout, pipeline, filter) =
+            self.select_pipeline(texture_descriptor, options, view_format)?;
+        let format = texture_descriptor.format;
+        let reinterpret = view_format != format;
+        let mip_levels = options.mip_levels(texture_descriptor)?;
         // Now dispatch the compute pipeline for each mip level
-        // TODO: Likely need more flexibility here
-        // - The compute shaders must have matching local_size_x and local_size_y values
-        // - When the image size is less than 32x32, more work is performed than required
-        let x_work_group_count = 32;
-        let y_work_group_count = 32;
-        for mip in 1..mip_count as usize {
-            let src_view = &views[mip - 1];
-            let dst_view = &views[mip];
+        // When the image size is less than the workgroup size, more work is performed than required
+        let box_filter = reinterpret || filter == MipFilter::Box;
+        let work_group_size = if !box_filter {
+            FILTER_WORKGROUP_SIZE
+        } else {
+            self.workgroup_size.dimension()
+        };
+        let x_work_group_count = work_group_size;
+        let y_work_group_count = work_group_size;
+        // The two level and tail shaders are only built for views of the texture's own format,
+        // and only box filter
+        let two_level = if options.two_levels_per_pass && box_filter {
+            self.pipeline(PipelineKind::TwoLevel, texture_descriptor.format)
+        } else {
+            None
+        };
+        // Textures that are also sampled can be box filtered with gathers instead of loads
+        let gather = if !reinterpret
+            && filter == MipFilter::Box
+            && texture_descriptor.usage.contains(TextureUsage::SAMPLED)
+        {
+            self.pipeline(PipelineKind::Gather(options.rounding), format)
+                .zip(self.gather_sampler.as_deref())
+        } else {
+            None
+        };
+        let tail = if options.tail_combine && box_filter {
+            self.pipeline(PipelineKind::Tail, texture_descriptor.format)
+        } else {
+            None
+        };
+        // The first level the tail shader can finish the chain from, if any
+        let tail_start = tail.and_then(|_| tail_start(texture_descriptor, &mip_levels));
+        let loop_end = tail_start.unwrap_or(mip_levels.end) as usize;
+        // Levels past the end of the chain are bound to 1x1 placeholder layers
+        let placeholder_views = match tail_start {
+            Some(_) => {
+                let placeholder = device.create_texture(&TextureDescriptor {
+                    label: Some("wgpu-mipmap-tail-placeholder"),
+                    size: Extent3d {
+                        width: 1,
+                        height: 1,
+                        depth: TAIL_LEVEL_COUNT - 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: texture_descriptor.format,
+                    usage: Self::required_usage(),
+                });
+                (0..TAIL_LEVEL_COUNT - 1)
+                    .map(|layer| {
+                        placeholder.create_view(&TextureViewDescriptor {
+                            label: None,
+                            format: None,
+                            dimension: Some(TextureViewDimension::D2),
+                            aspect: TextureAspect::All,
+                            base_mip_level: 0,
+                            level_count: NonZeroU32::new(1),
+                            base_array_layer: layer,
+                            array_layer_count: NonZeroU32::new(1),
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            }
+            None => Vec::new(),
+        };
+        // DPID first box filters each level into a scratch guidance texture, then uses it to
+        // weight the texels of the 2x2 source block
+        let dpid = if !box_filter && filter == MipFilter::Dpid {
+            let (guidance_layout, guidance_pipeline) = self
+                .pipeline(PipelineKind::DpidGuidance, format)
+                .ok_or(Error::UnknownFormat(format))?;
+            let guidance = device.create_texture(&TextureDescriptor {
+                label: Some("wgpu-mipmap-dpid-guidance"),
+                size: Extent3d {
+                    depth: 1,
+                    ..get_mip_extent(&texture_descriptor.size, mip_levels.start)
+                },
+                mip_level_count: mip_levels.end - mip_levels.start,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: DPID_GUIDANCE_FORMAT,
+                usage: TextureUsage::STORAGE,
+            });
+            let guidance_views = (0..mip_levels.end - mip_levels.start)
+                .map(|base_mip_level| {
+                    guidance.create_view(&TextureViewDescriptor {
+                        label: None,
+                        format: None,
+                        dimension: None,
+                        aspect: TextureAspect::All,
+                        base_mip_level,
+                        level_count: NonZeroU32::new(1),
+                        array_layer_count: None,
+                        base_array_layer: 0,
+                    })
+                })
+                .collect::<Vec<_>>();
+            Some((guidance_layout, guidance_pipeline, guidance_views))
+        } else {
+            None
+        };
+        let mut dispatches: Vec<Dispatch> = Vec::new();
+        let mut mip = mip_levels.start as usize;
+        while mip < loop_end {
+            event!(
+                TRACE,
+                { backend = "compute", format = format, level = mip, filter = filter },
+                "encoding level"
+            );
             let mip_ext = get_mip_extent(&texture_descriptor.size, mip as u32);
+            let entry = |binding, view| BindGroupEntry {
+                binding,
+                resource: BindingResource::TextureView(view),
+            };
+            match two_level {
+                Some((two_level_layout, two_level_pipeline)) if mip + 1 < loop_end => {
+                    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                        label: None,
+                        layout: two_level_layout,
+                        entries: &[
+                            entry(0, views[mip - 1]),
+                            entry(1, views[mip]),
+                            entry(2, views[mip + 1]),
+                        ],
+                    });
+                    let work_groups = (
+                        mip_ext.width.div_ceil(TWO_LEVEL_WORKGROUP_SIZE),
+                        mip_ext.height.div_ceil(TWO_LEVEL_WORKGROUP_SIZE),
+                    );
+                    dispatches.push((two_level_pipeline, bind_group, work_groups));
+                    mip += 2;
+                }
+                _ => {
+                    let work_groups = (
+                        mip_ext.width.div_ceil(x_work_group_count),
+                        mip_ext.height.div_ceil(y_work_group_count),
+                    );
+                    if let Some(((gather_layout, gather_pipeline), sampler)) = gather {
+                        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                            label: None,
+                            layout: gather_layout,
+                            entries: &[
+                                entry(0, views[mip - 1]),
+                                entry(1, views[mip]),
+                                BindGroupEntry {
+                                    binding: 2,
+                                    resource: BindingResource::Sampler(sampler),
+                                },
+                            ],
+                        });
+                        dispatches.push((gather_pipeline, bind_group, work_groups));
+                        mip += 1;
+                        continue;
+                    }
+                    let mut entries = vec![entry(0, views[mip - 1])];
+                    if let Some((guidance_layout, guidance_pipeline, guidance_views)) = &dpid {
+                        let guidance_view = &guidance_views[mip - mip_levels.start as usize];
+                        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                            label: None,
+                            layout: guidance_layout,
+                            entries: &[entry(0, views[mip - 1]), entry(1, guidance_view)],
+                        });
+                        dispatches.push((*guidance_pipeline, bind_group, work_groups));
+                        entries.push(entry(1, guidance_view));
+                    }
+                    entries.push(entry(entries.len() as u32, views[mip]));
+                    let params = match filter {
+                        MipFilter::Stochastic { seed } => {
+                            Some(create_stochastic_params(device, seed, mip as u32))
+                        }
+                        _ => None,
+                    };
+                    if let Some(params) = &params {
+                        entries.push(BindGroupEntry {
+                            binding: 2,
+                            resource: BindingResource::Buffer {
+                                buffer: params,
+                                offset: 0,
+                                size: None,
+                            },
+                        });
+                    }
+                    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                        label: None,
+                        layout,
+                        entries: &entries,
+                    });
+                    dispatches.push((pipeline, bind_group, work_groups));
+                    mip += 1;
+                }
+            }
+        }
+        if let (Some((tail_layout, tail_pipeline)), Some(tail_start)) = (tail, tail_start) {
+            let tail_start = tail_start as usize;
+            let mut entries = vec![BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(views[tail_start - 1]),
+            }];
+            let dst_views = views[tail_start..mip_levels.end as usize]
+                .iter()
+                .copied()
+                .chain(placeholder_views.iter())
+                .take(TAIL_LEVEL_COUNT as usize);
+            for (binding, view) in (1..).zip(dst_views) {
+                entries.push(BindGroupEntry {
+                    binding,
+                    resource: BindingResource::TextureView(view),
+                });
+            }
             let bind_group = device.create_bind_group(&BindGroupDescriptor {
                 label: None,
-                layout,
-                entries: &[
-                    BindGroupEntry {
-                        binding: 0,
-                        resource: BindingResource::TextureView(&src_view),
-                    },
-                    BindGroupEntry {
-                        binding: 1,
-                        resource: BindingResource::TextureView(&dst_view),
-                    },
-                ],
+                layout: tail_layout,
+                entries: &entries,
             });
-            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
-            pass.set_pipeline(pipeline);
-            pass.set_bind_group(0, &bind_group, &[]);
-            pass.dispatch(
-                (mip_ext.width / x_work_group_count).max(1),
-                (mip_ext.height / y_work_group_count).max(1),
-                1,
-            );
+            dispatches.push((tail_pipeline, bind_group, (1, 1)));
         }
-        Ok(())
+        Ok(dispatches)
     }
 }
 
-fn shader_for_format(device: &Device, format: TextureFormat) -> Option<ShaderModule> {
+/// Creates a view of each of the `mip_count` levels of `array_layer` of `texture`, to bind as
+/// storage textures of `view_format`.
+pub(crate) fn storage_views(
+    texture: &Texture,
+    mip_count: u32,
+    view_format: TextureFormat,
+    array_layer: u32,
+) -> Vec<TextureView> {
+    (0..mip_count)
+        .map(|base_mip_level| {
+            texture.create_view(&TextureViewDescriptor {
+                label: None,
+                format: Some(view_format),
+                dimension: Some(TextureViewDimension::D2),
+                aspect: TextureAspect::All,
+                base_mip_level,
+                level_count: NonZeroU32::new(1),
+                array_layer_count: NonZeroU32::new(1),
+                base_array_layer: array_layer,
+            })
+        })
+        .collect()
+}
+
+/// Returns the first level of `mip_levels` the tail shader can finish the chain from, if any.
+fn tail_start(texture_descriptor: &TextureDescriptor, mip_levels: &Range<u32>) -> Option<u32> {
+    (mip_levels.start..mip_levels.end.saturating_sub(1)).find(|&mip| {
+        let src_ext = get_mip_extent(&texture_descriptor.size, mip - 1);
+        src_ext.width <= TAIL_MAX_SIZE && src_ext.height <= TAIL_MAX_SIZE
+    })
+}
+
+// Selects the variant of shaders/$name.comp compiled for `$workgroup_size`
+macro_rules! box_shader {
+    ($s:expr, $workgroup_size:expr, $name:literal) => {
+        match $workgroup_size {
+            WorkgroupSize::X8 => $s(include_bytes!(concat!("shaders/", $name, "_8x8.comp.spv"))),
+            WorkgroupSize::X16 => $s(include_bytes!(concat!(
+                "shaders/",
+                $name,
+                "_16x16.comp.spv"
+            ))),
+            WorkgroupSize::X32 => $s(include_bytes!(concat!("shaders/", $name, ".comp.spv"))),
+        }
+    };
+}
+
+fn shader_for_format(
+    device: &Device,
+    format: TextureFormat,
+    workgroup_size: WorkgroupSize,
+    srgb_conversion: SrgbStorageConversion,
+) -> Option<ShaderModule> {
     let s = |d| {
         Some(device.create_shader_module(&ShaderModuleDescriptor {
             label: None,
@@ -142,41 +1277,545 @@ fn shader_for_format(device: &Device, format: TextureFormat) -> Option<ShaderMod
         }))
     };
     match format {
-        TextureFormat::R8Unorm => s(include_bytes!("shaders/box_r8.comp.spv")),
-        TextureFormat::R8Snorm => s(include_bytes!("shaders/box_r8_snorm.comp.spv")),
-        TextureFormat::R16Float => s(include_bytes!("shaders/box_r16f.comp.spv")),
-        TextureFormat::Rg8Unorm => s(include_bytes!("shaders/box_rg8.comp.spv")),
-        TextureFormat::Rg8Snorm => s(include_bytes!("shaders/box_rg8_snorm.comp.spv")),
-        TextureFormat::R32Float => s(include_bytes!("shaders/box_r32f.comp.spv")),
-        TextureFormat::Rg16Float => s(include_bytes!("shaders/box_rg16f.comp.spv")),
-        TextureFormat::Rgba8Unorm => s(include_bytes!("shaders/box_rgba8.comp.spv")),
-        TextureFormat::Rgba8UnormSrgb | TextureFormat::Bgra8UnormSrgb => {
-            // On MacOS, my GPUFamily2 v1 capable GPU
-            // seems to perform the srgb -> linear before I load it
-            // in the shader, but expects me to perform the linear -> srgb
-            // conversion before storing.
-            #[cfg(target_os = "macos")]
-            {
-                s(include_bytes!("shaders/box_srgb_macos.comp.spv"))
-            }
-            // On  Vulkan (and DX12?), the implementation does not perform
-            // any conversion, so this shader handles it all
-            #[cfg(not(target_os = "macos"))]
-            {
-                s(include_bytes!("shaders/box_srgb.comp.spv"))
-            }
+        TextureFormat::R8Unorm
+        | TextureFormat::Rg8Unorm
+        | TextureFormat::Rgba8Unorm
+        | TextureFormat::Bgra8Unorm => {
+            unorm8_shader_for_format(device, format, workgroup_size, BoxRounding::HalfEven)
+        }
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::R8Snorm => box_shader!(s, workgroup_size, "box_r8_snorm"),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::R16Float => box_shader!(s, workgroup_size, "box_r16f"),
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::Rg8Snorm => box_shader!(s, workgroup_size, "box_rg8_snorm"),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::R32Float => box_shader!(s, workgroup_size, "box_r32f"),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::Rg16Float => box_shader!(s, workgroup_size, "box_rg16f"),
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::Rgba8UnormSrgb | TextureFormat::Bgra8UnormSrgb => match srgb_conversion {
+            // The shader converts whichever way the implementation doesn't
+            SrgbStorageConversion::None => box_shader!(s, workgroup_size, "box_srgb"),
+            SrgbStorageConversion::OnLoad => box_shader!(s, workgroup_size, "box_srgb_macos"),
+            SrgbStorageConversion::OnStore => box_shader!(s, workgroup_size, "box_srgb_store"),
+            SrgbStorageConversion::OnLoadAndStore => box_shader!(s, workgroup_size, "box_rgba8"),
+        },
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::Rgba8Snorm => box_shader!(s, workgroup_size, "box_rgba8_snorm"),
+        #[cfg(feature = "format-packed")]
+        TextureFormat::Rgb10a2Unorm => box_shader!(s, workgroup_size, "box_rgb10_a2"),
+        #[cfg(feature = "format-packed")]
+        TextureFormat::Rg11b10Float => box_shader!(s, workgroup_size, "box_r11f_g11f_b10f"),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::Rg32Float => box_shader!(s, workgroup_size, "box_rg32f"),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::Rgba16Float => box_shader!(s, workgroup_size, "box_rgba16f"),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::Rgba32Float => box_shader!(s, workgroup_size, "box_rgba32f"),
+        _ => None,
+    }
+}
+
+/// Returns the box filter of 8 bit unorm textures of `format`, which rounds the average of
+/// each 2x2 block with `rounding` rather than the implementation's float to unorm conversion.
+/// `Bgra8Unorm` shares the `rgba8` shaders, as its channels are filtered independently.
+fn unorm8_shader_for_format(
+    device: &Device,
+    format: TextureFormat,
+    workgroup_size: WorkgroupSize,
+    rounding: BoxRounding,
+) -> Option<ShaderModule> {
+    let s = |d| {
+        Some(device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(d),
+            flags: ShaderFlags::empty(),
+        }))
+    };
+    match (format, rounding) {
+        #[cfg(feature = "format-unorm8")]
+        (TextureFormat::R8Unorm, BoxRounding::HalfEven) => {
+            box_shader!(s, workgroup_size, "box_r8_half_even")
+        }
+        #[cfg(feature = "format-unorm8")]
+        (TextureFormat::R8Unorm, BoxRounding::HalfUp) => {
+            box_shader!(s, workgroup_size, "box_r8_half_up")
+        }
+        #[cfg(feature = "format-unorm8")]
+        (TextureFormat::Rg8Unorm, BoxRounding::HalfEven) => {
+            box_shader!(s, workgroup_size, "box_rg8_half_even")
+        }
+        #[cfg(feature = "format-unorm8")]
+        (TextureFormat::Rg8Unorm, BoxRounding::HalfUp) => {
+            box_shader!(s, workgroup_size, "box_rg8_half_up")
+        }
+        #[cfg(feature = "format-unorm8")]
+        (TextureFormat::Rgba8Unorm | TextureFormat::Bgra8Unorm, BoxRounding::HalfEven) => {
+            box_shader!(s, workgroup_size, "box_rgba8_half_even")
+        }
+        #[cfg(feature = "format-unorm8")]
+        (TextureFormat::Rgba8Unorm | TextureFormat::Bgra8Unorm, BoxRounding::HalfUp) => {
+            box_shader!(s, workgroup_size, "box_rgba8_half_up")
+        }
+        _ => None,
+    }
+}
+
+/// Returns the box filter for textures of `format` that are also sampled. It fetches each
+/// channel of a 2x2 footprint with a single `textureGather`, through the texture cache
+/// rather than storage loads, and averages the four 8 bit values packed in a `uint` with
+/// integer math, rounding halves with `rounding`.
+///
+/// `Bgra8Unorm` isn't included: its storage views may see the texels in memory order, which
+/// only cancels out when the source is loaded through a storage view too.
+fn gather_shader_for_format(
+    device: &Device,
+    format: TextureFormat,
+    workgroup_size: WorkgroupSize,
+    rounding: BoxRounding,
+) -> Option<ShaderModule> {
+    let s = |d| {
+        Some(device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(d),
+            flags: ShaderFlags::empty(),
+        }))
+    };
+    match (format, rounding) {
+        #[cfg(feature = "format-unorm8")]
+        (TextureFormat::Rgba8Unorm, BoxRounding::HalfEven) => {
+            box_shader!(s, workgroup_size, "box_rgba8_gather_half_even")
+        }
+        #[cfg(feature = "format-unorm8")]
+        (TextureFormat::Rgba8Unorm, BoxRounding::HalfUp) => {
+            box_shader!(s, workgroup_size, "box_rgba8_gather_half_up")
+        }
+        _ => None,
+    }
+}
+
+/// Returns the linear format whose storage views can write to a texture of `format`.
+///
+/// `Rgba8UnormSrgb` and `Bgra8UnormSrgb` share the srgb shaders, whose storage images are
+/// declared `rgba8`. A backend may hand the shader the texels of a BGRA view in memory order,
+/// with red and blue swapped, but it then stores them in the same order. The shaders filter
+/// each channel independently and apply the same transfer function to red, green and blue,
+/// so the swap cancels out. Alpha, which stays linear, is the last channel in both orders.
+fn linear_view_format(format: TextureFormat) -> Option<TextureFormat> {
+    match format {
+        TextureFormat::Rgba8UnormSrgb => Some(TextureFormat::Rgba8Unorm),
+        TextureFormat::Bgra8UnormSrgb => Some(TextureFormat::Bgra8Unorm),
+        _ => None,
+    }
+}
+
+fn reinterpreted_shader_for_format(
+    device: &Device,
+    format: TextureFormat,
+    view_format: TextureFormat,
+    workgroup_size: WorkgroupSize,
+) -> Option<ShaderModule> {
+    let s = |d| {
+        Some(device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(d),
+            flags: ShaderFlags::empty(),
+        }))
+    };
+    // A linear view never converts on load or store on any platform,
+    // so box_srgb.comp performs both conversions
+    #[cfg(feature = "format-unorm8")]
+    if linear_view_format(format) == Some(view_format) {
+        return box_shader!(s, workgroup_size, "box_srgb");
+    }
+    None
+}
+
+fn two_level_shader_for_format(device: &Device, format: TextureFormat) -> Option<ShaderModule> {
+    let s = |d| {
+        Some(device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(d),
+            flags: ShaderFlags::empty(),
+        }))
+    };
+    // The srgb formats convert to and from linear by hand in box_srgb.comp
+    // and always generate one level per pass
+    match format {
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::R8Unorm => s(include_bytes!("shaders/box2_r8.comp.spv")),
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::R8Snorm => s(include_bytes!("shaders/box2_r8_snorm.comp.spv")),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::R16Float => s(include_bytes!("shaders/box2_r16f.comp.spv")),
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::Rg8Unorm => s(include_bytes!("shaders/box2_rg8.comp.spv")),
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::Rg8Snorm => s(include_bytes!("shaders/box2_rg8_snorm.comp.spv")),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::R32Float => s(include_bytes!("shaders/box2_r32f.comp.spv")),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::Rg16Float => s(include_bytes!("shaders/box2_rg16f.comp.spv")),
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::Rgba8Unorm => s(include_bytes!("shaders/box2_rgba8.comp.spv")),
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::Rgba8Snorm => s(include_bytes!("shaders/box2_rgba8_snorm.comp.spv")),
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::Bgra8Unorm => s(include_bytes!("shaders/box2_rgba8.comp.spv")),
+        #[cfg(feature = "format-packed")]
+        TextureFormat::Rgb10a2Unorm => s(include_bytes!("shaders/box2_rgb10_a2.comp.spv")),
+        #[cfg(feature = "format-packed")]
+        TextureFormat::Rg11b10Float => s(include_bytes!("shaders/box2_r11f_g11f_b10f.comp.spv")),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::Rg32Float => s(include_bytes!("shaders/box2_rg32f.comp.spv")),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::Rgba16Float => s(include_bytes!("shaders/box2_rgba16f.comp.spv")),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::Rgba32Float => s(include_bytes!("shaders/box2_rgba32f.comp.spv")),
+        _ => None,
+    }
+}
+
+macro_rules! point_shader {
+    ($s:expr, $stochastic:expr, $name:literal) => {
+        if $stochastic {
+            $s(include_bytes!(concat!(
+                "shaders/stochastic_",
+                $name,
+                ".comp.spv"
+            )))
+        } else {
+            $s(include_bytes!(concat!(
+                "shaders/nearest_",
+                $name,
+                ".comp.spv"
+            )))
+        }
+    };
+}
+
+fn point_shader_for_format(
+    device: &Device,
+    format: TextureFormat,
+    stochastic: bool,
+) -> Option<ShaderModule> {
+    let s = |d| {
+        Some(device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(d),
+            flags: ShaderFlags::empty(),
+        }))
+    };
+    // Point sampling copies texels without converting them, so the srgb
+    // formats can use the shaders of their linear counterpart
+    match format {
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::R8Unorm => point_shader!(s, stochastic, "r8"),
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::R8Snorm => point_shader!(s, stochastic, "r8_snorm"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::R8Uint => point_shader!(s, stochastic, "r8ui"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::R8Sint => point_shader!(s, stochastic, "r8i"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::R16Uint => point_shader!(s, stochastic, "r16ui"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::R16Sint => point_shader!(s, stochastic, "r16i"),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::R16Float => point_shader!(s, stochastic, "r16f"),
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::Rg8Unorm => point_shader!(s, stochastic, "rg8"),
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::Rg8Snorm => point_shader!(s, stochastic, "rg8_snorm"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rg8Uint => point_shader!(s, stochastic, "rg8ui"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rg8Sint => point_shader!(s, stochastic, "rg8i"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::R32Uint => point_shader!(s, stochastic, "r32ui"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::R32Sint => point_shader!(s, stochastic, "r32i"),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::R32Float => point_shader!(s, stochastic, "r32f"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rg16Uint => point_shader!(s, stochastic, "rg16ui"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rg16Sint => point_shader!(s, stochastic, "rg16i"),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::Rg16Float => point_shader!(s, stochastic, "rg16f"),
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::Rgba8Unorm
+        | TextureFormat::Rgba8UnormSrgb
+        | TextureFormat::Bgra8Unorm
+        | TextureFormat::Bgra8UnormSrgb => point_shader!(s, stochastic, "rgba8"),
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::Rgba8Snorm => point_shader!(s, stochastic, "rgba8_snorm"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rgba8Uint => point_shader!(s, stochastic, "rgba8ui"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rgba8Sint => point_shader!(s, stochastic, "rgba8i"),
+        #[cfg(feature = "format-packed")]
+        TextureFormat::Rgb10a2Unorm => point_shader!(s, stochastic, "rgb10_a2"),
+        #[cfg(feature = "format-packed")]
+        TextureFormat::Rg11b10Float => point_shader!(s, stochastic, "r11f_g11f_b10f"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rg32Uint => point_shader!(s, stochastic, "rg32ui"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rg32Sint => point_shader!(s, stochastic, "rg32i"),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::Rg32Float => point_shader!(s, stochastic, "rg32f"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rgba16Uint => point_shader!(s, stochastic, "rgba16ui"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rgba16Sint => point_shader!(s, stochastic, "rgba16i"),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::Rgba16Float => point_shader!(s, stochastic, "rgba16f"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rgba32Uint => point_shader!(s, stochastic, "rgba32ui"),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rgba32Sint => point_shader!(s, stochastic, "rgba32i"),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::Rgba32Float => point_shader!(s, stochastic, "rgba32f"),
+        _ => None,
+    }
+}
+
+/// Returns the guidance and downscale shaders of the DPID filter.
+fn dpid_shaders_for_format(
+    device: &Device,
+    format: TextureFormat,
+) -> Option<(ShaderModule, ShaderModule)> {
+    let s = |d| {
+        device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(d),
+            flags: ShaderFlags::empty(),
+        })
+    };
+    match format {
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::Rgba8Unorm | TextureFormat::Bgra8Unorm => Some((
+            s(include_bytes!("shaders/dpid_guidance_rgba8.comp.spv")),
+            s(include_bytes!("shaders/dpid_rgba8.comp.spv")),
+        )),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::Rgba16Float => Some((
+            s(include_bytes!("shaders/dpid_guidance_rgba16f.comp.spv")),
+            s(include_bytes!("shaders/dpid_rgba16f.comp.spv")),
+        )),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::Rgba32Float => Some((
+            s(include_bytes!("shaders/dpid_guidance_rgba32f.comp.spv")),
+            s(include_bytes!("shaders/dpid_rgba32f.comp.spv")),
+        )),
+        _ => None,
+    }
+}
+
+fn equirect_shader_for_format(device: &Device, format: TextureFormat) -> Option<ShaderModule> {
+    let s = |d| {
+        Some(device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(d),
+            flags: ShaderFlags::empty(),
+        }))
+    };
+    match format {
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::R8Unorm => s(include_bytes!("shaders/equirect_r8.comp.spv")),
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::R8Snorm => s(include_bytes!("shaders/equirect_r8_snorm.comp.spv")),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::R16Float => s(include_bytes!("shaders/equirect_r16f.comp.spv")),
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::Rg8Unorm => s(include_bytes!("shaders/equirect_rg8.comp.spv")),
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::Rg8Snorm => s(include_bytes!("shaders/equirect_rg8_snorm.comp.spv")),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::R32Float => s(include_bytes!("shaders/equirect_r32f.comp.spv")),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::Rg16Float => s(include_bytes!("shaders/equirect_rg16f.comp.spv")),
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::Rgba8Unorm => s(include_bytes!("shaders/equirect_rgba8.comp.spv")),
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::Rgba8Snorm => s(include_bytes!("shaders/equirect_rgba8_snorm.comp.spv")),
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::Bgra8Unorm => s(include_bytes!("shaders/equirect_rgba8.comp.spv")),
+        #[cfg(feature = "format-packed")]
+        TextureFormat::Rgb10a2Unorm => s(include_bytes!("shaders/equirect_rgb10_a2.comp.spv")),
+        #[cfg(feature = "format-packed")]
+        TextureFormat::Rg11b10Float => {
+            s(include_bytes!("shaders/equirect_r11f_g11f_b10f.comp.spv"))
         }
-        TextureFormat::Rgba8Snorm => s(include_bytes!("shaders/box_rgba8_snorm.comp.spv")),
-        TextureFormat::Bgra8Unorm => s(include_bytes!("shaders/box_rgba8.comp.spv")),
-        TextureFormat::Rgb10a2Unorm => s(include_bytes!("shaders/box_rgb10_a2.comp.spv")),
-        TextureFormat::Rg11b10Float => s(include_bytes!("shaders/box_r11f_g11f_b10f.comp.spv")),
-        TextureFormat::Rg32Float => s(include_bytes!("shaders/box_rg32f.comp.spv")),
-        TextureFormat::Rgba16Float => s(include_bytes!("shaders/box_rgba16f.comp.spv")),
-        TextureFormat::Rgba32Float => s(include_bytes!("shaders/box_rgba32f.comp.spv")),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::Rg32Float => s(include_bytes!("shaders/equirect_rg32f.comp.spv")),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::Rgba16Float => s(include_bytes!("shaders/equirect_rgba16f.comp.spv")),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::Rgba32Float => s(include_bytes!("shaders/equirect_rgba32f.comp.spv")),
+        _ => None,
+    }
+}
+
+fn max_magnitude_shader_for_format(device: &Device, format: TextureFormat) -> Option<ShaderModule> {
+    let s = |d| {
+        Some(device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(d),
+            flags: ShaderFlags::empty(),
+        }))
+    };
+    match format {
+        #[cfg(feature = "format-float16")]
+        TextureFormat::Rg16Float => s(include_bytes!("shaders/max_magnitude_rg16f.comp.spv")),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::Rg32Float => s(include_bytes!("shaders/max_magnitude_rg32f.comp.spv")),
         _ => None,
     }
 }
 
+fn mode_shader_for_format(device: &Device, format: TextureFormat) -> Option<ShaderModule> {
+    let s = |d| {
+        Some(device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(d),
+            flags: ShaderFlags::empty(),
+        }))
+    };
+    match format {
+        #[cfg(feature = "format-integer")]
+        TextureFormat::R8Uint => s(include_bytes!("shaders/mode_r8ui.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::R8Sint => s(include_bytes!("shaders/mode_r8i.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::R16Uint => s(include_bytes!("shaders/mode_r16ui.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::R16Sint => s(include_bytes!("shaders/mode_r16i.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rg8Uint => s(include_bytes!("shaders/mode_rg8ui.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rg8Sint => s(include_bytes!("shaders/mode_rg8i.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::R32Uint => s(include_bytes!("shaders/mode_r32ui.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::R32Sint => s(include_bytes!("shaders/mode_r32i.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rg16Uint => s(include_bytes!("shaders/mode_rg16ui.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rg16Sint => s(include_bytes!("shaders/mode_rg16i.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rgba8Uint => s(include_bytes!("shaders/mode_rgba8ui.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rgba8Sint => s(include_bytes!("shaders/mode_rgba8i.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rg32Uint => s(include_bytes!("shaders/mode_rg32ui.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rg32Sint => s(include_bytes!("shaders/mode_rg32i.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rgba16Uint => s(include_bytes!("shaders/mode_rgba16ui.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rgba16Sint => s(include_bytes!("shaders/mode_rgba16i.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rgba32Uint => s(include_bytes!("shaders/mode_rgba32ui.comp.spv")),
+        #[cfg(feature = "format-integer")]
+        TextureFormat::Rgba32Sint => s(include_bytes!("shaders/mode_rgba32i.comp.spv")),
+        _ => None,
+    }
+}
+
+fn tail_shader_for_format(device: &Device, format: TextureFormat) -> Option<ShaderModule> {
+    let s = |d| {
+        Some(device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(d),
+            flags: ShaderFlags::empty(),
+        }))
+    };
+    match format {
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::R8Unorm => s(include_bytes!("shaders/tail_r8.comp.spv")),
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::R8Snorm => s(include_bytes!("shaders/tail_r8_snorm.comp.spv")),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::R16Float => s(include_bytes!("shaders/tail_r16f.comp.spv")),
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::Rg8Unorm => s(include_bytes!("shaders/tail_rg8.comp.spv")),
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::Rg8Snorm => s(include_bytes!("shaders/tail_rg8_snorm.comp.spv")),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::R32Float => s(include_bytes!("shaders/tail_r32f.comp.spv")),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::Rg16Float => s(include_bytes!("shaders/tail_rg16f.comp.spv")),
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::Rgba8Unorm => s(include_bytes!("shaders/tail_rgba8.comp.spv")),
+        #[cfg(feature = "format-snorm8")]
+        TextureFormat::Rgba8Snorm => s(include_bytes!("shaders/tail_rgba8_snorm.comp.spv")),
+        #[cfg(feature = "format-unorm8")]
+        TextureFormat::Bgra8Unorm => s(include_bytes!("shaders/tail_rgba8.comp.spv")),
+        #[cfg(feature = "format-packed")]
+        TextureFormat::Rgb10a2Unorm => s(include_bytes!("shaders/tail_rgb10_a2.comp.spv")),
+        #[cfg(feature = "format-packed")]
+        TextureFormat::Rg11b10Float => s(include_bytes!("shaders/tail_r11f_g11f_b10f.comp.spv")),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::Rg32Float => s(include_bytes!("shaders/tail_rg32f.comp.spv")),
+        #[cfg(feature = "format-float16")]
+        TextureFormat::Rgba16Float => s(include_bytes!("shaders/tail_rgba16f.comp.spv")),
+        #[cfg(feature = "format-float32")]
+        TextureFormat::Rgba32Float => s(include_bytes!("shaders/tail_rgba32f.comp.spv")),
+        _ => None,
+    }
+}
+
+/// A layout with one read-only source level followed by `level_count` write-only levels.
+fn multi_level_bind_group_layout_for_format(
+    device: &Device,
+    format: TextureFormat,
+    level_count: u32,
+) -> BindGroupLayout {
+    let entry = |binding, access| BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStage::COMPUTE,
+        ty: BindingType::StorageTexture {
+            access,
+            format,
+            view_dimension: TextureViewDimension::D2,
+        },
+        count: None,
+    };
+    let entries = std::iter::once(entry(0, StorageTextureAccess::ReadOnly))
+        .chain((1..=level_count).map(|binding| entry(binding, StorageTextureAccess::WriteOnly)))
+        .collect::<Vec<_>>();
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &entries,
+    })
+}
+
+/// A layout with one 2D storage texture per `(access, format)` pair, bound in order.
+fn storage_bind_group_layout(
+    device: &Device,
+    textures: &[(StorageTextureAccess, TextureFormat)],
+) -> BindGroupLayout {
+    let entries = (0..)
+        .zip(textures)
+        .map(|(binding, &(access, format))| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStage::COMPUTE,
+            ty: BindingType::StorageTexture {
+                access,
+                format,
+                view_dimension: TextureViewDimension::D2,
+            },
+            count: None,
+        })
+        .collect::<Vec<_>>();
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &entries,
+    })
+}
+
 fn bind_group_layout_for_format(device: &Device, format: TextureFormat) -> BindGroupLayout {
     device.create_bind_group_layout(&BindGroupLayoutDescriptor {
         label: None,
@@ -192,12 +1831,82 @@ fn bind_group_layout_for_format(device: &Device, format: TextureFormat) -> BindG
                 count: None,
             },
             BindGroupLayoutEntry {
-                binding: 1,
+                binding: 1,
+                visibility: ShaderStage::COMPUTE,
+                ty: BindingType::StorageTexture {
+                    access: StorageTextureAccess::WriteOnly,
+                    format,
+                    view_dimension: TextureViewDimension::D2,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+fn gather_bind_group_layout_for_format(device: &Device, format: TextureFormat) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStage::COMPUTE,
+                ty: BindingType::Texture {
+                    multisampled: false,
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStage::COMPUTE,
+                ty: BindingType::StorageTexture {
+                    access: StorageTextureAccess::WriteOnly,
+                    format,
+                    view_dimension: TextureViewDimension::D2,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStage::COMPUTE,
+                ty: BindingType::Sampler {
+                    filtering: false,
+                    comparison: false,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+fn stochastic_bind_group_layout_for_format(
+    device: &Device,
+    format: TextureFormat,
+) -> BindGroupLayout {
+    let storage_entry = |binding, access| BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStage::COMPUTE,
+        ty: BindingType::StorageTexture {
+            access,
+            format,
+            view_dimension: TextureViewDimension::D2,
+        },
+        count: None,
+    };
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[
+            storage_entry(0, StorageTextureAccess::ReadOnly),
+            storage_entry(1, StorageTextureAccess::WriteOnly),
+            BindGroupLayoutEntry {
+                binding: 2,
                 visibility: ShaderStage::COMPUTE,
-                ty: BindingType::StorageTexture {
-                    access: StorageTextureAccess::ReadOnly,
-                    format,
-                    view_dimension: TextureViewDimension::D2,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
                 },
                 count: None,
             },
@@ -213,7 +1922,7 @@ fn compute_pipeline_for_format(
 ) -> ComputePipeline {
     let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
         label: None,
-        bind_group_layouts: &[&bind_group_layout],
+        bind_group_layouts: &[bind_group_layout],
         push_constant_ranges: &[],
     });
     device.create_compute_pipeline(&ComputePipelineDescriptor {
@@ -243,23 +1952,78 @@ mod tests {
             &device,
             &[texture_descriptor.format],
         );
-        Ok(
-            generate_and_copy_to_cpu(&device, &queue, &generator, buffer, texture_descriptor)
-                .await?,
-        )
+        generate_and_copy_to_cpu(&device, &queue, &generator, buffer, texture_descriptor).await
     }
 
     async fn generate_test(texture_descriptor: &wgpu::TextureDescriptor<'_>) -> Result<(), Error> {
         let (_instance, _adapter, device, _queue) = wgpu_setup().await;
         let generator =
             ComputeMipmapGenerator::new_with_format_hints(&device, &[texture_descriptor.format]);
-        let texture = device.create_texture(&texture_descriptor);
+        let texture = device.create_texture(texture_descriptor);
         let mut encoder = device.create_command_encoder(&Default::default());
-        generator.generate(&device, &mut encoder, &texture, &texture_descriptor)
+        generator.generate(&device, &mut encoder, &texture, texture_descriptor)
+    }
+
+    #[test]
+    fn regeneration_overwrites() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 64;
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 7,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage(),
+            label: None,
+        };
+        let (first, second) = (
+            checkerboard_rgba8(size, size, 8),
+            checkerboard_rgba8(size, size, 2),
+        );
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = ComputeMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            for options in &[
+                GenerateOptions::default(),
+                GenerateOptions {
+                    two_levels_per_pass: true,
+                    ..Default::default()
+                },
+                GenerateOptions {
+                    tail_combine: true,
+                    ..Default::default()
+                },
+            ] {
+                assert_regeneration_overwrites(
+                    &device,
+                    &queue,
+                    &generator,
+                    &first,
+                    &second,
+                    &texture_descriptor,
+                    options,
+                )
+                .await;
+            }
+        });
     }
 
     #[test]
     fn sanity_check() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -286,8 +2050,516 @@ mod tests {
         });
     }
 
+    #[test]
+    fn box_filter_rounding() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 16;
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size / 2,
+                depth: 1,
+            },
+            mip_level_count: 5,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage()
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        // Random texels, so some channels of some 2x2 blocks average to a tie
+        let mut rng = TestRng::new(7);
+        let data = (0..size * size / 2 * 4)
+            .map(|_| rng.range(0..256) as u8)
+            .collect::<Vec<_>>();
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = ComputeMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            // Sampled textures are filtered with gathers, the others with storage loads
+            for &usage in &[
+                texture_descriptor.usage,
+                texture_descriptor.usage | wgpu::TextureUsage::SAMPLED,
+            ] {
+                for &rounding in &[BoxRounding::HalfEven, BoxRounding::HalfUp] {
+                    let texture_descriptor = wgpu::TextureDescriptor {
+                        usage,
+                        ..texture_descriptor.clone()
+                    };
+                    let options = GenerateOptions {
+                        rounding,
+                        ..Default::default()
+                    };
+                    let mip_buffers = generate_and_copy_to_cpu_with_options(
+                        &device,
+                        &queue,
+                        &generator,
+                        &data,
+                        &texture_descriptor,
+                        &options,
+                    )
+                    .await
+                    .unwrap();
+                    // An integer box filter, clamping 1 texel high levels
+                    let mut expected = data.clone();
+                    for (level, mip) in mip_buffers.iter().enumerate().skip(1) {
+                        let src_ext = get_mip_extent(&texture_descriptor.size, level as u32 - 1);
+                        let (w, h) = (mip.width(), mip.height());
+                        let texel = |x: u32, y: u32, c: u32| {
+                            let (x, y) = (x.min(src_ext.width - 1), y.min(src_ext.height - 1));
+                            expected[((y * src_ext.width + x) * 4 + c) as usize] as u32
+                        };
+                        let level_expected = (0..w * h * 4)
+                            .map(|i| {
+                                let (x, y, c) = ((i / 4) % w, (i / 4) / w, i % 4);
+                                let sum = texel(2 * x, 2 * y, c)
+                                    + texel(2 * x + 1, 2 * y, c)
+                                    + texel(2 * x, 2 * y + 1, c)
+                                    + texel(2 * x + 1, 2 * y + 1, c);
+                                let rounded = match rounding {
+                                    BoxRounding::HalfEven => sum + 1 + ((sum >> 2) & 1),
+                                    BoxRounding::HalfUp => sum + 2,
+                                };
+                                (rounded / 4) as u8
+                            })
+                            .collect::<Vec<_>>();
+                        assert_eq!(
+                            mip.buffer, level_expected,
+                            "level {}, {:?}, {:?}",
+                            level, usage, rounding
+                        );
+                        expected = level_expected;
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn synchronization_stress() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 512;
+        let mip_level_count = 1 + (size as f32).log2() as u32;
+        let data = checkerboard_r8(size, size, 1);
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count,
+            format: wgpu::TextureFormat::R8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage()
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let mut generator = ComputeMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let mut results = Vec::new();
+            for &synchronization in &[
+                ComputeSynchronization::PassPerLevel,
+                ComputeSynchronization::SinglePass,
+            ] {
+                generator.set_synchronization(synchronization);
+                // Repeat to give a missing barrier a chance to show up as a stale read
+                for _ in 0..8 {
+                    let mipmap_buffers = generate_and_copy_to_cpu(
+                        &device,
+                        &queue,
+                        &generator,
+                        &data,
+                        &texture_descriptor,
+                    )
+                    .await
+                    .unwrap();
+                    // A 1x1 checkerboard averages to mid-gray at every level past 0
+                    for mip in mipmap_buffers.iter().skip(1) {
+                        assert!(mip.buffer.iter().all(|&v| v == 128));
+                    }
+                    results.push(mipmap_buffers);
+                }
+            }
+            for mipmap_buffers in &results[1..] {
+                for (a, b) in mipmap_buffers.iter().zip(results[0].iter()) {
+                    assert_eq!(a.buffer, b.buffer);
+                }
+            }
+        });
+    }
+
+    // Generates a checkerboard with and without `options`, which may only
+    // change the rounding of intermediate levels
+    fn assert_close_to_default(options: GenerateOptions) {
+        init();
+        let size = 512;
+        let mip_level_count = 1 + (size as f32).log2() as u32;
+        let data = checkerboard_r8(size, size, 3);
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count,
+            format: wgpu::TextureFormat::R8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage()
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = ComputeMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let expected =
+                generate_and_copy_to_cpu(&device, &queue, &generator, &data, &texture_descriptor)
+                    .await
+                    .unwrap();
+            let actual = generate_and_copy_to_cpu_with_options(
+                &device,
+                &queue,
+                &generator,
+                &data,
+                &texture_descriptor,
+                &options,
+            )
+            .await
+            .unwrap();
+            assert_eq!(expected.len(), actual.len());
+            for (a, b) in expected.iter().zip(actual.iter()) {
+                for (&a, &b) in a.buffer.iter().zip(b.buffer.iter()) {
+                    assert!((a as i32 - b as i32).abs() <= 1);
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn two_levels_per_pass() {
+        if !adapter_available() {
+            return;
+        }
+        assert_close_to_default(GenerateOptions {
+            two_levels_per_pass: true,
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn tail_combine() {
+        if !adapter_available() {
+            return;
+        }
+        assert_close_to_default(GenerateOptions {
+            tail_combine: true,
+            ..Default::default()
+        });
+        assert_close_to_default(GenerateOptions {
+            two_levels_per_pass: true,
+            tail_combine: true,
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn workgroup_size_for_adapter() {
+        let adapter_info = |vendor, device_type| AdapterInfo {
+            name: String::new(),
+            vendor,
+            device: 0,
+            device_type,
+            backend: wgpu::Backend::Vulkan,
+        };
+        // The default fits within the invocation limit of every adapter
+        assert_eq!(WorkgroupSize::default(), WorkgroupSize::X16);
+        assert_eq!(
+            WorkgroupSize::for_adapter(&adapter_info(0x10de, DeviceType::DiscreteGpu)),
+            WorkgroupSize::X16
+        );
+        assert_eq!(
+            WorkgroupSize::for_adapter(&adapter_info(0x13b5, DeviceType::IntegratedGpu)),
+            WorkgroupSize::X8
+        );
+        assert_eq!(
+            WorkgroupSize::for_adapter(&adapter_info(0, DeviceType::Cpu)),
+            WorkgroupSize::X8
+        );
+    }
+
+    #[test]
+    fn workgroup_sizes() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 512;
+        let mip_level_count = 1 + (size as f32).log2() as u32;
+        let data = checkerboard_r8(size, size, 16);
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count,
+            format: wgpu::TextureFormat::R8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage()
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let mut results = Vec::new();
+            for &workgroup_size in &[WorkgroupSize::X8, WorkgroupSize::X16, WorkgroupSize::X32] {
+                let generator = ComputeMipmapGenerator::new_with_workgroup_size(
+                    &device,
+                    &[texture_descriptor.format],
+                    workgroup_size,
+                );
+                assert_eq!(generator.workgroup_size(), workgroup_size);
+                results.push(
+                    generate_and_copy_to_cpu(
+                        &device,
+                        &queue,
+                        &generator,
+                        &data,
+                        &texture_descriptor,
+                    )
+                    .await
+                    .unwrap(),
+                );
+            }
+            for mipmap_buffers in &results[1..] {
+                for (a, b) in mipmap_buffers.iter().zip(results[0].iter()) {
+                    assert_eq!(a.buffer, b.buffer);
+                }
+            }
+        });
+    }
+
+    fn view_format_test(
+        format: wgpu::TextureFormat,
+        storage_view_format: wgpu::TextureFormat,
+    ) -> Result<(), Error> {
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 256,
+                height: 256,
+                depth: 1,
+            },
+            mip_level_count: 9,
+            format,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage(),
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let generator = ComputeMipmapGenerator::new_with_format_hints(&device, &[format]);
+            let texture = device.create_texture(&texture_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            generator.generate_with_view_formats(
+                &device,
+                &mut encoder,
+                &texture,
+                &texture_descriptor,
+                storage_view_format,
+            )
+        })
+    }
+
+    #[test]
+    fn srgb_through_linear_view() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let res = view_format_test(
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            wgpu::TextureFormat::Rgba8Unorm,
+        );
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn probed_srgb_conversion() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 2,
+                height: 2,
+                depth: 1,
+            },
+            mip_level_count: 2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage()
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        // Two black and two white texels average to linear 0.5, encoded as 188
+        let data = [[0u8, 0, 0, 255], [255; 4], [255; 4], [0, 0, 0, 255]].concat();
+        futures::executor::block_on(async {
+            let (_instance, adapter, device, queue) = wgpu_setup().await;
+            let conversion = SrgbStorageConversion::probe(&adapter, &device, &queue)
+                .await
+                .unwrap();
+            let generator = ComputeMipmapGenerator::new_with_srgb_conversion(
+                &device,
+                &[texture_descriptor.format],
+                WorkgroupSize::default(),
+                conversion,
+                &PipelineCache::new(),
+            );
+            let mip_buffers =
+                generate_and_copy_to_cpu(&device, &queue, &generator, &data, &texture_descriptor)
+                    .await
+                    .unwrap();
+            let texel = &mip_buffers[1].buffer[..4];
+            for &c in &texel[..3] {
+                assert!((c as i32 - 188).abs() <= 2, "{:?} {:?}", conversion, texel);
+            }
+        });
+    }
+
+    #[test]
+    fn level_views_match_generate() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 16;
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 5,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage()
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        let data = checkerboard_rgba8(size, size, 2);
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = ComputeMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let expected =
+                generate_and_copy_to_cpu(&device, &queue, &generator, &data, &texture_descriptor)
+                    .await
+                    .unwrap();
+            let texture = device.create_texture(&texture_descriptor);
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &data,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * size,
+                    rows_per_image: size,
+                },
+                texture_descriptor.size,
+            );
+            let views = (0..texture_descriptor.mip_level_count)
+                .map(|level| crate::util::create_mip_level_view(&texture, level))
+                .collect::<Vec<_>>();
+            let views = views.iter().collect::<Vec<_>>();
+            let mut encoder = device.create_command_encoder(&Default::default());
+            assert_eq!(
+                generator.generate_with_level_views(
+                    &device,
+                    &mut encoder,
+                    &texture_descriptor,
+                    &views[1..],
+                    &Default::default(),
+                ),
+                Err(Error::LevelViewCount(4, 5))
+            );
+            generator
+                .generate_with_level_views(
+                    &device,
+                    &mut encoder,
+                    &texture_descriptor,
+                    &views,
+                    &Default::default(),
+                )
+                .unwrap();
+            let readback =
+                MipReadback::encode(&device, &mut encoder, &texture, &texture_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let mips = readback.read();
+            device.poll(wgpu::Maintain::Wait);
+            let mips = mips.await.unwrap();
+            for (a, b) in mips.iter().zip(expected.iter()) {
+                assert_eq!(a.buffer, b.buffer, "level {}", a.level);
+            }
+        });
+    }
+
+    #[test]
+    fn incompatible_view_format() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let res = view_format_test(
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureFormat::R8Unorm,
+        );
+        assert!(
+            res.err()
+                == Some(Error::IncompatibleViewFormat(
+                    wgpu::TextureFormat::Rgba8Unorm,
+                    wgpu::TextureFormat::R8Unorm
+                ))
+        );
+    }
+
     #[test]
     fn unsupported_npot() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 511;
@@ -315,8 +2587,36 @@ mod tests {
         });
     }
 
+    #[test]
+    fn pipeline_accessors() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let generator = ComputeMipmapGenerator::new_with_format_hints(
+                &device,
+                &[TextureFormat::Rgba8Unorm, TextureFormat::Rgba8Uint],
+            );
+            for format in &[TextureFormat::Rgba8Unorm, TextureFormat::Rgba8Uint] {
+                assert!(generator.compute_pipeline_for(*format).is_some());
+                assert!(generator.bind_group_layout_for(*format).is_some());
+            }
+            assert!(generator
+                .compute_pipeline_for(TextureFormat::R32Float)
+                .is_none());
+            assert!(generator
+                .bind_group_layout_for(TextureFormat::R32Float)
+                .is_none());
+        });
+    }
+
     #[test]
     fn unsupported_usage() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -344,14 +2644,128 @@ mod tests {
         });
     }
 
+    #[test]
+    fn cost_estimate() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 512,
+                height: 512,
+                depth: 1,
+            },
+            mip_level_count: 10,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage(),
+            label: None,
+        };
+        let bytes_written = GenerationCost::per_level(&texture_descriptor, 1..10).bytes_written;
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let mut generator = ComputeMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let estimate = |options| {
+                generator
+                    .estimate_cost_with_options(&texture_descriptor, &options)
+                    .map(|cost| (cost.passes, cost.dispatches))
+            };
+            assert_eq!(estimate(GenerateOptions::default()), Ok((9, 9)));
+            // levels 1..=9 in pairs
+            let two_levels_per_pass = GenerateOptions {
+                two_levels_per_pass: true,
+                ..Default::default()
+            };
+            assert_eq!(estimate(two_levels_per_pass), Ok((5, 5)));
+            // levels 1..=3, then 4..=9 in the tail dispatch
+            let tail_combine = GenerateOptions {
+                tail_combine: true,
+                ..Default::default()
+            };
+            assert_eq!(estimate(tail_combine), Ok((4, 4)));
+            let dpid = GenerateOptions {
+                filter: MipFilter::Dpid,
+                ..Default::default()
+            };
+            assert_eq!(estimate(dpid), Ok((18, 18)));
+            assert_eq!(
+                generator.estimate_cost(&texture_descriptor),
+                Ok(GenerationCost {
+                    passes: 9,
+                    dispatches: 9,
+                    bytes_written,
+                })
+            );
+            generator.set_synchronization(ComputeSynchronization::SinglePass);
+            assert_eq!(
+                generator
+                    .estimate_cost(&texture_descriptor)
+                    .map(|cost| cost.passes),
+                Ok(1)
+            );
+        });
+    }
+
+    #[test]
+    fn invalid_base_mip_level() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 512;
+        let mip_level_count = 1 + (size as f32).log2() as u32;
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count,
+            format: wgpu::TextureFormat::R8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage(),
+            label: None,
+        };
+        let options = GenerateOptions {
+            base_mip_level: mip_level_count,
+            ..Default::default()
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let generator = ComputeMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let texture = device.create_texture(&texture_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let res = generator.generate_with_options(
+                &device,
+                &mut encoder,
+                &texture,
+                &texture_descriptor,
+                &options,
+            );
+            assert!(res.err() == Some(Error::InvalidBaseMipLevel(mip_level_count)));
+        });
+    }
+
     #[test]
     fn unknown_format() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
         let mip_level_count = 1 + (size as f32).log2() as u32;
         // Create a texture
-        let format = wgpu::TextureFormat::Rg16Sint;
+        let format = wgpu::TextureFormat::Bc1RgbaUnorm;
         let texture_extent = wgpu::Extent3d {
             width: size,
             height: size,
@@ -369,7 +2783,85 @@ mod tests {
         futures::executor::block_on(async {
             let res = generate_test(&texture_descriptor).await;
             assert!(res.is_err());
-            assert!(res.err() == Some(Error::UnknownFormat(wgpu::TextureFormat::Rg16Sint)));
+            assert!(res.err() == Some(Error::UnknownFormat(wgpu::TextureFormat::Bc1RgbaUnorm)));
+        });
+    }
+
+    async fn generate_dpid(data: &[u8], size: u32, format: TextureFormat) -> Vec<MipBuffer> {
+        let (_instance, _adapter, device, queue) = wgpu_setup().await;
+        let generator = ComputeMipmapGenerator::new_with_format_hints(&device, &[format]);
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage(),
+            label: None,
+        };
+        generate_and_copy_to_cpu_with_options(
+            &device,
+            &queue,
+            &generator,
+            data,
+            &texture_descriptor,
+            &GenerateOptions {
+                filter: MipFilter::Dpid,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn dpid_constant_color() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 64;
+        let data = [40u8, 80, 120, 255].repeat((size * size) as usize);
+        futures::executor::block_on(async {
+            let mipmap_buffers = generate_dpid(&data, size, TextureFormat::Rgba8Unorm).await;
+            for mip in &mipmap_buffers {
+                for texel in mip.as_slice::<[u8; 4]>() {
+                    for (actual, expected) in texel.iter().zip(&[40u8, 80, 120, 255]) {
+                        assert!((*actual as i32 - *expected as i32).abs() <= 1);
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn dpid_preserves_detail() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        // A single white texel in each 4x4 block of a black texture
+        let size = 64;
+        let data = (0..size * size)
+            .flat_map(|id| {
+                let v = if id % 4 == 0 && (id / size) % 4 == 0 {
+                    255
+                } else {
+                    0
+                };
+                vec![v, v, v, 255]
+            })
+            .collect::<Vec<_>>();
+        futures::executor::block_on(async {
+            let mipmap_buffers = generate_dpid(&data, size, TextureFormat::Rgba8Unorm).await;
+            // A box filter would average each white texel down to 64
+            let level1 = mipmap_buffers[1].as_slice::<[u8; 4]>();
+            assert!(level1[0][0] > 64);
+            assert_eq!(level1[1][0], 0);
         });
     }
 }
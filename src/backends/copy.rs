@@ -1,8 +1,9 @@
 use wgpu::{
-    CommandEncoder, Device, Origin3d, Texture, TextureCopyView, TextureDescriptor, TextureUsage,
+    CommandEncoder, Device, Extent3d, Origin3d, Texture, TextureCopyView, TextureDescriptor,
+    TextureUsage,
 };
 
-use crate::{backends::RenderMipmapGenerator, core::*, util::get_mip_extent};
+use crate::{backends::RenderMipmapGenerator, core::*, trace::span, util::get_mip_extent};
 
 /// Generates mipmaps for textures with sampled usage.
 pub struct CopyMipmapGenerator<'a> {
@@ -25,21 +26,36 @@ impl<'a> CopyMipmapGenerator<'a> {
 }
 
 impl<'a> MipmapGenerator for CopyMipmapGenerator<'a> {
-    fn generate(
+    fn generate_with_options(
         &self,
         device: &Device,
         encoder: &mut CommandEncoder,
         texture: &Texture,
         texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
     ) -> Result<(), Error> {
-        // Create a temporary texture with half the resolution
-        // of the original texture, and one less mip level
+        let _span = span!("generate_mipmaps", {
+            backend = "copy",
+            format = texture_descriptor.format,
+            levels = texture_descriptor.mip_level_count,
+            filter = options.filter,
+        });
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        // Nothing to generate, and a temporary texture with no levels can't be created
+        if mip_levels.start == mip_levels.end {
+            return Ok(());
+        }
+        // Create a temporary texture the size of the first generated
+        // mip level, with one mip level per generated level
         // We'll generate mipmaps into this texture, then
         // copy the results back into the mip levels of the original texture
         let tmp_descriptor = TextureDescriptor {
             label: None,
-            size: get_mip_extent(&texture_descriptor.size, 1),
-            mip_level_count: texture_descriptor.mip_level_count - 1,
+            size: Extent3d {
+                depth: 1,
+                ..get_mip_extent(&texture_descriptor.size, mip_levels.start)
+            },
+            mip_level_count: mip_levels.end - mip_levels.start,
             sample_count: texture_descriptor.sample_count,
             dimension: texture_descriptor.dimension,
             format: texture_descriptor.format,
@@ -49,29 +65,98 @@ impl<'a> MipmapGenerator for CopyMipmapGenerator<'a> {
         self.generator.generate_src_dst(
             device,
             encoder,
-            &texture,
+            texture,
+            &tmp_texture,
+            texture_descriptor,
+            &tmp_descriptor,
+            mip_levels.start,
+            options.array_layer,
+            0,
+            mip_levels.clone(),
+            options.filter,
+            options.rounding,
+            // The temporary texture starts out undefined, and the texels computed from the
+            // dirty rect read texels around it in the level above, so every level is drawn
+            // whole and only the texels computed from the dirty rect are copied back
+            None,
+        )?;
+        copy_written_rects(
+            encoder,
             &tmp_texture,
+            mip_levels.start,
+            texture,
+            options.array_layer,
+            &options.written_rects(texture_descriptor)?,
+        );
+        Ok(())
+    }
+
+    fn estimate_cost_with_options(
+        &self,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<GenerationCost, Error> {
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        if mip_levels.start == mip_levels.end {
+            return Ok(GenerationCost::default());
+        }
+        // The levels are rendered into a temporary texture, then copied back
+        let tmp_descriptor = TextureDescriptor {
+            usage: RenderMipmapGenerator::required_usage(),
+            ..texture_descriptor.clone()
+        };
+        self.generator.validate(
             texture_descriptor,
             &tmp_descriptor,
-            1,
+            options.filter,
+            options.rounding,
         )?;
-        let mip_count = tmp_descriptor.mip_level_count;
-        for i in 0..mip_count {
-            encoder.copy_texture_to_texture(
-                TextureCopyView {
-                    texture: &tmp_texture,
-                    mip_level: i,
-                    origin: Origin3d::default(),
+        let cost = GenerationCost::per_level(texture_descriptor, mip_levels);
+        Ok(GenerationCost {
+            bytes_written: 2 * cost.bytes_written,
+            ..cost
+        })
+    }
+}
+
+/// Copies each `(level, rect)` of `rects` from level `level - tmp_base_level` of
+/// `tmp_texture` to layer `array_layer` of `texture`, e.g. the rects returned by
+/// `GenerateOptions::written_rects`, so the texels of `texture` outside the dirty rect are
+/// left untouched.
+pub(crate) fn copy_written_rects(
+    encoder: &mut CommandEncoder,
+    tmp_texture: &Texture,
+    tmp_base_level: u32,
+    texture: &Texture,
+    array_layer: u32,
+    rects: &[(u32, MipRect)],
+) {
+    for &(mip_level, rect) in rects {
+        encoder.copy_texture_to_texture(
+            TextureCopyView {
+                texture: tmp_texture,
+                mip_level: mip_level - tmp_base_level,
+                origin: Origin3d {
+                    x: rect.x,
+                    y: rect.y,
+                    z: 0,
                 },
-                TextureCopyView {
-                    texture: &texture,
-                    mip_level: i + 1,
-                    origin: Origin3d::default(),
+            },
+            TextureCopyView {
+                texture,
+                mip_level,
+                origin: Origin3d {
+                    x: rect.x,
+                    y: rect.y,
+                    z: array_layer,
                 },
-                get_mip_extent(&tmp_descriptor.size, i),
-            );
-        }
-        Ok(())
+            },
+            Extent3d {
+                width: rect.width,
+                height: rect.height,
+                depth: 1,
+            },
+        );
     }
 }
 
@@ -96,10 +181,7 @@ mod tests {
             &[texture_descriptor.format],
         );
         let fallback = CopyMipmapGenerator::new(&generator);
-        Ok(
-            generate_and_copy_to_cpu(&device, &queue, &fallback, buffer, texture_descriptor)
-                .await?,
-        )
+        generate_and_copy_to_cpu(&device, &queue, &fallback, buffer, texture_descriptor).await
     }
 
     async fn generate_test(texture_descriptor: &wgpu::TextureDescriptor<'_>) -> Result<(), Error> {
@@ -109,13 +191,157 @@ mod tests {
             &[texture_descriptor.format],
         );
         let generator = CopyMipmapGenerator::new(&render);
-        let texture = device.create_texture(&texture_descriptor);
+        let texture = device.create_texture(texture_descriptor);
         let mut encoder = device.create_command_encoder(&Default::default());
-        generator.generate(&device, &mut encoder, &texture, &texture_descriptor)
+        generator.generate(&device, &mut encoder, &texture, texture_descriptor)
+    }
+
+    #[test]
+    fn regeneration_overwrites() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 64;
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 7,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: CopyMipmapGenerator::required_usage(),
+            label: None,
+        };
+        let (first, second) = (
+            checkerboard_rgba8(size, size, 8),
+            checkerboard_rgba8(size, size, 2),
+        );
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let render = crate::backends::RenderMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let generator = CopyMipmapGenerator::new(&render);
+            assert_regeneration_overwrites(
+                &device,
+                &queue,
+                &generator,
+                &first,
+                &second,
+                &texture_descriptor,
+                &GenerateOptions::default(),
+            )
+            .await;
+        });
+    }
+
+    #[test]
+    fn dirty_rect() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 32;
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 6,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: CopyMipmapGenerator::required_usage() | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        let (first, second) = (
+            checkerboard_rgba8(size, size, 8),
+            checkerboard_rgba8(size, size, 2),
+        );
+        let options = GenerateOptions {
+            dirty_rect: Some(MipRect {
+                x: 8,
+                y: 4,
+                width: 8,
+                height: 12,
+            }),
+            ..GenerateOptions::default()
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let render = crate::backends::RenderMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let generator = CopyMipmapGenerator::new(&render);
+            let texture = device.create_texture(&texture_descriptor);
+            let mut levels = Vec::new();
+            for (data, options) in [
+                (&first, GenerateOptions::default()),
+                (&second, GenerateOptions::default()),
+                (&first, options),
+            ] {
+                queue.write_texture(
+                    wgpu::TextureCopyView {
+                        texture: &texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d::ZERO,
+                    },
+                    data,
+                    wgpu::TextureDataLayout {
+                        offset: 0,
+                        bytes_per_row: 4 * size,
+                        rows_per_image: 0,
+                    },
+                    texture_descriptor.size,
+                );
+                let mut encoder = device.create_command_encoder(&Default::default());
+                generator
+                    .generate_with_options(
+                        &device,
+                        &mut encoder,
+                        &texture,
+                        &texture_descriptor,
+                        &options,
+                    )
+                    .unwrap();
+                let readback =
+                    MipReadback::encode(&device, &mut encoder, &texture, &texture_descriptor);
+                queue.submit(std::iter::once(encoder.finish()));
+                let read = readback.read();
+                device.poll(wgpu::Maintain::Wait);
+                levels.push(read.await.unwrap());
+            }
+            // The texels computed from the dirty rect are those of the first level 0, and the
+            // others are left as generated from the second
+            let rects = options.written_rects(&texture_descriptor).unwrap();
+            assert_eq!(rects.len(), 5);
+            for (level, rect) in rects {
+                let level = level as usize;
+                let width = levels[0][level].width();
+                let texels = |i: usize| levels[i][level].as_slice::<[u8; 4]>();
+                for (i, texel) in texels(2).iter().enumerate() {
+                    let (x, y) = (i as u32 % width, i as u32 / width);
+                    let inside = (rect.x..rect.x + rect.width).contains(&x)
+                        && (rect.y..rect.y + rect.height).contains(&y);
+                    let expected = if inside { texels(0) } else { texels(1) };
+                    assert_eq!(*texel, expected[i], "level {} texel {:?}", level, (x, y));
+                }
+            }
+        });
     }
 
     #[test]
     fn sanity_check() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 511;
@@ -144,6 +370,9 @@ mod tests {
 
     #[test]
     fn unsupported_format() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 511;
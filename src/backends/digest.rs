@@ -0,0 +1,640 @@
+use crate::{
+    core::*,
+    trace::span,
+    util::{readback_layout, MipBuffer, MipBufferDimensions},
+};
+use std::{convert::TryInto, ops::Range};
+use wgpu::{
+    util::{make_spirv, BufferInitDescriptor, DeviceExt},
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferCopyView,
+    BufferDescriptor, BufferSize, BufferUsage, CommandEncoder, ComputePassDescriptor,
+    ComputePipeline, ComputePipelineDescriptor, Device, Origin3d, PipelineLayoutDescriptor,
+    ShaderFlags, ShaderModuleDescriptor, ShaderStage, Texture, TextureCopyView, TextureDataLayout,
+    TextureDescriptor, TextureSampleType, TextureUsage,
+};
+
+// Must match the local_size_x value in digest.comp
+const WORKGROUP_SIZE: u32 = 64;
+// The rows of a level are hashed in chunks no larger than the default
+// maxStorageBufferBindingSize of WebGPU
+const MAX_CHUNK_BYTES: u64 = 1 << 27;
+
+const PRIME64_1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME64_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME64_3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME64_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME64_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+const PRIME32_2: u32 = 0x85EB_CA77;
+const PRIME32_3: u32 = 0xC2B2_AE3D;
+const PRIME32_4: u32 = 0x27D4_EB2F;
+const PRIME32_5: u32 = 0x1656_67B1;
+
+/// The digest of the texels of one mip level, including every array layer.
+///
+/// Equal levels always have equal digests, and a changed level almost certainly has a different
+/// one, so caching layers can compare digests to tell whether regenerated levels actually
+/// changed before invalidating the resources that depend on them. Digests are not a standard
+/// xxHash of the level, and are only comparable with digests computed by the same version of
+/// this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MipDigest {
+    pub level: u32,
+    pub digest: u64,
+}
+
+impl MipDigest {
+    /// Computes the digest of a level already read back to the CPU, e.g. with
+    /// [`crate::util::MipReadback`]. It's equal to the digest [`MipDigester`] computes on the
+    /// GPU for the same texels.
+    pub fn of_buffer(mip_buffer: &MipBuffer) -> Self {
+        let dims = &mip_buffer.dimensions;
+        let row_hashes = mip_buffer
+            .buffer
+            .chunks(dims.unpadded_bytes_per_row)
+            .map(hash_row);
+        Self {
+            level: mip_buffer.level,
+            digest: fold_rows(dims, row_hashes),
+        }
+    }
+}
+
+/// Hashes the levels of a texture on the GPU and reads back a [`MipDigest`] per level.
+///
+/// Each level is copied to a buffer, a compute pass hashes each of its rows, and only the
+/// 4 byte hash of every row is read back, then combined into the level's digest on the CPU.
+/// Hashing the copied bytes, rather than texels loaded by a shader, makes the digest exact for
+/// every uncompressed color format.
+///
+/// ```no_run
+/// # use wgpu_mipmap::*;
+/// # async fn example(device: &wgpu::Device, queue: &wgpu::Queue,
+/// #     generator: &RecommendedMipmapGenerator, texture: &wgpu::Texture,
+/// #     texture_descriptor: &wgpu::TextureDescriptor<'_>, previous: &[MipDigest]) -> Result<(), Error> {
+/// let digester = MipDigester::new(device);
+/// let options = GenerateOptions::default();
+/// let mut encoder = device.create_command_encoder(&Default::default());
+/// generator.generate_with_options(device, &mut encoder, texture, texture_descriptor, &options)?;
+/// let levels = options.mip_levels(texture_descriptor)?;
+/// let readback = digester.encode(device, &mut encoder, texture, texture_descriptor, levels)?;
+/// queue.submit(std::iter::once(encoder.finish()));
+/// let digests = readback.read();
+/// device.poll(wgpu::Maintain::Wait);
+/// for (digest, previous) in digests.await?.iter().zip(previous) {
+///     if digest != previous {
+///         println!("level {} changed", digest.level);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MipDigester {
+    layout: BindGroupLayout,
+    pipeline: ComputePipeline,
+}
+
+impl MipDigester {
+    /// Returns the texture usage `MipDigester` requires of the texture it hashes.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::COPY_SRC
+    }
+
+    /// Creates a new `MipDigester`. Once created, it can be used repeatedly to hash the levels
+    /// of any texture with an uncompressed color format.
+    pub fn new(device: &Device) -> Self {
+        let buffer_entry = |binding, ty| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStage::COMPUTE,
+            ty: BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("wgpu-mipmap-digest-bg-layout"),
+            entries: &[
+                buffer_entry(0, BufferBindingType::Uniform),
+                buffer_entry(1, BufferBindingType::Storage { read_only: true }),
+                buffer_entry(2, BufferBindingType::Storage { read_only: false }),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let module = device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(include_bytes!("shaders/digest.comp.spv")),
+            flags: ShaderFlags::empty(),
+        });
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("wgpu-mipmap-digest-pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: "main",
+        });
+        Self { layout, pipeline }
+    }
+
+    /// Encodes commands to hash `levels` of `texture`, e.g. the levels returned by
+    /// `GenerateOptions::mip_levels` after generating them, and returns the buffer their row
+    /// hashes are read back from.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`,
+    ///   which must have `TextureUsage::COPY_SRC` and an uncompressed color format.
+    pub fn encode(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        levels: Range<u32>,
+    ) -> Result<MipDigestReadback, Error> {
+        let _span = span!("digest_mipmaps", {
+            format = texture_descriptor.format,
+            levels = levels.end - levels.start,
+        });
+        if !texture_descriptor.usage.contains(Self::required_usage()) {
+            return Err(Error::UnsupportedUsage(texture_descriptor.usage));
+        }
+        let info = texture_descriptor.format.describe();
+        if info.block_dimensions != (1, 1) || info.sample_type == TextureSampleType::Depth {
+            return Err(Error::UnsupportedFormat(texture_descriptor.format));
+        }
+        if levels.end > texture_descriptor.mip_level_count {
+            return Err(Error::InvalidBaseMipLevel(levels.start));
+        }
+        let (layout, _) = readback_layout(texture_descriptor);
+        // Only the requested levels are copied, each starting at a padded row, so every chunk
+        // of rows starts at an offset aligned for binding
+        let mut texel_bytes = 0;
+        let mut row_count = 0;
+        let levels = levels
+            .map(|level| {
+                let dimensions = layout[level as usize].dimensions;
+                let rows = dimensions.height * dimensions.layers;
+                let digest_level = DigestLevel {
+                    level,
+                    dimensions,
+                    offset: texel_bytes,
+                    rows: row_count..row_count + rows,
+                };
+                texel_bytes += (rows * dimensions.padded_bytes_per_row) as u64;
+                row_count += rows;
+                digest_level
+            })
+            .collect::<Vec<_>>();
+        let hash_bytes = (row_count * 4) as u64;
+        let texels = device.create_buffer(&BufferDescriptor {
+            label: Some("wgpu-mipmap-digest-texels"),
+            // Bindings can't be empty
+            size: texel_bytes.max(4),
+            usage: BufferUsage::COPY_DST | BufferUsage::STORAGE,
+            mapped_at_creation: false,
+        });
+        let hashes = device.create_buffer(&BufferDescriptor {
+            label: Some("wgpu-mipmap-digest-hashes"),
+            size: hash_bytes.max(4),
+            usage: BufferUsage::STORAGE | BufferUsage::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        for level in &levels {
+            let dims = &level.dimensions;
+            encoder.copy_texture_to_buffer(
+                TextureCopyView {
+                    texture,
+                    mip_level: level.level,
+                    origin: Origin3d::ZERO,
+                },
+                BufferCopyView {
+                    buffer: &texels,
+                    layout: TextureDataLayout {
+                        offset: level.offset,
+                        bytes_per_row: dims.padded_bytes_per_row as u32,
+                        rows_per_image: dims.height as u32,
+                    },
+                },
+                wgpu::Extent3d {
+                    width: dims.width as u32,
+                    height: dims.height as u32,
+                    depth: dims.layers as u32,
+                },
+            );
+        }
+        for level in &levels {
+            self.encode_level(device, encoder, &texels, &hashes, level);
+        }
+        // Storage buffers can't be mapped, so the hashes are copied to a readback buffer
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: Some("wgpu-mipmap-readback"),
+            size: hash_bytes.max(4),
+            usage: BufferUsage::COPY_DST | BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_buffer_to_buffer(&hashes, 0, &readback, 0, hash_bytes.max(4));
+        Ok(MipDigestReadback {
+            buffer: readback,
+            levels,
+        })
+    }
+
+    /// Encodes the dispatches hashing the rows of `level`, in chunks that fit a binding.
+    fn encode_level(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texels: &Buffer,
+        hashes: &Buffer,
+        level: &DigestLevel,
+    ) {
+        let dims = &level.dimensions;
+        let stride = dims.padded_bytes_per_row;
+        let rows_per_chunk = (MAX_CHUNK_BYTES as usize / stride).max(1);
+        let rows = level.rows.end - level.rows.start;
+        for first_row in (0..rows).step_by(rows_per_chunk) {
+            let chunk_rows = rows_per_chunk.min(rows - first_row);
+            let params = [
+                chunk_rows as u32,
+                (stride / 4) as u32,
+                dims.unpadded_bytes_per_row as u32,
+                (level.rows.start + first_row) as u32,
+            ];
+            let params = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("wgpu-mipmap-digest-params"),
+                contents: bytemuck::cast_slice(&params),
+                usage: BufferUsage::UNIFORM,
+            });
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &self.layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::Buffer {
+                            buffer: &params,
+                            offset: 0,
+                            size: None,
+                        },
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Buffer {
+                            buffer: texels,
+                            offset: level.offset + (first_row * stride) as u64,
+                            size: BufferSize::new((chunk_rows * stride) as u64),
+                        },
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::Buffer {
+                            buffer: hashes,
+                            offset: 0,
+                            size: None,
+                        },
+                    },
+                ],
+            });
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch((chunk_rows as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+    }
+}
+
+/// A level hashed by [`MipDigester::encode`].
+#[derive(Debug)]
+struct DigestLevel {
+    level: u32,
+    dimensions: MipBufferDimensions,
+    // Where the level is copied in the texel buffer, and its rows' hashes in the hash buffer
+    offset: u64,
+    rows: Range<usize>,
+}
+
+/// A GPU buffer holding the row hashes of the levels of a texture, recorded by
+/// [`MipDigester::encode`] and read back to the CPU with [`MipDigestReadback::read`].
+#[derive(Debug)]
+pub struct MipDigestReadback {
+    buffer: Buffer,
+    levels: Vec<DigestLevel>,
+}
+
+impl MipDigestReadback {
+    /// Maps the buffer and returns a future resolving to the digest of each level once the
+    /// commands recorded by [`MipDigester::encode`] are complete. Like
+    /// [`crate::util::MipReadback::read`], the future never polls the device.
+    pub fn read(&self) -> impl std::future::Future<Output = Result<Vec<MipDigest>, Error>> + '_ {
+        let slice = self.buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        async move {
+            mapping.await.map_err(|_| Error::ReadbackFailed)?;
+            let digests = {
+                let mapped = slice.get_mapped_range();
+                let row_hashes: &[u32] = bytemuck::cast_slice(&mapped);
+                self.levels
+                    .iter()
+                    .map(|level| MipDigest {
+                        level: level.level,
+                        digest: fold_rows(
+                            &level.dimensions,
+                            row_hashes[level.rows.clone()].iter().copied(),
+                        ),
+                    })
+                    .collect()
+            };
+            self.buffer.unmap();
+            Ok(digests)
+        }
+    }
+}
+
+/// Hashes any bytes with XXH64 and `seed`, for keys that must stay stable across runs, unlike
+/// `std::collections::hash_map::DefaultHasher`. Every byte goes through the 64 bit state, so
+/// unlike the digests, whose rows are reduced to 32 bits first, two inputs collide with a
+/// probability of about 2^-64.
+pub(crate) fn hash_bytes(bytes: &[u8], seed: u64) -> u64 {
+    fn round(acc: u64, input: u64) -> u64 {
+        acc.wrapping_add(input.wrapping_mul(PRIME64_2))
+            .rotate_left(31)
+            .wrapping_mul(PRIME64_1)
+    }
+    fn merge_round(acc: u64, value: u64) -> u64 {
+        (acc ^ round(0, value))
+            .wrapping_mul(PRIME64_1)
+            .wrapping_add(PRIME64_4)
+    }
+    let read_u64 = |bytes: &[u8]| u64::from_le_bytes(bytes[..8].try_into().unwrap());
+    let stripes = bytes.chunks_exact(32);
+    let tail = stripes.remainder();
+    let mut h = if bytes.len() >= 32 {
+        let mut v = [
+            seed.wrapping_add(PRIME64_1).wrapping_add(PRIME64_2),
+            seed.wrapping_add(PRIME64_2),
+            seed,
+            seed.wrapping_sub(PRIME64_1),
+        ];
+        for stripe in stripes {
+            for (i, lane) in v.iter_mut().enumerate() {
+                *lane = round(*lane, read_u64(&stripe[i * 8..]));
+            }
+        }
+        let h = v[0]
+            .rotate_left(1)
+            .wrapping_add(v[1].rotate_left(7))
+            .wrapping_add(v[2].rotate_left(12))
+            .wrapping_add(v[3].rotate_left(18));
+        v.iter().fold(h, |h, &lane| merge_round(h, lane))
+    } else {
+        seed.wrapping_add(PRIME64_5)
+    };
+    h = h.wrapping_add(bytes.len() as u64);
+    let words = tail.chunks_exact(8);
+    let mut tail = words.remainder();
+    for word in words {
+        h = (h ^ round(0, read_u64(word)))
+            .rotate_left(27)
+            .wrapping_mul(PRIME64_1)
+            .wrapping_add(PRIME64_4);
+    }
+    if tail.len() >= 4 {
+        let word = u32::from_le_bytes(tail[..4].try_into().unwrap()) as u64;
+        h = (h ^ word.wrapping_mul(PRIME64_1))
+            .rotate_left(23)
+            .wrapping_mul(PRIME64_2)
+            .wrapping_add(PRIME64_3);
+        tail = &tail[4..];
+    }
+    for &byte in tail {
+        h = (h ^ (byte as u64).wrapping_mul(PRIME64_5))
+            .rotate_left(11)
+            .wrapping_mul(PRIME64_1);
+    }
+    h ^= h >> 33;
+    h = h.wrapping_mul(PRIME64_2);
+    h ^= h >> 29;
+    h = h.wrapping_mul(PRIME64_3);
+    h ^ (h >> 32)
+}
+
+/// Hashes a row of bytes like digest.comp: the small input path of XXH32, with the trailing
+/// bytes of the last word zero filled.
+fn hash_row(row: &[u8]) -> u32 {
+    let mut h = PRIME32_5.wrapping_add(row.len() as u32);
+    for word in row.chunks(4) {
+        let mut bytes = [0u8; 4];
+        bytes[..word.len()].copy_from_slice(word);
+        let w = u32::from_le_bytes(bytes);
+        h = h
+            .wrapping_add(w.wrapping_mul(PRIME32_3))
+            .rotate_left(17)
+            .wrapping_mul(PRIME32_4);
+    }
+    h ^= h >> 15;
+    h = h.wrapping_mul(PRIME32_2);
+    h ^= h >> 13;
+    h = h.wrapping_mul(PRIME32_3);
+    h ^ (h >> 16)
+}
+
+/// Combines the dimensions of a level and the hashes of its rows, in order, into its digest,
+/// mixing each 4 byte value in like the tail of XXH64.
+fn fold_rows(dims: &MipBufferDimensions, row_hashes: impl Iterator<Item = u32>) -> u64 {
+    let header = [
+        dims.width as u32,
+        dims.height as u32,
+        dims.layers as u32,
+        dims.bytes_per_channel as u32,
+    ];
+    let mut h = PRIME64_5;
+    for value in header.iter().copied().chain(row_hashes) {
+        h ^= (value as u64).wrapping_mul(PRIME64_1);
+        h = h
+            .rotate_left(23)
+            .wrapping_mul(PRIME64_2)
+            .wrapping_add(PRIME64_3);
+    }
+    h ^= h >> 33;
+    h = h.wrapping_mul(PRIME64_2);
+    h ^= h >> 29;
+    h = h.wrapping_mul(PRIME64_3);
+    h ^ (h >> 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::ComputeMipmapGenerator;
+    use crate::util::*;
+
+    #[test]
+    fn hash_bytes_matches_xxh64() {
+        assert_eq!(hash_bytes(b"", 0), 0xEF46_DB37_51D8_E999);
+        assert_eq!(hash_bytes(b"a", 0), 0xD24E_C4F1_A98C_6E5B);
+        assert_eq!(hash_bytes(b"abc", 0), 0x44BC_2CF5_AD77_0999);
+        // Longer than a 32 byte stripe
+        assert_eq!(
+            hash_bytes(b"Nobody inspects the spammish repetition", 0),
+            0xFBCE_A83C_8A37_8BF1
+        );
+        let bytes: Vec<u8> = (0..20).flat_map(|_| 0..=255u8).collect();
+        assert_eq!(hash_bytes(&bytes, 0), 0x53FB_2468_13D6_DE9F);
+        assert_eq!(hash_bytes(b"abc", 1), 0xBEA9_CA81_9932_8908);
+    }
+
+    #[test]
+    fn row_hash_matches_xxh32() {
+        // Inputs shorter than 16 bytes take the small input path of XXH32, with seed 0
+        assert_eq!(hash_row(b""), 0x02CC_5D05);
+        assert_eq!(hash_row(b"abcd"), 0xA3643705);
+    }
+
+    #[test]
+    fn digests_depend_on_every_texel_and_row_order() {
+        let dimensions = MipBufferDimensions::new(3, 2, 1);
+        let digest = |buffer: Vec<u8>| {
+            MipDigest::of_buffer(&MipBuffer {
+                buffer,
+                dimensions,
+                level: 1,
+                format: wgpu::TextureFormat::R8Unorm,
+            })
+        };
+        let a = digest(vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(a, digest(vec![1, 2, 3, 4, 5, 6]));
+        assert_eq!(a.level, 1);
+        assert_ne!(a, digest(vec![1, 2, 3, 4, 5, 7]));
+        assert_ne!(a, digest(vec![4, 5, 6, 1, 2, 3]));
+        // The same bytes in a level of another size
+        let wide = MipDigest::of_buffer(&MipBuffer {
+            buffer: vec![1, 2, 3, 4, 5, 6],
+            dimensions: MipBufferDimensions::new(6, 1, 1),
+            level: 1,
+            format: wgpu::TextureFormat::R8Unorm,
+        });
+        assert_ne!(a, wide);
+    }
+
+    #[test]
+    fn gpu_digests_match_cpu() {
+        if !adapter_available() {
+            return;
+        }
+        // Rows of 3 bytes exercise the masking of the last word
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 16,
+                height: 8,
+                depth: 1,
+            },
+            mip_level_count: 5,
+            format: wgpu::TextureFormat::R8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage()
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        let data = checkerboard_r8(16, 8, 1);
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = ComputeMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let digester = MipDigester::new(&device);
+            let texture = device.create_texture(&texture_descriptor);
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &data,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 16,
+                    rows_per_image: 0,
+                },
+                texture_descriptor.size,
+            );
+            let mut encoder = device.create_command_encoder(&Default::default());
+            generator
+                .generate(&device, &mut encoder, &texture, &texture_descriptor)
+                .unwrap();
+            let digests = digester
+                .encode(&device, &mut encoder, &texture, &texture_descriptor, 0..5)
+                .unwrap();
+            let levels = MipReadback::encode(&device, &mut encoder, &texture, &texture_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let (digests, levels) = (digests.read(), levels.read());
+            device.poll(wgpu::Maintain::Wait);
+            let expected = levels
+                .await
+                .unwrap()
+                .iter()
+                .map(MipDigest::of_buffer)
+                .collect::<Vec<_>>();
+            assert_eq!(digests.await.unwrap(), expected);
+        });
+    }
+
+    #[test]
+    fn rejects_invalid_textures() {
+        if !adapter_available() {
+            return;
+        }
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 1,
+            },
+            mip_level_count: 3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let digester = MipDigester::new(&device);
+            let texture = device.create_texture(&texture_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let mut encode = |descriptor: &wgpu::TextureDescriptor, levels| {
+                digester
+                    .encode(&device, &mut encoder, &texture, descriptor, levels)
+                    .map(|_| ())
+            };
+            assert_eq!(
+                encode(&texture_descriptor, 2..4),
+                Err(Error::InvalidBaseMipLevel(2))
+            );
+            let sampled = wgpu::TextureDescriptor {
+                usage: wgpu::TextureUsage::SAMPLED,
+                ..texture_descriptor.clone()
+            };
+            assert_eq!(
+                encode(&sampled, 0..3),
+                Err(Error::UnsupportedUsage(wgpu::TextureUsage::SAMPLED))
+            );
+            let compressed = wgpu::TextureDescriptor {
+                format: wgpu::TextureFormat::Bc1RgbaUnorm,
+                ..texture_descriptor.clone()
+            };
+            assert_eq!(
+                encode(&compressed, 0..3),
+                Err(Error::UnsupportedFormat(wgpu::TextureFormat::Bc1RgbaUnorm))
+            );
+        });
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    backends::prefilter::{
+        cube_view, validate_environment_textures, EnvironmentPipeline, PREFILTERED_ENV_MAP_FORMAT,
+    },
+    core::*,
+};
+use wgpu::{
+    CommandEncoder, Device, Extent3d, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsage,
+};
+
+/// The format of every irradiance map.
+pub const IRRADIANCE_MAP_FORMAT: TextureFormat = PREFILTERED_ENV_MAP_FORMAT;
+
+/// The face size of the irradiance maps returned by
+/// [`IrradianceMapGenerator::destination_descriptor`]. Irradiance varies slowly with direction,
+/// so a small map is enough.
+pub const IRRADIANCE_MAP_SIZE: u32 = 32;
+
+/// Generates diffuse irradiance cubemaps for image based lighting: each texel holds the
+/// cosine-weighted average of the source cubemap over the hemisphere around its direction,
+/// divided by pi so a constant environment stays constant.
+#[derive(Debug)]
+pub struct IrradianceMapGenerator {
+    pipeline: EnvironmentPipeline,
+}
+
+impl IrradianceMapGenerator {
+    /// Returns the texture usage `IrradianceMapGenerator` requires of the destination texture.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::STORAGE
+    }
+
+    /// Creates a new `IrradianceMapGenerator`. Once created, it can be used repeatedly to
+    /// convolve any floating-point cubemap.
+    pub fn new(device: &Device) -> Self {
+        Self {
+            pipeline: EnvironmentPipeline::new(
+                device,
+                "irradiance",
+                include_bytes!("shaders/irradiance.comp.spv"),
+            ),
+        }
+    }
+
+    /// Returns a descriptor for a single level [`IRRADIANCE_MAP_SIZE`] cubemap with
+    /// [`IRRADIANCE_MAP_FORMAT`], and usage suitable for generation and sampling.
+    pub fn destination_descriptor() -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-irradiance-map"),
+            size: Extent3d {
+                width: IRRADIANCE_MAP_SIZE,
+                height: IRRADIANCE_MAP_SIZE,
+                depth: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: IRRADIANCE_MAP_FORMAT,
+            usage: Self::required_usage() | TextureUsage::SAMPLED | TextureUsage::COPY_SRC,
+        }
+    }
+
+    /// Creates a texture described by [`IrradianceMapGenerator::destination_descriptor`].
+    pub fn create_destination_texture(device: &Device) -> (Texture, TextureDescriptor<'static>) {
+        let descriptor = Self::destination_descriptor();
+        (device.create_texture(&descriptor), descriptor)
+    }
+
+    /// Encodes commands to write every level of `dst_texture` from `src_texture`.
+    ///
+    /// Expectations:
+    /// - `src_texture` is a cubemap (6 square layers) with `TextureUsage::SAMPLED` and a
+    ///   floating-point format. Its mipmaps should already be generated, since the convolution
+    ///   reads the source level closest to the size of level 0 of `dst_texture`.
+    /// - `dst_texture` is a cubemap with format [`IRRADIANCE_MAP_FORMAT`] and
+    ///   `TextureUsage::STORAGE`, of any size.
+    pub fn generate_from(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_texture: &Texture,
+        src_texture_descriptor: &TextureDescriptor,
+        dst_texture: &Texture,
+        dst_texture_descriptor: &TextureDescriptor,
+    ) -> Result<(), Error> {
+        validate_environment_textures(
+            src_texture_descriptor,
+            dst_texture_descriptor,
+            Self::required_usage(),
+        )?;
+        let src_view = cube_view(src_texture);
+        let lod = source_lod(
+            src_texture_descriptor.size.width,
+            dst_texture_descriptor.size.width,
+        );
+        for mip in 0..dst_texture_descriptor.mip_level_count {
+            self.pipeline.dispatch(
+                device,
+                encoder,
+                &src_view,
+                dst_texture,
+                dst_texture_descriptor,
+                mip,
+                &[lod.to_bits(), 0, 0, 0],
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Returns the level of a source cubemap of face size `src_size` closest to `dst_size`.
+fn source_lod(src_size: u32, dst_size: u32) -> f32 {
+    (src_size as f32 / dst_size as f32).log2().max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn cubemap_descriptor(size: u32) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            size: Extent3d {
+                width: size,
+                height: size,
+                depth: 6,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format: TextureFormat::Rgba16Float,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: TextureUsage::SAMPLED,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn source_lod_matches_destination_size() {
+        assert_eq!(source_lod(512, 32), 4.0);
+        assert_eq!(source_lod(32, 32), 0.0);
+        assert_eq!(source_lod(16, 32), 0.0);
+    }
+
+    #[test]
+    fn sanity_check() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let src_descriptor = cubemap_descriptor(128);
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let generator = IrradianceMapGenerator::new(&device);
+            let src = device.create_texture(&src_descriptor);
+            let (dst, dst_descriptor) = IrradianceMapGenerator::create_destination_texture(&device);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let res = generator.generate_from(
+                &device,
+                &mut encoder,
+                &src,
+                &src_descriptor,
+                &dst,
+                &dst_descriptor,
+            );
+            assert!(res.is_ok());
+        });
+    }
+}
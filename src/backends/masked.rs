@@ -0,0 +1,528 @@
+use crate::{
+    core::*,
+    trace::event,
+    util::{create_mip_level_view, get_mip_extent},
+};
+use std::collections::HashMap;
+use wgpu::{
+    util::make_spirv, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, CommandEncoder,
+    ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
+    PipelineLayoutDescriptor, ShaderFlags, ShaderModule, ShaderModuleDescriptor, ShaderStage,
+    StorageTextureAccess, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsage, TextureView, TextureViewDimension,
+};
+
+/// The format of every coverage mask: 0 marks an invalid texel, 1 a valid one, and values in
+/// between a partially covered texel.
+pub const MASK_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+// Must match the local_size_x and local_size_y values in masked.comp
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Generates mipmaps that ignore invalid texels, such as the unfilled texels between the
+/// charts of a lightmap or the borders of virtual texture pages.
+///
+/// Each texel is the average of the 2x2 block it covers, weighted by the coverage of each
+/// texel in a [`MASK_FORMAT`] mask texture. The mask's own mip levels are written with the
+/// average coverage of each block, so a texel is valid as long as any texel it covers in the
+/// base level is, and blocks of invalid texels stay invalid.
+///
+/// Invalid texels of the generated levels are written as zero, so charts still bleed black
+/// into their surroundings under bilinear or trilinear sampling. Enabling dilation with
+/// [`MaskedMipmapGenerator::set_dilation`] fills them instead (push-pull): once every level
+/// is generated, each level is rewritten from the coarsest up, with invalid texels taking the
+/// upsampled value of the filled level below them.
+#[derive(Debug)]
+pub struct MaskedMipmapGenerator {
+    layout_cache: HashMap<TextureFormat, BindGroupLayout>,
+    pipeline_cache: HashMap<TextureFormat, ComputePipeline>,
+    pull_layout_cache: HashMap<TextureFormat, BindGroupLayout>,
+    pull_pipeline_cache: HashMap<TextureFormat, ComputePipeline>,
+    dilation: bool,
+}
+
+impl MaskedMipmapGenerator {
+    /// Returns the texture usage `MaskedMipmapGenerator` requires of both the texture and its
+    /// mask.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::STORAGE
+    }
+
+    /// Creates a new `MaskedMipmapGenerator`. Once created, it can be used repeatedly to
+    /// generate mipmaps for any texture with format specified in `format_hints`.
+    pub fn new_with_format_hints(device: &Device, format_hints: &[TextureFormat]) -> Self {
+        let mut layout_cache = HashMap::new();
+        let mut pipeline_cache = HashMap::new();
+        let mut pull_layout_cache = HashMap::new();
+        let mut pull_pipeline_cache = HashMap::new();
+        for &format in format_hints {
+            if let (Some(module), Some(pull_module)) = (
+                shader_for_format(device, format, MaskedPass::Push),
+                shader_for_format(device, format, MaskedPass::Pull),
+            ) {
+                let layout = bind_group_layout(
+                    device,
+                    &[
+                        (StorageTextureAccess::ReadOnly, format),
+                        (StorageTextureAccess::ReadOnly, MASK_FORMAT),
+                        (StorageTextureAccess::WriteOnly, format),
+                        (StorageTextureAccess::WriteOnly, MASK_FORMAT),
+                    ],
+                );
+                let pull_layout = bind_group_layout(
+                    device,
+                    &[
+                        (StorageTextureAccess::ReadOnly, format),
+                        (StorageTextureAccess::ReadOnly, MASK_FORMAT),
+                        (StorageTextureAccess::ReadOnly, format),
+                        (StorageTextureAccess::WriteOnly, format),
+                    ],
+                );
+                let pipeline = create_pipeline(device, &module, &layout, "push", format);
+                let pull_pipeline =
+                    create_pipeline(device, &pull_module, &pull_layout, "pull", format);
+                layout_cache.insert(format, layout);
+                pipeline_cache.insert(format, pipeline);
+                pull_layout_cache.insert(format, pull_layout);
+                pull_pipeline_cache.insert(format, pull_pipeline);
+            } else {
+                event!(
+                    WARN,
+                    { format = format, backend = "masked" },
+                    "unsupported format"
+                );
+            }
+        }
+        Self {
+            layout_cache,
+            pipeline_cache,
+            pull_layout_cache,
+            pull_pipeline_cache,
+            dilation: false,
+        }
+    }
+
+    /// Returns true if invalid texels of the generated levels are filled by dilation.
+    pub fn dilation(&self) -> bool {
+        self.dilation
+    }
+
+    /// Sets whether invalid texels of the generated levels are filled by dilation. This
+    /// doubles the number of dispatches. The base level and the mask are never modified.
+    pub fn set_dilation(&mut self, dilation: bool) {
+        self.dilation = dilation;
+    }
+
+    /// Returns a descriptor for a mask matching `texture_descriptor`: same size and mip level
+    /// count, [`MASK_FORMAT`], and usage suitable for uploading, generation and sampling.
+    pub fn mask_descriptor(texture_descriptor: &TextureDescriptor) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-coverage-mask"),
+            size: texture_descriptor.size,
+            mip_level_count: texture_descriptor.mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: MASK_FORMAT,
+            usage: Self::required_usage()
+                | TextureUsage::SAMPLED
+                | TextureUsage::COPY_SRC
+                | TextureUsage::COPY_DST,
+        }
+    }
+
+    /// Creates a texture described by [`MaskedMipmapGenerator::mask_descriptor`].
+    pub fn create_mask_texture(
+        device: &Device,
+        texture_descriptor: &TextureDescriptor,
+    ) -> (Texture, TextureDescriptor<'static>) {
+        let descriptor = Self::mask_descriptor(texture_descriptor);
+        (device.create_texture(&descriptor), descriptor)
+    }
+
+    /// Encodes commands to generate the levels of `texture` and `mask_texture` selected by
+    /// `options`, from the base level of each.
+    ///
+    /// Expectations:
+    /// - `texture` has a power of two size, `TextureUsage::STORAGE` and a floating-point
+    ///   format specified in `format_hints`.
+    /// - `mask_texture` must match the descriptor returned by
+    ///   [`MaskedMipmapGenerator::mask_descriptor`], up to usage flags, and its base level
+    ///   should already hold the coverage of each texel.
+    /// - `options.filter` is ignored.
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate_with_mask(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        mask_texture: &Texture,
+        mask_texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<(), Error> {
+        for descriptor in &[texture_descriptor, mask_texture_descriptor] {
+            if descriptor.dimension != TextureDimension::D2 {
+                return Err(Error::UnsupportedDimension(descriptor.dimension));
+            }
+            if !descriptor.usage.contains(Self::required_usage()) {
+                return Err(Error::UnsupportedUsage(descriptor.usage));
+            }
+        }
+        if !texture_descriptor.size.width.is_power_of_two()
+            || !texture_descriptor.size.height.is_power_of_two()
+        {
+            return Err(Error::NpotTexture);
+        }
+        if mask_texture_descriptor.format != MASK_FORMAT {
+            return Err(Error::UnsupportedFormat(mask_texture_descriptor.format));
+        }
+        if texture_descriptor.size != mask_texture_descriptor.size
+            || texture_descriptor.mip_level_count != mask_texture_descriptor.mip_level_count
+        {
+            return Err(Error::ExtentMismatch);
+        }
+        let format = texture_descriptor.format;
+        let layout = self
+            .layout_cache
+            .get(&format)
+            .ok_or(Error::UnknownFormat(format))?;
+        let pipeline = self
+            .pipeline_cache
+            .get(&format)
+            .ok_or(Error::UnknownFormat(format))?;
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        let mip_count = texture_descriptor.mip_level_count;
+        let views = (0..mip_count)
+            .map(|mip| create_mip_level_view(texture, mip))
+            .collect::<Vec<_>>();
+        let mask_views = (0..mip_count)
+            .map(|mip| create_mip_level_view(mask_texture, mip))
+            .collect::<Vec<_>>();
+        for mip in mip_levels.start as usize..mip_levels.end as usize {
+            dispatch(
+                device,
+                encoder,
+                layout,
+                pipeline,
+                &[
+                    &views[mip - 1],
+                    &mask_views[mip - 1],
+                    &views[mip],
+                    &mask_views[mip],
+                ],
+                get_mip_extent(&texture_descriptor.size, mip as u32),
+            );
+        }
+        if self.dilation {
+            let layout = &self.pull_layout_cache[&format];
+            let pipeline = &self.pull_pipeline_cache[&format];
+            // From the coarsest level up, so the level below each level is already filled.
+            // The last level has no level below it to pull from.
+            for mip in (mip_levels.start as usize..mip_levels.end as usize - 1).rev() {
+                dispatch(
+                    device,
+                    encoder,
+                    layout,
+                    pipeline,
+                    &[
+                        &views[mip - 1],
+                        &mask_views[mip - 1],
+                        &views[mip + 1],
+                        &views[mip],
+                    ],
+                    get_mip_extent(&texture_descriptor.size, mip as u32),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn dispatch(
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    layout: &BindGroupLayout,
+    pipeline: &ComputePipeline,
+    views: &[&TextureView],
+    extent: wgpu::Extent3d,
+) {
+    let entries = (0..)
+        .zip(views)
+        .map(|(binding, view)| BindGroupEntry {
+            binding,
+            resource: BindingResource::TextureView(view),
+        })
+        .collect::<Vec<_>>();
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &entries,
+    });
+    let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, &bind_group, &[]);
+    pass.dispatch(
+        extent.width.div_ceil(WORKGROUP_SIZE),
+        extent.height.div_ceil(WORKGROUP_SIZE),
+        1,
+    );
+}
+
+/// The passes of masked generation and push-pull hole filling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MaskedPass {
+    /// Writes a level and its mask from the level before.
+    Push,
+    /// Rewrites a level, filling its invalid texels from the level after it.
+    Pull,
+    /// Blends a level with the level after it by coverage.
+    Fill,
+}
+
+macro_rules! masked_shader {
+    ($s:expr, $pass:expr, $name:literal) => {
+        match $pass {
+            MaskedPass::Push => $s(include_bytes!(concat!(
+                "shaders/masked_",
+                $name,
+                ".comp.spv"
+            ))),
+            MaskedPass::Pull => $s(include_bytes!(concat!(
+                "shaders/masked_pull_",
+                $name,
+                ".comp.spv"
+            ))),
+            MaskedPass::Fill => $s(include_bytes!(concat!(
+                "shaders/masked_fill_",
+                $name,
+                ".comp.spv"
+            ))),
+        }
+    };
+}
+
+pub(crate) fn shader_for_format(
+    device: &Device,
+    format: TextureFormat,
+    pass: MaskedPass,
+) -> Option<ShaderModule> {
+    let s = |d| {
+        Some(device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(d),
+            flags: ShaderFlags::empty(),
+        }))
+    };
+    match format {
+        TextureFormat::R8Unorm => masked_shader!(s, pass, "r8"),
+        TextureFormat::R8Snorm => masked_shader!(s, pass, "r8_snorm"),
+        TextureFormat::R16Float => masked_shader!(s, pass, "r16f"),
+        TextureFormat::Rg8Unorm => masked_shader!(s, pass, "rg8"),
+        TextureFormat::Rg8Snorm => masked_shader!(s, pass, "rg8_snorm"),
+        TextureFormat::R32Float => masked_shader!(s, pass, "r32f"),
+        TextureFormat::Rg16Float => masked_shader!(s, pass, "rg16f"),
+        TextureFormat::Rgba8Unorm => masked_shader!(s, pass, "rgba8"),
+        TextureFormat::Rgba8Snorm => masked_shader!(s, pass, "rgba8_snorm"),
+        TextureFormat::Bgra8Unorm => masked_shader!(s, pass, "rgba8"),
+        TextureFormat::Rgb10a2Unorm => masked_shader!(s, pass, "rgb10_a2"),
+        TextureFormat::Rg11b10Float => masked_shader!(s, pass, "r11f_g11f_b10f"),
+        TextureFormat::Rg32Float => masked_shader!(s, pass, "rg32f"),
+        TextureFormat::Rgba16Float => masked_shader!(s, pass, "rgba16f"),
+        TextureFormat::Rgba32Float => masked_shader!(s, pass, "rgba32f"),
+        _ => None,
+    }
+}
+
+pub(crate) fn bind_group_layout(
+    device: &Device,
+    textures: &[(StorageTextureAccess, TextureFormat)],
+) -> BindGroupLayout {
+    let entries = (0..)
+        .zip(textures)
+        .map(|(binding, &(access, format))| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStage::COMPUTE,
+            ty: BindingType::StorageTexture {
+                access,
+                format,
+                view_dimension: TextureViewDimension::D2,
+            },
+            count: None,
+        })
+        .collect::<Vec<_>>();
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &entries,
+    })
+}
+
+pub(crate) fn create_pipeline(
+    device: &Device,
+    module: &ShaderModule,
+    bind_group_layout: &BindGroupLayout,
+    name: &str,
+    format: TextureFormat,
+) -> ComputePipeline {
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some(&format!(
+            "wgpu-mipmap-masked-{}-pipeline-{:?}",
+            name, format
+        )),
+        layout: Some(&pipeline_layout),
+        module,
+        entry_point: "main",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn descriptor(size: u32) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format: TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: TextureUsage::STORAGE | TextureUsage::COPY_SRC | TextureUsage::COPY_DST,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn mask_descriptor() {
+        let texture_descriptor = descriptor(64);
+        let mask = MaskedMipmapGenerator::mask_descriptor(&texture_descriptor);
+        assert_eq!(mask.size, texture_descriptor.size);
+        assert_eq!(mask.mip_level_count, texture_descriptor.mip_level_count);
+        assert_eq!(mask.format, MASK_FORMAT);
+        assert!(mask.usage.contains(MaskedMipmapGenerator::required_usage()));
+    }
+
+    // Generates a 16x16 texture whose left half is valid and red, and whose right half is
+    // invalid and green, and returns its levels and the levels of its mask
+    async fn generate_half_masked(dilation: bool) -> (Vec<MipBuffer>, Vec<MipBuffer>) {
+        let size = 16;
+        let texture_descriptor = descriptor(size);
+        let data = (0..size * size)
+            .flat_map(|id| {
+                if id % size < size / 2 {
+                    vec![255u8, 0, 0, 255]
+                } else {
+                    vec![0u8, 255, 0, 255]
+                }
+            })
+            .collect::<Vec<_>>();
+        let mask_data = (0..size * size)
+            .map(|id| if id % size < size / 2 { 255u8 } else { 0 })
+            .collect::<Vec<_>>();
+        let (_instance, _adapter, device, queue) = wgpu_setup().await;
+        let mut generator =
+            MaskedMipmapGenerator::new_with_format_hints(&device, &[texture_descriptor.format]);
+        generator.set_dilation(dilation);
+        let texture = device.create_texture(&texture_descriptor);
+        let (mask, mask_descriptor) =
+            MaskedMipmapGenerator::create_mask_texture(&device, &texture_descriptor);
+        for (texture, descriptor, data, bytes_per_texel) in &[
+            (&texture, &texture_descriptor, &data, 4),
+            (&mask, &mask_descriptor, &mask_data, 1),
+        ] {
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                data,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: bytes_per_texel * size,
+                    rows_per_image: size,
+                },
+                descriptor.size,
+            );
+        }
+        let mut encoder = device.create_command_encoder(&Default::default());
+        generator
+            .generate_with_mask(
+                &device,
+                &mut encoder,
+                &texture,
+                &texture_descriptor,
+                &mask,
+                &mask_descriptor,
+                &Default::default(),
+            )
+            .unwrap();
+        let readback = MipReadback::encode(&device, &mut encoder, &texture, &texture_descriptor);
+        let mask_readback = MipReadback::encode(&device, &mut encoder, &mask, &mask_descriptor);
+        queue.submit(std::iter::once(encoder.finish()));
+        let (mips, mask_mips) = (readback.read(), mask_readback.read());
+        device.poll(wgpu::Maintain::Wait);
+        (mips.await.unwrap(), mask_mips.await.unwrap())
+    }
+
+    #[test]
+    fn invalid_texels_are_ignored() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let (mips, mask_mips) = generate_half_masked(false).await;
+            // Every level is red wherever it covers a valid texel
+            for (mip, mask_mip) in mips.iter().zip(mask_mips.iter()).skip(1) {
+                let texels = mip.as_slice::<[u8; 4]>();
+                let coverage = mask_mip.as_slice::<u8>();
+                for (texel, coverage) in texels.iter().zip(coverage) {
+                    if *coverage > 0 {
+                        assert_eq!(*texel, [255, 0, 0, 255], "level {}", mip.level);
+                    } else {
+                        assert_eq!(*texel, [0, 0, 0, 0], "level {}", mip.level);
+                    }
+                }
+            }
+            // The last level covers the whole texture, half of which is valid
+            let last = mask_mips.last().unwrap().as_slice::<u8>()[0];
+            assert!((last as i32 - 128).abs() <= 1);
+        });
+    }
+
+    #[test]
+    fn dilation_fills_invalid_texels() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let (mips, mask_mips) = generate_half_masked(true).await;
+            // The invalid half of every level is filled from the red levels below it
+            for mip in mips.iter().skip(1) {
+                for texel in mip.as_slice::<[u8; 4]>() {
+                    assert_eq!(*texel, [255, 0, 0, 255], "level {}", mip.level);
+                }
+            }
+            // The mask is left as it is without dilation
+            let (_, undilated_mask_mips) = generate_half_masked(false).await;
+            for (a, b) in mask_mips.iter().zip(undilated_mask_mips.iter()) {
+                assert_eq!(a.buffer, b.buffer);
+            }
+        });
+    }
+}
@@ -0,0 +1,407 @@
+use crate::{
+    backends::render::to_sample_type,
+    core::*,
+    util::{create_mip_level_view, get_mip_extent},
+};
+use std::{collections::HashMap, num::NonZeroU32, ops::Range};
+use wgpu::{
+    util::make_spirv, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, CommandEncoder,
+    ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
+    PipelineLayoutDescriptor, ShaderFlags, ShaderModule, ShaderModuleDescriptor, ShaderStage,
+    StorageTextureAccess, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureSampleType, TextureUsage, TextureViewDescriptor, TextureViewDimension,
+};
+
+/// The format of every min-max pyramid: the minimum is stored in the red channel
+/// and the maximum in the green channel.
+pub const MIN_MAX_FORMAT: TextureFormat = TextureFormat::Rg32Float;
+
+// Must match the local_size_x and local_size_y values in minmax.comp and minmax_seed.comp
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Generates min-max pyramids, where each texel of level `n` holds the minimum and maximum
+/// of the texels it covers in level 0. Useful for ray-marching heightfields and
+/// hierarchical-z culling.
+#[derive(Debug)]
+pub struct MinMaxMipmapGenerator {
+    seed_layout_cache: HashMap<TextureSampleType, BindGroupLayout>,
+    seed_pipeline_cache: HashMap<TextureSampleType, ComputePipeline>,
+    reduce_layout: BindGroupLayout,
+    reduce_pipeline: ComputePipeline,
+}
+
+impl MinMaxMipmapGenerator {
+    /// Returns the texture usage `MinMaxMipmapGenerator` requires of the destination texture.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::STORAGE
+    }
+
+    /// Creates a new `MinMaxMipmapGenerator`. Once created, it can be used repeatedly to
+    /// generate min-max pyramids from any single channel float or depth texture.
+    pub fn new(device: &Device) -> Self {
+        let seed_module = device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(include_bytes!("shaders/minmax_seed.comp.spv")),
+            flags: ShaderFlags::empty(),
+        });
+        let reduce_module = device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(include_bytes!("shaders/minmax.comp.spv")),
+            flags: ShaderFlags::empty(),
+        });
+        let mut seed_layout_cache = HashMap::new();
+        let mut seed_pipeline_cache = HashMap::new();
+        for &sample_type in &[
+            TextureSampleType::Float { filterable: false },
+            TextureSampleType::Depth,
+        ] {
+            let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some(&format!(
+                    "wgpu-mipmap-minmax-seed-bg-layout-{:?}",
+                    sample_type
+                )),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStage::COMPUTE,
+                        ty: BindingType::Texture {
+                            view_dimension: TextureViewDimension::D2,
+                            sample_type,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    storage_entry(1, StorageTextureAccess::WriteOnly),
+                ],
+            });
+            let pipeline = pipeline(device, &seed_module, &layout, "seed");
+            seed_layout_cache.insert(sample_type, layout);
+            seed_pipeline_cache.insert(sample_type, pipeline);
+        }
+        let reduce_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("wgpu-mipmap-minmax-bg-layout"),
+            entries: &[
+                storage_entry(0, StorageTextureAccess::ReadOnly),
+                storage_entry(1, StorageTextureAccess::WriteOnly),
+            ],
+        });
+        let reduce_pipeline = pipeline(device, &reduce_module, &reduce_layout, "reduce");
+        Self {
+            seed_layout_cache,
+            seed_pipeline_cache,
+            reduce_layout,
+            reduce_pipeline,
+        }
+    }
+
+    /// Returns a descriptor for a min-max pyramid matching `src_texture_descriptor`:
+    /// same size and mip level count, [`MIN_MAX_FORMAT`], and usage suitable for generation
+    /// and sampling.
+    pub fn destination_descriptor(
+        src_texture_descriptor: &TextureDescriptor,
+    ) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-minmax-pyramid"),
+            size: src_texture_descriptor.size,
+            mip_level_count: src_texture_descriptor.mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: MIN_MAX_FORMAT,
+            usage: Self::required_usage() | TextureUsage::SAMPLED | TextureUsage::COPY_SRC,
+        }
+    }
+
+    /// Creates a texture described by [`MinMaxMipmapGenerator::destination_descriptor`].
+    pub fn create_destination_texture(
+        device: &Device,
+        src_texture_descriptor: &TextureDescriptor,
+    ) -> (Texture, TextureDescriptor<'static>) {
+        let descriptor = Self::destination_descriptor(src_texture_descriptor);
+        (device.create_texture(&descriptor), descriptor)
+    }
+
+    /// Encodes commands to seed level 0 of `dst_texture` from the first channel of level 0
+    /// of `src_texture`, then reduce it down the rest of the mip chain.
+    ///
+    /// Expectations:
+    /// - `src_texture` must have `TextureUsage::SAMPLED` and a float or depth format.
+    /// - `dst_texture` must match the descriptor returned by
+    ///   [`MinMaxMipmapGenerator::destination_descriptor`], up to extra usage flags.
+    pub fn generate_from(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_texture: &Texture,
+        src_texture_descriptor: &TextureDescriptor,
+        dst_texture: &Texture,
+        dst_texture_descriptor: &TextureDescriptor,
+    ) -> Result<(), Error> {
+        if src_texture_descriptor.dimension != TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(
+                src_texture_descriptor.dimension,
+            ));
+        }
+        if !src_texture_descriptor.usage.contains(TextureUsage::SAMPLED) {
+            return Err(Error::UnsupportedUsage(src_texture_descriptor.usage));
+        }
+        if src_texture_descriptor.size != dst_texture_descriptor.size {
+            return Err(Error::ExtentMismatch);
+        }
+        self.validate(dst_texture_descriptor)?;
+        let src_format = src_texture_descriptor.format;
+        let sample_type =
+            seed_sample_type(src_format).ok_or(Error::UnsupportedFormat(src_format))?;
+        let layout = self
+            .seed_layout_cache
+            .get(&sample_type)
+            .ok_or(Error::UnknownFormat(src_format))?;
+        let pipeline = self
+            .seed_pipeline_cache
+            .get(&sample_type)
+            .ok_or(Error::UnknownFormat(src_format))?;
+        let aspect = match sample_type {
+            TextureSampleType::Depth => TextureAspect::DepthOnly,
+            _ => TextureAspect::All,
+        };
+        let src_view = src_texture.create_view(&TextureViewDescriptor {
+            label: None,
+            format: None,
+            dimension: None,
+            aspect,
+            base_mip_level: 0,
+            level_count: NonZeroU32::new(1),
+            array_layer_count: None,
+            base_array_layer: 0,
+        });
+        let dst_view = create_mip_level_view(dst_texture, 0);
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&src_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&dst_view),
+                },
+            ],
+        });
+        {
+            let extent = dst_texture_descriptor.size;
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch(
+                extent.width.div_ceil(WORKGROUP_SIZE),
+                extent.height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        self.reduce(
+            device,
+            encoder,
+            dst_texture,
+            dst_texture_descriptor,
+            1..dst_texture_descriptor.mip_level_count,
+        );
+        Ok(())
+    }
+
+    fn validate(&self, texture_descriptor: &TextureDescriptor) -> Result<(), Error> {
+        if texture_descriptor.dimension != TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(texture_descriptor.dimension));
+        }
+        if texture_descriptor.format != MIN_MAX_FORMAT {
+            return Err(Error::UnsupportedFormat(texture_descriptor.format));
+        }
+        if !texture_descriptor.usage.contains(Self::required_usage()) {
+            return Err(Error::UnsupportedUsage(texture_descriptor.usage));
+        }
+        Ok(())
+    }
+
+    fn reduce(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        mip_levels: Range<u32>,
+    ) {
+        let mip_count = texture_descriptor.mip_level_count;
+        let views = (0..mip_count)
+            .map(|mip| create_mip_level_view(texture, mip))
+            .collect::<Vec<_>>();
+        for mip in mip_levels.start as usize..mip_levels.end as usize {
+            let mip_ext = get_mip_extent(&texture_descriptor.size, mip as u32);
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &self.reduce_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&views[mip - 1]),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&views[mip]),
+                    },
+                ],
+            });
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+            pass.set_pipeline(&self.reduce_pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch(
+                mip_ext.width.div_ceil(WORKGROUP_SIZE),
+                mip_ext.height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+    }
+}
+
+impl MipmapGenerator for MinMaxMipmapGenerator {
+    /// Reduces a min-max pyramid whose base level has already been written,
+    /// e.g. by a previous call to [`MinMaxMipmapGenerator::generate_from`].
+    fn generate_with_options(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<(), Error> {
+        // A single level texture has no levels to generate
+        if texture_descriptor.mip_level_count == 1 {
+            return Ok(());
+        }
+        self.validate(texture_descriptor)?;
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        self.reduce(device, encoder, texture, texture_descriptor, mip_levels);
+        Ok(())
+    }
+}
+
+fn seed_sample_type(format: TextureFormat) -> Option<TextureSampleType> {
+    match format {
+        TextureFormat::Depth32Float
+        | TextureFormat::Depth24Plus
+        | TextureFormat::Depth24PlusStencil8 => Some(TextureSampleType::Depth),
+        format => match to_sample_type(format) {
+            TextureSampleType::Float { .. } => Some(TextureSampleType::Float { filterable: false }),
+            _ => None,
+        },
+    }
+}
+
+fn storage_entry(binding: u32, access: StorageTextureAccess) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStage::COMPUTE,
+        ty: BindingType::StorageTexture {
+            access,
+            format: MIN_MAX_FORMAT,
+            view_dimension: TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+fn pipeline(
+    device: &Device,
+    module: &ShaderModule,
+    bind_group_layout: &BindGroupLayout,
+    name: &str,
+) -> ComputePipeline {
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some(&format!("wgpu-mipmap-minmax-{}-pipeline", name)),
+        layout: Some(&pipeline_layout),
+        module,
+        entry_point: "main",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn src_descriptor(size: u32, format: TextureFormat) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: TextureUsage::SAMPLED,
+            label: None,
+        }
+    }
+
+    async fn generate_test(src_descriptor: &TextureDescriptor<'_>) -> Result<(), Error> {
+        let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+        let generator = MinMaxMipmapGenerator::new(&device);
+        let src = device.create_texture(src_descriptor);
+        let (dst, dst_descriptor) =
+            MinMaxMipmapGenerator::create_destination_texture(&device, src_descriptor);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        generator.generate_from(
+            &device,
+            &mut encoder,
+            &src,
+            src_descriptor,
+            &dst,
+            &dst_descriptor,
+        )
+    }
+
+    #[test]
+    fn destination_descriptor() {
+        let src = src_descriptor(511, TextureFormat::R32Float);
+        let dst = MinMaxMipmapGenerator::destination_descriptor(&src);
+        assert_eq!(dst.size, src.size);
+        assert_eq!(dst.mip_level_count, src.mip_level_count);
+        assert_eq!(dst.format, MIN_MAX_FORMAT);
+        assert!(dst.usage.contains(MinMaxMipmapGenerator::required_usage()));
+    }
+
+    #[test]
+    fn sanity_check() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let res = generate_test(&src_descriptor(511, TextureFormat::R32Float)).await;
+            assert!(res.is_ok());
+        });
+    }
+
+    #[test]
+    fn unsupported_format() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let res = generate_test(&src_descriptor(512, TextureFormat::R32Uint)).await;
+            assert!(res.err() == Some(Error::UnsupportedFormat(TextureFormat::R32Uint)));
+        });
+    }
+}
@@ -1,9 +1,49 @@
+mod atlas;
+mod batch;
+mod bilateral;
+mod cache;
+mod compressed;
 mod compute;
 mod copy;
+#[cfg(feature = "unstable")]
+mod digest;
+mod irradiance;
+mod masked;
+mod minmax;
+mod moments;
+mod packed;
+mod prefilter;
+mod probe;
+mod push_pull;
 mod recommended;
 mod render;
+mod sat;
+mod scratch;
+mod srgb;
+mod toksvig;
+mod yuv;
 
+pub use atlas::*;
+pub use batch::*;
+pub use bilateral::*;
+pub use cache::*;
+pub use compressed::*;
 pub use compute::*;
 pub use copy::*;
+#[cfg(feature = "unstable")]
+pub use digest::*;
+pub use irradiance::*;
+pub use masked::*;
+pub use minmax::*;
+pub use moments::*;
+pub use packed::*;
+pub use prefilter::*;
+pub use probe::*;
+pub use push_pull::*;
 pub use recommended::*;
 pub use render::*;
+pub use sat::*;
+pub use scratch::*;
+pub use srgb::*;
+pub use toksvig::*;
+pub use yuv::*;
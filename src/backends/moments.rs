@@ -0,0 +1,591 @@
+use crate::{
+    backends::render::to_sample_type,
+    core::*,
+    trace::event,
+    util::{create_mip_level_view, get_mip_extent},
+};
+use std::{collections::HashMap, num::NonZeroU32, ops::Range};
+use wgpu::{
+    util::{make_spirv, BufferInitDescriptor, DeviceExt},
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsage,
+    CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
+    PipelineLayoutDescriptor, ShaderFlags, ShaderModule, ShaderModuleDescriptor, ShaderStage,
+    StorageTextureAccess, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureSampleType, TextureUsage, TextureViewDescriptor, TextureViewDimension,
+};
+
+// Must match the local_size_x and local_size_y values in moments_seed.comp and moments.comp
+const WORKGROUP_SIZE: u32 = 8;
+
+/// How [`MomentMipmapGenerator`] turns depths into moments.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum MomentWarp {
+    /// Variance shadow maps: the moments are the depth and its square.
+    #[default]
+    Variance,
+    /// Exponential variance shadow maps: the depth is remapped to `[-1, 1]` and warped by
+    /// `exp(positive_exponent * d)` and `-exp(-negative_exponent * d)`, and the moments are
+    /// each warped depth and its square. `Rg32Float` moment maps only store the positive
+    /// pair, `Rgba32Float` ones store both. The exponents must keep the squared moments in
+    /// range of an `f32`; 40 and 5 are common choices.
+    Exponential {
+        positive_exponent: f32,
+        negative_exponent: f32,
+    },
+}
+
+/// Generates mipmaps of variance and exponential variance shadow maps, where each texel of a
+/// level holds the average moments of the texels it covers in level 0.
+///
+/// Moments are seeded from a depth texture, warping each depth at full resolution, then
+/// averaged down the mip chain. Warping is not linear, so warping a level's filtered depth,
+/// or averaging a depth pyramid before warping it, gives moments that disagree with the
+/// base level. Moment maps must be `Rg32Float` or `Rgba32Float`: 16-bit floats overflow
+/// with useful EVSM exponents and lose the precision VSM relies on.
+#[derive(Debug)]
+pub struct MomentMipmapGenerator {
+    seed_layout_cache: HashMap<(TextureSampleType, TextureFormat), BindGroupLayout>,
+    seed_pipeline_cache: HashMap<(TextureSampleType, TextureFormat), ComputePipeline>,
+    layout_cache: HashMap<TextureFormat, BindGroupLayout>,
+    pipeline_cache: HashMap<TextureFormat, ComputePipeline>,
+    warp: MomentWarp,
+}
+
+impl MomentMipmapGenerator {
+    /// Returns the texture usage `MomentMipmapGenerator` requires of the moment map.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::STORAGE
+    }
+
+    /// Creates a new `MomentMipmapGenerator`. Once created, it can be used repeatedly to
+    /// generate `Rg32Float` and `Rgba32Float` moment maps.
+    pub fn new(device: &Device) -> Self {
+        let mut seed_layout_cache = HashMap::new();
+        let mut seed_pipeline_cache = HashMap::new();
+        let mut layout_cache = HashMap::new();
+        let mut pipeline_cache = HashMap::new();
+        for &format in &[TextureFormat::Rg32Float, TextureFormat::Rgba32Float] {
+            let (seed_module, module) = shaders_for_format(device, format);
+            for &sample_type in &[
+                TextureSampleType::Float { filterable: false },
+                TextureSampleType::Depth,
+            ] {
+                let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: Some(&format!(
+                        "wgpu-mipmap-moments-seed-bg-layout-{:?}-{:?}",
+                        sample_type, format
+                    )),
+                    entries: &[
+                        BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: ShaderStage::COMPUTE,
+                            ty: BindingType::Texture {
+                                view_dimension: TextureViewDimension::D2,
+                                sample_type,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        storage_entry(1, StorageTextureAccess::WriteOnly, format),
+                        BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: ShaderStage::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+                let pipeline = pipeline(device, &seed_module, &layout, "seed", format);
+                seed_layout_cache.insert((sample_type, format), layout);
+                seed_pipeline_cache.insert((sample_type, format), pipeline);
+            }
+            let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some(&format!("wgpu-mipmap-moments-bg-layout-{:?}", format)),
+                entries: &[
+                    storage_entry(0, StorageTextureAccess::ReadOnly, format),
+                    storage_entry(1, StorageTextureAccess::WriteOnly, format),
+                ],
+            });
+            let pipeline = pipeline(device, &module, &layout, "average", format);
+            layout_cache.insert(format, layout);
+            pipeline_cache.insert(format, pipeline);
+        }
+        Self {
+            seed_layout_cache,
+            seed_pipeline_cache,
+            layout_cache,
+            pipeline_cache,
+            warp: MomentWarp::Variance,
+        }
+    }
+
+    /// Returns how depths are turned into moments. Defaults to [`MomentWarp::Variance`].
+    pub fn warp(&self) -> MomentWarp {
+        self.warp
+    }
+
+    /// Sets how depths are turned into moments by later calls to
+    /// [`MomentMipmapGenerator::generate_from`]. The shadow lookup must apply the same warp
+    /// to the receiver's depth.
+    pub fn set_warp(&mut self, warp: MomentWarp) {
+        self.warp = warp;
+    }
+
+    /// Returns a descriptor for a moment map matching `src_texture_descriptor`: same size
+    /// and mip level count, `format`, and usage suitable for generation and sampling.
+    pub fn destination_descriptor(
+        src_texture_descriptor: &TextureDescriptor,
+        format: TextureFormat,
+    ) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-moment-map"),
+            size: src_texture_descriptor.size,
+            mip_level_count: src_texture_descriptor.mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: Self::required_usage() | TextureUsage::SAMPLED | TextureUsage::COPY_SRC,
+        }
+    }
+
+    /// Encodes commands to seed level 0 of `dst_texture` with the moments of the depths in
+    /// the first channel of level 0 of `src_texture`, warped by [`MomentMipmapGenerator::warp`],
+    /// then average them down the rest of the mip chain.
+    ///
+    /// Expectations:
+    /// - `src_texture` must have `TextureUsage::SAMPLED` and a float or depth format, with
+    ///   depths in `[0, 1]`.
+    /// - `dst_texture` must match the descriptor returned by
+    ///   [`MomentMipmapGenerator::destination_descriptor`], up to extra usage flags.
+    pub fn generate_from(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_texture: &Texture,
+        src_texture_descriptor: &TextureDescriptor,
+        dst_texture: &Texture,
+        dst_texture_descriptor: &TextureDescriptor,
+    ) -> Result<(), Error> {
+        if src_texture_descriptor.dimension != TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(
+                src_texture_descriptor.dimension,
+            ));
+        }
+        if !src_texture_descriptor.usage.contains(TextureUsage::SAMPLED) {
+            return Err(Error::UnsupportedUsage(src_texture_descriptor.usage));
+        }
+        if src_texture_descriptor.size != dst_texture_descriptor.size {
+            return Err(Error::ExtentMismatch);
+        }
+        self.validate(dst_texture_descriptor)?;
+        let src_format = src_texture_descriptor.format;
+        let format = dst_texture_descriptor.format;
+        let sample_type =
+            seed_sample_type(src_format).ok_or(Error::UnsupportedFormat(src_format))?;
+        let layout = self
+            .seed_layout_cache
+            .get(&(sample_type, format))
+            .ok_or(Error::UnknownFormat(src_format))?;
+        let pipeline = self
+            .seed_pipeline_cache
+            .get(&(sample_type, format))
+            .ok_or(Error::UnknownFormat(src_format))?;
+        let aspect = match sample_type {
+            TextureSampleType::Depth => TextureAspect::DepthOnly,
+            _ => TextureAspect::All,
+        };
+        let src_view = src_texture.create_view(&TextureViewDescriptor {
+            label: None,
+            format: None,
+            dimension: None,
+            aspect,
+            base_mip_level: 0,
+            level_count: NonZeroU32::new(1),
+            array_layer_count: None,
+            base_array_layer: 0,
+        });
+        let dst_view = create_mip_level_view(dst_texture, 0);
+        let params = match self.warp {
+            MomentWarp::Variance => [0, 0, 0, 0],
+            MomentWarp::Exponential {
+                positive_exponent,
+                negative_exponent,
+            } => [
+                1,
+                positive_exponent.to_bits(),
+                negative_exponent.to_bits(),
+                0,
+            ],
+        };
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("wgpu-mipmap-moments-params"),
+            contents: bytemuck::cast_slice(&params),
+            usage: BufferUsage::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&src_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&dst_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Buffer {
+                        buffer: &params,
+                        offset: 0,
+                        size: None,
+                    },
+                },
+            ],
+        });
+        {
+            let extent = dst_texture_descriptor.size;
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch(
+                extent.width.div_ceil(WORKGROUP_SIZE),
+                extent.height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        self.average(
+            device,
+            encoder,
+            dst_texture,
+            dst_texture_descriptor,
+            1..dst_texture_descriptor.mip_level_count,
+        );
+        Ok(())
+    }
+
+    fn validate(&self, texture_descriptor: &TextureDescriptor) -> Result<(), Error> {
+        if texture_descriptor.dimension != TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(texture_descriptor.dimension));
+        }
+        if !self.pipeline_cache.contains_key(&texture_descriptor.format) {
+            return Err(Error::UnsupportedFormat(texture_descriptor.format));
+        }
+        if !texture_descriptor.usage.contains(Self::required_usage()) {
+            return Err(Error::UnsupportedUsage(texture_descriptor.usage));
+        }
+        Ok(())
+    }
+
+    fn average(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        mip_levels: Range<u32>,
+    ) {
+        let format = texture_descriptor.format;
+        let layout = &self.layout_cache[&format];
+        let pipeline = &self.pipeline_cache[&format];
+        for mip in mip_levels {
+            event!(
+                TRACE,
+                {
+                    backend = "moments",
+                    format = format,
+                    level = mip,
+                },
+                "dispatching level"
+            );
+            let src_view = create_mip_level_view(texture, mip - 1);
+            let dst_view = create_mip_level_view(texture, mip);
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&src_view),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&dst_view),
+                    },
+                ],
+            });
+            let extent = get_mip_extent(&texture_descriptor.size, mip);
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch(
+                extent.width.div_ceil(WORKGROUP_SIZE),
+                extent.height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+    }
+}
+
+impl MipmapGenerator for MomentMipmapGenerator {
+    /// Averages a moment map whose base level has already been written, e.g. by a shadow
+    /// pass that outputs moments directly or a previous call to
+    /// [`MomentMipmapGenerator::generate_from`]. The moments are averaged as they are, so
+    /// they must already be warped.
+    fn generate_with_options(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<(), Error> {
+        // A single level texture has no levels to generate
+        if texture_descriptor.mip_level_count == 1 {
+            return Ok(());
+        }
+        self.validate(texture_descriptor)?;
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        self.average(device, encoder, texture, texture_descriptor, mip_levels);
+        Ok(())
+    }
+}
+
+fn shaders_for_format(device: &Device, format: TextureFormat) -> (ShaderModule, ShaderModule) {
+    let s = |d| {
+        device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(d),
+            flags: ShaderFlags::empty(),
+        })
+    };
+    match format {
+        TextureFormat::Rg32Float => (
+            s(include_bytes!("shaders/moments_seed_rg32f.comp.spv")),
+            s(include_bytes!("shaders/moments_rg32f.comp.spv")),
+        ),
+        TextureFormat::Rgba32Float => (
+            s(include_bytes!("shaders/moments_seed_rgba32f.comp.spv")),
+            s(include_bytes!("shaders/moments_rgba32f.comp.spv")),
+        ),
+        _ => unreachable!("moment maps are Rg32Float or Rgba32Float"),
+    }
+}
+
+fn seed_sample_type(format: TextureFormat) -> Option<TextureSampleType> {
+    match format {
+        TextureFormat::Depth32Float
+        | TextureFormat::Depth24Plus
+        | TextureFormat::Depth24PlusStencil8 => Some(TextureSampleType::Depth),
+        format => match to_sample_type(format) {
+            TextureSampleType::Float { .. } => Some(TextureSampleType::Float { filterable: false }),
+            _ => None,
+        },
+    }
+}
+
+fn storage_entry(
+    binding: u32,
+    access: StorageTextureAccess,
+    format: TextureFormat,
+) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStage::COMPUTE,
+        ty: BindingType::StorageTexture {
+            access,
+            format,
+            view_dimension: TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+fn pipeline(
+    device: &Device,
+    module: &ShaderModule,
+    bind_group_layout: &BindGroupLayout,
+    name: &str,
+    format: TextureFormat,
+) -> ComputePipeline {
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some(&format!(
+            "wgpu-mipmap-moments-{}-pipeline-{:?}",
+            name, format
+        )),
+        layout: Some(&pipeline_layout),
+        module,
+        entry_point: "main",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn src_descriptor(size: u32) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format: TextureFormat::R32Float,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: TextureUsage::SAMPLED | TextureUsage::COPY_DST,
+            label: None,
+        }
+    }
+
+    // Generates the moment map of a depth texture whose left half is at depth 0.25 and right
+    // half at depth 0.75, and returns the moments of its last level
+    async fn last_level_moments(warp: MomentWarp, format: TextureFormat) -> [f32; 4] {
+        let (_instance, _adapter, device, queue) = wgpu_setup().await;
+        let mut generator = MomentMipmapGenerator::new(&device);
+        generator.set_warp(warp);
+        let size = 8;
+        let src_descriptor = src_descriptor(size);
+        let src = device.create_texture(&src_descriptor);
+        let depths = (0..size * size)
+            .map(|id| if id % size < size / 2 { 0.25f32 } else { 0.75 })
+            .collect::<Vec<_>>();
+        queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &src,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            bytemuck::cast_slice(&depths),
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: 4 * size,
+                rows_per_image: size,
+            },
+            src_descriptor.size,
+        );
+        let dst_descriptor = MomentMipmapGenerator::destination_descriptor(&src_descriptor, format);
+        let dst = device.create_texture(&dst_descriptor);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        generator
+            .generate_from(
+                &device,
+                &mut encoder,
+                &src,
+                &src_descriptor,
+                &dst,
+                &dst_descriptor,
+            )
+            .unwrap();
+        let readback = MipReadback::encode(&device, &mut encoder, &dst, &dst_descriptor);
+        queue.submit(std::iter::once(encoder.finish()));
+        let mips = readback.read();
+        device.poll(wgpu::Maintain::Wait);
+        let mips = mips.await.unwrap();
+        let last = mips.last().unwrap();
+        let mut moments = [0.0; 4];
+        match format {
+            TextureFormat::Rg32Float => {
+                moments[..2].copy_from_slice(&last.as_slice::<[f32; 2]>()[0]);
+            }
+            _ => moments = last.as_slice::<[f32; 4]>()[0],
+        }
+        moments
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * 1e-5,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn destination_descriptor() {
+        let src = src_descriptor(64);
+        let dst = MomentMipmapGenerator::destination_descriptor(&src, TextureFormat::Rgba32Float);
+        assert_eq!(dst.size, src.size);
+        assert_eq!(dst.mip_level_count, src.mip_level_count);
+        assert_eq!(dst.format, TextureFormat::Rgba32Float);
+        assert!(dst.usage.contains(MomentMipmapGenerator::required_usage()));
+    }
+
+    #[test]
+    fn variance_moments() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let moments = last_level_moments(MomentWarp::Variance, TextureFormat::Rg32Float).await;
+            assert_close(moments[0], 0.5);
+            assert_close(moments[1], (0.25 * 0.25 + 0.75 * 0.75) / 2.0);
+        });
+    }
+
+    #[test]
+    fn exponential_moments() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let (positive_exponent, negative_exponent) = (40.0f32, 5.0f32);
+        let warp = MomentWarp::Exponential {
+            positive_exponent,
+            negative_exponent,
+        };
+        futures::executor::block_on(async {
+            let moments = last_level_moments(warp, TextureFormat::Rgba32Float).await;
+            // The average of the warped depths -0.5 and 0.5, not the warp of their average 0
+            let p = |w: f32| (positive_exponent * w).exp();
+            let n = |w: f32| -(-negative_exponent * w).exp();
+            assert_close(moments[0], (p(-0.5) + p(0.5)) / 2.0);
+            assert_close(moments[1], (p(-0.5).powi(2) + p(0.5).powi(2)) / 2.0);
+            assert_close(moments[2], (n(-0.5) + n(0.5)) / 2.0);
+            assert_close(moments[3], (n(-0.5).powi(2) + n(0.5).powi(2)) / 2.0);
+        });
+    }
+
+    #[test]
+    fn unsupported_format() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let generator = MomentMipmapGenerator::new(&device);
+            let src_descriptor = src_descriptor(16);
+            let dst_descriptor = MomentMipmapGenerator::destination_descriptor(
+                &src_descriptor,
+                TextureFormat::Rgba16Float,
+            );
+            let dst = device.create_texture(&dst_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let res = generator.generate(&device, &mut encoder, &dst, &dst_descriptor);
+            assert_eq!(
+                res,
+                Err(Error::UnsupportedFormat(TextureFormat::Rgba16Float))
+            );
+        });
+    }
+}
@@ -0,0 +1,446 @@
+// Without the format-packed feature, no shader uses the arguments of `packed_shader`
+#![cfg_attr(not(feature = "format-packed"), allow(unused_variables))]
+
+use crate::{
+    core::*,
+    trace::span,
+    util::{get_mip_extent, MipBufferDimensions},
+};
+use std::{collections::HashMap, num::NonZeroU32, sync::Arc};
+use wgpu::{
+    util::{make_spirv, BufferInitDescriptor, DeviceExt},
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferCopyView,
+    BufferDescriptor, BufferSize, BufferUsage, CommandEncoder, ComputePassDescriptor,
+    ComputePipeline, ComputePipelineDescriptor, Device, Extent3d, Origin3d,
+    PipelineLayoutDescriptor, ShaderFlags, ShaderModule, ShaderModuleDescriptor, ShaderStage,
+    Texture, TextureAspect, TextureCopyView, TextureDataLayout, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsage, TextureViewDescriptor,
+    TextureViewDimension,
+};
+
+// Must match the local_size_x and local_size_y values in packed.comp
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Generates mipmaps for `Rgb10a2Unorm` and `Rg11b10Float` textures with sampled and copy
+/// destination usage.
+///
+/// `Rg11b10Float` can't be rendered to, and neither format is guaranteed to be writable as a
+/// storage texture, so on some devices neither the render nor the compute backend can write
+/// them. This generator box filters each level with a compute shader that samples the level
+/// before it and packs the filtered texels into a buffer, then copies the buffer into the
+/// level. [`RecommendedMipmapGenerator`](crate::RecommendedMipmapGenerator) falls back to it
+/// after every other backend.
+#[derive(Debug, Clone)]
+pub struct PackedMipmapGenerator {
+    layout: Arc<BindGroupLayout>,
+    pipelines: HashMap<TextureFormat, Arc<ComputePipeline>>,
+}
+
+impl PackedMipmapGenerator {
+    /// Returns the texture usage `PackedMipmapGenerator` requires for mipmap generation.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::SAMPLED | TextureUsage::COPY_DST
+    }
+
+    /// Returns true if `PackedMipmapGenerator` can be created with pipelines for `format`.
+    pub fn is_supported(format: TextureFormat) -> bool {
+        matches!(
+            format,
+            TextureFormat::Rgb10a2Unorm | TextureFormat::Rg11b10Float
+        )
+    }
+
+    /// Creates a new `PackedMipmapGenerator` with a pipeline for each format of
+    /// `format_hints` it supports. The shaders are only embedded with the `format-packed`
+    /// cargo feature; without it, no pipelines are created.
+    pub fn new_with_format_hints(device: &Device, format_hints: &[TextureFormat]) -> Self {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("wgpu-mipmap-packed-bg-layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStage::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStage::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStage::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let mut pipelines = HashMap::new();
+        for &format in format_hints {
+            if pipelines.contains_key(&format) {
+                continue;
+            }
+            if let Some(module) = packed_shader(device, format) {
+                let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+                    label: Some("wgpu-mipmap-packed-pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &module,
+                    entry_point: "main",
+                });
+                pipelines.insert(format, Arc::new(pipeline));
+            }
+        }
+        Self {
+            layout: Arc::new(layout),
+            pipelines,
+        }
+    }
+
+    /// Returns the pipeline used for `format`, if this generator was created for it.
+    pub fn pipeline_for(&self, format: TextureFormat) -> Option<&ComputePipeline> {
+        self.pipelines
+            .get(&format)
+            .map(|pipeline| pipeline.as_ref())
+    }
+
+    fn validate(
+        &self,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<&ComputePipeline, Error> {
+        let format = texture_descriptor.format;
+        if texture_descriptor.dimension != TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(texture_descriptor.dimension));
+        }
+        if !texture_descriptor.usage.contains(Self::required_usage()) {
+            return Err(Error::UnsupportedUsage(texture_descriptor.usage));
+        }
+        if !Self::is_supported(format) {
+            return Err(Error::UnsupportedFormat(format));
+        }
+        if options.filter != MipFilter::Box {
+            return Err(Error::UnsupportedFilter(options.filter));
+        }
+        self.pipeline_for(format)
+            .ok_or(Error::UnknownFormat(format))
+    }
+}
+
+fn packed_shader(device: &Device, format: TextureFormat) -> Option<ShaderModule> {
+    let s = |bytes: &[u8]| {
+        Some(device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(bytes),
+            flags: ShaderFlags::empty(),
+        }))
+    };
+    match format {
+        #[cfg(feature = "format-packed")]
+        TextureFormat::Rgb10a2Unorm => s(include_bytes!("shaders/packed_rgb10_a2.comp.spv")),
+        #[cfg(feature = "format-packed")]
+        TextureFormat::Rg11b10Float => s(include_bytes!("shaders/packed_r11f_g11f_b10f.comp.spv")),
+        _ => None,
+    }
+}
+
+impl MipmapGenerator for PackedMipmapGenerator {
+    fn generate_with_options(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<(), Error> {
+        let _span = span!("generate_mipmaps", {
+            backend = "packed",
+            format = texture_descriptor.format,
+            levels = texture_descriptor.mip_level_count,
+            filter = options.filter,
+        });
+        let pipeline = self.validate(texture_descriptor, options)?;
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        if mip_levels.start == mip_levels.end {
+            return Ok(());
+        }
+        // Each level is packed at an offset of one buffer, with its rows padded for the copy.
+        // The padded rows keep every offset aligned for binding as a storage buffer.
+        let mut levels = Vec::new();
+        let mut buffer_size = 0;
+        for mip_level in mip_levels.clone() {
+            let extent = get_mip_extent(&texture_descriptor.size, mip_level);
+            let dimensions =
+                MipBufferDimensions::new(extent.width as usize, extent.height as usize, 4);
+            let bytes = (dimensions.padded_bytes_per_row * dimensions.height) as u64;
+            levels.push((mip_level, extent, dimensions, buffer_size, bytes));
+            buffer_size += bytes;
+        }
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("wgpu-mipmap-packed-buffer"),
+            size: buffer_size,
+            usage: BufferUsage::STORAGE | BufferUsage::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        for (mip_level, extent, dimensions, offset, bytes) in levels {
+            let src_view = texture.create_view(&TextureViewDescriptor {
+                label: None,
+                format: None,
+                dimension: Some(TextureViewDimension::D2),
+                aspect: TextureAspect::All,
+                base_mip_level: mip_level - 1,
+                level_count: NonZeroU32::new(1),
+                array_layer_count: NonZeroU32::new(1),
+                base_array_layer: options.array_layer,
+            });
+            let row_pitch = (dimensions.padded_bytes_per_row / 4) as u32;
+            let params = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("wgpu-mipmap-packed-params"),
+                contents: bytemuck::cast_slice(&[extent.width, extent.height, row_pitch, 0]),
+                usage: BufferUsage::UNIFORM,
+            });
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &self.layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&src_view),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Buffer {
+                            buffer: &buffer,
+                            offset,
+                            size: BufferSize::new(bytes),
+                        },
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::Buffer {
+                            buffer: &params,
+                            offset: 0,
+                            size: None,
+                        },
+                    },
+                ],
+            });
+            {
+                let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch(
+                    extent.width.div_ceil(WORKGROUP_SIZE),
+                    extent.height.div_ceil(WORKGROUP_SIZE),
+                    1,
+                );
+            }
+            // The next level samples this one, so it's copied in before the next dispatch
+            encoder.copy_buffer_to_texture(
+                BufferCopyView {
+                    buffer: &buffer,
+                    layout: TextureDataLayout {
+                        offset,
+                        bytes_per_row: dimensions.padded_bytes_per_row as u32,
+                        rows_per_image: extent.height,
+                    },
+                },
+                TextureCopyView {
+                    texture,
+                    mip_level,
+                    origin: Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: options.array_layer,
+                    },
+                },
+                Extent3d {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn estimate_cost_with_options(
+        &self,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<GenerationCost, Error> {
+        self.validate(texture_descriptor, options)?;
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        // Each level is written to the buffer, then copied into the texture
+        let cost = GenerationCost::per_level(texture_descriptor, mip_levels);
+        Ok(GenerationCost {
+            bytes_written: 2 * cost.bytes_written,
+            ..cost
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    #[test]
+    fn supported_formats() {
+        assert!(PackedMipmapGenerator::is_supported(
+            TextureFormat::Rgb10a2Unorm
+        ));
+        assert!(PackedMipmapGenerator::is_supported(
+            TextureFormat::Rg11b10Float
+        ));
+        assert!(!PackedMipmapGenerator::is_supported(
+            TextureFormat::Rgba8Unorm
+        ));
+    }
+
+    // Generates a 4x4 checkerboard of `one` and `zero` texels, and returns its last level
+    async fn checkerboard_last_level(format: TextureFormat, one: u32, zero: u32) -> u32 {
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 1,
+            },
+            mip_level_count: 3,
+            format,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: PackedMipmapGenerator::required_usage() | TextureUsage::COPY_SRC,
+            label: None,
+        };
+        let data: Vec<u32> = (0..16)
+            .map(|i| if (i % 4 + i / 4) % 2 == 0 { one } else { zero })
+            .collect();
+        let (_instance, _adapter, device, queue) = wgpu_setup().await;
+        let generator = PackedMipmapGenerator::new_with_format_hints(&device, &[format]);
+        let mips = generate_and_copy_to_cpu(
+            &device,
+            &queue,
+            &generator,
+            bytemuck::cast_slice(&data),
+            &texture_descriptor,
+        )
+        .await
+        .unwrap();
+        assert_eq!(mips.len(), 3);
+        // Every level below the checkerboard is a uniform gray
+        let level_1 = mips[1].as_slice::<u32>();
+        assert!(level_1.iter().all(|&texel| texel == level_1[0]));
+        mips[2].as_slice::<u32>()[0]
+    }
+
+    #[test]
+    fn rgb10a2() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let one = 0x3ff | 0x3ff << 10 | 0x3ff << 20 | 3 << 30;
+            let texel = checkerboard_last_level(TextureFormat::Rgb10a2Unorm, one, 3 << 30).await;
+            // 0.5 of 1023 rounds up to 512, and the alpha is opaque
+            assert_eq!(texel, 512 | 512 << 10 | 512 << 20 | 3 << 30);
+        });
+    }
+
+    #[test]
+    fn rg11b10() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            // 1.0 and 0.5 have a biased exponent of 15 and 14, and no mantissa bits
+            let float = |exponent: u32| exponent << 6 | exponent << 17 | exponent << 27;
+            let texel = checkerboard_last_level(TextureFormat::Rg11b10Float, float(15), 0).await;
+            assert_eq!(texel, float(14));
+        });
+    }
+
+    #[test]
+    fn unsupported() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let format = TextureFormat::Rg11b10Float;
+            let generator = PackedMipmapGenerator::new_with_format_hints(&device, &[format]);
+            let texture_descriptor = wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: 4,
+                    height: 4,
+                    depth: 1,
+                },
+                mip_level_count: 3,
+                format,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                usage: PackedMipmapGenerator::required_usage(),
+                label: None,
+            };
+            assert!(generator.estimate_cost(&texture_descriptor).is_ok());
+            let options = GenerateOptions {
+                filter: MipFilter::Nearest,
+                ..Default::default()
+            };
+            assert_eq!(
+                generator.estimate_cost_with_options(&texture_descriptor, &options),
+                Err(Error::UnsupportedFilter(MipFilter::Nearest))
+            );
+            let rgb10a2 = wgpu::TextureDescriptor {
+                format: TextureFormat::Rgb10a2Unorm,
+                ..texture_descriptor.clone()
+            };
+            assert_eq!(
+                generator.estimate_cost(&rgb10a2),
+                Err(Error::UnknownFormat(TextureFormat::Rgb10a2Unorm))
+            );
+            let rgba8 = wgpu::TextureDescriptor {
+                format: TextureFormat::Rgba8Unorm,
+                ..texture_descriptor.clone()
+            };
+            assert_eq!(
+                generator.estimate_cost(&rgba8),
+                Err(Error::UnsupportedFormat(TextureFormat::Rgba8Unorm))
+            );
+            let sampled = wgpu::TextureDescriptor {
+                usage: TextureUsage::SAMPLED,
+                ..texture_descriptor
+            };
+            assert_eq!(
+                generator.estimate_cost(&sampled),
+                Err(Error::UnsupportedUsage(TextureUsage::SAMPLED))
+            );
+        });
+    }
+}
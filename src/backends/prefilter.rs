@@ -0,0 +1,449 @@
+use crate::{core::*, util::get_mip_extent};
+use std::num::NonZeroU32;
+use wgpu::{
+    util::{make_spirv, BufferInitDescriptor, DeviceExt},
+    AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsage,
+    CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
+    Extent3d, FilterMode, PipelineLayoutDescriptor, Sampler, SamplerDescriptor, ShaderFlags,
+    ShaderModuleDescriptor, ShaderStage, StorageTextureAccess, Texture, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsage,
+    TextureView, TextureViewDescriptor, TextureViewDimension,
+};
+
+/// The format of every prefiltered environment map.
+pub const PREFILTERED_ENV_MAP_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+// Must match the local_size_x and local_size_y values in prefilter.comp and irradiance.comp
+const WORKGROUP_SIZE: u32 = 8;
+
+// The number of GGX samples taken per texel of the rough levels
+const SAMPLE_COUNT: u32 = 64;
+
+const CUBE_FACE_COUNT: u32 = 6;
+
+/// Generates prefiltered environment maps for split-sum image based lighting: level `n` of
+/// the destination cubemap holds the source cubemap convolved with a GGX lobe of roughness
+/// `n / (mip_level_count - 1)`, so a shader can pick the level matching a surface's roughness.
+#[derive(Debug)]
+pub struct PrefilteredEnvMapGenerator {
+    pipeline: EnvironmentPipeline,
+}
+
+impl PrefilteredEnvMapGenerator {
+    /// Returns the texture usage `PrefilteredEnvMapGenerator` requires of the destination
+    /// texture.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::STORAGE
+    }
+
+    /// Creates a new `PrefilteredEnvMapGenerator`. Once created, it can be used repeatedly to
+    /// prefilter any floating-point cubemap.
+    pub fn new(device: &Device) -> Self {
+        Self {
+            pipeline: EnvironmentPipeline::new(
+                device,
+                "prefilter",
+                include_bytes!("shaders/prefilter.comp.spv"),
+            ),
+        }
+    }
+
+    /// Returns a descriptor for a prefiltered environment map of `src_texture_descriptor`:
+    /// same face size and mip level count, [`PREFILTERED_ENV_MAP_FORMAT`], and usage suitable
+    /// for generation and sampling.
+    pub fn destination_descriptor(
+        src_texture_descriptor: &TextureDescriptor,
+    ) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-prefiltered-env-map"),
+            size: src_texture_descriptor.size,
+            mip_level_count: src_texture_descriptor.mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: PREFILTERED_ENV_MAP_FORMAT,
+            usage: Self::required_usage() | TextureUsage::SAMPLED | TextureUsage::COPY_SRC,
+        }
+    }
+
+    /// Creates a texture described by [`PrefilteredEnvMapGenerator::destination_descriptor`].
+    pub fn create_destination_texture(
+        device: &Device,
+        src_texture_descriptor: &TextureDescriptor,
+    ) -> (Texture, TextureDescriptor<'static>) {
+        let descriptor = Self::destination_descriptor(src_texture_descriptor);
+        (device.create_texture(&descriptor), descriptor)
+    }
+
+    /// Encodes commands to write every level of `dst_texture` from `src_texture`.
+    ///
+    /// Expectations:
+    /// - `src_texture` is a cubemap (6 square layers) with `TextureUsage::SAMPLED` and a
+    ///   floating-point format. Its mipmaps should already be generated, since rough levels
+    ///   read the source level matching the footprint of each sample.
+    /// - `dst_texture` is a cubemap with format [`PREFILTERED_ENV_MAP_FORMAT`] and
+    ///   `TextureUsage::STORAGE`.
+    pub fn generate_from(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_texture: &Texture,
+        src_texture_descriptor: &TextureDescriptor,
+        dst_texture: &Texture,
+        dst_texture_descriptor: &TextureDescriptor,
+    ) -> Result<(), Error> {
+        validate_environment_textures(
+            src_texture_descriptor,
+            dst_texture_descriptor,
+            Self::required_usage(),
+        )?;
+        let src_view = cube_view(src_texture);
+        let mip_count = dst_texture_descriptor.mip_level_count;
+        for mip in 0..mip_count {
+            let roughness = level_roughness(mip, mip_count);
+            // A mirror-like level only needs the one sample in the reflected direction
+            let sample_count = if roughness == 0.0 { 1 } else { SAMPLE_COUNT };
+            self.pipeline.dispatch(
+                device,
+                encoder,
+                &src_view,
+                dst_texture,
+                dst_texture_descriptor,
+                mip,
+                &[
+                    roughness.to_bits(),
+                    sample_count,
+                    (src_texture_descriptor.size.width as f32).to_bits(),
+                    0,
+                ],
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The sampler, layout and pipeline of a shader writing one level of an environment cubemap.
+/// Its bindings are a source cubemap and its sampler, every face of the destination level, and
+/// a uniform buffer of parameters.
+#[derive(Debug)]
+pub(crate) struct EnvironmentPipeline {
+    sampler: Sampler,
+    layout: BindGroupLayout,
+    pipeline: ComputePipeline,
+}
+
+impl EnvironmentPipeline {
+    pub(crate) fn new(device: &Device, name: &str, spirv: &[u8]) -> Self {
+        // Trilinear filtering, so samples can read the source level matching their footprint
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some(&format!("wgpu-mipmap-{}-sampler", name)),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let module = device.create_shader_module(&ShaderModuleDescriptor {
+            label: None,
+            source: make_spirv(spirv),
+            flags: ShaderFlags::empty(),
+        });
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some(&format!("wgpu-mipmap-{}-bg-layout", name)),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStage::COMPUTE,
+                    ty: BindingType::Texture {
+                        view_dimension: TextureViewDimension::Cube,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStage::COMPUTE,
+                    ty: BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStage::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: PREFILTERED_ENV_MAP_FORMAT,
+                        view_dimension: TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStage::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some(&format!("wgpu-mipmap-{}-pipeline", name)),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: "main",
+        });
+        Self {
+            sampler,
+            layout,
+            pipeline,
+        }
+    }
+
+    /// Records a dispatch writing every face of level `mip` of `dst_texture` from `src_view`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn dispatch(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_view: &TextureView,
+        dst_texture: &Texture,
+        dst_texture_descriptor: &TextureDescriptor,
+        mip: u32,
+        params: &[u32],
+    ) {
+        let dst_view = dst_texture.create_view(&TextureViewDescriptor {
+            label: None,
+            format: None,
+            dimension: Some(TextureViewDimension::D2Array),
+            aspect: TextureAspect::All,
+            base_mip_level: mip,
+            level_count: NonZeroU32::new(1),
+            base_array_layer: 0,
+            array_layer_count: NonZeroU32::new(CUBE_FACE_COUNT),
+        });
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(params),
+            usage: BufferUsage::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(src_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&dst_view),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::Buffer {
+                        buffer: &params,
+                        offset: 0,
+                        size: None,
+                    },
+                },
+            ],
+        });
+        let mip_ext = get_mip_extent(&dst_texture_descriptor.size, mip);
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch(
+            mip_ext.width.div_ceil(WORKGROUP_SIZE),
+            mip_ext.height.div_ceil(WORKGROUP_SIZE),
+            CUBE_FACE_COUNT,
+        );
+    }
+}
+
+/// Returns a cube view of every level of `texture`.
+pub(crate) fn cube_view(texture: &Texture) -> TextureView {
+    texture.create_view(&TextureViewDescriptor {
+        label: None,
+        format: None,
+        dimension: Some(TextureViewDimension::Cube),
+        aspect: TextureAspect::All,
+        base_mip_level: 0,
+        level_count: None,
+        base_array_layer: 0,
+        array_layer_count: NonZeroU32::new(CUBE_FACE_COUNT),
+    })
+}
+
+/// Checks the source and destination cubemaps of an [`EnvironmentPipeline`].
+pub(crate) fn validate_environment_textures(
+    src_texture_descriptor: &TextureDescriptor,
+    dst_texture_descriptor: &TextureDescriptor,
+    dst_usage: TextureUsage,
+) -> Result<(), Error> {
+    validate_cubemap(src_texture_descriptor)?;
+    validate_cubemap(dst_texture_descriptor)?;
+    if !src_texture_descriptor.usage.contains(TextureUsage::SAMPLED) {
+        return Err(Error::UnsupportedUsage(src_texture_descriptor.usage));
+    }
+    let src_format = src_texture_descriptor.format;
+    if !is_supported_source_format(src_format) {
+        return Err(Error::UnsupportedFormat(src_format));
+    }
+    if dst_texture_descriptor.format != PREFILTERED_ENV_MAP_FORMAT {
+        return Err(Error::UnsupportedFormat(dst_texture_descriptor.format));
+    }
+    if !dst_texture_descriptor.usage.contains(dst_usage) {
+        return Err(Error::UnsupportedUsage(dst_texture_descriptor.usage));
+    }
+    Ok(())
+}
+
+/// Returns the GGX roughness level `mip` of a chain of `mip_count` levels is filtered with.
+pub(crate) fn level_roughness(mip: u32, mip_count: u32) -> f32 {
+    if mip_count <= 1 {
+        0.0
+    } else {
+        mip as f32 / (mip_count - 1) as f32
+    }
+}
+
+fn validate_cubemap(texture_descriptor: &TextureDescriptor) -> Result<(), Error> {
+    let Extent3d {
+        width,
+        height,
+        depth,
+    } = texture_descriptor.size;
+    if texture_descriptor.dimension != TextureDimension::D2 {
+        return Err(Error::UnsupportedDimension(texture_descriptor.dimension));
+    }
+    if width != height || depth != CUBE_FACE_COUNT {
+        return Err(Error::NotACubemap(texture_descriptor.size));
+    }
+    Ok(())
+}
+
+// Uncompressed formats that can be filtered by the sampler
+fn is_supported_source_format(format: TextureFormat) -> bool {
+    matches!(
+        format,
+        TextureFormat::R8Unorm
+            | TextureFormat::R8Snorm
+            | TextureFormat::R16Float
+            | TextureFormat::Rg8Unorm
+            | TextureFormat::Rg8Snorm
+            | TextureFormat::Rg16Float
+            | TextureFormat::Rgba8Unorm
+            | TextureFormat::Rgba8UnormSrgb
+            | TextureFormat::Rgba8Snorm
+            | TextureFormat::Bgra8Unorm
+            | TextureFormat::Bgra8UnormSrgb
+            | TextureFormat::Rgb10a2Unorm
+            | TextureFormat::Rg11b10Float
+            | TextureFormat::Rgba16Float
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn cubemap_descriptor(size: u32, depth: u32) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            size: Extent3d {
+                width: size,
+                height: size,
+                depth,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format: TextureFormat::Rgba16Float,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: TextureUsage::SAMPLED,
+            label: None,
+        }
+    }
+
+    async fn generate_test(src_descriptor: &TextureDescriptor<'_>) -> Result<(), Error> {
+        let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+        let generator = PrefilteredEnvMapGenerator::new(&device);
+        let src = device.create_texture(src_descriptor);
+        let (dst, dst_descriptor) =
+            PrefilteredEnvMapGenerator::create_destination_texture(&device, src_descriptor);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        generator.generate_from(
+            &device,
+            &mut encoder,
+            &src,
+            src_descriptor,
+            &dst,
+            &dst_descriptor,
+        )
+    }
+
+    #[test]
+    fn level_roughness_spans_chain() {
+        assert_eq!(level_roughness(0, 1), 0.0);
+        assert_eq!(level_roughness(0, 5), 0.0);
+        assert_eq!(level_roughness(2, 5), 0.5);
+        assert_eq!(level_roughness(4, 5), 1.0);
+    }
+
+    #[test]
+    fn destination_descriptor() {
+        let src = cubemap_descriptor(128, 6);
+        let dst = PrefilteredEnvMapGenerator::destination_descriptor(&src);
+        assert_eq!(dst.size, src.size);
+        assert_eq!(dst.mip_level_count, src.mip_level_count);
+        assert_eq!(dst.format, PREFILTERED_ENV_MAP_FORMAT);
+        assert!(dst
+            .usage
+            .contains(PrefilteredEnvMapGenerator::required_usage()));
+    }
+
+    #[test]
+    fn sanity_check() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let res = generate_test(&cubemap_descriptor(128, 6)).await;
+            assert!(res.is_ok());
+        });
+    }
+
+    #[test]
+    fn not_a_cubemap() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let descriptor = cubemap_descriptor(128, 1);
+        futures::executor::block_on(async {
+            let res = generate_test(&descriptor).await;
+            assert!(res.err() == Some(Error::NotACubemap(descriptor.size)));
+        });
+    }
+}
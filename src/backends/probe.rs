@@ -0,0 +1,164 @@
+use crate::{
+    backends::{srgb::ProbeKey, ComputeMipmapGenerator, PipelineCache, SrgbStorageConversion},
+    core::*,
+    util::generate_and_copy_to_cpu,
+};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+use wgpu::{Adapter, Device, Extent3d, Queue, TextureDescriptor, TextureDimension, TextureFormat};
+
+/// How an implementation rounds the floats a shader stores to 8 bit unorm texels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnormRounding {
+    /// Rounded to the nearest representable value.
+    Nearest,
+    /// Truncated, which darkens each level by up to one step.
+    TowardZero,
+}
+
+/// The implementation-defined behavior of a device that affects the levels generated on it,
+/// measured by [`DeviceBehavior::probe`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceBehavior {
+    pub srgb_conversion: SrgbStorageConversion,
+    pub unorm_rounding: UnormRounding,
+}
+
+impl DeviceBehavior {
+    /// Measures the behavior of `device` by probing its srgb storage conversion with
+    /// [`SrgbStorageConversion::probe`], then generating a 4x4 `Rgba8Unorm` texture whose
+    /// second level averages to a fraction of a step and reading it back. The result is
+    /// cached per adapter for the lifetime of the process.
+    ///
+    /// Unlike readbacks, the returned future polls `device` until the probe completes.
+    pub async fn probe(adapter: &Adapter, device: &Device, queue: &Queue) -> Result<Self, Error> {
+        static PROBED: OnceLock<Mutex<HashMap<ProbeKey, DeviceBehavior>>> = OnceLock::new();
+        let key = ProbeKey::new(&adapter.get_info());
+        let probed = PROBED.get_or_init(Default::default);
+        if let Some(&behavior) = probed.lock().unwrap().get(&key) {
+            return Ok(behavior);
+        }
+        let behavior = Self {
+            srgb_conversion: SrgbStorageConversion::probe(adapter, device, queue).await?,
+            unorm_rounding: probe_unorm_rounding(device, queue).await?,
+        };
+        probed.lock().unwrap().insert(key, behavior);
+        Ok(behavior)
+    }
+
+    /// Returns the largest per channel difference, in steps of 1/255, expected between level
+    /// `level` of a texture with `format` generated on the device and a reference box filter
+    /// that rounds each level to nearest, or `None` if `format` doesn't have 8 bit unorm
+    /// channels.
+    pub fn tolerance(&self, format: TextureFormat, level: u32) -> Option<u8> {
+        let srgb = match format {
+            TextureFormat::R8Unorm
+            | TextureFormat::Rg8Unorm
+            | TextureFormat::Rgba8Unorm
+            | TextureFormat::Bgra8Unorm => false,
+            TextureFormat::Rgba8UnormSrgb | TextureFormat::Bgra8UnormSrgb => true,
+            _ => return None,
+        };
+        // Ties and float precision
+        let mut tolerance = 1u8;
+        if self.unorm_rounding == UnormRounding::TowardZero {
+            // Each level truncates the already truncated level above it
+            tolerance = tolerance.saturating_add(level.min(u8::MAX as u32) as u8);
+        }
+        if srgb && self.srgb_conversion != SrgbStorageConversion::OnLoadAndStore {
+            // The transfer function is evaluated by the shader rather than the hardware
+            tolerance = tolerance.saturating_add(1);
+        }
+        Some(tolerance)
+    }
+}
+
+async fn probe_unorm_rounding(device: &Device, queue: &Queue) -> Result<UnormRounding, Error> {
+    let format = TextureFormat::Rgba8Unorm;
+    let texture_descriptor = TextureDescriptor {
+        label: Some("wgpu-mipmap-rounding-probe"),
+        size: Extent3d {
+            width: 4,
+            height: 4,
+            depth: 1,
+        },
+        mip_level_count: 2,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: ComputeMipmapGenerator::required_usage(),
+    };
+    // The top left 2x2 footprint averages to 0.75 steps
+    let mut data = vec![0u8; 4 * 4 * 4];
+    for &(x, y) in &[(1, 0), (0, 1), (1, 1)] {
+        data[(y * 4 + x) * 4] = 1;
+    }
+    let generator = ComputeMipmapGenerator::new_with_cache(
+        device,
+        &[format],
+        Default::default(),
+        &PipelineCache::new(),
+    );
+    let mip_buffers =
+        generate_and_copy_to_cpu(device, queue, &generator, &data, &texture_descriptor).await?;
+    Ok(unorm_rounding_from_probe(mip_buffers[1].buffer[0]))
+}
+
+fn unorm_rounding_from_probe(stored: u8) -> UnormRounding {
+    if stored == 0 {
+        UnormRounding::TowardZero
+    } else {
+        UnormRounding::Nearest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    #[test]
+    fn unorm_rounding() {
+        assert_eq!(unorm_rounding_from_probe(1), UnormRounding::Nearest);
+        assert_eq!(unorm_rounding_from_probe(0), UnormRounding::TowardZero);
+    }
+
+    #[test]
+    fn tolerance() {
+        let nearest = DeviceBehavior {
+            srgb_conversion: SrgbStorageConversion::None,
+            unorm_rounding: UnormRounding::Nearest,
+        };
+        assert_eq!(nearest.tolerance(TextureFormat::Rgba8Unorm, 5), Some(1));
+        assert_eq!(nearest.tolerance(TextureFormat::Rgba8UnormSrgb, 5), Some(2));
+        assert_eq!(nearest.tolerance(TextureFormat::Rgba16Float, 5), None);
+        let truncating = DeviceBehavior {
+            srgb_conversion: SrgbStorageConversion::OnLoadAndStore,
+            unorm_rounding: UnormRounding::TowardZero,
+        };
+        assert_eq!(truncating.tolerance(TextureFormat::R8Unorm, 3), Some(4));
+        assert_eq!(
+            truncating.tolerance(TextureFormat::Bgra8UnormSrgb, 3),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn probe_is_cached() {
+        if !adapter_available() {
+            return;
+        }
+        futures::executor::block_on(async {
+            let (_instance, adapter, device, queue) = wgpu_setup().await;
+            let first = DeviceBehavior::probe(&adapter, &device, &queue)
+                .await
+                .unwrap();
+            let second = DeviceBehavior::probe(&adapter, &device, &queue)
+                .await
+                .unwrap();
+            assert_eq!(first, second);
+        });
+    }
+}
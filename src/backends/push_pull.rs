@@ -0,0 +1,266 @@
+use crate::{
+    backends::masked::{
+        bind_group_layout, create_pipeline, dispatch, shader_for_format, MaskedMipmapGenerator,
+        MaskedPass, MASK_FORMAT,
+    },
+    core::*,
+    util::create_mip_level_view,
+};
+use std::collections::HashMap;
+use wgpu::{
+    BindGroupLayout, CommandEncoder, ComputePipeline, Device, StorageTextureAccess, Texture,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsage,
+};
+
+/// Fills the holes of sparse textures, such as reprojected frames or scattered samples, with
+/// the push-pull algorithm.
+///
+/// The push step builds a mip chain of the valid texels, marked by a [`MASK_FORMAT`] coverage
+/// mask, as [`MaskedMipmapGenerator`] does. The pull step then fills the holes of each level
+/// from the coarsest up with the bilinearly upsampled level below it, and finally writes the
+/// base level, with its holes filled, to a destination texture.
+#[derive(Debug)]
+pub struct PushPullGenerator {
+    masked: MaskedMipmapGenerator,
+    fill_layout_cache: HashMap<TextureFormat, BindGroupLayout>,
+    fill_pipeline_cache: HashMap<TextureFormat, ComputePipeline>,
+}
+
+impl PushPullGenerator {
+    /// Returns the texture usage `PushPullGenerator` requires of the source texture, its mask
+    /// and the destination texture.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::STORAGE
+    }
+
+    /// Creates a new `PushPullGenerator`. Once created, it can be used repeatedly to fill
+    /// textures with any format specified in `format_hints`.
+    pub fn new_with_format_hints(device: &Device, format_hints: &[TextureFormat]) -> Self {
+        let mut masked = MaskedMipmapGenerator::new_with_format_hints(device, format_hints);
+        masked.set_dilation(true);
+        let mut fill_layout_cache = HashMap::new();
+        let mut fill_pipeline_cache = HashMap::new();
+        for &format in format_hints {
+            if let Some(module) = shader_for_format(device, format, MaskedPass::Fill) {
+                let layout = bind_group_layout(
+                    device,
+                    &[
+                        (StorageTextureAccess::ReadOnly, format),
+                        (StorageTextureAccess::ReadOnly, MASK_FORMAT),
+                        (StorageTextureAccess::ReadOnly, format),
+                        (StorageTextureAccess::WriteOnly, format),
+                    ],
+                );
+                let pipeline = create_pipeline(device, &module, &layout, "fill", format);
+                fill_layout_cache.insert(format, layout);
+                fill_pipeline_cache.insert(format, pipeline);
+            }
+        }
+        Self {
+            masked,
+            fill_layout_cache,
+            fill_pipeline_cache,
+        }
+    }
+
+    /// Returns a descriptor for a destination texture matching `src_texture_descriptor`: same
+    /// size and format, a single level, and usage suitable for filling and sampling.
+    pub fn destination_descriptor(
+        src_texture_descriptor: &TextureDescriptor,
+    ) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-push-pull"),
+            size: src_texture_descriptor.size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: src_texture_descriptor.format,
+            usage: Self::required_usage() | TextureUsage::SAMPLED | TextureUsage::COPY_SRC,
+        }
+    }
+
+    /// Creates a texture described by [`PushPullGenerator::destination_descriptor`].
+    pub fn create_destination_texture(
+        device: &Device,
+        src_texture_descriptor: &TextureDescriptor,
+    ) -> (Texture, TextureDescriptor<'static>) {
+        let descriptor = Self::destination_descriptor(src_texture_descriptor);
+        (device.create_texture(&descriptor), descriptor)
+    }
+
+    /// Encodes commands to write level 0 of `dst_texture` from level 0 of `src_texture`, with
+    /// the texels marked invalid by `mask_texture` filled.
+    ///
+    /// Expectations:
+    /// - `src_texture` and `mask_texture` meet the expectations of
+    ///   [`MaskedMipmapGenerator::generate_with_mask`]. Their levels after the base level are
+    ///   used as scratch space and overwritten. Holes are filled from the levels of the
+    ///   texture, so it should have a full mip chain.
+    /// - `dst_texture` has the same size and format as `src_texture`, and
+    ///   `TextureUsage::STORAGE`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate_from(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_texture: &Texture,
+        src_texture_descriptor: &TextureDescriptor,
+        mask_texture: &Texture,
+        mask_texture_descriptor: &TextureDescriptor,
+        dst_texture: &Texture,
+        dst_texture_descriptor: &TextureDescriptor,
+    ) -> Result<(), Error> {
+        if dst_texture_descriptor.dimension != TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(
+                dst_texture_descriptor.dimension,
+            ));
+        }
+        if !dst_texture_descriptor
+            .usage
+            .contains(Self::required_usage())
+        {
+            return Err(Error::UnsupportedUsage(dst_texture_descriptor.usage));
+        }
+        if src_texture_descriptor.size != dst_texture_descriptor.size {
+            return Err(Error::ExtentMismatch);
+        }
+        let format = src_texture_descriptor.format;
+        if dst_texture_descriptor.format != format {
+            return Err(Error::UnsupportedFormat(dst_texture_descriptor.format));
+        }
+        let layout = self
+            .fill_layout_cache
+            .get(&format)
+            .ok_or(Error::UnknownFormat(format))?;
+        let pipeline = self
+            .fill_pipeline_cache
+            .get(&format)
+            .ok_or(Error::UnknownFormat(format))?;
+        self.masked.generate_with_mask(
+            device,
+            encoder,
+            src_texture,
+            src_texture_descriptor,
+            mask_texture,
+            mask_texture_descriptor,
+            &Default::default(),
+        )?;
+        // A single level texture has nothing coarser to fill from, so its holes are filled
+        // from its own neighbors
+        let coarse_level = 1.min(src_texture_descriptor.mip_level_count - 1);
+        dispatch(
+            device,
+            encoder,
+            layout,
+            pipeline,
+            &[
+                &create_mip_level_view(src_texture, 0),
+                &create_mip_level_view(mask_texture, 0),
+                &create_mip_level_view(src_texture, coarse_level),
+                &create_mip_level_view(dst_texture, 0),
+            ],
+            dst_texture_descriptor.size,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn descriptor(size: u32) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format: TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: TextureUsage::STORAGE | TextureUsage::COPY_DST,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn fills_holes() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        // Every fourth texel of each row and column is a valid sample with value 200
+        let size = 32;
+        let src_descriptor = descriptor(size);
+        let valid = |id: u32| id.is_multiple_of(4) && (id / size).is_multiple_of(4);
+        let data = (0..size * size)
+            .flat_map(|id| {
+                if valid(id) {
+                    vec![200u8; 4]
+                } else {
+                    vec![0u8; 4]
+                }
+            })
+            .collect::<Vec<_>>();
+        let mask_data = (0..size * size)
+            .map(|id| if valid(id) { 255u8 } else { 0 })
+            .collect::<Vec<_>>();
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator =
+                PushPullGenerator::new_with_format_hints(&device, &[src_descriptor.format]);
+            let src = device.create_texture(&src_descriptor);
+            let (mask, mask_descriptor) =
+                MaskedMipmapGenerator::create_mask_texture(&device, &src_descriptor);
+            let (dst, dst_descriptor) =
+                PushPullGenerator::create_destination_texture(&device, &src_descriptor);
+            for (texture, data, bytes_per_texel) in &[(&src, &data, 4), (&mask, &mask_data, 1)] {
+                queue.write_texture(
+                    wgpu::TextureCopyView {
+                        texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d::ZERO,
+                    },
+                    data,
+                    wgpu::TextureDataLayout {
+                        offset: 0,
+                        bytes_per_row: bytes_per_texel * size,
+                        rows_per_image: size,
+                    },
+                    src_descriptor.size,
+                );
+            }
+            let mut encoder = device.create_command_encoder(&Default::default());
+            generator
+                .generate_from(
+                    &device,
+                    &mut encoder,
+                    &src,
+                    &src_descriptor,
+                    &mask,
+                    &mask_descriptor,
+                    &dst,
+                    &dst_descriptor,
+                )
+                .unwrap();
+            let readback = MipReadback::encode(&device, &mut encoder, &dst, &dst_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let mips = readback.read();
+            device.poll(wgpu::Maintain::Wait);
+            let mips = mips.await.unwrap();
+            // Every hole takes the value of the samples around it
+            for texel in mips[0].as_slice::<[u8; 4]>() {
+                for channel in texel {
+                    assert!((*channel as i32 - 200).abs() <= 1, "{:?}", texel);
+                }
+            }
+        });
+    }
+}
@@ -1,15 +1,63 @@
-use super::{compute::*, copy::*, render::*};
-use crate::core::*;
+use super::{cache::*, compute::*, copy::*, packed::*, render::*};
+use crate::{
+    core::*,
+    stats::{EncodeTimer, StatsCollector},
+    trace::{event, span},
+};
+use std::sync::Arc;
 
-/// Generates mipmaps for textures with any usage using the compute, render, or copy backends.
-#[derive(Debug)]
+/// Generates mipmaps for textures with any usage using the compute, render, copy or packed
+/// backends.
+#[derive(Debug, Clone)]
 pub struct RecommendedMipmapGenerator {
     render: RenderMipmapGenerator,
     compute: ComputeMipmapGenerator,
+    packed: PackedMipmapGenerator,
+    // Whether the device can create storage textures beyond the formats WebGPU guarantees
+    adapter_specific_formats: bool,
+    strict: bool,
+    stats: Option<Arc<StatsCollector>>,
+    // Backends tests forced to fail, to cover the fallback chain on any device
+    #[cfg(test)]
+    failing: Vec<Backend>,
+    // The copies the compute-copy fallback encoded, so tests can check failed attempts
+    // leave the encoder untouched
+    #[cfg(test)]
+    compute_copies: Arc<std::sync::atomic::AtomicU32>,
+}
+
+/// The backends [`RecommendedMipmapGenerator`] tries, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Compute,
+    Render,
+    Copy,
+    ComputeCopy,
+    Packed,
+}
+
+/// Why [`RecommendedMipmapGenerator::try_new_with_format_hints`] can't generate mipmaps for a
+/// format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsupportedFormatReason {
+    /// Block-compressed formats can't be written by the compute, render or copy backends. See
+    /// `CompressedMipmapGenerator` for BC1 and BC3 textures, or generate the mipmaps of the
+    /// uncompressed texture, then compress every level.
+    Compressed,
+    /// Depth and stencil formats can't be written by any backend. See `MinMaxMipmapGenerator`
+    /// for depth pyramids.
+    Depth,
+    /// No backend created pipelines for the format, e.g. because its cargo feature is
+    /// disabled and it can't be rendered to.
+    NoPipelines,
+    /// The device can't write the format from a compute shader, and no other backend can
+    /// write it either. See [`StorageSupport`]. `Rg11b10Float`, which some adapters can't
+    /// write from a compute shader, is written by [`PackedMipmapGenerator`] instead.
+    StorageUnsupported,
 }
 
-/// A list of supported texture formats.
-const SUPPORTED_FORMATS: [wgpu::TextureFormat; 17] = {
+/// A list of supported texture formats. Integer formats are point sampled.
+const SUPPORTED_FORMATS: [wgpu::TextureFormat; 35] = {
     use wgpu::TextureFormat;
     [
         TextureFormat::R8Unorm,
@@ -29,6 +77,24 @@ const SUPPORTED_FORMATS: [wgpu::TextureFormat; 17] = {
         TextureFormat::Rg32Float,
         TextureFormat::Rgba16Float,
         TextureFormat::Rgba32Float,
+        TextureFormat::R8Uint,
+        TextureFormat::R8Sint,
+        TextureFormat::R16Uint,
+        TextureFormat::R16Sint,
+        TextureFormat::Rg8Uint,
+        TextureFormat::Rg8Sint,
+        TextureFormat::R32Uint,
+        TextureFormat::R32Sint,
+        TextureFormat::Rg16Uint,
+        TextureFormat::Rg16Sint,
+        TextureFormat::Rgba8Uint,
+        TextureFormat::Rgba8Sint,
+        TextureFormat::Rg32Uint,
+        TextureFormat::Rg32Sint,
+        TextureFormat::Rgba16Uint,
+        TextureFormat::Rgba16Sint,
+        TextureFormat::Rgba32Uint,
+        TextureFormat::Rgba32Sint,
     ]
 };
 
@@ -39,62 +105,495 @@ impl RecommendedMipmapGenerator {
         Self::new_with_format_hints(device, &SUPPORTED_FORMATS)
     }
 
+    /// Creates a new `RecommendedMipmapGenerator` whose compute backend uses the workgroup
+    /// size [`WorkgroupSize::for_adapter`] picks for the adapter described by `adapter_info`.
+    pub fn new_for_adapter(device: &wgpu::Device, adapter_info: &wgpu::AdapterInfo) -> Self {
+        let workgroup_size = WorkgroupSize::for_adapter(adapter_info);
+        event!(
+            DEBUG,
+            { adapter = adapter_info.name, workgroup_size = workgroup_size },
+            "selected compute workgroup size"
+        );
+        Self::new_with_workgroup_size(device, &SUPPORTED_FORMATS, workgroup_size)
+    }
+
     /// Creates a new `RecommendedMipmapGenerator`. Once created, it can be used repeatedly to
     /// generate mipmaps for any texture with format specified in `format_hints`.
     pub fn new_with_format_hints(
         device: &wgpu::Device,
         format_hints: &[wgpu::TextureFormat],
     ) -> Self {
-        for format in format_hints {
-            if !SUPPORTED_FORMATS.contains(&format) {
-                log::warn!("[RecommendedMipmapGenerator::new] No support for requested texture format {:?}", *format);
-                log::warn!("[RecommendedMipmapGenerator::new] Attempting to continue, but calls to generate may fail or produce unexpected results.");
-                continue;
-            }
+        Self::new_with_workgroup_size(device, format_hints, WorkgroupSize::default())
+    }
+
+    /// Like [`RecommendedMipmapGenerator::new_for_adapter`], but also queries the storage
+    /// support of each format in `format_hints` from `adapter`, see
+    /// [`ComputeMipmapGenerator::new_with_adapter`]. Textures whose format the adapter can't
+    /// write from a compute shader are generated by the render or copy backend.
+    pub fn new_with_adapter(
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        format_hints: &[wgpu::TextureFormat],
+    ) -> Self {
+        let cache = PipelineCache::new();
+        let workgroup_size = WorkgroupSize::for_adapter(&adapter.get_info());
+        Self::from_backends(
+            device,
+            format_hints,
+            RenderMipmapGenerator::new_with_cache(device, format_hints, &cache),
+            ComputeMipmapGenerator::new_with_adapter(
+                adapter,
+                device,
+                format_hints,
+                workgroup_size,
+                &cache,
+            ),
+        )
+    }
+
+    /// Creates a new `RecommendedMipmapGenerator` whose compute backend uses `workgroup_size`.
+    pub fn new_with_workgroup_size(
+        device: &wgpu::Device,
+        format_hints: &[wgpu::TextureFormat],
+        workgroup_size: WorkgroupSize,
+    ) -> Self {
+        Self::new_with_cache(device, format_hints, workgroup_size, &PipelineCache::new())
+    }
+
+    /// Like [`RecommendedMipmapGenerator::new_with_workgroup_size`], but reuses the shader
+    /// modules, samplers and pipelines in `cache`, and adds the ones it creates to it. Creating
+    /// a generator per thread from one cache compiles each pipeline once.
+    pub fn new_with_cache(
+        device: &wgpu::Device,
+        format_hints: &[wgpu::TextureFormat],
+        workgroup_size: WorkgroupSize,
+        cache: &PipelineCache,
+    ) -> Self {
+        let render = RenderMipmapGenerator::new_with_cache(device, format_hints, cache);
+        let compute =
+            ComputeMipmapGenerator::new_with_cache(device, format_hints, workgroup_size, cache);
+        Self::from_backends(device, format_hints, render, compute)
+    }
+
+    fn from_backends(
+        device: &wgpu::Device,
+        format_hints: &[wgpu::TextureFormat],
+        render: RenderMipmapGenerator,
+        compute: ComputeMipmapGenerator,
+    ) -> Self {
+        let adapter_specific_formats = device
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        let generator = Self {
+            render,
+            compute,
+            packed: PackedMipmapGenerator::new_with_format_hints(device, format_hints),
+            adapter_specific_formats,
+            strict: false,
+            stats: None,
+            #[cfg(test)]
+            failing: Vec::new(),
+            #[cfg(test)]
+            compute_copies: Arc::default(),
+        };
+        for (format, reason) in generator.unsupported_formats(format_hints) {
+            event!(
+                WARN,
+                { format = format, reason = reason, backend = "recommended" },
+                "unsupported format; calls to generate may fail or produce unexpected results"
+            );
         }
-        let render = RenderMipmapGenerator::new_with_format_hints(device, format_hints);
-        let compute = ComputeMipmapGenerator::new_with_format_hints(device, format_hints);
-        Self { render, compute }
+        generator
+    }
+
+    /// Like [`RecommendedMipmapGenerator::new_with_format_hints`], but returns every format in
+    /// `format_hints` that can't be generated, and why, instead of only logging a warning, so
+    /// applications can fail fast or change how they import textures.
+    pub fn try_new_with_format_hints(
+        device: &wgpu::Device,
+        format_hints: &[wgpu::TextureFormat],
+    ) -> Result<Self, Vec<(wgpu::TextureFormat, UnsupportedFormatReason)>> {
+        let generator = Self::new_with_format_hints(device, format_hints);
+        let unsupported = generator.unsupported_formats(format_hints);
+        if unsupported.is_empty() {
+            Ok(generator)
+        } else {
+            Err(unsupported)
+        }
+    }
+
+    /// Returns the formats in `format_hints` neither backend can generate mipmaps for.
+    fn unsupported_formats(
+        &self,
+        format_hints: &[wgpu::TextureFormat],
+    ) -> Vec<(wgpu::TextureFormat, UnsupportedFormatReason)> {
+        format_hints
+            .iter()
+            .filter_map(|&format| {
+                let description = format.describe();
+                let reason = if description.block_dimensions != (1, 1) {
+                    UnsupportedFormatReason::Compressed
+                } else if description.sample_type == wgpu::TextureSampleType::Depth {
+                    UnsupportedFormatReason::Depth
+                } else if self.compute.compute_pipeline_for(format).is_none()
+                    && self.render.render_pipeline_for(format).is_none()
+                    && self.packed.pipeline_for(format).is_none()
+                {
+                    if self.compute.skipped_for_storage(format) {
+                        UnsupportedFormatReason::StorageUnsupported
+                    } else {
+                        UnsupportedFormatReason::NoPipelines
+                    }
+                } else {
+                    return None;
+                };
+                Some((format, reason))
+            })
+            .collect()
+    }
+
+    /// Returns the device features to request so the compute backend, rather than a render
+    /// or copy fallback, can generate mipmaps for textures with formats in `format_hints`.
+    /// See [`ComputeMipmapGenerator::required_features`]. The render and copy backends need
+    /// no features.
+    pub fn required_features(format_hints: &[wgpu::TextureFormat]) -> wgpu::Features {
+        ComputeMipmapGenerator::required_features(format_hints)
+    }
+
+    /// Returns the device limits to request so every backend can use all of its code paths.
+    /// See [`ComputeMipmapGenerator::required_limits`].
+    pub fn required_limits() -> wgpu::Limits {
+        ComputeMipmapGenerator::required_limits()
+    }
+
+    /// Returns the workgroup size used by the compute backend.
+    pub fn compute_workgroup_size(&self) -> WorkgroupSize {
+        self.compute.workgroup_size()
+    }
+
+    /// Returns true if falling back from the compute backend is an error.
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Sets whether falling back from the compute backend is an error. In strict mode,
+    /// `generate` and `estimate_cost` return `Error::FallbackRequired` with the compute
+    /// backend's error instead of trying the render and copy backends, so misconfigured
+    /// textures, e.g. missing `TextureUsage::STORAGE`, are caught in debug builds and CI.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Returns the collector counting the work of `generate`, if any.
+    pub fn stats_collector(&self) -> Option<&Arc<StatsCollector>> {
+        self.stats.as_ref()
+    }
+
+    /// Sets the collector counting the textures, levels and bytes `generate` writes, the
+    /// fallbacks it takes from the compute backend and the time it spends encoding. Pass
+    /// `None` to stop counting.
+    pub fn set_stats_collector(&mut self, stats: Option<Arc<StatsCollector>>) {
+        self.stats = stats;
+    }
+
+    /// Forces `backend` to fail every texture with `Error::UnsupportedUsage`, as if the
+    /// device lacked the capabilities it needs.
+    #[cfg(test)]
+    fn fail_backend(&mut self, backend: Backend) {
+        self.failing.push(backend);
+    }
+
+    /// Returns the result of `f`, which runs `backend`, unless a test forced it to fail.
+    #[cfg(test)]
+    fn attempt<T>(
+        &self,
+        backend: Backend,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        f: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        if self.failing.contains(&backend) {
+            return Err(Error::UnsupportedUsage(texture_descriptor.usage));
+        }
+        f()
+    }
+
+    /// Returns the result of `f`, which runs `backend`.
+    #[cfg(not(test))]
+    #[inline]
+    fn attempt<T>(
+        &self,
+        _backend: Backend,
+        _texture_descriptor: &wgpu::TextureDescriptor,
+        f: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        f()
     }
 }
 
-impl MipmapGenerator for RecommendedMipmapGenerator {
-    fn generate(
+impl RecommendedMipmapGenerator {
+    /// Generates mipmaps with the compute backend in a temporary storage texture holding the
+    /// source level and the generated levels, then copies the generated levels back. This
+    /// serves textures that can be copied but neither bound as storage nor rendered to.
+    fn generate_compute_copy(
         &self,
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
         texture: &wgpu::Texture,
         texture_descriptor: &wgpu::TextureDescriptor,
+        options: &GenerateOptions,
     ) -> Result<(), Error> {
-        // compute backend
-        match self
+        let (tmp_descriptor, tmp_options, mip_levels) =
+            self.compute_copy_descriptor(texture_descriptor, options)?;
+        if mip_levels.start == mip_levels.end {
+            return Ok(());
+        }
+        // Validate before creating the temporary texture or encoding the first copy, so the
+        // next backend is tried on an untouched encoder
+        self.compute
+            .estimate_cost_with_options(&tmp_descriptor, &tmp_options)?;
+        let tmp_texture = device.create_texture(&tmp_descriptor);
+        let base_mip_level = mip_levels.start - 1;
+        // Only the generated layer of the original texture is copied in and back
+        let layer = wgpu::Origin3d {
+            z: options.array_layer,
+            ..wgpu::Origin3d::ZERO
+        };
+        let copy = |encoder: &mut wgpu::CommandEncoder,
+                    src: &wgpu::Texture,
+                    src_level,
+                    dst,
+                    dst_level,
+                    size| {
+            let (src_origin, dst_origin) = if std::ptr::eq(src, texture) {
+                (layer, wgpu::Origin3d::ZERO)
+            } else {
+                (wgpu::Origin3d::ZERO, layer)
+            };
+            #[cfg(test)]
+            self.compute_copies
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            encoder.copy_texture_to_texture(
+                wgpu::TextureCopyView {
+                    texture: src,
+                    mip_level: src_level,
+                    origin: src_origin,
+                },
+                wgpu::TextureCopyView {
+                    texture: dst,
+                    mip_level: dst_level,
+                    origin: dst_origin,
+                },
+                size,
+            );
+        };
+        copy(
+            encoder,
+            texture,
+            base_mip_level,
+            &tmp_texture,
+            0,
+            tmp_descriptor.size,
+        );
+        self.compute.generate_with_options(
+            device,
+            encoder,
+            &tmp_texture,
+            &tmp_descriptor,
+            &tmp_options,
+        )?;
+        for mip_level in mip_levels {
+            let tmp_level = mip_level - base_mip_level;
+            let size = crate::util::get_mip_extent(&tmp_descriptor.size, tmp_level);
+            copy(encoder, &tmp_texture, tmp_level, texture, mip_level, size);
+        }
+        Ok(())
+    }
+
+    fn estimate_compute_copy(
+        &self,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<GenerationCost, Error> {
+        let (tmp_descriptor, tmp_options, mip_levels) =
+            self.compute_copy_descriptor(texture_descriptor, options)?;
+        if mip_levels.start == mip_levels.end {
+            return Ok(GenerationCost::default());
+        }
+        let cost = self
             .compute
-            .generate(device, encoder, texture, texture_descriptor)
+            .estimate_cost_with_options(&tmp_descriptor, &tmp_options)?;
+        // The source level is copied in, and the generated levels copied back
+        let copies =
+            crate::util::level_bytes(texture_descriptor, mip_levels.start - 1..mip_levels.end);
+        Ok(GenerationCost {
+            bytes_written: cost.bytes_written + copies,
+            ..cost
+        })
+    }
+
+    /// Returns the temporary texture and options [`RecommendedMipmapGenerator::generate_compute_copy`]
+    /// generates with, and the levels of the original texture it writes.
+    fn compute_copy_descriptor(
+        &self,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<
+        (
+            wgpu::TextureDescriptor<'static>,
+            GenerateOptions,
+            std::ops::Range<u32>,
+        ),
+        Error,
+    > {
+        let copy_usage = wgpu::TextureUsage::COPY_SRC | wgpu::TextureUsage::COPY_DST;
+        if !texture_descriptor.usage.contains(copy_usage) {
+            return Err(Error::UnsupportedUsage(texture_descriptor.usage));
+        }
+        // Only a single layer is copied in and out, which holds a whole 2D level but a single
+        // slice of a 3D one
+        if texture_descriptor.dimension != wgpu::TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(texture_descriptor.dimension));
+        }
+        // Snorm formats, for one, only have guaranteed storage support as `Rgba8Snorm`
+        let format = texture_descriptor.format;
+        if !self.adapter_specific_formats && !is_storage_format(format) {
+            return Err(Error::UnsupportedFormat(format));
+        }
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        let base_mip_level = mip_levels.start - 1;
+        let tmp_descriptor = wgpu::TextureDescriptor {
+            label: Some("wgpu-mipmap-compute-copy"),
+            size: wgpu::Extent3d {
+                depth: 1,
+                ..crate::util::get_mip_extent(&texture_descriptor.size, base_mip_level)
+            },
+            mip_level_count: mip_levels.end - base_mip_level,
+            sample_count: 1,
+            dimension: texture_descriptor.dimension,
+            format: texture_descriptor.format,
+            usage: ComputeMipmapGenerator::required_usage() | copy_usage,
+        };
+        // The temporary texture holds exactly the levels to generate
+        let tmp_options = GenerateOptions {
+            base_mip_level: 0,
+            min_dimension: 1,
+            max_level_count: None,
+            array_layer: 0,
+            ..*options
+        };
+        Ok((tmp_descriptor, tmp_options, mip_levels))
+    }
+
+    /// Returns the usage of a scratch texture with `format` that the backends able to generate
+    /// `format` can use, besides the copies in and out of it.
+    pub(crate) fn scratch_usage(&self, format: wgpu::TextureFormat) -> wgpu::TextureUsage {
+        let mut usage = wgpu::TextureUsage::COPY_SRC
+            | wgpu::TextureUsage::COPY_DST
+            | wgpu::TextureUsage::SAMPLED;
+        if self.render.render_pipeline_for(format).is_some() {
+            usage |= wgpu::TextureUsage::RENDER_ATTACHMENT;
+        }
+        if self.compute.compute_pipeline_for(format).is_some()
+            && (self.adapter_specific_formats || is_storage_format(format))
         {
+            usage |= wgpu::TextureUsage::STORAGE;
+        }
+        usage
+    }
+}
+
+impl RecommendedMipmapGenerator {
+    /// Tries each backend in turn, counting the backends that failed in `fallbacks`.
+    fn generate_with_fallbacks(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        options: &GenerateOptions,
+        fallbacks: &mut u64,
+    ) -> Result<(), Error> {
+        // compute backend
+        match self.attempt(Backend::Compute, texture_descriptor, || {
+            self.compute.generate_with_options(
+                device,
+                encoder,
+                texture,
+                texture_descriptor,
+                options,
+            )
+        }) {
+            Err(e) if self.strict => return Err(Error::FallbackRequired(Box::new(e))),
             Err(e) => {
-                log::debug!("[RecommendedMipmapGenerator::generate] compute error {}.\n falling back to render backend.", e);
+                *fallbacks += 1;
+                event!(
+                    DEBUG,
+                    { format = texture_descriptor.format, backend = "compute", error = e },
+                    "falling back to the render backend"
+                );
             }
             ok => return ok,
         };
         // render backend
-        match self
-            .render
-            .generate(device, encoder, texture, texture_descriptor)
-        {
+        match self.attempt(Backend::Render, texture_descriptor, || {
+            self.render
+                .generate_with_options(device, encoder, texture, texture_descriptor, options)
+        }) {
             Err(e) => {
-                log::debug!("[RecommendedMipmapGenerator::generate] render error {}.\n falling back to copy backend.", e);
+                *fallbacks += 1;
+                event!(
+                    DEBUG,
+                    { format = texture_descriptor.format, backend = "render", error = e },
+                    "falling back to the copy backend"
+                );
             }
             ok => return ok,
         };
         // copy backend
-        match CopyMipmapGenerator::new(&self.render).generate(
-            device,
-            encoder,
-            texture,
-            texture_descriptor,
-        ) {
+        match self.attempt(Backend::Copy, texture_descriptor, || {
+            CopyMipmapGenerator::new(&self.render).generate_with_options(
+                device,
+                encoder,
+                texture,
+                texture_descriptor,
+                options,
+            )
+        }) {
+            Err(e) => {
+                *fallbacks += 1;
+                event!(
+                    DEBUG,
+                    { format = texture_descriptor.format, backend = "copy", error = e },
+                    "falling back to the compute backend with copies"
+                );
+            }
+            ok => return ok,
+        }
+        // compute backend on a copy, for formats that can't be rendered to
+        match self.attempt(Backend::ComputeCopy, texture_descriptor, || {
+            self.generate_compute_copy(device, encoder, texture, texture_descriptor, options)
+        }) {
+            Err(e) => {
+                *fallbacks += 1;
+                event!(
+                    DEBUG,
+                    { format = texture_descriptor.format, backend = "compute-copy", error = e },
+                    "falling back to the packed backend"
+                );
+            }
+            ok => return ok,
+        }
+        // packed backend, for packed formats that can neither be stored nor rendered to
+        match self.attempt(Backend::Packed, texture_descriptor, || {
+            self.packed
+                .generate_with_options(device, encoder, texture, texture_descriptor, options)
+        }) {
             Err(e) => {
-                log::debug!("[RecommendedMipmapGenerator::generate] copy error {}.", e);
+                event!(
+                    DEBUG,
+                    { format = texture_descriptor.format, backend = "packed", error = e },
+                    "no backend can generate mipmaps"
+                );
             }
             ok => return ok,
         }
@@ -102,6 +601,102 @@ impl MipmapGenerator for RecommendedMipmapGenerator {
     }
 }
 
+/// Returns true if WebGPU guarantees that textures with `format` can be storage textures.
+fn is_storage_format(format: wgpu::TextureFormat) -> bool {
+    format
+        .describe()
+        .guaranteed_format_features
+        .allowed_usages
+        .contains(wgpu::TextureUsage::STORAGE)
+}
+
+impl MipmapGenerator for RecommendedMipmapGenerator {
+    fn generate_with_options(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<(), Error> {
+        let _span = span!("generate_mipmaps", {
+            backend = "recommended",
+            format = texture_descriptor.format,
+            levels = texture_descriptor.mip_level_count,
+            filter = options.filter,
+        });
+        let stats = match &self.stats {
+            Some(stats) => stats,
+            None => {
+                return self.generate_with_fallbacks(
+                    device,
+                    encoder,
+                    texture,
+                    texture_descriptor,
+                    options,
+                    &mut 0,
+                )
+            }
+        };
+        let timer = EncodeTimer::start();
+        let mut fallbacks = 0;
+        let result = self.generate_with_fallbacks(
+            device,
+            encoder,
+            texture,
+            texture_descriptor,
+            options,
+            &mut fallbacks,
+        );
+        stats.record_encode_time(timer.elapsed());
+        stats.record_fallbacks(fallbacks);
+        if result.is_ok() {
+            stats.record_generated(texture_descriptor, options.mip_levels(texture_descriptor)?);
+        }
+        result
+    }
+
+    fn estimate_cost_with_options(
+        &self,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<GenerationCost, Error> {
+        // Estimates with the backend `generate_with_options` would use
+        let compute = self.attempt(Backend::Compute, texture_descriptor, || {
+            self.compute
+                .estimate_cost_with_options(texture_descriptor, options)
+        });
+        if self.strict {
+            return compute.map_err(|e| Error::FallbackRequired(Box::new(e)));
+        }
+        compute
+            .or_else(|_| {
+                self.attempt(Backend::Render, texture_descriptor, || {
+                    self.render
+                        .estimate_cost_with_options(texture_descriptor, options)
+                })
+            })
+            .or_else(|_| {
+                self.attempt(Backend::Copy, texture_descriptor, || {
+                    CopyMipmapGenerator::new(&self.render)
+                        .estimate_cost_with_options(texture_descriptor, options)
+                })
+            })
+            .or_else(|_| {
+                self.attempt(Backend::ComputeCopy, texture_descriptor, || {
+                    self.estimate_compute_copy(texture_descriptor, options)
+                })
+            })
+            .or_else(|_| {
+                self.attempt(Backend::Packed, texture_descriptor, || {
+                    self.packed
+                        .estimate_cost_with_options(texture_descriptor, options)
+                })
+            })
+            .map_err(|_| Error::UnsupportedUsage(texture_descriptor.usage))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -121,13 +716,13 @@ mod tests {
             &device,
             &[texture_descriptor.format],
         );
-        Ok(
-            generate_and_copy_to_cpu(&device, &queue, &generator, buffer, texture_descriptor)
-                .await?,
-        )
+        generate_and_copy_to_cpu(&device, &queue, &generator, buffer, texture_descriptor).await
     }
     #[test]
     fn checkerboard_r8_render() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -176,18 +771,18 @@ mod tests {
                 assert!(width == 1);
                 assert!(height == 1);
                 assert!(data.len() == width * height * bpp);
-                // The final result is implementation dependent
-                // but we expect the pixel to be a perfect
-                // blend of white and black, i.e 255 / 2 = 127.5
-                // Depending on the platform and underlying implementation,
-                // this might round up or down so check 127 and 128
-                assert!(data[0] == 127 || data[0] == 128);
+                // A perfect blend of white and black, i.e 255 / 2 = 127.5, which
+                // BoxRounding::HalfEven rounds to 128 on every implementation
+                assert_eq!(data[0], 128);
             }
         });
     }
 
     #[test]
     fn checkerboard_rgba8_render() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -233,9 +828,7 @@ mod tests {
                 assert!(width == 1);
                 assert!(height == 1);
                 assert!(data.len() == width * height * bpp);
-                assert!(data[0] == 127 || data[0] == 128);
-                assert!(data[1] == 127 || data[1] == 128);
-                assert!(data[2] == 127 || data[2] == 128);
+                assert_eq!(&data[..3], &[128, 128, 128]);
                 assert!(data[3] == 255);
             }
         });
@@ -243,6 +836,9 @@ mod tests {
 
     #[test]
     fn checkerboard_srgba8_render() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -304,6 +900,9 @@ mod tests {
 
     #[test]
     fn checkerboard_r8_compute() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -350,18 +949,18 @@ mod tests {
                 assert!(width == 1);
                 assert!(height == 1);
                 assert!(data.len() == width * height * bpp);
-                // The final result is implementation dependent
-                // but we expect the pixel to be a perfect
-                // blend of white and black, i.e 255 / 2 = 127.5
-                // Depending on the platform and underlying implementation,
-                // this might round up or down so check 127 and 128
-                assert!(data[0] == 127 || data[0] == 128);
+                // A perfect blend of white and black, i.e 255 / 2 = 127.5, which
+                // BoxRounding::HalfEven rounds to 128 on every implementation
+                assert_eq!(data[0], 128);
             }
         });
     }
 
     #[test]
     fn checkerboard_rgba8_compute() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -407,9 +1006,7 @@ mod tests {
                 assert!(width == 1);
                 assert!(height == 1);
                 assert!(data.len() == width * height * bpp);
-                assert!(data[0] == 127 || data[0] == 128);
-                assert!(data[1] == 127 || data[1] == 128);
-                assert!(data[2] == 127 || data[2] == 128);
+                assert_eq!(&data[..3], &[128, 128, 128]);
                 assert!(data[3] == 255);
             }
         });
@@ -417,6 +1014,9 @@ mod tests {
 
     #[test]
     fn checkerboard_srgba8_compute() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -479,6 +1079,9 @@ mod tests {
 
     #[test]
     fn checkerboard_rgba32f_render() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -527,7 +1130,7 @@ mod tests {
                 assert!(width == 1);
                 assert!(height == 1);
                 assert!(data.len() == width * height * bpp);
-                let data: &[f32] = bytemuck::try_cast_slice(data).unwrap();
+                let data: &[f32] = mip.as_slice();
                 dbg!(data);
                 assert!((data[0] - 0.5).abs() < f32::EPSILON);
                 assert!((data[1] - 0.5).abs() < f32::EPSILON);
@@ -539,6 +1142,9 @@ mod tests {
 
     #[test]
     fn checkerboard_rgba32f_compute() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -587,7 +1193,7 @@ mod tests {
                 assert!(width == 1);
                 assert!(height == 1);
                 assert!(data.len() == width * height * bpp);
-                let data: &[f32] = bytemuck::try_cast_slice(data).unwrap();
+                let data: &[f32] = mip.as_slice();
                 dbg!(data);
                 assert!((data[0] - 0.5).abs() < f32::EPSILON);
                 assert!((data[1] - 0.5).abs() < f32::EPSILON);
@@ -599,6 +1205,9 @@ mod tests {
 
     #[test]
     fn checkerboard_rgba8_copy() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -645,11 +1254,1505 @@ mod tests {
                 assert!(width == 1);
                 assert!(height == 1);
                 assert!(data.len() == width * height * bpp);
-                assert!(data[0] == 127 || data[0] == 128);
-                assert!(data[1] == 127 || data[1] == 128);
-                assert!(data[2] == 127 || data[2] == 128);
+                assert_eq!(&data[..3], &[128, 128, 128]);
                 assert!(data[3] == 255);
             }
         });
     }
+
+    // Lightmaps and LUT strips are often far from square, so every backend is
+    // checked against extreme aspect ratios in both orientations
+    fn rectangle_test(
+        width: u32,
+        height: u32,
+        usage: wgpu::TextureUsage,
+        options: GenerateOptions,
+    ) {
+        init();
+        let mip_level_count = 1 + (width.max(height) as f32).log2() as u32;
+        let data = checkerboard_rgba8(width, height, 4);
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage,
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, queue) = wgpu_setup().await;
+            let generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let mipmap_buffers = generate_and_copy_to_cpu_with_options(
+                &device,
+                &queue,
+                &generator,
+                &data,
+                &texture_descriptor,
+                &options,
+            )
+            .await
+            .unwrap();
+            assert_eq!(mipmap_buffers.len(), mip_level_count as usize);
+            let last = mipmap_buffers.last().unwrap();
+            assert_eq!((last.dimensions.width, last.dimensions.height), (1, 1));
+            assert_levels_preserve_mean(&mipmap_buffers, 4.0);
+        });
+    }
+
+    #[test]
+    fn rectangle_compute() {
+        if !adapter_available() {
+            return;
+        }
+        let usage = crate::ComputeMipmapGenerator::required_usage();
+        for &(width, height) in &[(4096, 16), (16, 4096), (2048, 1), (1, 2048)] {
+            rectangle_test(width, height, usage, GenerateOptions::default());
+            rectangle_test(
+                width,
+                height,
+                usage,
+                GenerateOptions {
+                    two_levels_per_pass: true,
+                    tail_combine: true,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn rectangle_render() {
+        if !adapter_available() {
+            return;
+        }
+        let usage = crate::RenderMipmapGenerator::required_usage();
+        for &(width, height) in &[
+            (4096, 16),
+            (16, 4096),
+            (3, 1025),
+            (1025, 3),
+            (1, 1024),
+            (1023, 1),
+        ] {
+            rectangle_test(width, height, usage, GenerateOptions::default());
+        }
+    }
+
+    #[test]
+    fn rectangle_copy() {
+        if !adapter_available() {
+            return;
+        }
+        let usage = crate::CopyMipmapGenerator::required_usage();
+        for &(width, height) in &[
+            (4096, 16),
+            (16, 4096),
+            (3, 1025),
+            (1025, 3),
+            (1, 1024),
+            (1023, 1),
+        ] {
+            rectangle_test(width, height, usage, GenerateOptions::default());
+        }
+    }
+
+    #[test]
+    fn single_level_is_noop() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        // A texture no backend could generate mipmaps for, if it had more than one level
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 513,
+                height: 7,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, _queue) = wgpu_setup().await;
+            let format_hints = [texture_descriptor.format];
+            let compute = ComputeMipmapGenerator::new_with_format_hints(&device, &format_hints);
+            let render = RenderMipmapGenerator::new_with_format_hints(&device, &format_hints);
+            let copy = CopyMipmapGenerator::new(&render);
+            let recommended = RecommendedMipmapGenerator::new_with_format_hints(&device, &[]);
+            let minmax = crate::MinMaxMipmapGenerator::new(&device);
+            let generators: [&dyn MipmapGenerator; 5] =
+                [&compute, &render, &copy, &recommended, &minmax];
+            let texture = device.create_texture(&texture_descriptor);
+            for generator in &generators {
+                let mut encoder = device.create_command_encoder(&Default::default());
+                assert_eq!(
+                    generator.generate(&device, &mut encoder, &texture, &texture_descriptor),
+                    Ok(())
+                );
+                assert_eq!(
+                    generator.estimate_cost(&texture_descriptor),
+                    Ok(GenerationCost::default())
+                );
+            }
+        });
+    }
+
+    // Random descriptors and options must either generate every requested level, leaving
+    // the levels up to the base untouched, or fail with an error, never panic. The estimate
+    // must agree with generation on whether the texture is supported.
+    #[test]
+    fn random_descriptors() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        use wgpu::TextureFormat;
+        let formats = [
+            TextureFormat::R8Unorm,
+            TextureFormat::Rg8Unorm,
+            TextureFormat::Rgba8Unorm,
+            TextureFormat::Rgba8UnormSrgb,
+            TextureFormat::Bgra8Unorm,
+            TextureFormat::Rgb10a2Unorm,
+            TextureFormat::R32Float,
+            TextureFormat::Rgba16Float,
+            TextureFormat::Rgba32Float,
+            TextureFormat::R8Uint,
+            TextureFormat::Rgba16Sint,
+        ];
+        let usages = [
+            ComputeMipmapGenerator::required_usage(),
+            RenderMipmapGenerator::required_usage(),
+            CopyMipmapGenerator::required_usage(),
+        ];
+        let mut rng = TestRng::new(0x5eed);
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, queue) = wgpu_setup().await;
+            let generator = RecommendedMipmapGenerator::new_with_format_hints(&device, &formats);
+            for _ in 0..64 {
+                let (width, height) = (rng.range(1..300), rng.range(1..300));
+                let format = *rng.choose(&formats);
+                let allowed_usages: Vec<_> = usages
+                    .iter()
+                    .copied()
+                    .filter(|&usage| {
+                        format
+                            .describe()
+                            .guaranteed_format_features
+                            .allowed_usages
+                            .contains(usage)
+                    })
+                    .collect();
+                let max_mip_level_count = 1 + (width.max(height) as f32).log2() as u32;
+                let texture_descriptor = wgpu::TextureDescriptor {
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth: 1,
+                    },
+                    mip_level_count: rng.range(1..max_mip_level_count + 1),
+                    format,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    usage: *rng.choose(&allowed_usages),
+                    label: None,
+                };
+                let options = GenerateOptions {
+                    base_mip_level: rng.range(0..texture_descriptor.mip_level_count),
+                    ..Default::default()
+                };
+                let data = (0..width * height * format_bytes_per_channel(&format) as u32)
+                    .map(|_| rng.next_u32() as u8)
+                    .collect::<Vec<_>>();
+                let estimate = generator.estimate_cost_with_options(&texture_descriptor, &options);
+                let generated = generate_and_copy_to_cpu_with_options(
+                    &device,
+                    &queue,
+                    &generator,
+                    &data,
+                    &texture_descriptor,
+                    &options,
+                )
+                .await;
+                let message = format!("{:?} {:?}", texture_descriptor, options);
+                assert_eq!(estimate.is_ok(), generated.is_ok(), "{}", message);
+                let mipmap_buffers = match generated {
+                    Ok(mipmap_buffers) => mipmap_buffers,
+                    Err(_) => continue,
+                };
+                assert_eq!(
+                    mipmap_buffers.len(),
+                    texture_descriptor.mip_level_count as usize,
+                    "{}",
+                    message
+                );
+                for mip in &mipmap_buffers {
+                    let extent = get_mip_extent(&texture_descriptor.size, mip.level);
+                    assert_eq!(
+                        (mip.width(), mip.height()),
+                        (extent.width, extent.height),
+                        "{}",
+                        message
+                    );
+                }
+                assert_eq!(mipmap_buffers[0].buffer, data, "{}", message);
+            }
+        });
+    }
+
+    #[test]
+    fn required_features() {
+        use wgpu::TextureFormat;
+        let guaranteed = [TextureFormat::Rgba8Unorm, TextureFormat::Rgba16Float];
+        assert_eq!(
+            RecommendedMipmapGenerator::required_features(&guaranteed),
+            wgpu::Features::empty()
+        );
+        assert_eq!(
+            RecommendedMipmapGenerator::required_features(&SUPPORTED_FORMATS),
+            wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+        );
+        assert!(
+            RecommendedMipmapGenerator::required_limits().max_storage_textures_per_shader_stage
+                > wgpu::Limits::default().max_storage_textures_per_shader_stage
+        );
+    }
+
+    // Each texel of a point sampled level must be a copy of the top-left
+    // texel of the 2x2 block it covers
+    fn nearest_test(format: wgpu::TextureFormat, usage: wgpu::TextureUsage, filter: MipFilter) {
+        init();
+        let size = 256;
+        let mip_level_count = 1 + (size as f32).log2() as u32;
+        // Texels hold their own coordinates, like an ID buffer
+        let data = (0..size * size)
+            .flat_map(|id| vec![(id % size) as u8, (id / size) as u8, 0, 255])
+            .collect::<Vec<_>>();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count,
+            format,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage,
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, queue) = wgpu_setup().await;
+            let generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let mipmap_buffers = generate_and_copy_to_cpu_with_options(
+                &device,
+                &queue,
+                &generator,
+                &data,
+                &texture_descriptor,
+                &GenerateOptions {
+                    filter,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+            assert_eq!(mipmap_buffers.len(), mip_level_count as usize);
+            for mip in &mipmap_buffers {
+                let texels = mip.as_slice::<[u8; 4]>();
+                for y in 0..mip.height() {
+                    for x in 0..mip.width() {
+                        let texel = texels[(y * mip.width() + x) as usize];
+                        assert_eq!(
+                            texel,
+                            [(x << mip.level) as u8, (y << mip.level) as u8, 0, 255]
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn nearest_integer_compute() {
+        if !adapter_available() {
+            return;
+        }
+        let usage = crate::ComputeMipmapGenerator::required_usage();
+        nearest_test(wgpu::TextureFormat::Rgba8Uint, usage, MipFilter::Box);
+        nearest_test(wgpu::TextureFormat::Rgba8Uint, usage, MipFilter::Nearest);
+    }
+
+    #[test]
+    fn nearest_integer_render() {
+        if !adapter_available() {
+            return;
+        }
+        let usage = crate::RenderMipmapGenerator::required_usage();
+        nearest_test(wgpu::TextureFormat::Rgba8Uint, usage, MipFilter::Box);
+        nearest_test(wgpu::TextureFormat::Rgba8Sint, usage, MipFilter::Nearest);
+    }
+
+    // Textures that can't be rendered to, and aren't storage textures, are generated by the
+    // compute backend in a temporary texture
+    #[test]
+    fn unrenderable_format_falls_back_to_compute_copy() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 16;
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 5,
+            format: wgpu::TextureFormat::Rgba8Snorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsage::SAMPLED
+                | wgpu::TextureUsage::COPY_SRC
+                | wgpu::TextureUsage::COPY_DST,
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, queue) = wgpu_setup().await;
+            let generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let render =
+                RenderMipmapGenerator::new_with_format_hints(&device, &[texture_descriptor.format]);
+            assert_eq!(
+                CopyMipmapGenerator::new(&render).estimate_cost(&texture_descriptor),
+                Err(Error::UnsupportedFormat(texture_descriptor.format))
+            );
+            assert!(generator.estimate_cost(&texture_descriptor).is_ok());
+            let data = vec![64u8; (4 * size * size) as usize];
+            let mipmap_buffers =
+                generate_and_copy_to_cpu(&device, &queue, &generator, &data, &texture_descriptor)
+                    .await
+                    .unwrap();
+            for mip in &mipmap_buffers {
+                assert!(mip.buffer.iter().all(|&x| x == 64), "level {}", mip.level);
+            }
+        });
+    }
+
+    // Textures the compute backend rejects fail before the compute-copy fallback encodes
+    // anything, so the next backend starts from an untouched encoder
+    #[test]
+    fn failed_compute_copy_encodes_nothing() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let descriptor = |width, depth, dimension| wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height: width,
+                depth,
+            },
+            mip_level_count: 3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension,
+            usage: wgpu::TextureUsage::SAMPLED
+                | wgpu::TextureUsage::COPY_SRC
+                | wgpu::TextureUsage::COPY_DST,
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, _queue) = wgpu_setup().await;
+            let generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[wgpu::TextureFormat::Rgba8Unorm],
+            );
+            for (texture_descriptor, error) in [
+                (
+                    descriptor(24, 1, wgpu::TextureDimension::D2),
+                    Error::NpotTexture,
+                ),
+                (
+                    descriptor(16, 16, wgpu::TextureDimension::D3),
+                    Error::UnsupportedDimension(wgpu::TextureDimension::D3),
+                ),
+            ] {
+                let texture = device.create_texture(&texture_descriptor);
+                let mut encoder = device.create_command_encoder(&Default::default());
+                assert_eq!(
+                    generator.generate_compute_copy(
+                        &device,
+                        &mut encoder,
+                        &texture,
+                        &texture_descriptor,
+                        &GenerateOptions::default(),
+                    ),
+                    Err(error)
+                );
+                assert_eq!(
+                    generator
+                        .compute_copies
+                        .load(std::sync::atomic::Ordering::Relaxed),
+                    0
+                );
+            }
+        });
+    }
+
+    // Bgra8UnormSrgb must produce the mips of Rgba8UnormSrgb, with red and blue swapped
+    fn bgra_srgb_test(usage: wgpu::TextureUsage) {
+        init();
+        use wgpu::TextureFormat;
+        let size = 64;
+        // Red and blue gradients in opposite directions, so a swap changes every level
+        let rgba = (0..size * size)
+            .flat_map(|id| {
+                let (x, y) = (id % size, id / size);
+                vec![
+                    (4 * x) as u8,
+                    (4 * y) as u8,
+                    (252 - 4 * x) as u8,
+                    (x ^ y) as u8,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let bgra = rgba
+            .chunks(4)
+            .flat_map(|t| vec![t[2], t[1], t[0], t[3]])
+            .collect::<Vec<_>>();
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, queue) = wgpu_setup().await;
+            // WebGPU doesn't guarantee storage support for either format
+            if usage.contains(wgpu::TextureUsage::STORAGE)
+                && !device
+                    .features()
+                    .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+            {
+                return;
+            }
+            let formats = [TextureFormat::Rgba8UnormSrgb, TextureFormat::Bgra8UnormSrgb];
+            let generator = RecommendedMipmapGenerator::new_with_format_hints(&device, &formats);
+            let mut mips = Vec::new();
+            for (&format, data) in formats.iter().zip(&[&rgba, &bgra]) {
+                let texture_descriptor = wgpu::TextureDescriptor {
+                    size: wgpu::Extent3d {
+                        width: size,
+                        height: size,
+                        depth: 1,
+                    },
+                    mip_level_count: 1 + (size as f32).log2() as u32,
+                    format,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    usage,
+                    label: None,
+                };
+                mips.push(
+                    generate_and_copy_to_cpu(
+                        &device,
+                        &queue,
+                        &generator,
+                        data,
+                        &texture_descriptor,
+                    )
+                    .await
+                    .unwrap(),
+                );
+            }
+            for (rgba_mip, bgra_mip) in mips[0].iter().zip(&mips[1]) {
+                for (rgba, bgra) in rgba_mip
+                    .as_slice::<[u8; 4]>()
+                    .iter()
+                    .zip(bgra_mip.as_slice::<[u8; 4]>())
+                {
+                    assert_eq!(
+                        [rgba[2], rgba[1], rgba[0], rgba[3]],
+                        *bgra,
+                        "level {}",
+                        bgra_mip.level
+                    );
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn bgra_srgb_compute() {
+        if !adapter_available() {
+            return;
+        }
+        bgra_srgb_test(ComputeMipmapGenerator::required_usage());
+    }
+
+    #[test]
+    fn bgra_srgb_render() {
+        if !adapter_available() {
+            return;
+        }
+        bgra_srgb_test(RenderMipmapGenerator::required_usage());
+    }
+
+    #[test]
+    fn bgra_srgb_copy() {
+        if !adapter_available() {
+            return;
+        }
+        bgra_srgb_test(CopyMipmapGenerator::required_usage());
+    }
+
+    // A 1 texel checkerboard of -1 and +1 must average to 0. Snorm encodes -1 as both -127
+    // and -128, so `low` checks that -128 isn't decoded as slightly less than -1.
+    fn snorm_test(usage: wgpu::TextureUsage, low: i8) {
+        init();
+        use wgpu::TextureFormat;
+        let size = 16;
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, queue) = wgpu_setup().await;
+            for &(format, channels) in &[
+                (TextureFormat::R8Snorm, 1),
+                (TextureFormat::Rg8Snorm, 2),
+                (TextureFormat::Rgba8Snorm, 4),
+            ] {
+                if !is_storage_format(format)
+                    && !device
+                        .features()
+                        .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+                {
+                    continue;
+                }
+                let texture_descriptor = wgpu::TextureDescriptor {
+                    size: wgpu::Extent3d {
+                        width: size,
+                        height: size,
+                        depth: 1,
+                    },
+                    mip_level_count: 1 + (size as f32).log2() as u32,
+                    format,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    usage,
+                    label: None,
+                };
+                let data = checkerboard_r8(size, size, 1)
+                    .into_iter()
+                    .flat_map(|v| vec![if v == 0 { low } else { 127 } as u8; channels])
+                    .collect::<Vec<_>>();
+                let generator =
+                    RecommendedMipmapGenerator::new_with_format_hints(&device, &[format]);
+                let mipmap_buffers = generate_and_copy_to_cpu(
+                    &device,
+                    &queue,
+                    &generator,
+                    &data,
+                    &texture_descriptor,
+                )
+                .await
+                .unwrap();
+                assert_eq!(mipmap_buffers[0].buffer, data);
+                for mip in mipmap_buffers.iter().skip(1) {
+                    for &v in mip.as_slice::<i8>() {
+                        assert!(v.abs() <= 1, "{:?} level {}: {}", format, mip.level, v);
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn snorm_compute() {
+        if !adapter_available() {
+            return;
+        }
+        for &low in &[-128, -127] {
+            snorm_test(ComputeMipmapGenerator::required_usage(), low);
+        }
+    }
+
+    #[test]
+    fn snorm_compute_copy() {
+        if !adapter_available() {
+            return;
+        }
+        for &low in &[-128, -127] {
+            snorm_test(
+                wgpu::TextureUsage::SAMPLED
+                    | wgpu::TextureUsage::COPY_SRC
+                    | wgpu::TextureUsage::COPY_DST,
+                low,
+            );
+        }
+    }
+
+    // `RecommendedMipmapGenerator::new` builds the integer pipelines of every backend
+    #[test]
+    fn integer_formats_are_supported_by_default() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 2,
+                height: 2,
+                depth: 1,
+            },
+            mip_level_count: 2,
+            format: wgpu::TextureFormat::R16Sint,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: RenderMipmapGenerator::required_usage(),
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, queue) = wgpu_setup().await;
+            let generator = RecommendedMipmapGenerator::new(&device);
+            let data = [-7i16, 1, 2, 3];
+            let mipmap_buffers = generate_and_copy_to_cpu(
+                &device,
+                &queue,
+                &generator,
+                bytemuck::cast_slice(&data),
+                &texture_descriptor,
+            )
+            .await
+            .unwrap();
+            assert_eq!(mipmap_buffers[1].as_slice::<i16>(), &[-7]);
+        });
+    }
+
+    #[test]
+    fn try_new_reports_unsupported_formats() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, _queue) = wgpu_setup().await;
+            use wgpu::TextureFormat;
+            let result = RecommendedMipmapGenerator::try_new_with_format_hints(
+                &device,
+                &[
+                    TextureFormat::Rgba8Unorm,
+                    TextureFormat::Bc1RgbaUnorm,
+                    TextureFormat::R16Sint,
+                    TextureFormat::Depth32Float,
+                ],
+            );
+            assert_eq!(
+                result.err(),
+                Some(vec![
+                    (
+                        TextureFormat::Bc1RgbaUnorm,
+                        UnsupportedFormatReason::Compressed
+                    ),
+                    (TextureFormat::Depth32Float, UnsupportedFormatReason::Depth),
+                ])
+            );
+            // Without adapter specific format features, Rg11b10Float can neither be a storage
+            // texture nor rendered to, so only the packed backend writes it
+            assert!(RecommendedMipmapGenerator::try_new_with_format_hints(
+                &device,
+                &SUPPORTED_FORMATS
+            )
+            .is_ok());
+        });
+    }
+
+    #[test]
+    fn storage_unsupported_falls_back() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let (_instance, adapter, device, _queue) = wgpu_setup().await;
+            let format = wgpu::TextureFormat::Rg11b10Float;
+            let generator = RecommendedMipmapGenerator::new_with_adapter(
+                &adapter,
+                &device,
+                &[format, wgpu::TextureFormat::Rgba8Unorm],
+            );
+            assert_eq!(
+                generator.compute.storage_support(format),
+                Some(StorageSupport::Unsupported)
+            );
+            assert_eq!(
+                generator
+                    .compute
+                    .storage_support(wgpu::TextureFormat::Rgba8Unorm),
+                Some(StorageSupport::Guaranteed)
+            );
+            let texture_descriptor = wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: 4,
+                    height: 4,
+                    depth: 1,
+                },
+                mip_level_count: 3,
+                format,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                usage: wgpu::TextureUsage::STORAGE,
+                label: None,
+            };
+            // The compute backend reports why it can't generate the texture, instead of
+            // failing to create its pipelines
+            assert_eq!(
+                generator.compute.estimate_cost(&texture_descriptor),
+                Err(Error::UnsupportedStorageFormat(format))
+            );
+            // Sampled and copied textures are generated by the packed backend
+            let texture_descriptor = wgpu::TextureDescriptor {
+                usage: PackedMipmapGenerator::required_usage(),
+                ..texture_descriptor
+            };
+            assert_eq!(
+                generator.estimate_cost(&texture_descriptor),
+                generator.packed.estimate_cost(&texture_descriptor)
+            );
+            let texture = device.create_texture(&texture_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            assert!(generator
+                .generate(&device, &mut encoder, &texture, &texture_descriptor)
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn stats_count_fallbacks() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 1,
+            },
+            mip_level_count: 3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: RenderMipmapGenerator::required_usage(),
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, _queue) = wgpu_setup().await;
+            let mut generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let stats = Arc::new(StatsCollector::new());
+            generator.set_stats_collector(Some(stats.clone()));
+            let texture = device.create_texture(&texture_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            // The compute backend can't write the texture, so the render backend does
+            generator
+                .generate(&device, &mut encoder, &texture, &texture_descriptor)
+                .unwrap();
+            let snapshot = stats.take();
+            assert_eq!(snapshot.textures, 1);
+            assert_eq!(snapshot.levels, 2);
+            assert_eq!(snapshot.bytes_written, (4 + 1) * 4);
+            assert_eq!(snapshot.fallbacks, 1);
+            generator.set_stats_collector(None);
+            generator
+                .generate(&device, &mut encoder, &texture, &texture_descriptor)
+                .unwrap();
+            assert_eq!(stats.snapshot().textures, 0);
+        });
+    }
+
+    #[test]
+    fn strict_mode_reports_fallbacks() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 1,
+            },
+            mip_level_count: 3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: RenderMipmapGenerator::required_usage(),
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, _queue) = wgpu_setup().await;
+            let mut generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            assert!(!generator.strict());
+            assert!(generator.estimate_cost(&texture_descriptor).is_ok());
+            generator.set_strict(true);
+            let expected = Err(Error::FallbackRequired(Box::new(Error::UnsupportedUsage(
+                texture_descriptor.usage,
+            ))));
+            assert_eq!(
+                generator.estimate_cost(&texture_descriptor).map(|_| ()),
+                expected
+            );
+            let texture = device.create_texture(&texture_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            assert_eq!(
+                generator.generate(&device, &mut encoder, &texture, &texture_descriptor),
+                expected
+            );
+            // Textures the compute backend can generate are unaffected
+            let storage_descriptor = wgpu::TextureDescriptor {
+                usage: ComputeMipmapGenerator::required_usage(),
+                ..texture_descriptor.clone()
+            };
+            assert!(generator.estimate_cost(&storage_descriptor).is_ok());
+        });
+    }
+
+    #[test]
+    fn injected_failures_fall_back_in_order() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        // Every backend can generate this texture, so only injected failures fall back
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 1,
+            },
+            mip_level_count: 3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage()
+                | RenderMipmapGenerator::required_usage()
+                | CopyMipmapGenerator::required_usage(),
+            label: None,
+        };
+        let data = checkerboard_rgba8(4, 4, 2);
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, queue) = wgpu_setup().await;
+            let mut generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let stats = Arc::new(StatsCollector::new());
+            generator.set_stats_collector(Some(stats.clone()));
+            let expected =
+                generate_and_copy_to_cpu(&device, &queue, &generator, &data, &texture_descriptor)
+                    .await
+                    .unwrap();
+            assert_eq!(stats.take().fallbacks, 0);
+            let backends = [
+                Backend::Compute,
+                Backend::Render,
+                Backend::Copy,
+                Backend::ComputeCopy,
+            ];
+            for (fallbacks, &backend) in backends.iter().enumerate() {
+                generator.fail_backend(backend);
+                let result = generate_and_copy_to_cpu(
+                    &device,
+                    &queue,
+                    &generator,
+                    &data,
+                    &texture_descriptor,
+                )
+                .await;
+                let estimate = generator.estimate_cost(&texture_descriptor);
+                if backend == Backend::ComputeCopy {
+                    // No backend is left to fall back to
+                    let unsupported = || Err(Error::UnsupportedUsage(texture_descriptor.usage));
+                    assert_eq!(result.map(|_| ()), unsupported());
+                    assert_eq!(estimate.map(|_| ()), unsupported());
+                    break;
+                }
+                assert!(estimate.is_ok(), "{:?}", backend);
+                // Backends may round the averages of the last level differently
+                for (level, expected) in result.unwrap().iter().zip(&expected) {
+                    for (&a, &b) in level.buffer.iter().zip(&expected.buffer) {
+                        assert!((a as i32 - b as i32).abs() <= 1, "{:?}", backend);
+                    }
+                }
+                assert_eq!(
+                    stats.take().fallbacks,
+                    fallbacks as u64 + 1,
+                    "{:?}",
+                    backend
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn strict_mode_reports_injected_failures() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 1,
+            },
+            mip_level_count: 3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage()
+                | RenderMipmapGenerator::required_usage(),
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, _queue) = wgpu_setup().await;
+            let mut generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            generator.set_strict(true);
+            // Failures after the compute backend are never reached
+            generator.fail_backend(Backend::Render);
+            assert!(generator.estimate_cost(&texture_descriptor).is_ok());
+            generator.fail_backend(Backend::Compute);
+            let expected = Err(Error::FallbackRequired(Box::new(Error::UnsupportedUsage(
+                texture_descriptor.usage,
+            ))));
+            assert_eq!(
+                generator.estimate_cost(&texture_descriptor).map(|_| ()),
+                expected
+            );
+            let texture = device.create_texture(&texture_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            assert_eq!(
+                generator.generate(&device, &mut encoder, &texture, &texture_descriptor),
+                expected
+            );
+        });
+    }
+
+    #[test]
+    fn generators_share_cached_pipelines() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, _queue) = wgpu_setup().await;
+            let cache = PipelineCache::new();
+            let formats = [wgpu::TextureFormat::Rgba8Unorm, wgpu::TextureFormat::R8Uint];
+            let a = RecommendedMipmapGenerator::new_with_cache(
+                &device,
+                &formats,
+                WorkgroupSize::default(),
+                &cache,
+            );
+            let b = RecommendedMipmapGenerator::new_with_cache(
+                &device,
+                &formats[..1],
+                WorkgroupSize::default(),
+                &cache,
+            );
+            let format = formats[0];
+            assert!(std::ptr::eq(
+                a.render.render_pipeline_for(format).unwrap(),
+                b.render.render_pipeline_for(format).unwrap()
+            ));
+            assert!(std::ptr::eq(
+                a.compute.compute_pipeline_for(format).unwrap(),
+                b.compute.compute_pipeline_for(format).unwrap()
+            ));
+            assert!(std::ptr::eq(
+                a.render.sampler_for(format).unwrap(),
+                b.render.sampler_for(format).unwrap()
+            ));
+            // Only the formats a generator was created with are supported by it
+            assert!(b.render.render_pipeline_for(formats[1]).is_none());
+            // A different workgroup size compiles different compute shaders
+            let c = RecommendedMipmapGenerator::new_with_cache(
+                &device,
+                &formats[..1],
+                WorkgroupSize::X8,
+                &cache,
+            );
+            assert!(!std::ptr::eq(
+                a.compute.compute_pipeline_for(format).unwrap(),
+                c.compute.compute_pipeline_for(format).unwrap()
+            ));
+        });
+    }
+
+    #[test]
+    fn nearest_float() {
+        if !adapter_available() {
+            return;
+        }
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let compute = crate::ComputeMipmapGenerator::required_usage();
+        let render = crate::RenderMipmapGenerator::required_usage();
+        nearest_test(format, compute, MipFilter::Nearest);
+        nearest_test(format, render, MipFilter::Nearest);
+    }
+
+    // Each texel of a stochastic level must be a copy of a texel of the base level
+    // inside the block it covers, and the same seed must pick the same texels
+    fn stochastic_test(format: wgpu::TextureFormat, usage: wgpu::TextureUsage) {
+        init();
+        let size = 256;
+        let mip_level_count = 1 + (size as f32).log2() as u32;
+        // Texels hold their own coordinates, so each texel of a level records
+        // which base level texel it was copied from
+        let data = (0..size * size)
+            .flat_map(|id| vec![(id % size) as u8, (id / size) as u8, 0, 255])
+            .collect::<Vec<_>>();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count,
+            format,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage,
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, queue) = wgpu_setup().await;
+            let generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let mut results = Vec::new();
+            for &seed in &[7, 7, 8] {
+                let mipmap_buffers = generate_and_copy_to_cpu_with_options(
+                    &device,
+                    &queue,
+                    &generator,
+                    &data,
+                    &texture_descriptor,
+                    &GenerateOptions {
+                        filter: MipFilter::Stochastic { seed },
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+                assert_eq!(mipmap_buffers.len(), mip_level_count as usize);
+                for mip in &mipmap_buffers {
+                    let texels = mip.as_slice::<[u8; 4]>();
+                    for y in 0..mip.height() {
+                        for x in 0..mip.width() {
+                            let texel = texels[(y * mip.width() + x) as usize];
+                            assert_eq!(texel[0] as u32 >> mip.level, x);
+                            assert_eq!(texel[1] as u32 >> mip.level, y);
+                        }
+                    }
+                }
+                results.push(mipmap_buffers);
+            }
+            for (a, b) in results[0].iter().zip(results[1].iter()) {
+                assert_eq!(a.buffer, b.buffer);
+            }
+            assert!(results[0]
+                .iter()
+                .zip(results[2].iter())
+                .any(|(a, b)| a.buffer != b.buffer));
+        });
+    }
+
+    #[test]
+    fn stochastic_compute() {
+        if !adapter_available() {
+            return;
+        }
+        let usage = crate::ComputeMipmapGenerator::required_usage();
+        stochastic_test(wgpu::TextureFormat::Rgba8Unorm, usage);
+        stochastic_test(wgpu::TextureFormat::Rgba8Uint, usage);
+    }
+
+    #[test]
+    fn stochastic_render() {
+        if !adapter_available() {
+            return;
+        }
+        let usage = crate::RenderMipmapGenerator::required_usage();
+        stochastic_test(wgpu::TextureFormat::Rgba8Unorm, usage);
+        stochastic_test(wgpu::TextureFormat::Rgba8Uint, usage);
+    }
+
+    // Each texel of a mode level must be the most frequent texel of the block it covers,
+    // with ties broken in favor of the top-left, top-right, then bottom-left texel
+    fn mode_test(
+        usage: wgpu::TextureUsage,
+        (width, height): (u32, u32),
+        data: &[u8],
+        expected: &[&[u8]],
+    ) {
+        init();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count: 1 + expected.len() as u32,
+            format: wgpu::TextureFormat::R8Uint,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage,
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, queue) = wgpu_setup().await;
+            let generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let mipmap_buffers = generate_and_copy_to_cpu_with_options(
+                &device,
+                &queue,
+                &generator,
+                data,
+                &texture_descriptor,
+                &GenerateOptions {
+                    filter: MipFilter::Mode,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+            for (mip, expected) in mipmap_buffers.iter().skip(1).zip(expected) {
+                assert_eq!(mip.as_slice::<u8>(), *expected, "level {}", mip.level);
+            }
+        });
+    }
+
+    fn mode_tests(usage: wgpu::TextureUsage) {
+        #[rustfmt::skip]
+        let data = [
+            5u8, 5, 1, 2,
+            5, 9, 2, 3,
+            7, 8, 1, 2,
+            9, 8, 3, 4,
+        ];
+        mode_test(usage, (4, 4), &data, &[&[5, 2, 8, 1], &[5]]);
+        // Blocks of a 1 texel wide level hold each texel twice, so they must not read a
+        // second column past the edge
+        mode_test(usage, (1, 4), &[3, 7, 5, 5], &[&[3, 5], &[3]]);
+        mode_test(usage, (4, 1), &[3, 7, 5, 5], &[&[3, 5], &[3]]);
+    }
+
+    #[test]
+    fn mode_compute() {
+        if !adapter_available() {
+            return;
+        }
+        mode_tests(crate::ComputeMipmapGenerator::required_usage());
+    }
+
+    #[test]
+    fn mode_render() {
+        if !adapter_available() {
+            return;
+        }
+        mode_tests(crate::RenderMipmapGenerator::required_usage());
+    }
+
+    #[test]
+    fn mode_requires_integer_format() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 1,
+            },
+            mip_level_count: 3,
+            format: wgpu::TextureFormat::R8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: crate::ComputeMipmapGenerator::required_usage(),
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, _queue) = wgpu_setup().await;
+            let generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let texture = device.create_texture(&texture_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let res = generator.generate_with_options(
+                &device,
+                &mut encoder,
+                &texture,
+                &texture_descriptor,
+                &GenerateOptions {
+                    filter: MipFilter::Mode,
+                    ..Default::default()
+                },
+            );
+            assert!(res.is_err());
+        });
+    }
+
+    fn max_magnitude_test(usage: wgpu::TextureUsage) {
+        init();
+        #[rustfmt::skip]
+        let data: [[f32; 2]; 16] = [
+            [1.0, 0.0], [0.0, 2.0], [0.0, 0.0], [0.0, 0.0],
+            [-3.0, 0.0], [1.0, 1.0], [0.0, 0.0], [0.0, -0.5],
+            [2.0, 2.0], [2.0, -2.0], [5.0, 0.0], [0.0, 5.0],
+            [0.0, 0.0], [0.0, 0.0], [0.0, 0.0], [0.0, 0.0],
+        ];
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 1,
+            },
+            mip_level_count: 3,
+            format: wgpu::TextureFormat::Rg32Float,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage,
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, queue) = wgpu_setup().await;
+            let generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let mipmap_buffers = generate_and_copy_to_cpu_with_options(
+                &device,
+                &queue,
+                &generator,
+                bytemuck::cast_slice(&data),
+                &texture_descriptor,
+                &GenerateOptions {
+                    filter: MipFilter::MaxMagnitude,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+            // Ties between [2, 2] and [2, -2], and [5, 0] and [0, 5], go to the first texel
+            assert_eq!(
+                mipmap_buffers[1].as_slice::<[f32; 2]>(),
+                &[[-3.0, 0.0], [0.0, -0.5], [2.0, 2.0], [5.0, 0.0]]
+            );
+            assert_eq!(mipmap_buffers[2].as_slice::<[f32; 2]>(), &[[5.0, 0.0]]);
+        });
+    }
+
+    #[test]
+    fn max_magnitude_compute() {
+        if !adapter_available() {
+            return;
+        }
+        max_magnitude_test(crate::ComputeMipmapGenerator::required_usage());
+    }
+
+    #[test]
+    fn max_magnitude_render() {
+        if !adapter_available() {
+            return;
+        }
+        max_magnitude_test(crate::RenderMipmapGenerator::required_usage());
+    }
+
+    // Each row holds its own index, so level 1 rows are the cos(latitude) weighted
+    // average of the two level 0 rows they cover
+    fn equirect_test(usage: wgpu::TextureUsage) {
+        init();
+        let (width, height) = (16, 8);
+        let data = (0..width * height)
+            .flat_map(|id| vec![(id / width) as f32; 4])
+            .collect::<Vec<_>>();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count: 1 + (width as f32).log2() as u32,
+            format: wgpu::TextureFormat::Rgba32Float,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage,
+            label: None,
+        };
+        let latitude_weight = |y: u32, height: u32| {
+            (std::f32::consts::PI * ((y as f32 + 0.5) / height as f32 - 0.5)).cos()
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, queue) = wgpu_setup().await;
+            let generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let mipmap_buffers = generate_and_copy_to_cpu_with_options(
+                &device,
+                &queue,
+                &generator,
+                bytemuck::cast_slice(&data),
+                &texture_descriptor,
+                &GenerateOptions {
+                    filter: MipFilter::Equirectangular,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+            let level1 = &mipmap_buffers[1];
+            let texels = level1.as_slice::<[f32; 4]>();
+            for y in 0..level1.height() {
+                let (w0, w1) = (
+                    latitude_weight(2 * y, height),
+                    latitude_weight(2 * y + 1, height),
+                );
+                let expected = (w0 * (2 * y) as f32 + w1 * (2 * y + 1) as f32) / (w0 + w1);
+                for x in 0..level1.width() {
+                    let texel = texels[(y * level1.width() + x) as usize];
+                    assert!((texel[0] - expected).abs() < 1e-3);
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn equirect_compute() {
+        if !adapter_available() {
+            return;
+        }
+        equirect_test(crate::ComputeMipmapGenerator::required_usage());
+    }
+
+    #[test]
+    fn equirect_render() {
+        if !adapter_available() {
+            return;
+        }
+        equirect_test(crate::RenderMipmapGenerator::required_usage());
+    }
+
+    // Regenerates the second layer of a white texture array whose generated levels are
+    // black, and checks that only that layer's levels turn white
+    fn generate_layer_test(usage: wgpu::TextureUsage) {
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 2,
+            },
+            mip_level_count: 3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: usage | wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adaptor, device, queue) = wgpu_setup().await;
+            let generator = RecommendedMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let texture = device.create_texture(&texture_descriptor);
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &[255; 4 * 4 * 4 * 2],
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * 4,
+                    rows_per_image: 4,
+                },
+                texture_descriptor.size,
+            );
+            let mut encoder = device.create_command_encoder(&Default::default());
+            assert_eq!(
+                generator.generate_layer(&device, &mut encoder, &texture, &texture_descriptor, 2),
+                Err(Error::InvalidArrayLayer(2))
+            );
+            generator
+                .generate_layer(&device, &mut encoder, &texture, &texture_descriptor, 1)
+                .unwrap();
+            let readback =
+                MipReadback::encode(&device, &mut encoder, &texture, &texture_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let mips = readback.read();
+            device.poll(wgpu::Maintain::Wait);
+            let mips = mips.await.unwrap();
+            for mip in mips.iter().skip(1) {
+                assert!(mip.layer(0).iter().all(|&c| c == 0), "level {}", mip.level);
+                assert!(
+                    mip.layer(1).iter().all(|&c| c == 255),
+                    "level {}",
+                    mip.level
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn generate_layer_compute() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        generate_layer_test(crate::ComputeMipmapGenerator::required_usage());
+    }
+
+    #[test]
+    fn generate_layer_render() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        generate_layer_test(crate::RenderMipmapGenerator::required_usage());
+    }
+
+    #[test]
+    fn generate_layer_copy() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        generate_layer_test(crate::CopyMipmapGenerator::required_usage());
+    }
 }
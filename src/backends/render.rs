@@ -1,25 +1,119 @@
-use crate::{core::*, util::get_mip_extent};
-use std::{collections::HashMap, num::NonZeroU32};
+use crate::{
+    backends::PipelineCache,
+    core::*,
+    trace::{event, span},
+    util::{create_stochastic_params, get_mip_extent, level_bytes, to_core_extent},
+};
+use std::{collections::HashMap, num::NonZeroU32, ops::Range, sync::Arc};
 use wgpu::{
-    util::make_spirv, AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
-    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, CommandEncoder,
-    CullMode, Device, FilterMode, FragmentState, FrontFace, LoadOp, MultisampleState, Operations,
-    PipelineLayoutDescriptor, PrimitiveState, RenderPassColorAttachmentDescriptor,
-    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerDescriptor,
-    ShaderFlags, ShaderModuleDescriptor, ShaderStage, Texture, TextureAspect, TextureDescriptor,
-    TextureDimension, TextureFormat, TextureSampleType, TextureUsage, TextureViewDescriptor,
-    TextureViewDimension, VertexState,
+    util::{make_spirv, BufferInitDescriptor, DeviceExt},
+    AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsage, Color,
+    CommandEncoder, CullMode, Device, Extent3d, FilterMode, FragmentState, FrontFace, LoadOp,
+    MultisampleState, Operations, Origin3d, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPassColorAttachmentDescriptor, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, Sampler, SamplerDescriptor, ShaderFlags, ShaderModule,
+    ShaderModuleDescriptor, ShaderSource, ShaderStage, Texture, TextureAspect, TextureCopyView,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsage,
+    TextureView, TextureViewDescriptor, TextureViewDimension, VertexState,
 };
 
+// The sample type the source of `generate_to` is bound as, so any floating-point format can
+// be converted
+const CONVERT_SAMPLE_TYPE: TextureSampleType = TextureSampleType::Float { filterable: false };
+
+// The largest level the levels drawn into an atlas by `GenerateOptions::tail_atlas` are
+// filtered from
+const TAIL_ATLAS_MAX_SIZE: u32 = 64;
+
+/// The format of the luminance pyramids generated by
+/// [`RenderMipmapGenerator::generate_luminance_pyramid`].
+pub const LUMINANCE_FORMAT: TextureFormat = TextureFormat::R16Float;
+
+/// An affine color transform from the texels of one format to another, applied by
+/// [`RenderMipmapGenerator::generate_to`]. Destination channels missing from the
+/// destination format are dropped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorConversion {
+    /// Row `i` holds the weights of the source red, green, blue and alpha channels in
+    /// destination channel `i`.
+    pub matrix: [[f32; 4]; 4],
+    /// Added to each destination channel after the matrix is applied.
+    pub offset: [f32; 4],
+}
+
+impl ColorConversion {
+    /// Copies each channel unchanged, e.g. from `Rgba16Float` to `Rg11b10Float`.
+    pub const IDENTITY: Self = Self {
+        matrix: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+        offset: [0.0; 4],
+    };
+
+    /// Writes the Rec. 709 luminance of the source to the red channel, e.g. from
+    /// `Rgba8Unorm` to `R8Unorm`, and keeps alpha in the alpha channel.
+    pub const LUMINANCE: Self = Self {
+        matrix: [
+            [0.2126, 0.7152, 0.0722, 0.0],
+            [0.0; 4],
+            [0.0; 4],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+        offset: [0.0; 4],
+    };
+}
+
+impl Default for ColorConversion {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// How [`RenderMipmapGenerator::upsample`] computes each texel of a level from the smaller
+/// level after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UpsampleFilter {
+    /// Interpolates the 2x2 nearest texels.
+    Bilinear,
+    /// Interpolates the 4x4 nearest texels with a Catmull-Rom spline, which keeps edges
+    /// sharper than `Bilinear` but may overshoot near them.
+    Bicubic,
+}
+
 /// Generates mipmaps for textures with output attachment usage.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RenderMipmapGenerator {
-    sampler: Sampler,
-    layout_cache: HashMap<TextureSampleType, BindGroupLayout>,
-    pipeline_cache: HashMap<TextureFormat, RenderPipeline>,
+    resources: Arc<RenderResources>,
+    pipelines: HashMap<TextureFormat, Arc<FormatPipelines>>,
+    load_op: LoadOp<Color>,
+}
+
+/// Returns true if WebGPU guarantees that textures with `format` can be render attachments.
+/// Formats such as `Rgba8Snorm` and `Rg11b10Float` can't be rendered to on every backend.
+pub(crate) fn is_render_attachable(format: TextureFormat) -> bool {
+    format
+        .describe()
+        .guaranteed_format_features
+        .allowed_usages
+        .contains(TextureUsage::RENDER_ATTACHMENT)
+}
+
+/// Creates the vertex shader of every fullscreen pass, which draws a triangle covering the
+/// viewport with 3 vertices and no vertex buffers, passing texture coordinates to the
+/// fragment shader at location 0.
+pub(crate) fn fullscreen_triangle(device: &Device) -> ShaderModule {
+    device.create_shader_module(&ShaderModuleDescriptor {
+        label: Some("wgpu-mipmap-fullscreen-triangle"),
+        source: ShaderSource::Wgsl(include_str!("shaders/triangle.wgsl").into()),
+        flags: ShaderFlags::empty(),
+    })
 }
 
-fn to_sample_type(format: TextureFormat) -> TextureSampleType {
+pub(crate) fn to_sample_type(format: TextureFormat) -> TextureSampleType {
     match format {
         TextureFormat::R8Uint
         | TextureFormat::R16Uint
@@ -41,12 +135,16 @@ fn to_sample_type(format: TextureFormat) -> TextureSampleType {
         | TextureFormat::Rgba16Sint
         | TextureFormat::Rgba32Sint => TextureSampleType::Sint,
 
+        // WebGPU doesn't guarantee that 32-bit floats can be filtered
+        TextureFormat::R32Float | TextureFormat::Rg32Float | TextureFormat::Rgba32Float => {
+            TextureSampleType::Float { filterable: false }
+        }
+
         TextureFormat::R8Unorm
         | TextureFormat::R8Snorm
         | TextureFormat::R16Float
         | TextureFormat::Rg8Unorm
         | TextureFormat::Rg8Snorm
-        | TextureFormat::R32Float
         | TextureFormat::Rg16Float
         | TextureFormat::Rgba8Unorm
         | TextureFormat::Rgba8UnormSrgb
@@ -55,9 +153,7 @@ fn to_sample_type(format: TextureFormat) -> TextureSampleType {
         | TextureFormat::Bgra8UnormSrgb
         | TextureFormat::Rgb10a2Unorm
         | TextureFormat::Rg11b10Float
-        | TextureFormat::Rg32Float
         | TextureFormat::Rgba16Float
-        | TextureFormat::Rgba32Float
         | TextureFormat::Depth32Float
         | TextureFormat::Depth24Plus
         | TextureFormat::Depth24PlusStencil8
@@ -116,22 +212,48 @@ fn to_sample_type(format: TextureFormat) -> TextureSampleType {
     }
 }
 
-impl RenderMipmapGenerator {
-    /// Returns the texture usage `RenderMipmapGenerator` requires for mipmap generation.
-    pub fn required_usage() -> TextureUsage {
-        TextureUsage::RENDER_ATTACHMENT | TextureUsage::SAMPLED
-    }
+/// The samplers, bind group layouts and shader modules shared by the pipelines of every
+/// format.
+#[derive(Debug)]
+pub(crate) struct RenderResources {
+    sampler: Sampler,
+    // Bound with floating-point textures that can't be filtered
+    non_filtering_sampler: Sampler,
+    layout_cache: HashMap<TextureSampleType, BindGroupLayout>,
+    stochastic_layout_cache: HashMap<TextureSampleType, BindGroupLayout>,
+    vertex_module: ShaderModule,
+    box_filter: ShaderModule,
+    // The box filters of 8 bit unorm formats, which round explicitly, see BoxRounding
+    box_unorm8_half_even: ShaderModule,
+    box_unorm8_half_up: ShaderModule,
+    npot_filter: ShaderModule,
+    equirect_filter: ShaderModule,
+    convert_filter: ShaderModule,
+    // Also box filters the source down a level, with the polyphase weights of `npot_filter`
+    convert_reduce_filter: ShaderModule,
+    atlas_filter: ShaderModule,
+    nearest_float: ShaderModule,
+    nearest_uint: ShaderModule,
+    nearest_sint: ShaderModule,
+    stochastic_float: ShaderModule,
+    stochastic_uint: ShaderModule,
+    stochastic_sint: ShaderModule,
+    mode_uint: ShaderModule,
+    mode_sint: ShaderModule,
+    max_magnitude: ShaderModule,
+    upsample_bilinear: ShaderModule,
+    upsample_bicubic: ShaderModule,
+}
 
-    /// Creates a new `RenderMipmapGenerator`. Once created, it can be used repeatedly to
-    /// generate mipmaps for any texture with format specified in `format_hints`.
-    pub fn new_with_format_hints(device: &Device, format_hints: &[TextureFormat]) -> Self {
+impl RenderResources {
+    pub(crate) fn new(device: &Device) -> Self {
         // A sampler for box filter with clamp to edge behavior
         // In practice, the final result may be implementation dependent
         // - [Vulkan](https://www.khronos.org/registry/vulkan/specs/1.2-extensions/html/vkspec.html#textures-texel-linear-filtering)
         // - [Metal](https://developer.apple.com/documentation/metal/mtlsamplerminmagfilter/linear)
         // - [DX12](https://docs.microsoft.com/en-us/windows/win32/api/d3d12/ne-d3d12-d3d12_filter)
         let sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some(&"wgpu-mipmap-sampler"),
+            label: Some("wgpu-mipmap-sampler"),
             address_mode_u: AddressMode::ClampToEdge,
             address_mode_v: AddressMode::ClampToEdge,
             address_mode_w: AddressMode::ClampToEdge,
@@ -141,10 +263,23 @@ impl RenderMipmapGenerator {
             ..Default::default()
         });
 
-        let render_layout_cache = {
+        let non_filtering_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("wgpu-mipmap-non-filtering-sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let layout_cache = {
             let mut layout_cache = HashMap::new();
-            // For now, we only cache a bind group layout for floating-point textures
-            for &sample_type in &[TextureSampleType::Float { filterable: true }] {
+            // Floating-point textures are bound with a sampler, which only filters if the
+            // texture's format can be filtered
+            for &filterable in &[true, false] {
+                let sample_type = TextureSampleType::Float { filterable };
                 let bind_group_layout =
                     device.create_bind_group_layout(&BindGroupLayoutDescriptor {
                         label: Some(&format!("wgpu-mipmap-bg-layout-{:?}", sample_type)),
@@ -163,7 +298,7 @@ impl RenderMipmapGenerator {
                                 binding: 1,
                                 visibility: ShaderStage::FRAGMENT,
                                 ty: BindingType::Sampler {
-                                    filtering: true,
+                                    filtering: filterable,
                                     comparison: false,
                                 },
                                 count: None,
@@ -172,121 +307,448 @@ impl RenderMipmapGenerator {
                     });
                 layout_cache.insert(sample_type, bind_group_layout);
             }
+            // Integer textures are only ever point sampled with texelFetch
+            for &sample_type in &[TextureSampleType::Uint, TextureSampleType::Sint] {
+                let bind_group_layout =
+                    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                        label: Some(&format!("wgpu-mipmap-bg-layout-{:?}", sample_type)),
+                        entries: &[BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: ShaderStage::FRAGMENT,
+                            ty: BindingType::Texture {
+                                view_dimension: TextureViewDimension::D2,
+                                sample_type,
+                                multisampled: false,
+                            },
+                            count: None,
+                        }],
+                    });
+                layout_cache.insert(sample_type, bind_group_layout);
+            }
             layout_cache
         };
 
-        let render_pipeline_cache = {
-            let mut pipeline_cache = HashMap::new();
-            let vertex_module = device.create_shader_module(&ShaderModuleDescriptor {
-                label: None,
-                source: make_spirv(include_bytes!("shaders/triangle.vert.spv")),
-                flags: ShaderFlags::empty(),
-            });
-            let box_filter = device.create_shader_module(&ShaderModuleDescriptor {
-                label: None,
-                source: make_spirv(include_bytes!("shaders/box.frag.spv")),
-                flags: ShaderFlags::empty(),
-            });
-            for format in format_hints {
-                let fragment_module = &box_filter;
-
-                let sample_type = to_sample_type(*format);
-                if let Some(bind_group_layout) = render_layout_cache.get(&sample_type) {
-                    let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-                        label: None,
-                        bind_group_layouts: &[bind_group_layout],
-                        push_constant_ranges: &[],
-                    });
-                    let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-                        label: Some(&format!("wgpu-mipmap-render-pipeline-{:?}", format)),
-                        layout: Some(&layout),
-                        vertex: VertexState {
-                            module: &vertex_module,
-                            entry_point: "main",
-                            buffers: &[],
-                        },
-                        primitive: PrimitiveState {
-                            topology: wgpu::PrimitiveTopology::TriangleList,
-                            front_face: FrontFace::Ccw,
-                            cull_mode: CullMode::Back,
-                            ..Default::default()
-                        },
-                        depth_stencil: None,
-                        multisample: MultisampleState {
-                            count: 1,
-                            mask: !0,
-                            alpha_to_coverage_enabled: false,
-                        },
-                        fragment: Some(FragmentState {
-                            module: &fragment_module,
-                            entry_point: "main",
-                            targets: &[(*format).into()],
-                        }),
+        // The stochastic shaders read their seed from a uniform buffer instead of a sampler
+        let stochastic_layout_cache = {
+            let mut layout_cache = HashMap::new();
+            for &sample_type in &[
+                TextureSampleType::Float { filterable: true },
+                TextureSampleType::Float { filterable: false },
+                TextureSampleType::Uint,
+                TextureSampleType::Sint,
+            ] {
+                let bind_group_layout =
+                    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                        label: Some(&format!(
+                            "wgpu-mipmap-stochastic-bg-layout-{:?}",
+                            sample_type
+                        )),
+                        entries: &[
+                            BindGroupLayoutEntry {
+                                binding: 0,
+                                visibility: ShaderStage::FRAGMENT,
+                                ty: BindingType::Texture {
+                                    view_dimension: TextureViewDimension::D2,
+                                    sample_type,
+                                    multisampled: false,
+                                },
+                                count: None,
+                            },
+                            BindGroupLayoutEntry {
+                                binding: 1,
+                                visibility: ShaderStage::FRAGMENT,
+                                ty: BindingType::Buffer {
+                                    ty: BufferBindingType::Uniform,
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                        ],
                     });
-                    pipeline_cache.insert(*format, pipeline);
-                } else {
-                    log::warn!(
-                        "RenderMipmapGenerator does not support requested format {:?}",
-                        format
-                    );
-                    continue;
-                }
+                layout_cache.insert(sample_type, bind_group_layout);
             }
-            pipeline_cache
+            layout_cache
         };
 
+        let shader = |source| {
+            device.create_shader_module(&ShaderModuleDescriptor {
+                label: None,
+                source: make_spirv(source),
+                flags: ShaderFlags::empty(),
+            })
+        };
+        let vertex_module = fullscreen_triangle(device);
+        let box_filter = shader(include_bytes!("shaders/box.frag.spv"));
+        let box_unorm8_half_even = shader(include_bytes!("shaders/box_unorm8_half_even.frag.spv"));
+        let box_unorm8_half_up = shader(include_bytes!("shaders/box_unorm8_half_up.frag.spv"));
+        let npot_filter = shader(include_bytes!("shaders/npot.frag.spv"));
+        let equirect_filter = shader(include_bytes!("shaders/equirect.frag.spv"));
+        let convert_filter = shader(include_bytes!("shaders/convert.frag.spv"));
+        let convert_reduce_filter = shader(include_bytes!("shaders/convert_reduce.frag.spv"));
+        let atlas_filter = shader(include_bytes!("shaders/atlas.frag.spv"));
+        let nearest_float = shader(include_bytes!("shaders/nearest_float.frag.spv"));
+        let nearest_uint = shader(include_bytes!("shaders/nearest_uint.frag.spv"));
+        let nearest_sint = shader(include_bytes!("shaders/nearest_sint.frag.spv"));
+        let stochastic_float = shader(include_bytes!("shaders/stochastic_float.frag.spv"));
+        let stochastic_uint = shader(include_bytes!("shaders/stochastic_uint.frag.spv"));
+        let stochastic_sint = shader(include_bytes!("shaders/stochastic_sint.frag.spv"));
+        let mode_uint = shader(include_bytes!("shaders/mode_uint.frag.spv"));
+        let mode_sint = shader(include_bytes!("shaders/mode_sint.frag.spv"));
+        let max_magnitude = shader(include_bytes!("shaders/max_magnitude.frag.spv"));
+        let upsample_bilinear = shader(include_bytes!("shaders/upsample_bilinear.frag.spv"));
+        let upsample_bicubic = shader(include_bytes!("shaders/upsample_bicubic.frag.spv"));
         Self {
             sampler,
-            layout_cache: render_layout_cache,
-            pipeline_cache: render_pipeline_cache,
+            non_filtering_sampler,
+            layout_cache,
+            stochastic_layout_cache,
+            vertex_module,
+            box_filter,
+            box_unorm8_half_even,
+            box_unorm8_half_up,
+            npot_filter,
+            equirect_filter,
+            convert_filter,
+            convert_reduce_filter,
+            atlas_filter,
+            nearest_float,
+            nearest_uint,
+            nearest_sint,
+            stochastic_float,
+            stochastic_uint,
+            stochastic_sint,
+            mode_uint,
+            mode_sint,
+            max_magnitude,
+            upsample_bilinear,
+            upsample_bicubic,
         }
     }
+}
 
-    /// Generate mipmaps from level 0 of `src_texture` to
-    /// levels `dst_mip_offset..dst_texture_descriptor.mip_level_count`
-    // of `dst_texture`.
-    #[allow(clippy::too_many_arguments)]
-    pub(crate) fn generate_src_dst(
-        &self,
+/// The pipelines `RenderMipmapGenerator` creates for one format.
+#[derive(Debug)]
+pub(crate) struct FormatPipelines {
+    // The box filter pipeline and the polyphase pipeline used for odd-sized source levels
+    box_filter: Option<(RenderPipeline, RenderPipeline)>,
+    // The box filter of BoxRounding::HalfUp, for 8 bit unorm formats only
+    box_half_up: Option<RenderPipeline>,
+    equirect: Option<RenderPipeline>,
+    // Converts a level of another format to this one, for `generate_to`, as is or reduced to
+    // the size of the next level
+    convert: Option<(RenderPipeline, RenderPipeline)>,
+    // Draws the last levels of a chain into an atlas, for `GenerateOptions::tail_atlas`
+    atlas: Option<RenderPipeline>,
+    // The bilinear and bicubic pipelines of `RenderMipmapGenerator::upsample`
+    upsample: Option<(RenderPipeline, RenderPipeline)>,
+    nearest: RenderPipeline,
+    stochastic: RenderPipeline,
+    mode: Option<RenderPipeline>,
+    // Only velocity formats have a max magnitude filter
+    max_magnitude: Option<RenderPipeline>,
+}
+
+impl FormatPipelines {
+    /// Creates the pipelines for `format`, or returns `None` if it can't be rendered to.
+    pub(crate) fn new(
         device: &Device,
-        encoder: &mut CommandEncoder,
-        src_texture: &Texture,
-        dst_texture: &Texture,
+        resources: &RenderResources,
+        format: TextureFormat,
+    ) -> Option<Self> {
+        if !is_render_attachable(format) {
+            event!(
+                DEBUG,
+                { format = format, backend = "render" },
+                "format can't be rendered to"
+            );
+            return None;
+        }
+        let sample_type = to_sample_type(format);
+        let (bind_group_layout, stochastic_bind_group_layout) = match (
+            resources.layout_cache.get(&sample_type),
+            resources.stochastic_layout_cache.get(&sample_type),
+        ) {
+            (Some(layout), Some(stochastic_layout)) => (layout, stochastic_layout),
+            _ => {
+                event!(
+                    WARN,
+                    { format = format, backend = "render" },
+                    "unsupported format"
+                );
+                return None;
+            }
+        };
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let stochastic_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[stochastic_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        // The conversion shader fetches texels of any floating-point format, and reads the
+        // conversion from a uniform buffer like the stochastic shaders
+        let convert_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&resources.stochastic_layout_cache[&CONVERT_SAMPLE_TYPE]],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label: &str, layout, fragment_module| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(&format!("{}-{:?}", label, format)),
+                layout: Some(layout),
+                vertex: VertexState {
+                    module: &resources.vertex_module,
+                    entry_point: "main",
+                    buffers: &[],
+                },
+                primitive: PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    front_face: FrontFace::Ccw,
+                    cull_mode: CullMode::Back,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                fragment: Some(FragmentState {
+                    module: fragment_module,
+                    entry_point: "main",
+                    targets: &[(format).into()],
+                }),
+            })
+        };
+        // Only integer formats have a mode filter, and only float formats have
+        // box and equirect filters
+        let (nearest_filter, stochastic_filter, mode_filter) = match sample_type {
+            TextureSampleType::Uint => (
+                &resources.nearest_uint,
+                &resources.stochastic_uint,
+                Some(&resources.mode_uint),
+            ),
+            TextureSampleType::Sint => (
+                &resources.nearest_sint,
+                &resources.stochastic_sint,
+                Some(&resources.mode_sint),
+            ),
+            _ => (&resources.nearest_float, &resources.stochastic_float, None),
+        };
+        let unorm8 = matches!(
+            format,
+            TextureFormat::R8Unorm
+                | TextureFormat::Rg8Unorm
+                | TextureFormat::Rgba8Unorm
+                | TextureFormat::Bgra8Unorm
+        );
+        let (box_pipelines, equirect_pipeline, convert_pipeline, atlas_pipeline, upsample) =
+            if mode_filter.is_none() {
+                // Without a filtering sampler, even-sized levels are downsampled
+                // with the texel fetches of the polyphase filter, which reduces
+                // to a 2x2 box along even dimensions
+                let box_filter = match sample_type {
+                    _ if unorm8 => &resources.box_unorm8_half_even,
+                    TextureSampleType::Float { filterable: true } => &resources.box_filter,
+                    _ => &resources.npot_filter,
+                };
+                (
+                    Some((
+                        create_pipeline("wgpu-mipmap-render-pipeline", &layout, box_filter),
+                        create_pipeline(
+                            "wgpu-mipmap-npot-render-pipeline",
+                            &layout,
+                            &resources.npot_filter,
+                        ),
+                    )),
+                    Some(create_pipeline(
+                        "wgpu-mipmap-equirect-render-pipeline",
+                        &layout,
+                        &resources.equirect_filter,
+                    )),
+                    Some((
+                        create_pipeline(
+                            "wgpu-mipmap-convert-render-pipeline",
+                            &convert_layout,
+                            &resources.convert_filter,
+                        ),
+                        create_pipeline(
+                            "wgpu-mipmap-convert-reduce-render-pipeline",
+                            &convert_layout,
+                            &resources.convert_reduce_filter,
+                        ),
+                    )),
+                    Some(create_pipeline(
+                        "wgpu-mipmap-atlas-render-pipeline",
+                        &layout,
+                        &resources.atlas_filter,
+                    )),
+                    Some((
+                        create_pipeline(
+                            "wgpu-mipmap-upsample-bilinear-render-pipeline",
+                            &layout,
+                            &resources.upsample_bilinear,
+                        ),
+                        create_pipeline(
+                            "wgpu-mipmap-upsample-bicubic-render-pipeline",
+                            &layout,
+                            &resources.upsample_bicubic,
+                        ),
+                    )),
+                )
+            } else {
+                (None, None, None, None, None)
+            };
+        Some(Self {
+            box_filter: box_pipelines,
+            box_half_up: if unorm8 {
+                Some(create_pipeline(
+                    "wgpu-mipmap-half-up-render-pipeline",
+                    &layout,
+                    &resources.box_unorm8_half_up,
+                ))
+            } else {
+                None
+            },
+            equirect: equirect_pipeline,
+            convert: convert_pipeline,
+            atlas: atlas_pipeline,
+            upsample,
+            nearest: create_pipeline(
+                "wgpu-mipmap-nearest-render-pipeline",
+                &layout,
+                nearest_filter,
+            ),
+            stochastic: create_pipeline(
+                "wgpu-mipmap-stochastic-render-pipeline",
+                &stochastic_layout,
+                stochastic_filter,
+            ),
+            mode: mode_filter.map(|mode_filter| {
+                create_pipeline("wgpu-mipmap-mode-render-pipeline", &layout, mode_filter)
+            }),
+            max_magnitude: match format {
+                TextureFormat::Rg16Float | TextureFormat::Rg32Float => Some(create_pipeline(
+                    "wgpu-mipmap-max-magnitude-render-pipeline",
+                    &layout,
+                    &resources.max_magnitude,
+                )),
+                _ => None,
+            },
+        })
+    }
+}
+
+impl RenderMipmapGenerator {
+    /// Returns the texture usage `RenderMipmapGenerator` requires for mipmap generation.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::RENDER_ATTACHMENT | TextureUsage::SAMPLED
+    }
+
+    /// Creates a new `RenderMipmapGenerator`. Once created, it can be used repeatedly to
+    /// generate mipmaps for any texture with format specified in `format_hints`.
+    pub fn new_with_format_hints(device: &Device, format_hints: &[TextureFormat]) -> Self {
+        Self::new_with_cache(device, format_hints, &PipelineCache::new())
+    }
+
+    /// Like [`RenderMipmapGenerator::new_with_format_hints`], but reuses the shader modules,
+    /// samplers and pipelines in `cache`, and adds the ones it creates to it.
+    ///
+    /// With the `parallel` feature, the pipelines of each format are created on their own
+    /// thread.
+    pub fn new_with_cache(
+        device: &Device,
+        format_hints: &[TextureFormat],
+        cache: &PipelineCache,
+    ) -> Self {
+        let resources = cache.render_resources(device);
+        let pipelines = format_hints
+            .iter()
+            .zip(cache.render_pipelines(device, &resources, format_hints))
+            .filter_map(|(&format, pipelines)| Some((format, pipelines?)))
+            .collect();
+        Self {
+            resources,
+            pipelines,
+            load_op: LoadOp::Clear(Color::TRANSPARENT),
+        }
+    }
+
+    /// Returns the pipeline `generate` draws with to write one level of a texture with
+    /// `format`, or `None` if `format` wasn't specified when creating the generator, for
+    /// recording the generator's passes in your own render graph or render bundles.
+    ///
+    /// Bind the level to read at binding 0 and, for floating-point formats, the sampler returned
+    /// by [`RenderMipmapGenerator::sampler_for`] at binding 1 (see
+    /// [`RenderMipmapGenerator::bind_group_layout_for`]), then draw 3 vertices
+    /// into the level to write. The pipeline takes no vertex buffers. Levels with an odd width
+    /// or height are drawn by `generate` with a different pipeline, so this pipeline drops
+    /// their last row or column.
+    pub fn render_pipeline_for(&self, format: TextureFormat) -> Option<&RenderPipeline> {
+        self.select_pipelines(format, MipFilter::Box, BoxRounding::default())
+            .ok()
+            .map(|(_, pipeline, _, _)| pipeline)
+    }
+
+    /// Returns the bind group layout of [`RenderMipmapGenerator::render_pipeline_for`].
+    pub fn bind_group_layout_for(&self, format: TextureFormat) -> Option<&BindGroupLayout> {
+        self.select_pipelines(format, MipFilter::Box, BoxRounding::default())
+            .ok()
+            .map(|(_, _, _, layout)| layout)
+    }
+
+    /// Returns the sampler bound at binding 1 for textures with `format`: a filtering sampler
+    /// for filterable floating-point formats, a non-filtering one for other floating-point
+    /// formats, such as `Rgba32Float`, and `None` for integer formats, which have no sampler
+    /// binding.
+    pub fn sampler_for(&self, format: TextureFormat) -> Option<&Sampler> {
+        match to_sample_type(format) {
+            TextureSampleType::Float { filterable: true } => Some(&self.resources.sampler),
+            TextureSampleType::Float { filterable: false } => {
+                Some(&self.resources.non_filtering_sampler)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns how each destination level is loaded at the start of its render pass.
+    pub fn load_op(&self) -> LoadOp<Color> {
+        self.load_op
+    }
+
+    /// Sets how each destination level is loaded at the start of its render pass. Every draw
+    /// covers the whole level, so the default, `LoadOp::Clear`, never changes the result, and
+    /// lets tile-based GPUs skip reading the level's previous contents from memory.
+    pub fn set_load_op(&mut self, load_op: LoadOp<Color>) {
+        self.load_op = load_op;
+    }
+
+    /// Checks that mipmaps can be generated from `src_texture_descriptor` into
+    /// `dst_texture_descriptor` with `filter`, and returns the pipelines as
+    /// [`RenderMipmapGenerator::select_pipelines`] does.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn validate(
+        &self,
         src_texture_descriptor: &TextureDescriptor,
         dst_texture_descriptor: &TextureDescriptor,
-        dst_mip_offset: u32,
-    ) -> Result<(), Error> {
-        let src_format = src_texture_descriptor.format;
-        let src_mip_count = src_texture_descriptor.mip_level_count;
-        let src_ext = src_texture_descriptor.size;
+        filter: MipFilter,
+        rounding: BoxRounding,
+    ) -> Result<
+        (
+            MipFilter,
+            &RenderPipeline,
+            Option<&RenderPipeline>,
+            &BindGroupLayout,
+        ),
+        Error,
+    > {
         let src_dim = src_texture_descriptor.dimension;
         let src_usage = src_texture_descriptor.usage;
-        let src_next_mip_ext = get_mip_extent(&src_ext, 1);
-
-        let dst_format = dst_texture_descriptor.format;
-        let dst_mip_count = dst_texture_descriptor.mip_level_count;
-        let dst_ext = dst_texture_descriptor.size;
-        let dst_dim = dst_texture_descriptor.dimension;
         let dst_usage = dst_texture_descriptor.usage;
-        // invariants that we expect callers to uphold
-        if src_format != dst_format {
-            dbg!(src_texture_descriptor);
-            dbg!(dst_texture_descriptor);
-            panic!("src and dst texture formats must be equal");
-        }
-        if src_dim != dst_dim {
-            dbg!(src_texture_descriptor);
-            dbg!(dst_texture_descriptor);
-            panic!("src and dst texture dimensions must be eqaul");
-        }
-        if !((src_mip_count == dst_mip_count && src_ext == dst_ext)
-            || (src_next_mip_ext == dst_ext))
-        {
-            dbg!(src_texture_descriptor);
-            dbg!(dst_texture_descriptor);
-            panic!("src and dst texture extents must match or dst must be half the size of src");
-        }
-
         if src_dim != TextureDimension::D2 {
             return Err(Error::UnsupportedDimension(src_dim));
         }
@@ -298,130 +760,1249 @@ impl RenderMipmapGenerator {
         if !dst_usage.contains(Self::required_usage()) {
             return Err(Error::UnsupportedUsage(dst_usage));
         }
-        let format = src_format;
-        let pipeline = self
-            .pipeline_cache
+        if !is_render_attachable(src_texture_descriptor.format) {
+            return Err(Error::UnsupportedFormat(src_texture_descriptor.format));
+        }
+        self.select_pipelines(src_texture_descriptor.format, filter, rounding)
+    }
+
+    /// Returns the filter used for `format`, its pipeline, the polyphase pipeline used for
+    /// odd-sized levels if the filter has one, and the bind group layout. `rounding` selects
+    /// the box filter pipeline of 8 bit unorm formats.
+    #[allow(clippy::type_complexity)]
+    fn select_pipelines(
+        &self,
+        format: TextureFormat,
+        filter: MipFilter,
+        rounding: BoxRounding,
+    ) -> Result<
+        (
+            MipFilter,
+            &RenderPipeline,
+            Option<&RenderPipeline>,
+            &BindGroupLayout,
+        ),
+        Error,
+    > {
+        let sample_type = to_sample_type(format);
+        // Integer texels can't be averaged, so integer formats are point sampled by default
+        let filter = match filter {
+            MipFilter::Box
+                if matches!(
+                    sample_type,
+                    TextureSampleType::Uint | TextureSampleType::Sint
+                ) =>
+            {
+                MipFilter::Nearest
+            }
+            filter => filter,
+        };
+        let pipelines = self
+            .pipelines
             .get(&format)
             .ok_or(Error::UnknownFormat(format))?;
-        let sample_type = to_sample_type(format);
-        let layout = self
-            .layout_cache
+        // The box filter switches to the polyphase pipeline for odd-sized levels
+        let (pipeline, npot_pipeline) = match filter {
+            MipFilter::Box => {
+                let (box_pipeline, npot_pipeline) = pipelines
+                    .box_filter
+                    .as_ref()
+                    .ok_or(Error::UnknownFormat(format))?;
+                let box_pipeline = match (&pipelines.box_half_up, rounding) {
+                    (Some(half_up), BoxRounding::HalfUp) => half_up,
+                    _ => box_pipeline,
+                };
+                (box_pipeline, Some(npot_pipeline))
+            }
+            MipFilter::Nearest => (&pipelines.nearest, None),
+            MipFilter::Stochastic { .. } => (&pipelines.stochastic, None),
+            MipFilter::Equirectangular => (
+                pipelines
+                    .equirect
+                    .as_ref()
+                    .ok_or(Error::UnknownFormat(format))?,
+                None,
+            ),
+            MipFilter::Mode => (
+                pipelines
+                    .mode
+                    .as_ref()
+                    .ok_or(Error::UnknownFormat(format))?,
+                None,
+            ),
+            MipFilter::MaxMagnitude => (
+                pipelines
+                    .max_magnitude
+                    .as_ref()
+                    .ok_or(Error::UnknownFormat(format))?,
+                None,
+            ),
+            MipFilter::Dpid => return Err(Error::UnsupportedFilter(filter)),
+        };
+        let layout_cache = match filter {
+            MipFilter::Stochastic { .. } => &self.resources.stochastic_layout_cache,
+            _ => &self.resources.layout_cache,
+        };
+        let layout = layout_cache
             .get(&sample_type)
             .ok_or(Error::UnknownFormat(format))?;
-        let views = (0..src_mip_count)
-            .map(|mip_level| {
-                // The first view is mip level 0 of the src texture
-                // Subsequent views are for the dst_texture
-                let (texture, base_mip_level) = if mip_level == 0 {
-                    (src_texture, 0)
-                } else {
-                    (dst_texture, mip_level - dst_mip_offset)
-                };
-                texture.create_view(&TextureViewDescriptor {
-                    label: None,
-                    format: None,
-                    dimension: None,
-                    aspect: TextureAspect::All,
-                    base_mip_level,
-                    level_count: NonZeroU32::new(1),
-                    array_layer_count: None,
-                    base_array_layer: 0,
-                })
-            })
-            .collect::<Vec<_>>();
-        for mip in 1..src_mip_count as usize {
-            let src_view = &views[mip - 1];
-            let dst_view = &views[mip];
-            let bind_group = device.create_bind_group(&BindGroupDescriptor {
-                label: None,
-                layout,
-                entries: &[
-                    BindGroupEntry {
-                        binding: 0,
-                        resource: BindingResource::TextureView(&src_view),
-                    },
-                    BindGroupEntry {
-                        binding: 1,
-                        resource: BindingResource::Sampler(&self.sampler),
-                    },
-                ],
-            });
-            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: None,
-                color_attachments: &[RenderPassColorAttachmentDescriptor {
-                    attachment: &dst_view,
-                    resolve_target: None,
-                    ops: Operations {
-                        load: LoadOp::Load,
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: None,
-            });
-            pass.set_pipeline(pipeline);
-            pass.set_bind_group(0, &bind_group, &[]);
-            pass.draw(0..3, 0..1);
-        }
-        Ok(())
+        Ok((filter, pipeline, npot_pipeline, layout))
     }
-}
 
-impl MipmapGenerator for RenderMipmapGenerator {
-    fn generate(
+    /// Encodes commands to generate the mip levels selected by `options`, sampling and
+    /// rendering to `level_views` instead of creating views of the texture, e.g. views
+    /// already retained by a material system. `GenerateOptions::tail_atlas` is ignored, as
+    /// it copies from a scratch texture into the texture itself.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the descriptor used to create the texture viewed.
+    /// - `level_views[n]` should be a 2D view of mip level `n` of array layer
+    ///   `options.array_layer`, with the texture's format and a single level and layer.
+    ///
+    /// Returns `Error::LevelViewCount` if there isn't exactly one view per mip level.
+    pub fn generate_with_level_views(
         &self,
         device: &Device,
         encoder: &mut CommandEncoder,
-        texture: &Texture,
         texture_descriptor: &TextureDescriptor,
+        level_views: &[&TextureView],
+        options: &GenerateOptions,
     ) -> Result<(), Error> {
-        self.generate_src_dst(
+        if level_views.len() != texture_descriptor.mip_level_count as usize {
+            return Err(Error::LevelViewCount(
+                level_views.len(),
+                texture_descriptor.mip_level_count,
+            ));
+        }
+        // A single level texture has no levels to generate
+        if texture_descriptor.mip_level_count == 1 {
+            return Ok(());
+        }
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        let (filter, pipeline, npot_pipeline, layout) = self.validate(
+            texture_descriptor,
+            texture_descriptor,
+            options.filter,
+            options.rounding,
+        )?;
+        let base_mip_level = mip_levels.start - 1;
+        self.draw_levels(
             device,
             encoder,
-            &texture,
-            &texture,
-            &texture_descriptor,
-            &texture_descriptor,
-            0,
-        )
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::util::*;
-
-    fn init() {
-        let _ = env_logger::builder().is_test(true).try_init();
+            texture_descriptor,
+            &level_views[base_mip_level as usize..mip_levels.end as usize],
+            base_mip_level,
+            filter,
+            (pipeline, npot_pipeline, layout),
+            options.dirty_rect,
+        );
+        Ok(())
     }
 
-    #[allow(dead_code)]
-    async fn generate_and_copy_to_cpu_render(
-        buffer: &[u8],
-        texture_descriptor: &TextureDescriptor<'_>,
-    ) -> Result<Vec<MipBuffer>, Error> {
+    /// Encodes commands to convert level 0 of `src_texture` into level 0 of `dst_texture`
+    /// with `conversion`, then generate the remaining levels of `dst_texture`, e.g. an
+    /// `R8Unorm` luminance pyramid of an `Rgba8Unorm` texture.
+    ///
+    /// `dst_texture` may be the size of a smaller mip level of `src_texture`, e.g. to import
+    /// an 8192x8192 source into a 2048x2048 texture. Level 0 of `src_texture` is then
+    /// converted and box filtered in one pass into a temporary texture the size of level 1,
+    /// and reduced down to the size of `dst_texture`, so its level 0 is the level the source
+    /// would have had. Sources a single level larger are reduced straight into
+    /// `dst_texture`.
+    ///
+    /// Expectations:
+    /// - `src_texture` must have `TextureUsage::SAMPLED` and a floating-point format.
+    /// - `dst_texture` must have the usage returned by
+    ///   [`RenderMipmapGenerator::required_usage`], a floating-point format this generator
+    ///   was created for, and the width and height of a mip level of `src_texture`.
+    ///   Otherwise, `Error::ExtentMismatch` is returned.
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate_to(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_texture: &Texture,
+        src_texture_descriptor: &TextureDescriptor,
+        dst_texture: &Texture,
+        dst_texture_descriptor: &TextureDescriptor,
+        conversion: &ColorConversion,
+    ) -> Result<(), Error> {
+        let _span = span!("generate_mipmaps", {
+            backend = "render",
+            format = dst_texture_descriptor.format,
+            levels = dst_texture_descriptor.mip_level_count,
+            src_format = src_texture_descriptor.format,
+        });
+        let src_format = src_texture_descriptor.format;
+        let dst_format = dst_texture_descriptor.format;
+        if src_texture_descriptor.dimension != TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(
+                src_texture_descriptor.dimension,
+            ));
+        }
+        if !src_texture_descriptor.usage.contains(TextureUsage::SAMPLED) {
+            return Err(Error::UnsupportedUsage(src_texture_descriptor.usage));
+        }
+        if !matches!(to_sample_type(src_format), TextureSampleType::Float { .. }) {
+            return Err(Error::UnsupportedFormat(src_format));
+        }
+        // The number of levels between the source and destination sizes
+        let reduction = wgpu_mipmap_core::mip_level_of(
+            to_core_extent(&src_texture_descriptor.size),
+            to_core_extent(&dst_texture_descriptor.size),
+        )
+        .ok_or(Error::ExtentMismatch)?;
+        self.validate(
+            dst_texture_descriptor,
+            dst_texture_descriptor,
+            MipFilter::Box,
+            BoxRounding::default(),
+        )?;
+        let level_view = |texture: &Texture| {
+            texture.create_view(&TextureViewDescriptor {
+                label: None,
+                format: None,
+                dimension: Some(TextureViewDimension::D2),
+                aspect: TextureAspect::All,
+                base_mip_level: 0,
+                level_count: NonZeroU32::new(1),
+                array_layer_count: NonZeroU32::new(1),
+                base_array_layer: 0,
+            })
+        };
+        let mip_count = dst_texture_descriptor.mip_level_count;
+        let src_view = level_view(src_texture);
+        if reduction > 0 {
+            event!(
+                DEBUG,
+                {
+                    backend = "render",
+                    format = dst_format,
+                    levels = reduction,
+                },
+                "reducing the source to the destination size"
+            );
+        }
+        // Sources the size of the destination or of the level above it are drawn straight
+        // into the destination
+        if reduction <= 1 {
+            let dst_view = level_view(dst_texture);
+            self.convert_into(
+                device,
+                encoder,
+                &src_view,
+                &dst_view,
+                dst_format,
+                conversion,
+                reduction == 1,
+            )?;
+            if mip_count == 1 {
+                return Ok(());
+            }
+            return self.generate_src_dst(
+                device,
+                encoder,
+                dst_texture,
+                dst_texture,
+                dst_texture_descriptor,
+                dst_texture_descriptor,
+                0,
+                0,
+                0,
+                1..mip_count,
+                MipFilter::Box,
+                BoxRounding::default(),
+                None,
+            );
+        }
+        let tmp_levels = reduction - 1;
+        let tmp_descriptor =
+            Self::reduction_descriptor(src_texture_descriptor, dst_format, reduction);
+        let tmp_texture = device.create_texture(&tmp_descriptor);
+        let tmp_view = level_view(&tmp_texture);
+        self.convert_into(
+            device, encoder, &src_view, &tmp_view, dst_format, conversion, true,
+        )?;
+        if tmp_levels > 1 {
+            self.generate_src_dst(
+                device,
+                encoder,
+                &tmp_texture,
+                &tmp_texture,
+                &tmp_descriptor,
+                &tmp_descriptor,
+                0,
+                0,
+                0,
+                1..tmp_levels,
+                MipFilter::Box,
+                BoxRounding::default(),
+                None,
+            )?;
+        }
+        // Level `tmp_levels + n` of the temporary texture is level `n` of the destination
+        self.generate_src_dst(
+            device,
+            encoder,
+            &tmp_texture,
+            dst_texture,
+            &tmp_descriptor,
+            dst_texture_descriptor,
+            tmp_levels,
+            0,
+            0,
+            tmp_levels..tmp_levels + mip_count,
+            MipFilter::Box,
+            BoxRounding::default(),
+            None,
+        )
+    }
+
+    /// Returns a descriptor for the temporary texture [`RenderMipmapGenerator::generate_to`]
+    /// reduces a source described by `src_texture_descriptor` in, `reduction` levels larger
+    /// than the destination: levels 1 up to `reduction - 1` of the source, in `format`. Level
+    /// 0 is reduced straight from the source, so it's never allocated.
+    fn reduction_descriptor(
+        src_texture_descriptor: &TextureDescriptor,
+        format: TextureFormat,
+        reduction: u32,
+    ) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-reduction-texture"),
+            size: Extent3d {
+                depth: 1,
+                ..get_mip_extent(&src_texture_descriptor.size, 1)
+            },
+            mip_level_count: reduction - 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: Self::required_usage(),
+        }
+    }
+
+    /// Draws `src_view`, a level of any floating-point format, into `dst_view`, a level of
+    /// `dst_format` the same size, converting each texel with `conversion`.
+    pub(crate) fn convert_level(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_view: &TextureView,
+        dst_view: &TextureView,
+        dst_format: TextureFormat,
+        conversion: &ColorConversion,
+    ) -> Result<(), Error> {
+        self.convert_into(
+            device, encoder, src_view, dst_view, dst_format, conversion, false,
+        )
+    }
+
+    /// Like [`RenderMipmapGenerator::convert_level`], but with `reduce`, `dst_view` is the
+    /// size of the level after `src_view`, and each texel is box filtered from the source
+    /// before it's converted.
+    #[allow(clippy::too_many_arguments)]
+    fn convert_into(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_view: &TextureView,
+        dst_view: &TextureView,
+        dst_format: TextureFormat,
+        conversion: &ColorConversion,
+        reduce: bool,
+    ) -> Result<(), Error> {
+        // Only floating-point formats have a conversion pipeline
+        let pipeline = self
+            .pipelines
+            .get(&dst_format)
+            .and_then(|pipelines| pipelines.convert.as_ref())
+            .map(|(convert, reduce_pipeline)| if reduce { reduce_pipeline } else { convert })
+            .ok_or(Error::UnsupportedFormat(dst_format))?;
+        let layout = &self.resources.stochastic_layout_cache[&CONVERT_SAMPLE_TYPE];
+        let mut params = [0.0f32; 20];
+        for (i, row) in conversion.matrix.iter().enumerate() {
+            params[i * 4..i * 4 + 4].copy_from_slice(row);
+        }
+        params[16..].copy_from_slice(&conversion.offset);
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("wgpu-mipmap-convert-params"),
+            contents: bytemuck::cast_slice(&params),
+            usage: BufferUsage::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(src_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Buffer {
+                        buffer: &params,
+                        offset: 0,
+                        size: None,
+                    },
+                },
+            ],
+        });
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: dst_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: self.load_op,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+        Ok(())
+    }
+
+    /// Returns a descriptor for a texture holding a texture described by
+    /// `src_texture_descriptor` downscaled to fit `max_dim`: the size of the largest mip level
+    /// of the source whose width and height are at most `max_dim`, the same format, levels
+    /// down to 1x1, and usage suitable for generation, sampling and readback. Levels are
+    /// halved, so the aspect ratio is kept, and the result may be up to half of `max_dim`.
+    pub fn fit_descriptor(
+        src_texture_descriptor: &TextureDescriptor,
+        max_dim: u32,
+    ) -> TextureDescriptor<'static> {
+        let size = to_core_extent(&src_texture_descriptor.size);
+        let level = (0..wgpu_mipmap_core::mip_level_count(size))
+            .find(|&level| {
+                let mip = size.mip(level);
+                mip.width.max(mip.height) <= max_dim
+            })
+            .unwrap_or_else(|| wgpu_mipmap_core::mip_level_count(size) - 1);
+        let mip = size.mip(level);
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-fit-texture"),
+            size: Extent3d {
+                width: mip.width,
+                height: mip.height,
+                depth: 1,
+            },
+            mip_level_count: wgpu_mipmap_core::mip_level_count(mip),
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: src_texture_descriptor.format,
+            usage: Self::required_usage() | TextureUsage::COPY_SRC,
+        }
+    }
+
+    /// Creates a texture described by [`RenderMipmapGenerator::fit_descriptor`] and encodes
+    /// commands to write level 0 of `src_texture` downscaled to fit `max_dim` into it, then
+    /// generate its mip chain, e.g. for thumbnails or to enforce a texture budget on
+    /// imported assets. Returns the texture and its descriptor.
+    ///
+    /// This is [`RenderMipmapGenerator::generate_to`] with [`ColorConversion::IDENTITY`], so
+    /// `src_texture` must have `TextureUsage::SAMPLED` and a floating-point format this
+    /// generator was created for.
+    pub fn generate_fit(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_texture: &Texture,
+        src_texture_descriptor: &TextureDescriptor,
+        max_dim: u32,
+    ) -> Result<(Texture, TextureDescriptor<'static>), Error> {
+        let dst_texture_descriptor = Self::fit_descriptor(src_texture_descriptor, max_dim);
+        let dst_texture = device.create_texture(&dst_texture_descriptor);
+        self.generate_to(
+            device,
+            encoder,
+            src_texture,
+            src_texture_descriptor,
+            &dst_texture,
+            &dst_texture_descriptor,
+            &ColorConversion::IDENTITY,
+        )?;
+        Ok((dst_texture, dst_texture_descriptor))
+    }
+
+    /// Returns a descriptor for the luminance pyramid of a texture described by
+    /// `src_texture_descriptor`: same width and height, [`LUMINANCE_FORMAT`], levels down to
+    /// 1x1, and usage suitable for generation, sampling and readback.
+    pub fn luminance_pyramid_descriptor(
+        src_texture_descriptor: &TextureDescriptor,
+    ) -> TextureDescriptor<'static> {
+        let size = src_texture_descriptor.size;
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-luminance-pyramid"),
+            size: Extent3d {
+                width: size.width,
+                height: size.height,
+                depth: 1,
+            },
+            mip_level_count: 32 - size.width.max(size.height).leading_zeros(),
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: LUMINANCE_FORMAT,
+            usage: Self::required_usage() | TextureUsage::COPY_SRC,
+        }
+    }
+
+    /// Encodes commands to write the Rec. 709 luminance of level 0 of `src_texture` to
+    /// `dst_texture` and generate its mip chain, whose last 1x1 level holds the average
+    /// luminance, e.g. for auto-exposure. Read it back with `util::LuminanceReadback`, which
+    /// needs the `unstable` feature.
+    ///
+    /// This is [`RenderMipmapGenerator::generate_to`] with [`ColorConversion::LUMINANCE`].
+    /// The generator must be created with [`LUMINANCE_FORMAT`] (or the format of
+    /// `dst_texture`) among its format hints.
+    ///
+    /// Expectations:
+    /// - `dst_texture_descriptor` should match the descriptor returned by
+    ///   [`RenderMipmapGenerator::luminance_pyramid_descriptor`], up to extra usage flags.
+    pub fn generate_luminance_pyramid(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_texture: &Texture,
+        src_texture_descriptor: &TextureDescriptor,
+        dst_texture: &Texture,
+        dst_texture_descriptor: &TextureDescriptor,
+    ) -> Result<(), Error> {
+        self.generate_to(
+            device,
+            encoder,
+            src_texture,
+            src_texture_descriptor,
+            dst_texture,
+            dst_texture_descriptor,
+            &ColorConversion::LUMINANCE,
+        )
+    }
+
+    /// Encodes commands to fill `levels` of `texture` by upsampling level `levels.end` with
+    /// `filter`, drawing each level from the smaller one after it, e.g. so a streamed texture
+    /// whose coarse levels arrived first can be displayed until its detailed levels load.
+    /// Only floating-point formats can be upsampled.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn upsample(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        levels: Range<u32>,
+        array_layer: u32,
+        filter: UpsampleFilter,
+    ) -> Result<(), Error> {
+        let _span = span!("upsample", {
+            backend = "render",
+            format = texture_descriptor.format,
+            levels = levels.len(),
+        });
+        let format = texture_descriptor.format;
+        if texture_descriptor.dimension != TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(texture_descriptor.dimension));
+        }
+        if !texture_descriptor.usage.contains(Self::required_usage()) {
+            return Err(Error::UnsupportedUsage(texture_descriptor.usage));
+        }
+        if !is_render_attachable(format) {
+            return Err(Error::UnsupportedFormat(format));
+        }
+        if levels.end >= texture_descriptor.mip_level_count {
+            return Err(Error::InvalidBaseMipLevel(levels.end));
+        }
+        if array_layer >= texture_descriptor.size.depth {
+            return Err(Error::InvalidArrayLayer(array_layer));
+        }
+        let (bilinear, bicubic) = self
+            .pipelines
+            .get(&format)
+            .and_then(|pipelines| pipelines.upsample.as_ref())
+            .ok_or(Error::UnknownFormat(format))?;
+        let pipeline = match filter {
+            UpsampleFilter::Bilinear => bilinear,
+            UpsampleFilter::Bicubic => bicubic,
+        };
+        let layout = self
+            .resources
+            .layout_cache
+            .get(&to_sample_type(format))
+            .ok_or(Error::UnknownFormat(format))?;
+        let sampler = self
+            .sampler_for(format)
+            .ok_or(Error::UnknownFormat(format))?;
+        let view = |mip_level| {
+            texture.create_view(&TextureViewDescriptor {
+                label: None,
+                format: None,
+                dimension: Some(TextureViewDimension::D2),
+                aspect: TextureAspect::All,
+                base_mip_level: mip_level,
+                level_count: NonZeroU32::new(1),
+                array_layer_count: NonZeroU32::new(1),
+                base_array_layer: array_layer,
+            })
+        };
+        // Each level is drawn from the one after it, so the smallest level is drawn first
+        for level in levels.rev() {
+            event!(
+                TRACE,
+                { backend = "render", format = format, level = level },
+                "upsampling level"
+            );
+            let src_view = view(level + 1);
+            let dst_view = view(level);
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&src_view),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(sampler),
+                    },
+                ],
+            });
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: None,
+                color_attachments: &[RenderPassColorAttachmentDescriptor {
+                    attachment: &dst_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: self.load_op,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        Ok(())
+    }
+
+    /// Returns the first level of `mip_levels` drawn into an atlas for
+    /// `GenerateOptions::tail_atlas`, if any.
+    fn tail_atlas_start(
+        &self,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+        mip_levels: &Range<u32>,
+    ) -> Option<u32> {
+        let format = texture_descriptor.format;
+        let box_filter = matches!(
+            self.select_pipelines(format, options.filter, options.rounding),
+            Ok((MipFilter::Box, ..))
+        );
+        let has_pipeline = self
+            .pipelines
+            .get(&format)
+            .is_some_and(|pipelines| pipelines.atlas.is_some());
+        if !options.tail_atlas
+            || options.dirty_rect.is_some()
+            || !box_filter
+            || !has_pipeline
+            || !texture_descriptor.usage.contains(TextureUsage::COPY_DST)
+        {
+            return None;
+        }
+        // A single tail level saves nothing
+        (mip_levels.start..mip_levels.end.saturating_sub(1)).find(|&mip| {
+            let src_ext = get_mip_extent(&texture_descriptor.size, mip - 1);
+            src_ext.width <= TAIL_ATLAS_MAX_SIZE && src_ext.height <= TAIL_ATLAS_MAX_SIZE
+        })
+    }
+
+    /// Draws `mip_levels` of `array_layer` of `texture` side by side into a scratch atlas in a
+    /// single render pass, each with its own viewport, then copies them into place.
+    fn generate_tail_atlas(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        array_layer: u32,
+        mip_levels: Range<u32>,
+    ) -> Result<(), Error> {
+        let format = texture_descriptor.format;
+        let (_, _, _, layout) =
+            self.select_pipelines(format, MipFilter::Box, BoxRounding::default())?;
+        let pipeline = self
+            .pipelines
+            .get(&format)
+            .and_then(|pipelines| pipelines.atlas.as_ref())
+            .ok_or(Error::UnknownFormat(format))?;
+        let extents = mip_levels
+            .clone()
+            .map(|mip_level| get_mip_extent(&texture_descriptor.size, mip_level))
+            .collect::<Vec<_>>();
+        let atlas = device.create_texture(&TextureDescriptor {
+            label: Some("wgpu-mipmap-tail-atlas"),
+            size: Extent3d {
+                width: extents.iter().map(|extent| extent.width).sum(),
+                height: extents[0].height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsage::RENDER_ATTACHMENT | TextureUsage::COPY_SRC,
+        });
+        let atlas_view = atlas.create_view(&Default::default());
+        let src_view = texture.create_view(&TextureViewDescriptor {
+            label: None,
+            format: None,
+            dimension: Some(TextureViewDimension::D2),
+            aspect: TextureAspect::All,
+            base_mip_level: mip_levels.start - 1,
+            level_count: NonZeroU32::new(1),
+            base_array_layer: array_layer,
+            array_layer_count: NonZeroU32::new(1),
+        });
+        let mut entries = vec![BindGroupEntry {
+            binding: 0,
+            resource: BindingResource::TextureView(&src_view),
+        }];
+        if let Some(sampler) = self.sampler_for(format) {
+            entries.push(BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(sampler),
+            });
+        }
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &entries,
+        });
+        {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: None,
+                color_attachments: &[RenderPassColorAttachmentDescriptor {
+                    attachment: &atlas_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let mut x = 0;
+            for (mip_level, extent) in mip_levels.clone().zip(&extents) {
+                event!(
+                    TRACE,
+                    {
+                        backend = "render",
+                        format = format,
+                        level = mip_level,
+                        filter = MipFilter::Box,
+                    },
+                    "drawing level into atlas"
+                );
+                pass.set_viewport(
+                    x as f32,
+                    0.0,
+                    extent.width as f32,
+                    extent.height as f32,
+                    0.0,
+                    1.0,
+                );
+                pass.set_scissor_rect(x, 0, extent.width, extent.height);
+                pass.draw(0..3, 0..1);
+                x += extent.width;
+            }
+        }
+        let mut x = 0;
+        for (mip_level, extent) in mip_levels.zip(&extents) {
+            encoder.copy_texture_to_texture(
+                TextureCopyView {
+                    texture: &atlas,
+                    mip_level: 0,
+                    origin: Origin3d { x, y: 0, z: 0 },
+                },
+                TextureCopyView {
+                    texture,
+                    mip_level,
+                    origin: Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: array_layer,
+                    },
+                },
+                Extent3d {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                },
+            );
+            x += extent.width;
+        }
+        Ok(())
+    }
+
+    /// Generate mipmaps `mip_levels` from level `mip_levels.start - 1` of `src_texture`,
+    /// writing level `n` to level `n - dst_mip_offset` of `dst_texture` with `filter` and
+    /// `rounding`.
+    /// With a `dirty_rect` of level `mip_levels.start - 1`, only the texels computed from it
+    /// are drawn.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn generate_src_dst(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_texture: &Texture,
+        dst_texture: &Texture,
+        src_texture_descriptor: &TextureDescriptor,
+        dst_texture_descriptor: &TextureDescriptor,
+        dst_mip_offset: u32,
+        src_layer: u32,
+        dst_layer: u32,
+        mip_levels: Range<u32>,
+        filter: MipFilter,
+        rounding: BoxRounding,
+        dirty_rect: Option<MipRect>,
+    ) -> Result<(), Error> {
+        let src_format = src_texture_descriptor.format;
+        let src_mip_count = src_texture_descriptor.mip_level_count;
+        let src_ext = src_texture_descriptor.size;
+        let src_dim = src_texture_descriptor.dimension;
+        let src_offset_mip_ext = get_mip_extent(&src_ext, dst_mip_offset);
+
+        let dst_format = dst_texture_descriptor.format;
+        let dst_mip_count = dst_texture_descriptor.mip_level_count;
+        let dst_ext = dst_texture_descriptor.size;
+        let dst_dim = dst_texture_descriptor.dimension;
+        // invariants that we expect callers to uphold
+        if src_format != dst_format {
+            dbg!(src_texture_descriptor);
+            dbg!(dst_texture_descriptor);
+            panic!("src and dst texture formats must be equal");
+        }
+        if src_dim != dst_dim {
+            dbg!(src_texture_descriptor);
+            dbg!(dst_texture_descriptor);
+            panic!("src and dst texture dimensions must be eqaul");
+        }
+        // Only a single layer of each texture is drawn, so their layer counts may differ
+        let same_size = |a: &Extent3d, b: &Extent3d| a.width == b.width && a.height == b.height;
+        if !((src_mip_count == dst_mip_count && same_size(&src_ext, &dst_ext))
+            || (dst_mip_offset > 0 && same_size(&src_offset_mip_ext, &dst_ext)))
+        {
+            dbg!(src_texture_descriptor);
+            dbg!(dst_texture_descriptor);
+            panic!("src and dst texture extents must match or dst must match a mip level of src");
+        }
+
+        let (filter, pipeline, npot_pipeline, layout) = self.validate(
+            src_texture_descriptor,
+            dst_texture_descriptor,
+            filter,
+            rounding,
+        )?;
+        let base_mip_level = mip_levels.start - 1;
+        let views = (base_mip_level..mip_levels.end)
+            .map(|mip_level| {
+                // The first view is the base mip level of the src texture
+                // Subsequent views are for the dst_texture
+                let (texture, base_mip_level, base_array_layer) = if mip_level == base_mip_level {
+                    (src_texture, base_mip_level, src_layer)
+                } else {
+                    (dst_texture, mip_level - dst_mip_offset, dst_layer)
+                };
+                texture.create_view(&TextureViewDescriptor {
+                    label: None,
+                    format: None,
+                    dimension: Some(TextureViewDimension::D2),
+                    aspect: TextureAspect::All,
+                    base_mip_level,
+                    level_count: NonZeroU32::new(1),
+                    array_layer_count: NonZeroU32::new(1),
+                    base_array_layer,
+                })
+            })
+            .collect::<Vec<_>>();
+        let views = views.iter().collect::<Vec<_>>();
+        self.draw_levels(
+            device,
+            encoder,
+            src_texture_descriptor,
+            &views,
+            base_mip_level,
+            filter,
+            (pipeline, npot_pipeline, layout),
+            dirty_rect,
+        );
+        Ok(())
+    }
+
+    /// Draws each level of `views` from the one before it, where `views[0]` is level
+    /// `base_mip_level` of a texture described by `texture_descriptor`.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_levels(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture_descriptor: &TextureDescriptor,
+        views: &[&TextureView],
+        base_mip_level: u32,
+        filter: MipFilter,
+        (pipeline, npot_pipeline, layout): (
+            &RenderPipeline,
+            Option<&RenderPipeline>,
+            &BindGroupLayout,
+        ),
+        dirty_rect: Option<MipRect>,
+    ) {
+        let src_ext = texture_descriptor.size;
+        let format = texture_descriptor.format;
+        // Texels outside the dirty rect must be preserved, so they can't be cleared
+        let load = match dirty_rect {
+            Some(_) => LoadOp::Load,
+            None => self.load_op,
+        };
+        let mut dirty_rect = dirty_rect;
+        for mip in 1..views.len() {
+            let src_view = views[mip - 1];
+            let dst_view = views[mip];
+            // A 2x2 box filter drops a row or column of an odd-sized level, so
+            // those levels use a 3 tap filter that weights every source texel
+            let src_level_ext = get_mip_extent(&src_ext, base_mip_level + mip as u32 - 1);
+            dirty_rect = dirty_rect.map(|rect| rect.next_level(&src_level_ext));
+            // Nothing below an empty rect changes either
+            if dirty_rect.is_some_and(|rect| rect.is_empty()) {
+                break;
+            }
+            event!(
+                TRACE,
+                {
+                    backend = "render",
+                    format = format,
+                    level = base_mip_level + mip as u32,
+                    filter = filter,
+                },
+                "drawing level"
+            );
+            let pipeline = match npot_pipeline {
+                Some(npot_pipeline)
+                    if src_level_ext.width % 2 == 1 || src_level_ext.height % 2 == 1 =>
+                {
+                    npot_pipeline
+                }
+                _ => pipeline,
+            };
+            let texture_entry = BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(src_view),
+            };
+            let params = match filter {
+                MipFilter::Stochastic { seed } => Some(create_stochastic_params(
+                    device,
+                    seed,
+                    base_mip_level + mip as u32,
+                )),
+                _ => None,
+            };
+            // Only the floating-point layouts have a sampler binding
+            let entries = match (&params, self.sampler_for(format)) {
+                (Some(params), _) => vec![
+                    texture_entry,
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Buffer {
+                            buffer: params,
+                            offset: 0,
+                            size: None,
+                        },
+                    },
+                ],
+                (None, Some(sampler)) => vec![
+                    texture_entry,
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(sampler),
+                    },
+                ],
+                (None, None) => vec![texture_entry],
+            };
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout,
+                entries: &entries,
+            });
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: None,
+                color_attachments: &[RenderPassColorAttachmentDescriptor {
+                    attachment: dst_view,
+                    resolve_target: None,
+                    ops: Operations { load, store: true },
+                }],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            if let Some(rect) = dirty_rect {
+                pass.set_scissor_rect(rect.x, rect.y, rect.width, rect.height);
+            }
+            pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+impl MipmapGenerator for RenderMipmapGenerator {
+    fn generate_with_options(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<(), Error> {
+        let _span = span!("generate_mipmaps", {
+            backend = "render",
+            format = texture_descriptor.format,
+            levels = texture_descriptor.mip_level_count,
+            filter = options.filter,
+        });
+        // A single level texture has no levels to generate
+        if texture_descriptor.mip_level_count == 1 {
+            return Ok(());
+        }
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        let tail_start = self.tail_atlas_start(texture_descriptor, options, &mip_levels);
+        self.generate_src_dst(
+            device,
+            encoder,
+            texture,
+            texture,
+            texture_descriptor,
+            texture_descriptor,
+            0,
+            options.array_layer,
+            options.array_layer,
+            mip_levels.start..tail_start.unwrap_or(mip_levels.end),
+            options.filter,
+            options.rounding,
+            options.dirty_rect,
+        )?;
+        match tail_start {
+            Some(tail_start) => self.generate_tail_atlas(
+                device,
+                encoder,
+                texture,
+                texture_descriptor,
+                options.array_layer,
+                tail_start..mip_levels.end,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    fn estimate_cost_with_options(
+        &self,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<GenerationCost, Error> {
+        // A single level texture has no levels to generate
+        if texture_descriptor.mip_level_count == 1 {
+            return Ok(GenerationCost::default());
+        }
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        self.validate(
+            texture_descriptor,
+            texture_descriptor,
+            options.filter,
+            options.rounding,
+        )?;
+        let tail_start = self.tail_atlas_start(texture_descriptor, options, &mip_levels);
+        let mut cost = GenerationCost::per_level(
+            texture_descriptor,
+            mip_levels.start..tail_start.unwrap_or(mip_levels.end),
+        );
+        if let Some(tail_start) = tail_start {
+            // One pass draws every tail level into the atlas, and each is then copied into place
+            cost.passes += 1;
+            cost.dispatches += mip_levels.end - tail_start;
+            cost.bytes_written += 2 * level_bytes(texture_descriptor, tail_start..mip_levels.end);
+        }
+        Ok(cost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    #[allow(dead_code)]
+    async fn generate_and_copy_to_cpu_render(
+        buffer: &[u8],
+        texture_descriptor: &TextureDescriptor<'_>,
+    ) -> Result<Vec<MipBuffer>, Error> {
         let (_instance, _adaptor, device, queue) = wgpu_setup().await;
         let generator = crate::backends::RenderMipmapGenerator::new_with_format_hints(
             &device,
             &[texture_descriptor.format],
         );
-        Ok(
-            generate_and_copy_to_cpu(&device, &queue, &generator, buffer, texture_descriptor)
-                .await?,
-        )
+        generate_and_copy_to_cpu(&device, &queue, &generator, buffer, texture_descriptor).await
     }
 
     async fn generate_test(texture_descriptor: &TextureDescriptor<'_>) -> Result<(), Error> {
         let (_instance, _adapter, device, _queue) = wgpu_setup().await;
         let generator =
             RenderMipmapGenerator::new_with_format_hints(&device, &[texture_descriptor.format]);
-        let texture = device.create_texture(&texture_descriptor);
+        let texture = device.create_texture(texture_descriptor);
         let mut encoder = device.create_command_encoder(&Default::default());
-        generator.generate(&device, &mut encoder, &texture, &texture_descriptor)
+        generator.generate(&device, &mut encoder, &texture, texture_descriptor)
+    }
+
+    #[test]
+    fn render_attachable_formats() {
+        use wgpu::TextureFormat;
+        assert!(is_render_attachable(TextureFormat::Rgba8Unorm));
+        assert!(is_render_attachable(TextureFormat::Rgba32Float));
+        assert!(is_render_attachable(TextureFormat::R16Sint));
+        assert!(!is_render_attachable(TextureFormat::Rgba8Snorm));
+        // Of the packed formats, only Rgb10a2Unorm is guaranteed, so Rg11b10Float falls back
+        // to PackedMipmapGenerator
+        assert!(is_render_attachable(TextureFormat::Rgb10a2Unorm));
+        assert!(!is_render_attachable(TextureFormat::Rg11b10Float));
+    }
+
+    // Binding a texture as filterable when WebGPU doesn't guarantee it fails validation
+    #[test]
+    fn sample_types_match_format_description() {
+        use wgpu::TextureFormat;
+        for &format in &[
+            TextureFormat::R8Unorm,
+            TextureFormat::R16Float,
+            TextureFormat::R32Float,
+            TextureFormat::Rg32Float,
+            TextureFormat::Rgba8UnormSrgb,
+            TextureFormat::Rgb10a2Unorm,
+            TextureFormat::Rgba16Float,
+            TextureFormat::Rgba32Float,
+            TextureFormat::R8Uint,
+            TextureFormat::Rgba32Sint,
+        ] {
+            assert_eq!(
+                to_sample_type(format),
+                format.describe().sample_type,
+                "{:?}",
+                format
+            );
+        }
+    }
+
+    // A classic bug of fullscreen passes is sampling the level above upside down on GL, whose
+    // framebuffer origin is the bottom left. Run with WGPU_MIPMAP_BACKEND=gl to check GL.
+    #[test]
+    fn not_vertically_flipped() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 1,
+            },
+            mip_level_count: 3,
+            format: wgpu::TextureFormat::R8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: RenderMipmapGenerator::required_usage(),
+            label: None,
+        };
+        // White top half, black bottom half, and a white right column in the bottom half so a
+        // horizontal flip is caught too
+        #[rustfmt::skip]
+        let data = [
+            255, 255, 255, 255,
+            255, 255, 255, 255,
+            0, 0, 255, 255,
+            0, 0, 255, 255,
+        ];
+        futures::executor::block_on(async {
+            let mip_buffers = generate_and_copy_to_cpu_render(&data, &texture_descriptor)
+                .await
+                .unwrap();
+            assert_eq!(mip_buffers[1].buffer, vec![255, 255, 0, 255]);
+        });
+    }
+
+    #[test]
+    fn regeneration_overwrites() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 64;
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 7,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: RenderMipmapGenerator::required_usage(),
+            label: None,
+        };
+        let (first, second) = (
+            checkerboard_rgba8(size, size, 8),
+            checkerboard_rgba8(size, size, 2),
+        );
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let mut generator =
+                RenderMipmapGenerator::new_with_format_hints(&device, &[texture_descriptor.format]);
+            let tail_atlas = GenerateOptions {
+                tail_atlas: true,
+                ..Default::default()
+            };
+            for &load_op in &[LoadOp::Clear(Color::TRANSPARENT), LoadOp::Load] {
+                generator.set_load_op(load_op);
+                for options in &[GenerateOptions::default(), tail_atlas] {
+                    assert_regeneration_overwrites(
+                        &device,
+                        &queue,
+                        &generator,
+                        &first,
+                        &second,
+                        &texture_descriptor,
+                        options,
+                    )
+                    .await;
+                }
+            }
+        });
     }
 
     #[test]
     fn sanity_check() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -448,8 +2029,355 @@ mod tests {
         });
     }
 
+    #[test]
+    fn rounding() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 2,
+                height: 2,
+                depth: 1,
+            },
+            mip_level_count: 2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: RenderMipmapGenerator::required_usage(),
+            label: None,
+        };
+        // Red averages to 126.5, green to 127.5 and blue to 0.25
+        let data = [
+            0, 0, 1, 255, 0, 0, 0, 255, //
+            253, 255, 0, 255, 253, 255, 0, 255,
+        ];
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator =
+                RenderMipmapGenerator::new_with_format_hints(&device, &[texture_descriptor.format]);
+            for &(rounding, expected) in &[
+                (BoxRounding::HalfEven, [126, 128, 0, 255]),
+                (BoxRounding::HalfUp, [127, 128, 0, 255]),
+            ] {
+                let options = GenerateOptions {
+                    rounding,
+                    ..Default::default()
+                };
+                let mipmap_buffers = generate_and_copy_to_cpu_with_options(
+                    &device,
+                    &queue,
+                    &generator,
+                    &data,
+                    &texture_descriptor,
+                    &options,
+                )
+                .await
+                .unwrap();
+                assert_eq!(mipmap_buffers[1].buffer, expected, "{:?}", rounding);
+            }
+        });
+    }
+
+    #[test]
+    fn clear_matches_load() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 64;
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: RenderMipmapGenerator::required_usage(),
+            label: None,
+        };
+        let data = checkerboard_rgba8(size, size, 4);
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let mut generator =
+                RenderMipmapGenerator::new_with_format_hints(&device, &[texture_descriptor.format]);
+            assert!(matches!(generator.load_op(), LoadOp::Clear(_)));
+            let cleared =
+                generate_and_copy_to_cpu(&device, &queue, &generator, &data, &texture_descriptor)
+                    .await
+                    .unwrap();
+            generator.set_load_op(LoadOp::Load);
+            let loaded =
+                generate_and_copy_to_cpu(&device, &queue, &generator, &data, &texture_descriptor)
+                    .await
+                    .unwrap();
+            for (cleared, loaded) in cleared.iter().zip(loaded.iter()) {
+                assert_eq!(cleared.buffer, loaded.buffer, "level {}", cleared.level);
+            }
+        });
+    }
+
+    #[test]
+    fn upsample() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 1,
+            },
+            mip_level_count: 3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: RenderMipmapGenerator::required_usage()
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        // Level 1 is black on the left and white on the right
+        let level_1 = [[0u8; 4], [255; 4], [0; 4], [255; 4]].concat();
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator =
+                RenderMipmapGenerator::new_with_format_hints(&device, &[texture_descriptor.format]);
+            let texture = device.create_texture(&texture_descriptor);
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level: 1,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &level_1,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 8,
+                    rows_per_image: 0,
+                },
+                wgpu::Extent3d {
+                    width: 2,
+                    height: 2,
+                    depth: 1,
+                },
+            );
+            // Texel centers of level 0 fall a quarter of a texel of level 1 from its centers,
+            // and bicubic weights overshoot past the edge, which is clamped
+            for &(filter, expected) in &[
+                (UpsampleFilter::Bilinear, [0, 64, 191, 255]),
+                (UpsampleFilter::Bicubic, [0, 52, 203, 255]),
+            ] {
+                let mut encoder = device.create_command_encoder(&Default::default());
+                generator
+                    .upsample(
+                        &device,
+                        &mut encoder,
+                        &texture,
+                        &texture_descriptor,
+                        0..1,
+                        0,
+                        filter,
+                    )
+                    .unwrap();
+                let readback =
+                    MipReadback::encode(&device, &mut encoder, &texture, &texture_descriptor);
+                queue.submit(std::iter::once(encoder.finish()));
+                let read = readback.read();
+                device.poll(wgpu::Maintain::Wait);
+                let levels = read.await.unwrap();
+                for (i, texel) in levels[0].buffer.chunks(4).enumerate() {
+                    let expected = expected[i % 4];
+                    assert!(
+                        texel.iter().all(|&c| (c as i32 - expected).abs() <= 1),
+                        "{:?} texel {}: {:?}",
+                        filter,
+                        i,
+                        texel
+                    );
+                }
+            }
+            let mut encoder = device.create_command_encoder(&Default::default());
+            assert_eq!(
+                generator.upsample(
+                    &device,
+                    &mut encoder,
+                    &texture,
+                    &texture_descriptor,
+                    0..3,
+                    0,
+                    UpsampleFilter::Bilinear,
+                ),
+                Err(Error::InvalidBaseMipLevel(3))
+            );
+        });
+    }
+
+    #[test]
+    fn dirty_rect() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 64;
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: RenderMipmapGenerator::required_usage(),
+            label: None,
+        };
+        let data = checkerboard_rgba8(size, size, 4);
+        let dirty_rect = MipRect {
+            x: 8,
+            y: 16,
+            width: 24,
+            height: 16,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator =
+                RenderMipmapGenerator::new_with_format_hints(&device, &[texture_descriptor.format]);
+            let expected =
+                generate_and_copy_to_cpu(&device, &queue, &generator, &data, &texture_descriptor)
+                    .await
+                    .unwrap();
+            let options = GenerateOptions {
+                dirty_rect: Some(dirty_rect),
+                ..Default::default()
+            };
+            let actual = generate_and_copy_to_cpu_with_options(
+                &device,
+                &queue,
+                &generator,
+                &data,
+                &texture_descriptor,
+                &options,
+            )
+            .await
+            .unwrap();
+            // Only the texels computed from the dirty rect are drawn
+            let mut rect = dirty_rect;
+            for (expected, actual) in expected.iter().zip(actual.iter()).skip(1) {
+                rect = rect.next_level(&get_mip_extent(
+                    &texture_descriptor.size,
+                    expected.level - 1,
+                ));
+                let width = expected.width();
+                let expected = expected.as_slice::<[u8; 4]>();
+                let actual = actual.as_slice::<[u8; 4]>();
+                for y in rect.y..rect.y + rect.height {
+                    let row =
+                        (y * width + rect.x) as usize..(y * width + rect.x + rect.width) as usize;
+                    assert_eq!(expected[row.clone()], actual[row]);
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn tail_atlas() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 256;
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: RenderMipmapGenerator::required_usage() | TextureUsage::COPY_DST,
+            label: None,
+        };
+        let data = checkerboard_rgba8(size, size, 8);
+        let options = GenerateOptions {
+            tail_atlas: true,
+            ..Default::default()
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator =
+                RenderMipmapGenerator::new_with_format_hints(&device, &[texture_descriptor.format]);
+            // Levels 1 and 2 are drawn one pass each, and levels 3 to 8 share a pass
+            let cost = generator
+                .estimate_cost_with_options(&texture_descriptor, &options)
+                .unwrap();
+            assert_eq!(cost.passes, 3);
+            assert_eq!(cost.dispatches, 8);
+            let expected =
+                generate_and_copy_to_cpu(&device, &queue, &generator, &data, &texture_descriptor)
+                    .await
+                    .unwrap();
+            let actual = generate_and_copy_to_cpu_with_options(
+                &device,
+                &queue,
+                &generator,
+                &data,
+                &texture_descriptor,
+                &options,
+            )
+            .await
+            .unwrap();
+            // The atlas levels only differ from level by level generation by rounding
+            for (expected, actual) in expected.iter().zip(actual.iter()) {
+                for (e, a) in expected.buffer.iter().zip(actual.buffer.iter()) {
+                    assert!(
+                        (*e as i32 - *a as i32).abs() <= 2,
+                        "level {}",
+                        expected.level
+                    );
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn pipeline_accessors() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let generator = RenderMipmapGenerator::new_with_format_hints(
+                &device,
+                &[TextureFormat::Rgba8Unorm, TextureFormat::Rgba8Uint],
+            );
+            for format in &[TextureFormat::Rgba8Unorm, TextureFormat::Rgba8Uint] {
+                assert!(generator.render_pipeline_for(*format).is_some());
+                assert!(generator.bind_group_layout_for(*format).is_some());
+            }
+            assert!(generator
+                .render_pipeline_for(TextureFormat::R32Float)
+                .is_none());
+            assert!(generator
+                .bind_group_layout_for(TextureFormat::R32Float)
+                .is_none());
+        });
+    }
+
     #[test]
     fn unsupported_usage() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -479,6 +2407,9 @@ mod tests {
 
     #[test]
     fn unknown_format() {
+        if !adapter_available() {
+            return;
+        }
         init();
         // Generate texture data on the CPU
         let size = 512;
@@ -500,9 +2431,446 @@ mod tests {
             label: None,
         };
         futures::executor::block_on(async {
-            let res = generate_test(&texture_descriptor).await;
+            // Create a generator for a different format
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let generator =
+                RenderMipmapGenerator::new_with_format_hints(&device, &[TextureFormat::R8Unorm]);
+            let texture = device.create_texture(&texture_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let res = generator.generate(&device, &mut encoder, &texture, &texture_descriptor);
             assert!(res.is_err());
             assert!(res.err() == Some(Error::UnknownFormat(wgpu::TextureFormat::Rgba8Sint)));
         });
     }
+
+    // Every level of a constant texture should stay constant, regardless of
+    // how many odd-sized levels the chain passes through
+    fn constant_color_test(width: u32, height: u32) {
+        init();
+        let mip_level_count = 1 + (width.max(height) as f32).log2() as u32;
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: RenderMipmapGenerator::required_usage(),
+            label: None,
+        };
+        let data = vec![200u8; (4 * width * height) as usize];
+        futures::executor::block_on(async {
+            let mipmap_buffers = generate_and_copy_to_cpu_render(&data, &texture_descriptor)
+                .await
+                .unwrap();
+            assert!(mipmap_buffers.len() == mip_level_count as usize);
+            for mip in &mipmap_buffers {
+                for value in &mip.buffer {
+                    assert!(
+                        (*value as i32 - 200).abs() <= 1,
+                        "level {} has value {}",
+                        mip.level,
+                        value
+                    );
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn odd_sizes() {
+        if !adapter_available() {
+            return;
+        }
+        constant_color_test(511, 255);
+    }
+
+    #[test]
+    fn odd_extreme_aspect_ratio() {
+        if !adapter_available() {
+            return;
+        }
+        constant_color_test(3, 1025);
+    }
+
+    #[test]
+    fn unsupported_filter() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 64,
+                height: 64,
+                depth: 1,
+            },
+            mip_level_count: 7,
+            format,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: RenderMipmapGenerator::required_usage(),
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let generator = RenderMipmapGenerator::new_with_format_hints(&device, &[format]);
+            let texture = device.create_texture(&texture_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let res = generator.generate_with_options(
+                &device,
+                &mut encoder,
+                &texture,
+                &texture_descriptor,
+                &GenerateOptions {
+                    filter: MipFilter::Dpid,
+                    ..Default::default()
+                },
+            );
+            assert!(res.err() == Some(Error::UnsupportedFilter(MipFilter::Dpid)));
+        });
+    }
+
+    #[test]
+    fn luminance_pyramid_descriptor() {
+        let src = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 300,
+                height: 17,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            format: TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: TextureUsage::SAMPLED,
+            label: None,
+        };
+        let dst = RenderMipmapGenerator::luminance_pyramid_descriptor(&src);
+        assert_eq!(dst.format, LUMINANCE_FORMAT);
+        // 300 halves to 1 after 8 levels
+        assert_eq!(dst.mip_level_count, 9);
+        assert_eq!(get_mip_extent(&dst.size, 8).width, 1);
+        assert!(dst.usage.contains(RenderMipmapGenerator::required_usage()));
+    }
+
+    #[test]
+    fn fit_descriptor() {
+        let src = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 8192,
+                height: 2048,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            format: TextureFormat::Rgba16Float,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: TextureUsage::SAMPLED,
+            label: None,
+        };
+        let dst = RenderMipmapGenerator::fit_descriptor(&src, 2048);
+        assert_eq!((dst.size.width, dst.size.height), (2048, 512));
+        assert_eq!(dst.mip_level_count, 12);
+        assert_eq!(dst.format, TextureFormat::Rgba16Float);
+        assert!(dst.usage.contains(RenderMipmapGenerator::required_usage()));
+        // Levels are halved, so a texture just over the limit is halved
+        let dst = RenderMipmapGenerator::fit_descriptor(&src, 8191);
+        assert_eq!((dst.size.width, dst.size.height), (4096, 1024));
+        // A texture already within the limit keeps its size
+        let dst = RenderMipmapGenerator::fit_descriptor(&src, 10000);
+        assert_eq!((dst.size.width, dst.size.height), (8192, 2048));
+        assert_eq!(dst.mip_level_count, 14);
+        // Nothing is smaller than the last level
+        let dst = RenderMipmapGenerator::fit_descriptor(&src, 0);
+        assert_eq!((dst.size.width, dst.size.height), (1, 1));
+        assert_eq!(dst.mip_level_count, 1);
+    }
+
+    #[test]
+    fn reduction_descriptor() {
+        let src = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 8192,
+                height: 2048,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            format: TextureFormat::Rgba16Float,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: TextureUsage::SAMPLED,
+            label: None,
+        };
+        // Importing into a 1024x256 texture only allocates levels 1 and 2 of the source
+        let tmp = RenderMipmapGenerator::reduction_descriptor(&src, TextureFormat::Rgba8Unorm, 3);
+        assert_eq!((tmp.size.width, tmp.size.height), (4096, 1024));
+        assert_eq!(tmp.mip_level_count, 2);
+        assert_eq!(tmp.format, TextureFormat::Rgba8Unorm);
+        assert!(tmp.usage.contains(RenderMipmapGenerator::required_usage()));
+    }
+
+    #[test]
+    fn average_luminance() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let src_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 32,
+                height: 8,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            format: TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: TextureUsage::SAMPLED | TextureUsage::COPY_DST,
+            label: None,
+        };
+        let dst_descriptor = RenderMipmapGenerator::luminance_pyramid_descriptor(&src_descriptor);
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator =
+                RenderMipmapGenerator::new_with_format_hints(&device, &[LUMINANCE_FORMAT]);
+            let src = device.create_texture(&src_descriptor);
+            let dst = device.create_texture(&dst_descriptor);
+            // Half white and half black texels average to a luminance of 0.5
+            let data: Vec<u8> = (0..32 * 8)
+                .flat_map(|i| if i % 2 == 0 { [255; 4] } else { [0, 0, 0, 255] })
+                .collect();
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &src,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &data,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * 32,
+                    rows_per_image: 8,
+                },
+                src_descriptor.size,
+            );
+            let mut encoder = device.create_command_encoder(&Default::default());
+            generator
+                .generate_luminance_pyramid(
+                    &device,
+                    &mut encoder,
+                    &src,
+                    &src_descriptor,
+                    &dst,
+                    &dst_descriptor,
+                )
+                .unwrap();
+            let readback = LuminanceReadback::encode(&device, &mut encoder, &dst, &dst_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let luminance = readback.read();
+            device.poll(wgpu::Maintain::Wait);
+            let luminance = luminance.await.unwrap();
+            assert!((luminance - 0.5).abs() < 0.01, "luminance {}", luminance);
+        });
+    }
+
+    #[test]
+    fn luminance_pyramid() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 16;
+        let descriptor = |format, mip_level_count, usage| wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count,
+            format,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage,
+            label: None,
+        };
+        let src_descriptor = descriptor(
+            TextureFormat::Rgba8Unorm,
+            1,
+            TextureUsage::SAMPLED | TextureUsage::COPY_DST,
+        );
+        let dst_descriptor = descriptor(
+            TextureFormat::R8Unorm,
+            5,
+            RenderMipmapGenerator::required_usage() | TextureUsage::COPY_SRC,
+        );
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = RenderMipmapGenerator::new_with_format_hints(
+                &device,
+                &[TextureFormat::R8Unorm, TextureFormat::R8Uint],
+            );
+            let src = device.create_texture(&src_descriptor);
+            let dst = device.create_texture(&dst_descriptor);
+            // Pure green has a luminance of 0.7152
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &src,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &[0, 255, 0, 255].repeat((size * size) as usize),
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * size,
+                    rows_per_image: size,
+                },
+                src_descriptor.size,
+            );
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let uint_descriptor = TextureDescriptor {
+                format: TextureFormat::R8Uint,
+                ..dst_descriptor.clone()
+            };
+            let uint = device.create_texture(&uint_descriptor);
+            assert_eq!(
+                generator.generate_to(
+                    &device,
+                    &mut encoder,
+                    &src,
+                    &src_descriptor,
+                    &uint,
+                    &uint_descriptor,
+                    &ColorConversion::LUMINANCE,
+                ),
+                Err(Error::UnsupportedFormat(TextureFormat::R8Uint))
+            );
+            generator
+                .generate_to(
+                    &device,
+                    &mut encoder,
+                    &src,
+                    &src_descriptor,
+                    &dst,
+                    &dst_descriptor,
+                    &ColorConversion::LUMINANCE,
+                )
+                .unwrap();
+            let readback = MipReadback::encode(&device, &mut encoder, &dst, &dst_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let mips = readback.read();
+            device.poll(wgpu::Maintain::Wait);
+            for mip in mips.await.unwrap() {
+                for &value in mip.as_slice::<u8>() {
+                    assert!((value as i32 - 182).abs() <= 1, "level {}", mip.level);
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn downscale_import() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let descriptor = |size, mip_level_count, usage| wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count,
+            format: TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage,
+            label: None,
+        };
+        let src_descriptor = descriptor(16, 1, TextureUsage::SAMPLED | TextureUsage::COPY_DST);
+        let dst_usage = RenderMipmapGenerator::required_usage() | TextureUsage::COPY_SRC;
+        let dst_descriptor = descriptor(4, 3, dst_usage);
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator =
+                RenderMipmapGenerator::new_with_format_hints(&device, &[TextureFormat::Rgba8Unorm]);
+            let src = device.create_texture(&src_descriptor);
+            let dst = device.create_texture(&dst_descriptor);
+            // The left half is white and the right half black
+            let data: Vec<u8> = (0..16 * 16)
+                .flat_map(|i| if i % 16 < 8 { [255; 4] } else { [0, 0, 0, 255] })
+                .collect();
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &src,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &data,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * 16,
+                    rows_per_image: 16,
+                },
+                src_descriptor.size,
+            );
+            let mut encoder = device.create_command_encoder(&Default::default());
+            // 3x3 isn't the size of any level of a 16x16 texture
+            let odd_descriptor = descriptor(3, 1, dst_usage);
+            let odd = device.create_texture(&odd_descriptor);
+            assert_eq!(
+                generator.generate_to(
+                    &device,
+                    &mut encoder,
+                    &src,
+                    &src_descriptor,
+                    &odd,
+                    &odd_descriptor,
+                    &ColorConversion::IDENTITY,
+                ),
+                Err(Error::ExtentMismatch)
+            );
+            generator
+                .generate_to(
+                    &device,
+                    &mut encoder,
+                    &src,
+                    &src_descriptor,
+                    &dst,
+                    &dst_descriptor,
+                    &ColorConversion::IDENTITY,
+                )
+                .unwrap();
+            let readback = MipReadback::encode(&device, &mut encoder, &dst, &dst_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let mips = readback.read();
+            device.poll(wgpu::Maintain::Wait);
+            let mips = mips.await.unwrap();
+            assert_eq!(mips.len(), 3);
+            // Level 0 is level 2 of the source
+            for (i, texel) in mips[0].as_slice::<[u8; 4]>().iter().enumerate() {
+                let expected = if i % 4 < 2 { 255 } else { 0 };
+                assert_eq!(texel[0], expected, "texel {}", i);
+            }
+            let last = mips[2].as_slice::<[u8; 4]>()[0];
+            assert!((last[0] as i32 - 128).abs() <= 1, "last level {:?}", last);
+
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let (fit, fit_descriptor) = generator
+                .generate_fit(&device, &mut encoder, &src, &src_descriptor, 5)
+                .unwrap();
+            assert_eq!(fit_descriptor.size, dst_descriptor.size);
+            let readback = MipReadback::encode(&device, &mut encoder, &fit, &fit_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let fit_mips = readback.read();
+            device.poll(wgpu::Maintain::Wait);
+            let fit_mips = fit_mips.await.unwrap();
+            assert_eq!(fit_mips.len(), 3);
+            assert_eq!(fit_mips[0].buffer, mips[0].buffer);
+        });
+    }
 }
@@ -0,0 +1,298 @@
+use crate::{
+    backends::render::to_sample_type,
+    core::*,
+    util::{create_mip_level_view, get_mip_extent},
+};
+use wgpu::{
+    util::make_spirv, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, CommandEncoder,
+    ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
+    PipelineLayoutDescriptor, ShaderFlags, ShaderModuleDescriptor, ShaderStage,
+    StorageTextureAccess, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureSampleType, TextureUsage, TextureViewDimension,
+};
+
+/// The format of every summed-area table.
+pub const SAT_FORMAT: TextureFormat = TextureFormat::Rgba32Float;
+
+// Must match the local_size_x value in sat_rows.comp and sat_columns.comp
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Generates summed-area tables, where each texel `(x, y)` holds the sum of all source
+/// texels in the rectangle `(0, 0)..=(x, y)`. Useful for variable-size box blurs and
+/// area lights.
+///
+/// Sums are accumulated in 32-bit floats, so large textures with large values will lose
+/// precision towards the bottom-right corner.
+#[derive(Debug)]
+pub struct SatGenerator {
+    rows_layout: BindGroupLayout,
+    rows_pipeline: ComputePipeline,
+    columns_layout: BindGroupLayout,
+    columns_pipeline: ComputePipeline,
+}
+
+impl SatGenerator {
+    /// Returns the texture usage `SatGenerator` requires of the destination texture.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::STORAGE
+    }
+
+    /// Creates a new `SatGenerator`. Once created, it can be used repeatedly to
+    /// generate summed-area tables from any float texture.
+    pub fn new(device: &Device) -> Self {
+        let rows_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("wgpu-mipmap-sat-rows-bg-layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStage::COMPUTE,
+                    ty: BindingType::Texture {
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                storage_entry(1, StorageTextureAccess::WriteOnly),
+            ],
+        });
+        let columns_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("wgpu-mipmap-sat-columns-bg-layout"),
+            entries: &[
+                storage_entry(0, StorageTextureAccess::ReadOnly),
+                storage_entry(1, StorageTextureAccess::WriteOnly),
+            ],
+        });
+        let pipeline = |layout: &BindGroupLayout, name: &str, source: &[u8]| {
+            let module = device.create_shader_module(&ShaderModuleDescriptor {
+                label: None,
+                source: make_spirv(source),
+                flags: ShaderFlags::empty(),
+            });
+            let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some(&format!("wgpu-mipmap-sat-{}-pipeline", name)),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point: "main",
+            })
+        };
+        let rows_pipeline = pipeline(
+            &rows_layout,
+            "rows",
+            include_bytes!("shaders/sat_rows.comp.spv"),
+        );
+        let columns_pipeline = pipeline(
+            &columns_layout,
+            "columns",
+            include_bytes!("shaders/sat_columns.comp.spv"),
+        );
+        Self {
+            rows_layout,
+            rows_pipeline,
+            columns_layout,
+            columns_pipeline,
+        }
+    }
+
+    /// Returns a descriptor for a summed-area table of level 0 of a texture described by
+    /// `src_texture_descriptor`.
+    pub fn destination_descriptor(
+        src_texture_descriptor: &TextureDescriptor,
+    ) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-sat"),
+            size: get_mip_extent(&src_texture_descriptor.size, 0),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: SAT_FORMAT,
+            usage: Self::required_usage() | TextureUsage::SAMPLED | TextureUsage::COPY_SRC,
+        }
+    }
+
+    /// Creates a texture described by [`SatGenerator::destination_descriptor`].
+    pub fn create_destination_texture(
+        device: &Device,
+        src_texture_descriptor: &TextureDescriptor,
+    ) -> (Texture, TextureDescriptor<'static>) {
+        let descriptor = Self::destination_descriptor(src_texture_descriptor);
+        (device.create_texture(&descriptor), descriptor)
+    }
+
+    /// Encodes commands to write the summed-area table of level 0 of `src_texture`
+    /// into level 0 of `dst_texture`.
+    ///
+    /// Expectations:
+    /// - `src_texture` must have `TextureUsage::SAMPLED` and a float format.
+    /// - `dst_texture` must match the descriptor returned by
+    ///   [`SatGenerator::destination_descriptor`], up to extra usage flags.
+    pub fn generate(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src_texture: &Texture,
+        src_texture_descriptor: &TextureDescriptor,
+        dst_texture: &Texture,
+        dst_texture_descriptor: &TextureDescriptor,
+    ) -> Result<(), Error> {
+        let src_format = src_texture_descriptor.format;
+        if src_texture_descriptor.dimension != TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(
+                src_texture_descriptor.dimension,
+            ));
+        }
+        if !src_texture_descriptor.usage.contains(TextureUsage::SAMPLED) {
+            return Err(Error::UnsupportedUsage(src_texture_descriptor.usage));
+        }
+        if !matches!(to_sample_type(src_format), TextureSampleType::Float { .. }) {
+            return Err(Error::UnsupportedFormat(src_format));
+        }
+        if dst_texture_descriptor.format != SAT_FORMAT {
+            return Err(Error::UnsupportedFormat(dst_texture_descriptor.format));
+        }
+        if !dst_texture_descriptor
+            .usage
+            .contains(Self::required_usage())
+        {
+            return Err(Error::UnsupportedUsage(dst_texture_descriptor.usage));
+        }
+        if src_texture_descriptor.size != dst_texture_descriptor.size {
+            return Err(Error::ExtentMismatch);
+        }
+        // The row sums are written to a temporary texture so that the
+        // column pass never reads and writes the same storage texture
+        let tmp_descriptor = TextureDescriptor {
+            label: None,
+            usage: Self::required_usage(),
+            ..Self::destination_descriptor(src_texture_descriptor)
+        };
+        let tmp_texture = device.create_texture(&tmp_descriptor);
+        let src_view = create_mip_level_view(src_texture, 0);
+        let tmp_view = create_mip_level_view(&tmp_texture, 0);
+        let dst_view = create_mip_level_view(dst_texture, 0);
+        let extent = dst_texture_descriptor.size;
+        let passes = [
+            (
+                &self.rows_layout,
+                &self.rows_pipeline,
+                &src_view,
+                &tmp_view,
+                extent.height,
+            ),
+            (
+                &self.columns_layout,
+                &self.columns_pipeline,
+                &tmp_view,
+                &dst_view,
+                extent.width,
+            ),
+        ];
+        for (layout, pipeline, src_view, dst_view, invocations) in passes.iter() {
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(src_view),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(dst_view),
+                    },
+                ],
+            });
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch(invocations.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        Ok(())
+    }
+}
+
+fn storage_entry(binding: u32, access: StorageTextureAccess) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStage::COMPUTE,
+        ty: BindingType::StorageTexture {
+            access,
+            format: SAT_FORMAT,
+            view_dimension: TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn src_descriptor(format: TextureFormat) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 300,
+                height: 200,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            format,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: TextureUsage::SAMPLED,
+            label: None,
+        }
+    }
+
+    async fn generate_test(src_descriptor: &TextureDescriptor<'_>) -> Result<(), Error> {
+        let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+        let generator = SatGenerator::new(&device);
+        let src = device.create_texture(src_descriptor);
+        let (dst, dst_descriptor) =
+            SatGenerator::create_destination_texture(&device, src_descriptor);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        generator.generate(
+            &device,
+            &mut encoder,
+            &src,
+            src_descriptor,
+            &dst,
+            &dst_descriptor,
+        )
+    }
+
+    #[test]
+    fn sanity_check() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let res = generate_test(&src_descriptor(TextureFormat::Rgba8Unorm)).await;
+            assert!(res.is_ok());
+        });
+    }
+
+    #[test]
+    fn unsupported_format() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let res = generate_test(&src_descriptor(TextureFormat::Rgba8Uint)).await;
+            assert!(res.err() == Some(Error::UnsupportedFormat(TextureFormat::Rgba8Uint)));
+        });
+    }
+}
@@ -0,0 +1,517 @@
+use super::{copy::copy_written_rects, recommended::RecommendedMipmapGenerator};
+use crate::{
+    core::*,
+    trace::span,
+    util::{format_bytes_per_channel, get_mip_extent, level_bytes, MipBufferDimensions},
+};
+use std::sync::Mutex;
+use wgpu::{
+    BufferCopyView, BufferDescriptor, BufferUsage, CommandEncoder, Device, Extent3d, Origin3d,
+    Texture, TextureCopyView, TextureDataLayout, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureSampleType, TextureUsage,
+};
+
+// The number of scratch textures kept for reuse. The least recently used is dropped first.
+const MAX_POOLED: usize = 8;
+
+/// The shape of a scratch texture, which textures with the same shape can share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ScratchKey {
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    mip_level_count: u32,
+}
+
+/// Generates mipmaps in scratch textures owned by the generator, then copies the generated
+/// levels into the destination texture, which therefore needs neither `SAMPLED`,
+/// `RENDER_ATTACHMENT` nor `STORAGE` usage.
+///
+/// The levels are generated by a [`RecommendedMipmapGenerator`], so every format it supports
+/// is supported. `generate` copies the source level out of the destination, which needs
+/// `COPY_SRC | COPY_DST`, see [`ScratchMipmapGenerator::required_usage`].
+/// [`ScratchMipmapGenerator::generate_from_data`] uploads the source level from the CPU
+/// instead, and only needs `COPY_DST`.
+///
+/// Scratch textures are pooled by format and size, so generating many textures of the same
+/// shape creates a single one. Commands reusing a scratch texture are ordered by the encoders
+/// they're recorded into, so any number of textures can be generated before submitting.
+///
+/// ```no_run
+/// # use wgpu_mipmap::*;
+/// # fn example(device: &wgpu::Device, queue: &wgpu::Queue, level_0: &[u8]) -> Result<(), Error> {
+/// let generator = ScratchMipmapGenerator::new(device);
+/// let texture_descriptor = wgpu::TextureDescriptor {
+///     size: wgpu::Extent3d { width: 256, height: 256, depth: 1 },
+///     mip_level_count: 9,
+///     format: wgpu::TextureFormat::Rgba8UnormSrgb,
+///     sample_count: 1,
+///     dimension: wgpu::TextureDimension::D2,
+///     usage: wgpu::TextureUsage::COPY_DST,
+///     label: None,
+/// };
+/// let texture = device.create_texture(&texture_descriptor);
+/// let mut encoder = device.create_command_encoder(&Default::default());
+/// generator.generate_from_data(
+///     device,
+///     &mut encoder,
+///     level_0,
+///     &texture,
+///     &texture_descriptor,
+///     &GenerateOptions::default(),
+/// )?;
+/// queue.submit(std::iter::once(encoder.finish()));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ScratchMipmapGenerator {
+    generator: RecommendedMipmapGenerator,
+    // Most recently used last
+    pool: Mutex<Vec<(ScratchKey, Texture)>>,
+}
+
+impl ScratchMipmapGenerator {
+    /// Creates a new `ScratchMipmapGenerator`. Once created, it can be used repeatedly to
+    /// generate mipmaps for any texture with a format `RecommendedMipmapGenerator` supports.
+    pub fn new(device: &Device) -> Self {
+        Self::from_generator(RecommendedMipmapGenerator::new(device))
+    }
+
+    /// Creates a new `ScratchMipmapGenerator` whose scratch textures are generated by
+    /// `generator`, e.g. one created with format hints or a pipeline cache.
+    pub fn from_generator(generator: RecommendedMipmapGenerator) -> Self {
+        Self {
+            generator,
+            pool: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the texture usage `generate` requires of the destination texture.
+    /// [`ScratchMipmapGenerator::generate_from_data`] only requires `COPY_DST`.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::COPY_SRC | TextureUsage::COPY_DST
+    }
+
+    /// Returns the generator that generates the scratch textures.
+    pub fn generator(&self) -> &RecommendedMipmapGenerator {
+        &self.generator
+    }
+
+    /// Returns the number of scratch textures kept for reuse.
+    pub fn pooled_count(&self) -> usize {
+        self.pool.lock().unwrap().len()
+    }
+
+    /// Drops every pooled scratch texture, e.g. after importing a batch of textures.
+    pub fn trim(&self) {
+        self.pool.lock().unwrap().clear();
+    }
+
+    /// Encodes commands to upload `source_level`, the tightly packed texels of level
+    /// `options.base_mip_level` and layer `options.array_layer`, and generate the levels
+    /// selected by `options` from it. Only the generated levels are written to `texture`, so
+    /// the source level must still be written to it, e.g. with `Queue::write_texture`, to be
+    /// sampled.
+    ///
+    /// Returns `Error::ExtentMismatch` if `source_level` doesn't hold exactly the texels of
+    /// the level.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`,
+    ///   which must have `TextureUsage::COPY_DST`.
+    pub fn generate_from_data(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        source_level: &[u8],
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<(), Error> {
+        let _span = span!("generate_mipmaps", {
+            backend = "scratch",
+            format = texture_descriptor.format,
+            levels = texture_descriptor.mip_level_count,
+            filter = options.filter,
+        });
+        self.generate_in_scratch(
+            device,
+            encoder,
+            texture,
+            texture_descriptor,
+            options,
+            TextureUsage::COPY_DST,
+            |encoder, scratch, extent| {
+                let dims = MipBufferDimensions::new(
+                    extent.width as usize,
+                    extent.height as usize,
+                    format_bytes_per_channel(&texture_descriptor.format),
+                );
+                let (unpadded_bytes_per_row, padded_bytes_per_row) =
+                    (dims.unpadded_bytes_per_row, dims.padded_bytes_per_row);
+                if source_level.len() != unpadded_bytes_per_row * dims.height {
+                    return Err(Error::ExtentMismatch);
+                }
+                // Uploading through a buffer copy, rather than `Queue::write_texture`, orders
+                // the upload with the other commands using the pooled scratch texture
+                let staging = device.create_buffer(&BufferDescriptor {
+                    label: Some("wgpu-mipmap-scratch-upload"),
+                    size: (padded_bytes_per_row * extent.height as usize) as u64,
+                    usage: BufferUsage::COPY_SRC,
+                    mapped_at_creation: true,
+                });
+                {
+                    let mut mapped = staging.slice(..).get_mapped_range_mut();
+                    for (dst, src) in mapped
+                        .chunks_mut(padded_bytes_per_row)
+                        .zip(source_level.chunks(unpadded_bytes_per_row))
+                    {
+                        dst[..unpadded_bytes_per_row].copy_from_slice(src);
+                    }
+                }
+                staging.unmap();
+                encoder.copy_buffer_to_texture(
+                    BufferCopyView {
+                        buffer: &staging,
+                        layout: TextureDataLayout {
+                            offset: 0,
+                            bytes_per_row: padded_bytes_per_row as u32,
+                            rows_per_image: 0,
+                        },
+                    },
+                    TextureCopyView {
+                        texture: scratch,
+                        mip_level: 0,
+                        origin: Origin3d::ZERO,
+                    },
+                    extent,
+                );
+                Ok(())
+            },
+        )
+    }
+
+    /// Encodes commands to fill level 0 of a scratch texture with `fill`, generate its other
+    /// levels and copy them into the levels of `texture` selected by `options`.
+    #[allow(clippy::too_many_arguments)]
+    fn generate_in_scratch(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+        usage: TextureUsage,
+        fill: impl FnOnce(&mut CommandEncoder, &Texture, Extent3d) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let (key, mip_levels) = validate(texture_descriptor, options, usage)?;
+        if mip_levels.start == mip_levels.end {
+            return Ok(());
+        }
+        let base_mip_level = mip_levels.start - 1;
+        let rects = options.written_rects(texture_descriptor)?;
+        let scratch_descriptor = self.scratch_descriptor(key);
+        let scratch = self.take_scratch(device, key, &scratch_descriptor);
+        let result = fill(encoder, &scratch, scratch_descriptor.size).and_then(|()| {
+            self.generator.generate_with_options(
+                device,
+                encoder,
+                &scratch,
+                &scratch_descriptor,
+                &scratch_options(options),
+            )
+        });
+        if result.is_ok() {
+            copy_written_rects(
+                encoder,
+                &scratch,
+                base_mip_level,
+                texture,
+                options.array_layer,
+                &rects,
+            );
+        }
+        self.return_scratch(key, scratch);
+        result
+    }
+
+    fn scratch_descriptor(&self, key: ScratchKey) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-scratch"),
+            size: Extent3d {
+                width: key.width,
+                height: key.height,
+                depth: 1,
+            },
+            mip_level_count: key.mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: key.format,
+            usage: self.generator.scratch_usage(key.format),
+        }
+    }
+
+    /// Returns a pooled scratch texture with the shape of `key`, or creates one.
+    fn take_scratch(
+        &self,
+        device: &Device,
+        key: ScratchKey,
+        descriptor: &TextureDescriptor,
+    ) -> Texture {
+        let mut pool = self.pool.lock().unwrap();
+        match pool.iter().position(|(pooled, _)| *pooled == key) {
+            Some(index) => pool.remove(index).1,
+            None => device.create_texture(descriptor),
+        }
+    }
+
+    fn return_scratch(&self, key: ScratchKey, scratch: Texture) {
+        let mut pool = self.pool.lock().unwrap();
+        if pool.len() == MAX_POOLED {
+            pool.remove(0);
+        }
+        pool.push((key, scratch));
+    }
+}
+
+impl MipmapGenerator for ScratchMipmapGenerator {
+    fn generate_with_options(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<(), Error> {
+        let _span = span!("generate_mipmaps", {
+            backend = "scratch",
+            format = texture_descriptor.format,
+            levels = texture_descriptor.mip_level_count,
+            filter = options.filter,
+        });
+        self.generate_in_scratch(
+            device,
+            encoder,
+            texture,
+            texture_descriptor,
+            options,
+            Self::required_usage(),
+            |encoder, scratch, extent| {
+                encoder.copy_texture_to_texture(
+                    TextureCopyView {
+                        texture,
+                        mip_level: options.base_mip_level,
+                        origin: Origin3d {
+                            z: options.array_layer,
+                            ..Origin3d::ZERO
+                        },
+                    },
+                    TextureCopyView {
+                        texture: scratch,
+                        mip_level: 0,
+                        origin: Origin3d::ZERO,
+                    },
+                    extent,
+                );
+                Ok(())
+            },
+        )
+    }
+
+    fn estimate_cost_with_options(
+        &self,
+        texture_descriptor: &TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<GenerationCost, Error> {
+        let (key, mip_levels) = validate(texture_descriptor, options, Self::required_usage())?;
+        if mip_levels.start == mip_levels.end {
+            return Ok(GenerationCost::default());
+        }
+        let cost = self
+            .generator
+            .estimate_cost_with_options(&self.scratch_descriptor(key), &scratch_options(options))?;
+        // The source level is copied in, and the generated levels copied back
+        let copies = level_bytes(texture_descriptor, mip_levels.start - 1..mip_levels.end);
+        Ok(GenerationCost {
+            bytes_written: cost.bytes_written + copies,
+            ..cost
+        })
+    }
+}
+
+/// Checks that the levels of `texture_descriptor` selected by `options` can be generated in a
+/// scratch texture and copied into a texture with `usage`, and returns the shape of the
+/// scratch texture and the levels.
+fn validate(
+    texture_descriptor: &TextureDescriptor,
+    options: &GenerateOptions,
+    usage: TextureUsage,
+) -> Result<(ScratchKey, std::ops::Range<u32>), Error> {
+    if !texture_descriptor.usage.contains(usage) {
+        return Err(Error::UnsupportedUsage(texture_descriptor.usage));
+    }
+    if texture_descriptor.dimension != TextureDimension::D2 {
+        return Err(Error::UnsupportedDimension(texture_descriptor.dimension));
+    }
+    let info = texture_descriptor.format.describe();
+    if info.block_dimensions != (1, 1) || info.sample_type == TextureSampleType::Depth {
+        return Err(Error::UnsupportedFormat(texture_descriptor.format));
+    }
+    let mip_levels = options.mip_levels(texture_descriptor)?;
+    let base_mip_level = mip_levels.start - 1;
+    let extent = get_mip_extent(&texture_descriptor.size, base_mip_level);
+    let key = ScratchKey {
+        format: texture_descriptor.format,
+        width: extent.width,
+        height: extent.height,
+        mip_level_count: mip_levels.end - base_mip_level,
+    };
+    Ok((key, mip_levels))
+}
+
+/// Returns the options generating every level of a scratch texture from its level 0.
+fn scratch_options(options: &GenerateOptions) -> GenerateOptions {
+    GenerateOptions {
+        base_mip_level: 0,
+        min_dimension: 1,
+        max_level_count: None,
+        array_layer: 0,
+        // The generated levels of the pooled scratch texture hold stale texels, which the
+        // texels computed from the dirty rect read around it, so every level is generated
+        // whole and only the texels computed from the dirty rect are copied back
+        dirty_rect: None,
+        ..*options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn descriptor(usage: TextureUsage) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            size: Extent3d {
+                width: 16,
+                height: 16,
+                depth: 1,
+            },
+            mip_level_count: 5,
+            format: TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn scratch_shape() {
+        let options = GenerateOptions {
+            base_mip_level: 1,
+            max_level_count: Some(2),
+            ..GenerateOptions::default()
+        };
+        let (key, levels) = validate(
+            &descriptor(TextureUsage::COPY_DST),
+            &options,
+            TextureUsage::COPY_DST,
+        )
+        .unwrap();
+        assert_eq!(levels, 2..4);
+        assert_eq!(
+            key,
+            ScratchKey {
+                format: TextureFormat::Rgba8Unorm,
+                width: 8,
+                height: 8,
+                mip_level_count: 3,
+            }
+        );
+        assert_eq!(
+            validate(
+                &descriptor(TextureUsage::COPY_DST),
+                &options,
+                ScratchMipmapGenerator::required_usage()
+            ),
+            Err(Error::UnsupportedUsage(TextureUsage::COPY_DST))
+        );
+    }
+
+    #[test]
+    fn generates_into_copy_dst_textures() {
+        if !adapter_available() {
+            return;
+        }
+        let data = checkerboard_rgba8(16, 16, 2);
+        // The readback needs COPY_SRC, which the generator doesn't use
+        let texture_descriptor = descriptor(TextureUsage::COPY_DST | TextureUsage::COPY_SRC);
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = ScratchMipmapGenerator::from_generator(
+                RecommendedMipmapGenerator::new_with_format_hints(
+                    &device,
+                    &[texture_descriptor.format],
+                ),
+            );
+            let expected = generate_and_copy_to_cpu(
+                &device,
+                &queue,
+                generator.generator(),
+                &data,
+                &wgpu::TextureDescriptor {
+                    usage: crate::RenderMipmapGenerator::required_usage() | TextureUsage::COPY_SRC,
+                    ..texture_descriptor.clone()
+                },
+            )
+            .await
+            .unwrap();
+            // Two textures of the same shape share a scratch texture
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let textures = (0..2)
+                .map(|_| {
+                    let texture = device.create_texture(&texture_descriptor);
+                    generator
+                        .generate_from_data(
+                            &device,
+                            &mut encoder,
+                            &data,
+                            &texture,
+                            &texture_descriptor,
+                            &GenerateOptions::default(),
+                        )
+                        .unwrap();
+                    texture
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(generator.pooled_count(), 1);
+            assert_eq!(
+                generator.generate_from_data(
+                    &device,
+                    &mut encoder,
+                    &data[4..],
+                    &textures[0],
+                    &texture_descriptor,
+                    &GenerateOptions::default(),
+                ),
+                Err(Error::ExtentMismatch)
+            );
+            let readbacks = textures
+                .iter()
+                .map(|texture| {
+                    MipReadback::encode(&device, &mut encoder, texture, &texture_descriptor)
+                })
+                .collect::<Vec<_>>();
+            queue.submit(std::iter::once(encoder.finish()));
+            let reads = readbacks.iter().map(|r| r.read()).collect::<Vec<_>>();
+            device.poll(wgpu::Maintain::Wait);
+            for read in reads {
+                let levels = read.await.unwrap();
+                for (level, expected) in levels.iter().zip(&expected).skip(1) {
+                    assert_eq!(level.buffer, expected.buffer, "level {}", level.level);
+                }
+            }
+        });
+    }
+}
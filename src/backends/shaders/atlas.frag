@@ -0,0 +1,33 @@
+#version 450
+#extension GL_EXT_samplerless_texture_functions : require
+// Draws the last levels of a mip chain into an atlas, laid out left to right starting
+// with the level after u_texture, in a single render pass. Each texel averages the block
+// of u_texture texels it covers, so every level is filtered from the same source level.
+layout(set = 0, binding = 0) uniform texture2D u_texture;
+
+layout(location = 0) out vec4 out_color;
+layout(location = 0) in vec2 v_uv;
+
+void main() {
+  ivec2 src_size = textureSize(u_texture, 0);
+  ivec2 size = max(src_size / 2, 1);
+  ivec2 uv = ivec2(gl_FragCoord.xy);
+  // Find the level the texel belongs to, and its position in that level
+  for (int i = 0; i < 16; ++i) {
+    if (uv.x >= size.x) {
+      uv.x -= size.x;
+      size = max(size / 2, 1);
+    }
+  }
+  // Odd-sized blocks are rounded so the blocks of a level cover every source texel
+  ivec2 lo = uv * src_size / size;
+  ivec2 hi = max((uv + 1) * src_size / size, lo + 1);
+  vec4 c = vec4(0.0);
+  for (int y = lo.y; y < hi.y; ++y) {
+    for (int x = lo.x; x < hi.x; ++x) {
+      c += texelFetch(u_texture, ivec2(x, y), 0);
+    }
+  }
+  ivec2 count = hi - lo;
+  out_color = c / float(count.x * count.y);
+}
@@ -0,0 +1,25 @@
+#version 450
+// Box filter for 8 bit unorm textures from even sized levels. The four texels are fetched and
+// their sum is recovered exactly as an integer, so the average rounds the same way on every
+// implementation: halves round up with ROUND_HALF_UP, or to even otherwise. The values must
+// match BoxRounding in core.rs
+layout(set = 0, binding = 0) uniform texture2D u_texture;
+layout(set = 0, binding = 1) uniform sampler u_sampler;
+
+layout(location = 0) out vec4 out_color;
+layout(location = 0) in vec2 v_uv;
+
+#define F(u) texelFetch(sampler2D(u_texture, u_sampler), clamp(u, ivec2(0), size - 1), 0)
+
+void main() {
+  ivec2 size = textureSize(sampler2D(u_texture, u_sampler), 0);
+  ivec2 src_uv = 2 * ivec2(gl_FragCoord.xy);
+  vec4 sum = F(src_uv + ivec2(0, 0)) + F(src_uv + ivec2(1, 0)) + F(src_uv + ivec2(0, 1)) +
+             F(src_uv + ivec2(1, 1));
+  uvec4 total = uvec4(roundEven(sum * 255.0));
+#ifdef ROUND_HALF_UP
+  out_color = vec4((total + 2u) >> 2) / 255.0;
+#else
+  out_color = vec4((total + 1u + ((total >> 2) & 1u)) >> 2) / 255.0;
+#endif
+}
@@ -0,0 +1,25 @@
+#version 450
+#extension GL_EXT_samplerless_texture_functions : require
+// Downsamples a chroma plane whose samples are co-sited with the left luma
+// sample of each pair (MPEG-2, BT.709 and most video decoders). Each
+// destination texel stays co-sited with source texel 2x, so horizontally it
+// covers source texels 2x - 1, 2x and 2x + 1 with weights (1, 2, 1) / 4.
+// Vertically chroma is centered between luma rows, so a 2 texel box is used.
+layout(set = 0, binding = 0) uniform texture2D u_texture;
+
+layout(location = 0) out vec4 out_color;
+layout(location = 0) in vec2 v_uv;
+
+void main() {
+  ivec2 src_size = textureSize(u_texture, 0);
+  ivec2 dst_uv = ivec2(gl_FragCoord.xy);
+  vec3 wx = vec3(0.25, 0.5, 0.25);
+  vec4 c = vec4(0.0);
+  for (int j = 0; j < 2; ++j) {
+    for (int i = 0; i < 3; ++i) {
+      ivec2 uv = clamp(2 * dst_uv + ivec2(i - 1, j), ivec2(0), src_size - 1);
+      c += 0.5 * wx[i] * texelFetch(u_texture, uv, 0);
+    }
+  }
+  out_color = c;
+}
@@ -2,16 +2,71 @@
 set -e
 
 function compile {
-  glslc -c $1 -o $2
+  glslc -c $1 -o $2 "${@:3}"
   spirv-opt -Os $2 -o $2
 }
 
 
 cd "$(dirname "$0")"
-compile triangle.vert  triangle.vert.spv
 compile box.frag box.frag.spv
+compile box_unorm8.frag box_unorm8_half_even.frag.spv
+compile box_unorm8.frag box_unorm8_half_up.frag.spv -DROUND_HALF_UP
+compile npot.frag npot.frag.spv
+compile chroma_cosited.frag chroma_cosited.frag.spv
+compile equirect.frag equirect.frag.spv
+compile convert.frag convert.frag.spv
+compile convert.frag convert_reduce.frag.spv -DREDUCE
+compile atlas.frag atlas.frag.spv
+compile max_magnitude.frag max_magnitude.frag.spv
+compile upsample.frag upsample_bilinear.frag.spv
+compile upsample.frag upsample_bicubic.frag.spv -DBICUBIC
+(PREFIX= envsubst < nearest.frag) > nearest_float.frag
+(PREFIX=u envsubst < nearest.frag) > nearest_uint.frag
+(PREFIX=i envsubst < nearest.frag) > nearest_sint.frag
+(PREFIX= envsubst < stochastic.frag) > stochastic_float.frag
+(PREFIX=u envsubst < stochastic.frag) > stochastic_uint.frag
+(PREFIX=i envsubst < stochastic.frag) > stochastic_sint.frag
+(PREFIX=u envsubst < mode.frag) > mode_uint.frag
+(PREFIX=i envsubst < mode.frag) > mode_sint.frag
+for NAME in nearest_float nearest_uint nearest_sint stochastic_float stochastic_uint stochastic_sint mode_uint mode_sint; do
+  compile ${NAME}.frag ${NAME}.frag.spv
+  rm ${NAME}.frag
+done
 compile box_srgb.comp box_srgb.comp.spv
 compile box_srgb_macos.comp box_srgb_macos.comp.spv
+compile box_srgb_store.comp box_srgb_store.comp.spv
+compile box_rgba8_gather.comp box_rgba8_gather_half_even.comp.spv
+compile box_rgba8_gather.comp box_rgba8_gather_half_up.comp.spv -DROUND_HALF_UP
+# The workgroup size variants selected by WorkgroupSize in backends/compute.rs
+WORKGROUP_SIZES=(8 16)
+for SIZE in ${WORKGROUP_SIZES[@]}; do
+  for NAME in box_srgb box_srgb_macos box_srgb_store box_rgba8_gather; do
+    sed "s/local_size_x = 32, local_size_y = 32/local_size_x = ${SIZE}, local_size_y = ${SIZE}/" \
+      ${NAME}.comp > ${NAME}_${SIZE}x${SIZE}.comp
+    if [ ${NAME} = box_rgba8_gather ]; then
+      compile ${NAME}_${SIZE}x${SIZE}.comp ${NAME}_half_even_${SIZE}x${SIZE}.comp.spv
+      compile ${NAME}_${SIZE}x${SIZE}.comp ${NAME}_half_up_${SIZE}x${SIZE}.comp.spv -DROUND_HALF_UP
+    else
+      compile ${NAME}_${SIZE}x${SIZE}.comp ${NAME}_${SIZE}x${SIZE}.comp.spv
+    fi
+    rm ${NAME}_${SIZE}x${SIZE}.comp
+  done
+done
+compile srgb_probe.comp srgb_probe.comp.spv
+compile minmax_seed.comp minmax_seed.comp.spv
+compile minmax.comp minmax.comp.spv
+compile toksvig_seed.comp toksvig_seed.comp.spv
+compile toksvig.comp toksvig.comp.spv
+compile sat_rows.comp sat_rows.comp.spv
+compile sat_columns.comp sat_columns.comp.spv
+compile prefilter.comp prefilter.comp.spv
+compile irradiance.comp irradiance.comp.spv
+compile bc.comp bc1.comp.spv
+compile bc.comp bc3.comp.spv -DBC3
+compile bc.comp bc7.comp.spv -DBC7
+compile digest.comp digest.comp.spv
+compile packed.comp packed_rgb10_a2.comp.spv
+compile packed.comp packed_r11f_g11f_b10f.comp.spv -DR11F_G11F_B10F
 
 # https://www.khronos.org/opengl/wiki/Image_Load_Store#Format_qualifiers
 SUPPORTED_FORMATS=(
@@ -33,8 +88,102 @@ SUPPORTED_FORMATS=(
   r16_snorm
   r8_snorm
 )
+# 8 bit unorm formats also get box filters that round explicitly, see BoxRounding in core.rs
+for FORMAT in rgba8 rg8 r8; do
+  for SIZE in 32 ${WORKGROUP_SIZES[@]}; do
+    if [ ${SIZE} = 32 ]; then SUFFIX=; else SUFFIX=_${SIZE}x${SIZE}; fi
+    (SIZE=${SIZE} FORMAT=${FORMAT} envsubst < box.comp) > box_${FORMAT}${SUFFIX}.comp
+    compile box_${FORMAT}${SUFFIX}.comp box_${FORMAT}_half_even${SUFFIX}.comp.spv -DUNORM8
+    compile box_${FORMAT}${SUFFIX}.comp box_${FORMAT}_half_up${SUFFIX}.comp.spv -DUNORM8 -DROUND_HALF_UP
+    rm box_${FORMAT}${SUFFIX}.comp
+  done
+done
 for FORMAT in ${SUPPORTED_FORMATS[@]}; do
-  (FORMAT=${FORMAT} envsubst < box.comp) > box_${FORMAT}.comp
+  (SIZE=32 FORMAT=${FORMAT} envsubst < box.comp) > box_${FORMAT}.comp
   compile box_${FORMAT}.comp box_${FORMAT}.comp.spv
   rm box_${FORMAT}.comp
+  for SIZE in ${WORKGROUP_SIZES[@]}; do
+    (SIZE=${SIZE} FORMAT=${FORMAT} envsubst < box.comp) > box_${FORMAT}_${SIZE}x${SIZE}.comp
+    compile box_${FORMAT}_${SIZE}x${SIZE}.comp box_${FORMAT}_${SIZE}x${SIZE}.comp.spv
+    rm box_${FORMAT}_${SIZE}x${SIZE}.comp
+  done
+  (FORMAT=${FORMAT} envsubst < box2.comp) > box2_${FORMAT}.comp
+  compile box2_${FORMAT}.comp box2_${FORMAT}.comp.spv
+  rm box2_${FORMAT}.comp
+  (FORMAT=${FORMAT} envsubst < tail.comp) > tail_${FORMAT}.comp
+  compile tail_${FORMAT}.comp tail_${FORMAT}.comp.spv
+  rm tail_${FORMAT}.comp
+  (PREFIX= FORMAT=${FORMAT} envsubst < nearest.comp) > nearest_${FORMAT}.comp
+  compile nearest_${FORMAT}.comp nearest_${FORMAT}.comp.spv
+  rm nearest_${FORMAT}.comp
+  (PREFIX= FORMAT=${FORMAT} envsubst < stochastic.comp) > stochastic_${FORMAT}.comp
+  compile stochastic_${FORMAT}.comp stochastic_${FORMAT}.comp.spv
+  rm stochastic_${FORMAT}.comp
+  (FORMAT=${FORMAT} envsubst < equirect.comp) > equirect_${FORMAT}.comp
+  compile equirect_${FORMAT}.comp equirect_${FORMAT}.comp.spv
+  rm equirect_${FORMAT}.comp
+  (FORMAT=${FORMAT} envsubst < masked.comp) > masked_${FORMAT}.comp
+  compile masked_${FORMAT}.comp masked_${FORMAT}.comp.spv
+  rm masked_${FORMAT}.comp
+  (FORMAT=${FORMAT} envsubst < masked_pull.comp) > masked_pull_${FORMAT}.comp
+  compile masked_pull_${FORMAT}.comp masked_pull_${FORMAT}.comp.spv
+  rm masked_pull_${FORMAT}.comp
+  (FORMAT=${FORMAT} envsubst < masked_fill.comp) > masked_fill_${FORMAT}.comp
+  compile masked_fill_${FORMAT}.comp masked_fill_${FORMAT}.comp.spv
+  rm masked_fill_${FORMAT}.comp
+  (FORMAT=${FORMAT} envsubst < bilateral.comp) > bilateral_${FORMAT}.comp
+  compile bilateral_${FORMAT}.comp bilateral_${FORMAT}.comp.spv
+  rm bilateral_${FORMAT}.comp
+done
+
+# Integer formats only support the nearest, stochastic and mode filters
+UNSIGNED_FORMATS=(rgba32ui rgba16ui rgba8ui rg32ui rg16ui rg8ui r32ui r16ui r8ui)
+SIGNED_FORMATS=(rgba32i rgba16i rgba8i rg32i rg16i rg8i r32i r16i r8i)
+for FORMAT in ${UNSIGNED_FORMATS[@]}; do
+  (PREFIX=u FORMAT=${FORMAT} envsubst < nearest.comp) > nearest_${FORMAT}.comp
+  compile nearest_${FORMAT}.comp nearest_${FORMAT}.comp.spv
+  rm nearest_${FORMAT}.comp
+  (PREFIX=u FORMAT=${FORMAT} envsubst < stochastic.comp) > stochastic_${FORMAT}.comp
+  compile stochastic_${FORMAT}.comp stochastic_${FORMAT}.comp.spv
+  rm stochastic_${FORMAT}.comp
+  (PREFIX=u FORMAT=${FORMAT} envsubst < mode.comp) > mode_${FORMAT}.comp
+  compile mode_${FORMAT}.comp mode_${FORMAT}.comp.spv
+  rm mode_${FORMAT}.comp
+done
+for FORMAT in ${SIGNED_FORMATS[@]}; do
+  (PREFIX=i FORMAT=${FORMAT} envsubst < nearest.comp) > nearest_${FORMAT}.comp
+  compile nearest_${FORMAT}.comp nearest_${FORMAT}.comp.spv
+  rm nearest_${FORMAT}.comp
+  (PREFIX=i FORMAT=${FORMAT} envsubst < stochastic.comp) > stochastic_${FORMAT}.comp
+  compile stochastic_${FORMAT}.comp stochastic_${FORMAT}.comp.spv
+  rm stochastic_${FORMAT}.comp
+  (PREFIX=i FORMAT=${FORMAT} envsubst < mode.comp) > mode_${FORMAT}.comp
+  compile mode_${FORMAT}.comp mode_${FORMAT}.comp.spv
+  rm mode_${FORMAT}.comp
+done
+
+# Max magnitude selection is only provided for velocity formats
+for FORMAT in rg16f rg32f; do
+  (FORMAT=${FORMAT} envsubst < max_magnitude.comp) > max_magnitude_${FORMAT}.comp
+  compile max_magnitude_${FORMAT}.comp max_magnitude_${FORMAT}.comp.spv
+  rm max_magnitude_${FORMAT}.comp
+done
+
+# Shadow moment maps are only provided for 32-bit float formats
+for FORMAT in rg32f rgba32f; do
+  for NAME in moments_seed moments; do
+    (FORMAT=${FORMAT} envsubst < ${NAME}.comp) > ${NAME}_${FORMAT}.comp
+    compile ${NAME}_${FORMAT}.comp ${NAME}_${FORMAT}.comp.spv
+    rm ${NAME}_${FORMAT}.comp
+  done
+done
+
+# Detail-preserving downscaling is only provided for color formats
+DPID_FORMATS=(rgba32f rgba16f rgba8)
+for FORMAT in ${DPID_FORMATS[@]}; do
+  for NAME in dpid_guidance dpid; do
+    (FORMAT=${FORMAT} envsubst < ${NAME}.comp) > ${NAME}_${FORMAT}.comp
+    compile ${NAME}_${FORMAT}.comp ${NAME}_${FORMAT}.comp.spv
+    rm ${NAME}_${FORMAT}.comp
+  done
 done
@@ -0,0 +1,51 @@
+#version 450
+#extension GL_EXT_samplerless_texture_functions : require
+// Converts each texel of a source level, of any floating-point format, to the destination
+// format with an affine color transform, e.g. to extract luminance.
+// With REDUCE, the destination is the size of the next level, and each texel is first
+// downsampled with the polyphase weights of npot.frag. Averaging before the affine transform
+// gives the same result as averaging the converted texels.
+layout(set = 0, binding = 0) uniform texture2D u_texture;
+layout(set = 0, binding = 1) uniform Conversion {
+  // Row i computes channel i of the destination texel
+  vec4 rows[4];
+  vec4 offset;
+} u_conversion;
+
+layout(location = 0) out vec4 out_color;
+layout(location = 0) in vec2 v_uv;
+
+#ifdef REDUCE
+// Returns the weights of the source texels 2x, 2x + 1 and 2x + 2
+vec3 weights(int x, int src_size) {
+  if (src_size == 1) {
+    return vec3(1.0, 0.0, 0.0);
+  }
+  if (src_size % 2 == 0) {
+    return vec3(0.5, 0.5, 0.0);
+  }
+  float n = float(src_size / 2);
+  return vec3(n - float(x), n, float(x + 1)) / (2.0 * n + 1.0);
+}
+#endif
+
+void main() {
+#ifdef REDUCE
+  ivec2 src_size = textureSize(u_texture, 0);
+  ivec2 dst_uv = ivec2(gl_FragCoord.xy);
+  vec3 wx = weights(dst_uv.x, src_size.x);
+  vec3 wy = weights(dst_uv.y, src_size.y);
+  vec4 c = vec4(0.0);
+  for (int j = 0; j < 3; ++j) {
+    for (int i = 0; i < 3; ++i) {
+      ivec2 uv = clamp(2 * dst_uv + ivec2(i, j), ivec2(0), src_size - 1);
+      c += wx[i] * wy[j] * texelFetch(u_texture, uv, 0);
+    }
+  }
+#else
+  vec4 c = texelFetch(u_texture, ivec2(gl_FragCoord.xy), 0);
+#endif
+  out_color = vec4(dot(u_conversion.rows[0], c), dot(u_conversion.rows[1], c),
+                   dot(u_conversion.rows[2], c), dot(u_conversion.rows[3], c)) +
+              u_conversion.offset;
+}
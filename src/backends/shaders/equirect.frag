@@ -0,0 +1,43 @@
+#version 450
+#extension GL_EXT_samplerless_texture_functions : require
+// Downsamples an equirectangular map, weighting each source row by the cosine
+// of its latitude so texels near the poles, which cover less solid angle,
+// don't bias the lower mip levels. Uses the polyphase weights of npot.frag so
+// odd-sized levels keep every row and column.
+layout(set = 0, binding = 0) uniform texture2D u_texture;
+
+layout(location = 0) out vec4 out_color;
+layout(location = 0) in vec2 v_uv;
+
+const float PI = 3.14159265358979;
+
+// Returns the weights of the source texels 2x, 2x + 1 and 2x + 2
+vec3 weights(int x, int src_size) {
+  if (src_size == 1) {
+    return vec3(1.0, 0.0, 0.0);
+  }
+  if (src_size % 2 == 0) {
+    return vec3(0.5, 0.5, 0.0);
+  }
+  float n = float(src_size / 2);
+  return vec3(n - float(x), n, float(x + 1)) / (2.0 * n + 1.0);
+}
+
+void main() {
+  ivec2 src_size = textureSize(u_texture, 0);
+  ivec2 dst_uv = ivec2(gl_FragCoord.xy);
+  vec3 wx = weights(dst_uv.x, src_size.x);
+  vec3 wy = weights(dst_uv.y, src_size.y);
+  vec4 c = vec4(0.0);
+  float weight_sum = 0.0;
+  for (int j = 0; j < 3; ++j) {
+    int y = min(2 * dst_uv.y + j, src_size.y - 1);
+    float w = wy[j] * cos(PI * ((float(y) + 0.5) / float(src_size.y) - 0.5));
+    for (int i = 0; i < 3; ++i) {
+      ivec2 uv = ivec2(min(2 * dst_uv.x + i, src_size.x - 1), y);
+      c += wx[i] * w * texelFetch(u_texture, uv, 0);
+    }
+    weight_sum += w;
+  }
+  out_color = c / weight_sum;
+}
@@ -0,0 +1,24 @@
+#version 450
+#extension GL_EXT_samplerless_texture_functions : require
+// Takes the texel of each 2x2 block whose red and green channels form the longest
+// vector, the reduction used for TAA and motion blur velocity pyramids. Ties go to
+// the first of the tied texels in the order top-left, top-right, bottom-left,
+// bottom-right.
+layout(set = 0, binding = 0) uniform texture2D u_texture;
+
+layout(location = 0) out vec4 out_color;
+layout(location = 0) in vec2 v_uv;
+
+void main() {
+  ivec2 src_uv = 2 * ivec2(gl_FragCoord.xy);
+  // Clamp to edge, so levels 1 texel wide or high don't read past their edge
+  ivec2 src_max = textureSize(u_texture, 0) - 1;
+  vec4 v = texelFetch(u_texture, src_uv, 0);
+  for (int i = 1; i < 4; ++i) {
+    vec4 t = texelFetch(u_texture, min(src_uv + ivec2(i % 2, i / 2), src_max), 0);
+    if (dot(t.xy, t.xy) > dot(v.xy, v.xy)) {
+      v = t;
+    }
+  }
+  out_color = v;
+}
@@ -0,0 +1,47 @@
+// This is a template file!
+// It is expectd that you wiill generate a real file from it using envsubst:
+//
+// PREFIX=u envsubst mode.frag
+//
+// PREFIX is u for unsigned integer formats and i for signed integer formats.
+//
+// Writes the most frequent texel of each 2x2 block, the majority vote used to
+// downsample label maps. Ties go to the first of the tied texels in the order
+// top-left, top-right, bottom-left, bottom-right.
+
+#version 450
+#extension GL_EXT_samplerless_texture_functions : require
+layout(set = 0, binding = 0) uniform ${PREFIX}texture2D u_texture;
+
+layout(location = 0) out ${PREFIX}vec4 out_color;
+layout(location = 0) in vec2 v_uv;
+
+// Clamp to edge, so levels 1 texel wide or high don't read past their edge
+#define L(u) texelFetch(u_texture, min(u, textureSize(u_texture, 0) - 1), 0)
+
+int same(${PREFIX}vec4 a, ${PREFIX}vec4 b) {
+  return all(equal(a, b)) ? 1 : 0;
+}
+
+void main() {
+  ivec2 src_uv = 2 * ivec2(gl_FragCoord.xy);
+  ${PREFIX}vec4 a = L(src_uv);
+  ${PREFIX}vec4 b = L(src_uv + ivec2(1, 0));
+  ${PREFIX}vec4 c = L(src_uv + ivec2(0, 1));
+  ${PREFIX}vec4 d = L(src_uv + ivec2(1, 1));
+  int ab = same(a, b), ac = same(a, c), ad = same(a, d);
+  int bc = same(b, c), bd = same(b, d), cd = same(c, d);
+  out_color = a;
+  int votes = ab + ac + ad;
+  if (ab + bc + bd > votes) {
+    out_color = b;
+    votes = ab + bc + bd;
+  }
+  if (ac + bc + cd > votes) {
+    out_color = c;
+    votes = ac + bc + cd;
+  }
+  if (ad + bd + cd > votes) {
+    out_color = d;
+  }
+}
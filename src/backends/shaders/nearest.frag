@@ -0,0 +1,21 @@
+// This is a template file!
+// It is expectd that you wiill generate a real file from it using envsubst:
+//
+// PREFIX=u envsubst nearest.frag
+//
+// PREFIX is empty for floating-point formats, u for unsigned integer formats
+// and i for signed integer formats.
+//
+// Point samples the top-left texel of each 2x2 block, for data textures such
+// as object IDs whose texels must not be blended.
+
+#version 450
+#extension GL_EXT_samplerless_texture_functions : require
+layout(set = 0, binding = 0) uniform ${PREFIX}texture2D u_texture;
+
+layout(location = 0) out ${PREFIX}vec4 out_color;
+layout(location = 0) in vec2 v_uv;
+
+void main() {
+  out_color = texelFetch(u_texture, 2 * ivec2(gl_FragCoord.xy), 0);
+}
@@ -0,0 +1,38 @@
+#version 450
+#extension GL_EXT_samplerless_texture_functions : require
+// Downsamples a level with an odd width or height using polyphase weights:
+// each destination texel along an odd axis covers 3 source texels with weights
+// ((n - x), n, (x + 1)) / (2n + 1), where n is the destination size. Along an
+// even axis this reduces to the usual 2 texel box filter.
+// See "Non-Power-of-Two Mipmapping" from the NVIDIA SDK.
+layout(set = 0, binding = 0) uniform texture2D u_texture;
+
+layout(location = 0) out vec4 out_color;
+layout(location = 0) in vec2 v_uv;
+
+// Returns the weights of the source texels 2x, 2x + 1 and 2x + 2
+vec3 weights(int x, int src_size) {
+  if (src_size == 1) {
+    return vec3(1.0, 0.0, 0.0);
+  }
+  if (src_size % 2 == 0) {
+    return vec3(0.5, 0.5, 0.0);
+  }
+  float n = float(src_size / 2);
+  return vec3(n - float(x), n, float(x + 1)) / (2.0 * n + 1.0);
+}
+
+void main() {
+  ivec2 src_size = textureSize(u_texture, 0);
+  ivec2 dst_uv = ivec2(gl_FragCoord.xy);
+  vec3 wx = weights(dst_uv.x, src_size.x);
+  vec3 wy = weights(dst_uv.y, src_size.y);
+  vec4 c = vec4(0.0);
+  for (int j = 0; j < 3; ++j) {
+    for (int i = 0; i < 3; ++i) {
+      ivec2 uv = clamp(2 * dst_uv + ivec2(i, j), ivec2(0), src_size - 1);
+      c += wx[i] * wy[j] * texelFetch(u_texture, uv, 0);
+    }
+  }
+  out_color = c;
+}
@@ -0,0 +1,37 @@
+// This is a template file!
+// It is expectd that you wiill generate a real file from it using envsubst:
+//
+// PREFIX=u envsubst stochastic.frag
+//
+// PREFIX is empty for floating-point formats, u for unsigned integer formats
+// and i for signed integer formats.
+//
+// Copies one texel of each 2x2 block, chosen by hashing the destination texel,
+// the destination level and a seed. See stochastic.comp.
+
+#version 450
+#extension GL_EXT_samplerless_texture_functions : require
+layout(set = 0, binding = 0) uniform ${PREFIX}texture2D u_texture;
+layout(set = 0, binding = 1) uniform Params {
+  uint u_seed;
+  uint u_level;
+};
+
+layout(location = 0) out ${PREFIX}vec4 out_color;
+layout(location = 0) in vec2 v_uv;
+
+// https://www.reedbeta.com/blog/hash-functions-for-gpu-rendering/
+uint pcg_hash(uint v) {
+  uint state = v * 747796405u + 2891336453u;
+  uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+  return (word >> 22u) ^ word;
+}
+
+void main() {
+  ivec2 dst_uv = ivec2(gl_FragCoord.xy);
+  uint h = pcg_hash(u_seed ^ pcg_hash(u_level ^ pcg_hash(uint(dst_uv.x) ^ pcg_hash(uint(dst_uv.y)))));
+  ivec2 offset = ivec2(h & 1u, (h >> 1u) & 1u);
+  // Clamp to edge for levels that are 1 texel wide or tall
+  ivec2 src_uv = min(2 * dst_uv + offset, textureSize(u_texture, 0) - 1);
+  out_color = texelFetch(u_texture, src_uv, 0);
+}
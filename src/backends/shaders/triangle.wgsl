@@ -0,0 +1,19 @@
+// A triangle covering the viewport, drawn with 3 vertices and no vertex buffers:
+// https://www.saschawillems.de/blog/2016/08/13/vulkan-tutorial-on-rendering-a-fullscreen-quad-without-buffers/
+//
+// wgpu presents every backend with WebGPU's conventions, where clip space y points up and
+// texture coordinates start at the top left, flipping GL's framebuffer itself. Clip space
+// y = 1 is the top row of the level being drawn, so it samples v = 0, the top row of the
+// level being read, on every backend.
+[[builtin(vertex_index)]] var<in> in_vertex_index: u32;
+[[builtin(position)]] var<out> out_position: vec4<f32>;
+[[location(0)]] var<out> out_uv: vec2<f32>;
+
+[[stage(vertex)]]
+fn main() {
+    // (0, 0), (2, 0) and (0, 2)
+    const x: f32 = f32((in_vertex_index & 1u) * 2u);
+    const y: f32 = f32(in_vertex_index & 2u);
+    out_position = vec4<f32>(x * 2.0 - 1.0, y * 2.0 - 1.0, 0.0, 1.0);
+    out_uv = vec2<f32>(x, 1.0 - y);
+}
@@ -0,0 +1,48 @@
+#version 450
+#extension GL_EXT_samplerless_texture_functions : require
+// Upsamples a coarser level to fill in a missing finer one, e.g. until the real level is
+// streamed in. Texels are fetched without a sampler, so formats that can't be filtered are
+// upsampled too, and texels past the edge of the level are clamped to it. Defining BICUBIC
+// selects a Catmull-Rom filter over 4x4 texels, otherwise a bilinear filter over 2x2 texels.
+layout(set = 0, binding = 0) uniform texture2D u_texture;
+
+layout(location = 0) out vec4 out_color;
+layout(location = 0) in vec2 v_uv;
+
+vec4 fetch(ivec2 uv, ivec2 size) {
+  return texelFetch(u_texture, clamp(uv, ivec2(0), size - 1), 0);
+}
+
+#ifdef BICUBIC
+// Returns the Catmull-Rom weights of the texels at -1, 0, 1 and 2 from a position t past
+// the texel at 0
+vec4 weights(float t) {
+  float t2 = t * t;
+  float t3 = t2 * t;
+  return 0.5 * vec4(-t3 + 2.0 * t2 - t, 3.0 * t3 - 5.0 * t2 + 2.0, -3.0 * t3 + 4.0 * t2 + t,
+                    t3 - t2);
+}
+#endif
+
+void main() {
+  ivec2 size = textureSize(u_texture, 0);
+  // The center of the destination texel, in source texels relative to the source centers
+  vec2 p = v_uv * vec2(size) - 0.5;
+  ivec2 base = ivec2(floor(p));
+  vec2 t = p - floor(p);
+#ifdef BICUBIC
+  vec4 wx = weights(t.x);
+  vec4 wy = weights(t.y);
+  vec4 c = vec4(0.0);
+  for (int j = 0; j < 4; ++j) {
+    for (int i = 0; i < 4; ++i) {
+      c += wx[i] * wy[j] * fetch(base + ivec2(i - 1, j - 1), size);
+    }
+  }
+  out_color = c;
+#else
+  vec4 top = mix(fetch(base, size), fetch(base + ivec2(1, 0), size), t.x);
+  vec4 bottom = mix(fetch(base + ivec2(0, 1), size), fetch(base + ivec2(1, 1), size), t.x);
+  out_color = mix(top, bottom, t.y);
+#endif
+}
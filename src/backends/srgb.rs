@@ -0,0 +1,295 @@
+use crate::{core::*, util::create_mip_level_view};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+use wgpu::{
+    util::make_spirv, Adapter, AdapterInfo, Backend, BindGroupDescriptor, BindGroupEntry,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
+    BufferBindingType, BufferCopyView, BufferDescriptor, BufferUsage, ComputePassDescriptor,
+    ComputePipelineDescriptor, Device, Extent3d, MapMode, Origin3d, PipelineLayoutDescriptor,
+    Queue, ShaderFlags, ShaderModuleDescriptor, ShaderStage, StorageTextureAccess, TextureCopyView,
+    TextureDataLayout, TextureDescriptor, TextureDimension, TextureFormat, TextureUsage,
+    TextureViewDimension,
+};
+
+// The srgb encoding of the texel srgb_probe.comp loads, linear 0.216
+const PROBE_SRC: u8 = 128;
+
+/// How an implementation converts the texels of `Rgba8UnormSrgb` and `Bgra8UnormSrgb`
+/// storage textures, which decides the shader `ComputeMipmapGenerator` generates them with.
+///
+/// The conversion isn't specified for storage textures and differs between backends and
+/// drivers. [`SrgbStorageConversion::default`] guesses it from the target OS at compile time;
+/// [`SrgbStorageConversion::probe`] measures it on the adapter at hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SrgbStorageConversion {
+    /// Texels are loaded and stored as they are encoded, so the shader converts both ways.
+    /// Observed on Vulkan.
+    None,
+    /// Texels are converted to linear on load but stored as they are. Observed on Metal.
+    OnLoad,
+    /// Texels are loaded as they are encoded but converted from linear on store.
+    OnStore,
+    /// Texels are converted both ways, like sampled and render target srgb textures.
+    OnLoadAndStore,
+}
+
+impl Default for SrgbStorageConversion {
+    /// Returns the conversion observed on the target OS's primary backend.
+    fn default() -> Self {
+        if cfg!(target_os = "macos") {
+            SrgbStorageConversion::OnLoad
+        } else {
+            SrgbStorageConversion::None
+        }
+    }
+}
+
+impl SrgbStorageConversion {
+    /// Measures how `device` converts srgb storage texels by loading a known texel and
+    /// storing a known value in a one-invocation dispatch, and returns the matching
+    /// conversion. The result is cached per adapter for the lifetime of the process, so only
+    /// the first call for an adapter submits work to `queue`.
+    ///
+    /// Unlike readbacks, the returned future polls `device` until the probe completes.
+    pub async fn probe(adapter: &Adapter, device: &Device, queue: &Queue) -> Result<Self, Error> {
+        static PROBED: OnceLock<Mutex<HashMap<ProbeKey, SrgbStorageConversion>>> = OnceLock::new();
+        let key = ProbeKey::new(&adapter.get_info());
+        let probed = PROBED.get_or_init(Default::default);
+        if let Some(&conversion) = probed.lock().unwrap().get(&key) {
+            return Ok(conversion);
+        }
+        let conversion = run_probe(device, queue).await?;
+        probed.lock().unwrap().insert(key, conversion);
+        Ok(conversion)
+    }
+
+    /// Returns the conversion matching the texel `loaded` by a shader from a storage texel
+    /// encoded as [`PROBE_SRC`], and the encoding `stored` of linear 0.5 stored by it.
+    fn from_probe(loaded: f32, stored: u8) -> Self {
+        // Halfway between linear 0.216 and srgb 0.502
+        let on_load = loaded < 0.36;
+        // Halfway between 128, 0.5 stored as is, and 188, 0.5 encoded as srgb
+        let on_store = stored > 158;
+        match (on_load, on_store) {
+            (false, false) => SrgbStorageConversion::None,
+            (true, false) => SrgbStorageConversion::OnLoad,
+            (false, true) => SrgbStorageConversion::OnStore,
+            (true, true) => SrgbStorageConversion::OnLoadAndStore,
+        }
+    }
+}
+
+// Identifies an adapter across the devices created from it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ProbeKey {
+    name: String,
+    vendor: usize,
+    device: usize,
+    backend: Backend,
+}
+
+impl ProbeKey {
+    pub(crate) fn new(info: &AdapterInfo) -> Self {
+        Self {
+            name: info.name.clone(),
+            vendor: info.vendor,
+            device: info.device,
+            backend: info.backend,
+        }
+    }
+}
+
+async fn run_probe(device: &Device, queue: &Queue) -> Result<SrgbStorageConversion, Error> {
+    let format = TextureFormat::Rgba8UnormSrgb;
+    let texture_descriptor = TextureDescriptor {
+        label: Some("wgpu-mipmap-srgb-probe"),
+        size: Extent3d {
+            width: 1,
+            height: 1,
+            depth: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsage::STORAGE | TextureUsage::COPY_SRC | TextureUsage::COPY_DST,
+    };
+    let src = device.create_texture(&texture_descriptor);
+    let dst = device.create_texture(&texture_descriptor);
+    queue.write_texture(
+        TextureCopyView {
+            texture: &src,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+        },
+        &[PROBE_SRC, PROBE_SRC, PROBE_SRC, 255],
+        TextureDataLayout {
+            offset: 0,
+            bytes_per_row: 4,
+            rows_per_image: 1,
+        },
+        texture_descriptor.size,
+    );
+    let loaded = device.create_buffer(&BufferDescriptor {
+        label: Some("wgpu-mipmap-srgb-probe-loaded"),
+        size: 16,
+        usage: BufferUsage::STORAGE | BufferUsage::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let readback = device.create_buffer(&BufferDescriptor {
+        label: Some("wgpu-mipmap-srgb-probe-readback"),
+        size: 16 + wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+        usage: BufferUsage::COPY_DST | BufferUsage::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let storage_entry = |binding, access| BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStage::COMPUTE,
+        ty: BindingType::StorageTexture {
+            access,
+            format,
+            view_dimension: TextureViewDimension::D2,
+        },
+        count: None,
+    };
+    let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("wgpu-mipmap-srgb-probe-bg-layout"),
+        entries: &[
+            storage_entry(0, StorageTextureAccess::ReadOnly),
+            storage_entry(1, StorageTextureAccess::WriteOnly),
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStage::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    });
+    let module = device.create_shader_module(&ShaderModuleDescriptor {
+        label: None,
+        source: make_spirv(include_bytes!("shaders/srgb_probe.comp.spv")),
+        flags: ShaderFlags::empty(),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[&layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some("wgpu-mipmap-srgb-probe-pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &module,
+        entry_point: "main",
+    });
+    let src_view = create_mip_level_view(&src, 0);
+    let dst_view = create_mip_level_view(&dst, 0);
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout: &layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&src_view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(&dst_view),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::Buffer {
+                    buffer: &loaded,
+                    offset: 0,
+                    size: None,
+                },
+            },
+        ],
+    });
+    let mut encoder = device.create_command_encoder(&Default::default());
+    {
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch(1, 1, 1);
+    }
+    encoder.copy_buffer_to_buffer(&loaded, 0, &readback, 0, 16);
+    encoder.copy_texture_to_buffer(
+        TextureCopyView {
+            texture: &dst,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+        },
+        BufferCopyView {
+            buffer: &readback,
+            layout: TextureDataLayout {
+                offset: 16,
+                bytes_per_row: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
+                rows_per_image: 1,
+            },
+        },
+        texture_descriptor.size,
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+    let slice = readback.slice(..);
+    let mapping = slice.map_async(MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    mapping.await.map_err(|_| Error::ReadbackFailed)?;
+    let (loaded, stored) = {
+        let mapped = slice.get_mapped_range();
+        let loaded = bytemuck::cast_slice::<_, f32>(&mapped[..16])[0];
+        (loaded, mapped[16])
+    };
+    readback.unmap();
+    Ok(SrgbStorageConversion::from_probe(loaded, stored))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    #[test]
+    fn from_probe() {
+        let linear = 0.216;
+        let encoded = PROBE_SRC as f32 / 255.0;
+        assert_eq!(
+            SrgbStorageConversion::from_probe(encoded, 128),
+            SrgbStorageConversion::None
+        );
+        assert_eq!(
+            SrgbStorageConversion::from_probe(linear, 128),
+            SrgbStorageConversion::OnLoad
+        );
+        assert_eq!(
+            SrgbStorageConversion::from_probe(encoded, 188),
+            SrgbStorageConversion::OnStore
+        );
+        assert_eq!(
+            SrgbStorageConversion::from_probe(linear, 188),
+            SrgbStorageConversion::OnLoadAndStore
+        );
+    }
+
+    #[test]
+    fn probe_is_cached() {
+        if !adapter_available() {
+            return;
+        }
+        futures::executor::block_on(async {
+            let (_instance, adapter, device, queue) = wgpu_setup().await;
+            let first = SrgbStorageConversion::probe(&adapter, &device, &queue)
+                .await
+                .unwrap();
+            let second = SrgbStorageConversion::probe(&adapter, &device, &queue)
+                .await
+                .unwrap();
+            assert_eq!(first, second);
+        });
+    }
+}
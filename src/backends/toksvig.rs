@@ -0,0 +1,550 @@
+use crate::{
+    backends::render::to_sample_type,
+    core::*,
+    trace::event,
+    util::{create_mip_level_view, get_mip_extent},
+};
+use wgpu::{
+    util::{make_spirv, BufferInitDescriptor, DeviceExt},
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsage,
+    CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
+    PipelineLayoutDescriptor, ShaderFlags, ShaderModuleDescriptor, ShaderStage,
+    StorageTextureAccess, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureSampleType, TextureUsage, TextureView, TextureViewDimension,
+};
+
+/// The format of the roughness mip chains written by [`ToksvigRoughnessGenerator`]:
+/// perceptual roughness, whose square is the GGX alpha, in the red channel.
+pub const ROUGHNESS_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+// The format of the intermediate pyramid of mean normals and alpha^2
+const MOMENTS_FORMAT: TextureFormat = TextureFormat::Rgba32Float;
+
+// Must match the local_size_x and local_size_y values in toksvig_seed.comp, toksvig.comp
+// and moments.comp
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Bakes the roughness mip chain of a normal map and roughness map pair for specular
+/// antialiasing.
+///
+/// Averaging a normal map shortens the mean normal of footprints whose normals disagree, and
+/// plain roughness mips then render those footprints too shiny, so distant bumpy surfaces
+/// sparkle. Each level of the roughness chain is instead widened by the spread of the
+/// normals it covers (Toksvig 2005, in the von Mises-Fisher form of Neubelt and Pettineo
+/// 2013): the length `r` of the mean normal gives `kappa = (3r - r^3) / (1 - r^2)`, and
+/// `2 / kappa` is added to the mean GGX alpha squared. Level 0 keeps the source roughness.
+/// The normal map's own mips can be generated by any other generator.
+#[derive(Debug)]
+pub struct ToksvigRoughnessGenerator {
+    seed_layout: BindGroupLayout,
+    seed_pipeline: ComputePipeline,
+    average_layout: BindGroupLayout,
+    average_pipeline: ComputePipeline,
+    resolve_layout: BindGroupLayout,
+    resolve_pipeline: ComputePipeline,
+}
+
+impl ToksvigRoughnessGenerator {
+    /// Returns the texture usage `ToksvigRoughnessGenerator` requires of the roughness mip
+    /// chain.
+    pub fn required_usage() -> TextureUsage {
+        TextureUsage::STORAGE
+    }
+
+    /// Creates a new `ToksvigRoughnessGenerator`. Once created, it can be used repeatedly to
+    /// bake roughness mip chains.
+    pub fn new(device: &Device) -> Self {
+        let sampled_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStage::COMPUTE,
+            ty: BindingType::Texture {
+                view_dimension: TextureViewDimension::D2,
+                sample_type: TextureSampleType::Float { filterable: false },
+                multisampled: false,
+            },
+            count: None,
+        };
+        let seed_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("wgpu-mipmap-toksvig-seed-bg-layout"),
+            entries: &[
+                sampled_entry(0),
+                sampled_entry(1),
+                storage_entry(2, StorageTextureAccess::WriteOnly, MOMENTS_FORMAT),
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStage::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let average_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("wgpu-mipmap-toksvig-average-bg-layout"),
+            entries: &[
+                storage_entry(0, StorageTextureAccess::ReadOnly, MOMENTS_FORMAT),
+                storage_entry(1, StorageTextureAccess::WriteOnly, MOMENTS_FORMAT),
+            ],
+        });
+        let resolve_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("wgpu-mipmap-toksvig-bg-layout"),
+            entries: &[
+                storage_entry(0, StorageTextureAccess::ReadOnly, MOMENTS_FORMAT),
+                storage_entry(1, StorageTextureAccess::WriteOnly, ROUGHNESS_FORMAT),
+            ],
+        });
+        let seed_pipeline = pipeline(
+            device,
+            include_bytes!("shaders/toksvig_seed.comp.spv"),
+            &seed_layout,
+            "seed",
+        );
+        // The mean normal and alpha^2 are averaged like shadow map moments
+        let average_pipeline = pipeline(
+            device,
+            include_bytes!("shaders/moments_rgba32f.comp.spv"),
+            &average_layout,
+            "average",
+        );
+        let resolve_pipeline = pipeline(
+            device,
+            include_bytes!("shaders/toksvig.comp.spv"),
+            &resolve_layout,
+            "resolve",
+        );
+        Self {
+            seed_layout,
+            seed_pipeline,
+            average_layout,
+            average_pipeline,
+            resolve_layout,
+            resolve_pipeline,
+        }
+    }
+
+    /// Returns a descriptor for a roughness mip chain matching `normal_texture_descriptor`:
+    /// same size and mip level count, [`ROUGHNESS_FORMAT`], and usage suitable for baking,
+    /// sampling and reading back.
+    pub fn roughness_descriptor(
+        normal_texture_descriptor: &TextureDescriptor,
+    ) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: Some("wgpu-mipmap-toksvig-roughness"),
+            size: normal_texture_descriptor.size,
+            mip_level_count: normal_texture_descriptor.mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: ROUGHNESS_FORMAT,
+            usage: Self::required_usage() | TextureUsage::SAMPLED | TextureUsage::COPY_SRC,
+        }
+    }
+
+    /// Encodes commands to write every level of `dst_texture` from level 0 of
+    /// `normal_texture` and the red channel of level 0 of `roughness_texture`.
+    ///
+    /// Expectations:
+    /// - `normal_texture` must have `TextureUsage::SAMPLED` and hold tangent-space normals.
+    ///   Unorm formats are decoded with `2 * n - 1`, and the z component of two channel
+    ///   formats, such as `Rg8Unorm` and `Bc5RgUnorm`, is reconstructed.
+    /// - `roughness_texture` must have `TextureUsage::SAMPLED`, a float format and the same
+    ///   size as `normal_texture`.
+    /// - `dst_texture` must match the descriptor returned by
+    ///   [`ToksvigRoughnessGenerator::roughness_descriptor`], up to extra usage flags and a
+    ///   smaller mip level count.
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        normal_texture: &Texture,
+        normal_texture_descriptor: &TextureDescriptor,
+        roughness_texture: &Texture,
+        roughness_texture_descriptor: &TextureDescriptor,
+        dst_texture: &Texture,
+        dst_texture_descriptor: &TextureDescriptor,
+    ) -> Result<(), Error> {
+        for descriptor in &[normal_texture_descriptor, roughness_texture_descriptor] {
+            if descriptor.dimension != TextureDimension::D2 {
+                return Err(Error::UnsupportedDimension(descriptor.dimension));
+            }
+            if !descriptor.usage.contains(TextureUsage::SAMPLED) {
+                return Err(Error::UnsupportedUsage(descriptor.usage));
+            }
+        }
+        if dst_texture_descriptor.dimension != TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(
+                dst_texture_descriptor.dimension,
+            ));
+        }
+        if dst_texture_descriptor.format != ROUGHNESS_FORMAT {
+            return Err(Error::UnsupportedFormat(dst_texture_descriptor.format));
+        }
+        if !dst_texture_descriptor
+            .usage
+            .contains(Self::required_usage())
+        {
+            return Err(Error::UnsupportedUsage(dst_texture_descriptor.usage));
+        }
+        if roughness_texture_descriptor.size != normal_texture_descriptor.size
+            || dst_texture_descriptor.size != normal_texture_descriptor.size
+        {
+            return Err(Error::ExtentMismatch);
+        }
+        let normal_format = normal_texture_descriptor.format;
+        let (remap, reconstruct_z) =
+            normal_encoding(normal_format).ok_or(Error::UnsupportedFormat(normal_format))?;
+        let roughness_format = roughness_texture_descriptor.format;
+        if !matches!(
+            to_sample_type(roughness_format),
+            TextureSampleType::Float { .. }
+        ) {
+            return Err(Error::UnsupportedFormat(roughness_format));
+        }
+
+        let mip_count = dst_texture_descriptor.mip_level_count;
+        let moments = device.create_texture(&TextureDescriptor {
+            label: Some("wgpu-mipmap-toksvig-moments"),
+            size: dst_texture_descriptor.size,
+            mip_level_count: mip_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: MOMENTS_FORMAT,
+            usage: TextureUsage::STORAGE,
+        });
+        let moment_views = (0..mip_count)
+            .map(|mip| create_mip_level_view(&moments, mip))
+            .collect::<Vec<_>>();
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("wgpu-mipmap-toksvig-params"),
+            contents: bytemuck::cast_slice(&[remap as u32, reconstruct_z as u32, 0, 0]),
+            usage: BufferUsage::UNIFORM,
+        });
+        let normal_view = create_mip_level_view(normal_texture, 0);
+        let roughness_view = create_mip_level_view(roughness_texture, 0);
+        let seed_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &self.seed_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&normal_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&roughness_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&moment_views[0]),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::Buffer {
+                        buffer: &params,
+                        offset: 0,
+                        size: None,
+                    },
+                },
+            ],
+        });
+        dispatch(
+            encoder,
+            &self.seed_pipeline,
+            &seed_bind_group,
+            get_mip_extent(&dst_texture_descriptor.size, 0),
+        );
+        for mip in 1..mip_count {
+            event!(
+                TRACE,
+                { backend = "toksvig", level = mip },
+                "dispatching level"
+            );
+            let bind_group = self.storage_bind_group(
+                device,
+                &self.average_layout,
+                &moment_views[mip as usize - 1],
+                &moment_views[mip as usize],
+            );
+            dispatch(
+                encoder,
+                &self.average_pipeline,
+                &bind_group,
+                get_mip_extent(&dst_texture_descriptor.size, mip),
+            );
+        }
+        for mip in 0..mip_count {
+            let dst_view = create_mip_level_view(dst_texture, mip);
+            let bind_group = self.storage_bind_group(
+                device,
+                &self.resolve_layout,
+                &moment_views[mip as usize],
+                &dst_view,
+            );
+            dispatch(
+                encoder,
+                &self.resolve_pipeline,
+                &bind_group,
+                get_mip_extent(&dst_texture_descriptor.size, mip),
+            );
+        }
+        Ok(())
+    }
+
+    fn storage_bind_group(
+        &self,
+        device: &Device,
+        layout: &BindGroupLayout,
+        src_view: &TextureView,
+        dst_view: &TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(src_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(dst_view),
+                },
+            ],
+        })
+    }
+}
+
+// Returns whether normals stored in `format` are remapped from [-1, 1] to [0, 1], and
+// whether only their x and y components are stored
+fn normal_encoding(format: TextureFormat) -> Option<(bool, bool)> {
+    match format {
+        TextureFormat::Rgba8Unorm
+        | TextureFormat::Bgra8Unorm
+        | TextureFormat::Rgb10a2Unorm
+        | TextureFormat::Bc1RgbaUnorm
+        | TextureFormat::Bc3RgbaUnorm
+        | TextureFormat::Bc7RgbaUnorm => Some((true, false)),
+        TextureFormat::Rg8Unorm | TextureFormat::Bc5RgUnorm => Some((true, true)),
+        TextureFormat::Rgba8Snorm | TextureFormat::Rgba16Float | TextureFormat::Rgba32Float => {
+            Some((false, false))
+        }
+        TextureFormat::Rg8Snorm
+        | TextureFormat::Bc5RgSnorm
+        | TextureFormat::Rg16Float
+        | TextureFormat::Rg32Float => Some((false, true)),
+        _ => None,
+    }
+}
+
+fn storage_entry(
+    binding: u32,
+    access: StorageTextureAccess,
+    format: TextureFormat,
+) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStage::COMPUTE,
+        ty: BindingType::StorageTexture {
+            access,
+            format,
+            view_dimension: TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+fn pipeline(
+    device: &Device,
+    shader: &[u8],
+    bind_group_layout: &BindGroupLayout,
+    name: &str,
+) -> ComputePipeline {
+    let module = device.create_shader_module(&ShaderModuleDescriptor {
+        label: None,
+        source: make_spirv(shader),
+        flags: ShaderFlags::empty(),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some(&format!("wgpu-mipmap-toksvig-{}-pipeline", name)),
+        layout: Some(&pipeline_layout),
+        module: &module,
+        entry_point: "main",
+    })
+}
+
+fn dispatch(
+    encoder: &mut CommandEncoder,
+    pipeline: &ComputePipeline,
+    bind_group: &wgpu::BindGroup,
+    extent: wgpu::Extent3d,
+) {
+    let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.dispatch(
+        extent.width.div_ceil(WORKGROUP_SIZE),
+        extent.height.div_ceil(WORKGROUP_SIZE),
+        1,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn descriptor(size: u32, format: TextureFormat) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: TextureUsage::SAMPLED | TextureUsage::COPY_DST,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn roughness_descriptor() {
+        let normal = descriptor(64, TextureFormat::Rgba8Unorm);
+        let roughness = ToksvigRoughnessGenerator::roughness_descriptor(&normal);
+        assert_eq!(roughness.size, normal.size);
+        assert_eq!(roughness.mip_level_count, normal.mip_level_count);
+        assert_eq!(roughness.format, ROUGHNESS_FORMAT);
+        assert!(roughness
+            .usage
+            .contains(ToksvigRoughnessGenerator::required_usage()));
+    }
+
+    #[test]
+    fn normal_encoding() {
+        assert_eq!(
+            super::normal_encoding(TextureFormat::Rgba8Unorm),
+            Some((true, false))
+        );
+        assert_eq!(
+            super::normal_encoding(TextureFormat::Bc5RgUnorm),
+            Some((true, true))
+        );
+        assert_eq!(
+            super::normal_encoding(TextureFormat::Rgba16Float),
+            Some((false, false))
+        );
+        assert_eq!(super::normal_encoding(TextureFormat::R8Unorm), None);
+    }
+
+    // Columns of normals alternately tilted left and right widen the roughness of every
+    // level above 0, which keeps the source roughness
+    #[test]
+    fn widens_roughness() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let size = 8;
+        let normal_descriptor = descriptor(size, TextureFormat::Rgba8Unorm);
+        let roughness_descriptor = descriptor(size, TextureFormat::R8Unorm);
+        let normals = (0..size * size)
+            .flat_map(|id| {
+                if id % 2 == 0 {
+                    vec![51u8, 128, 230, 255]
+                } else {
+                    vec![204u8, 128, 230, 255]
+                }
+            })
+            .collect::<Vec<_>>();
+        let roughness = vec![128u8; (size * size) as usize];
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = ToksvigRoughnessGenerator::new(&device);
+            let normal_texture = device.create_texture(&normal_descriptor);
+            let roughness_texture = device.create_texture(&roughness_descriptor);
+            for (texture, data, bytes_per_texel) in &[
+                (&normal_texture, &normals, 4),
+                (&roughness_texture, &roughness, 1),
+            ] {
+                queue.write_texture(
+                    wgpu::TextureCopyView {
+                        texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d::ZERO,
+                    },
+                    data,
+                    wgpu::TextureDataLayout {
+                        offset: 0,
+                        bytes_per_row: bytes_per_texel * size,
+                        rows_per_image: size,
+                    },
+                    normal_descriptor.size,
+                );
+            }
+            let dst_descriptor =
+                ToksvigRoughnessGenerator::roughness_descriptor(&normal_descriptor);
+            let dst = device.create_texture(&dst_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            generator
+                .generate(
+                    &device,
+                    &mut encoder,
+                    &normal_texture,
+                    &normal_descriptor,
+                    &roughness_texture,
+                    &roughness_descriptor,
+                    &dst,
+                    &dst_descriptor,
+                )
+                .unwrap();
+            let readback = MipReadback::encode(&device, &mut encoder, &dst, &dst_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let mips = readback.read();
+            device.poll(wgpu::Maintain::Wait);
+            let mips = mips.await.unwrap();
+            // The mean of the two decoded normals
+            let decode = |v: u8| v as f32 / 255.0 * 2.0 - 1.0;
+            let normalize = |n: [f32; 3]| {
+                let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+                [n[0] / len, n[1] / len, n[2] / len]
+            };
+            let left = normalize([decode(51), decode(128), decode(230)]);
+            let right = normalize([decode(204), decode(128), decode(230)]);
+            let mean = [
+                (left[0] + right[0]) / 2.0,
+                (left[1] + right[1]) / 2.0,
+                (left[2] + right[2]) / 2.0,
+            ];
+            let r = (mean[0] * mean[0] + mean[1] * mean[1] + mean[2] * mean[2]).sqrt();
+            let alpha2 = (128.0f32 / 255.0).powi(4) + 2.0 * (1.0 - r * r) / (3.0 * r - r * r * r);
+            let expected = (alpha2.min(1.0).sqrt().sqrt() * 255.0).round() as i32;
+            for mip in &mips {
+                let expected = if mip.level == 0 { 128 } else { expected };
+                for &texel in mip.as_slice::<u8>() {
+                    assert!(
+                        (texel as i32 - expected).abs() <= 1,
+                        "level {}: {} != {}",
+                        mip.level,
+                        texel,
+                        expected
+                    );
+                }
+            }
+        });
+    }
+}
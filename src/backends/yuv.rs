@@ -0,0 +1,349 @@
+use crate::{
+    backends::{render::fullscreen_triangle, RenderMipmapGenerator},
+    core::*,
+    util::create_mip_level_view,
+};
+use wgpu::{
+    util::make_spirv, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, CommandEncoder,
+    CullMode, Device, FragmentState, FrontFace, MultisampleState, Operations,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderFlags,
+    ShaderModuleDescriptor, ShaderStage, Texture, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureSampleType, TextureUsage, TextureViewDimension, VertexState,
+};
+
+/// The format of the luma (Y) plane accepted by [`YuvMipmapGenerator`].
+pub const LUMA_PLANE_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+/// The format of the interleaved chroma (UV) plane accepted by [`YuvMipmapGenerator`].
+pub const CHROMA_PLANE_FORMAT: TextureFormat = TextureFormat::Rg8Unorm;
+
+/// Where the samples of a subsampled chroma plane sit relative to the luma samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ChromaSiting {
+    /// Chroma samples are co-sited with the left luma sample of each pair and centered
+    /// vertically, as in MPEG-2, BT.709 and the output of most video decoders.
+    #[default]
+    Left,
+    /// Chroma samples are centered between the luma samples, as in JPEG and MPEG-1.
+    Center,
+}
+
+/// Generates mipmaps for the planes of a YUV texture stored as separate `R8Unorm` luma
+/// and `Rg8Unorm` chroma textures, e.g. streamed video frames.
+///
+/// Both planes are generated with the render backend. Chroma levels are filtered so their
+/// samples keep the plane's [`ChromaSiting`], which keeps trilinear sampling of the two
+/// planes registered at every level.
+#[derive(Debug)]
+pub struct YuvMipmapGenerator {
+    planes: RenderMipmapGenerator,
+    cosited_layout: BindGroupLayout,
+    cosited_pipeline: RenderPipeline,
+}
+
+impl YuvMipmapGenerator {
+    /// Returns the texture usage `YuvMipmapGenerator` requires of both planes.
+    pub fn required_usage() -> TextureUsage {
+        RenderMipmapGenerator::required_usage()
+    }
+
+    /// Creates a new `YuvMipmapGenerator`. Once created, it can be used repeatedly to
+    /// generate mipmaps for any pair of luma and chroma planes.
+    pub fn new(device: &Device) -> Self {
+        let planes = RenderMipmapGenerator::new_with_format_hints(
+            device,
+            &[LUMA_PLANE_FORMAT, CHROMA_PLANE_FORMAT],
+        );
+        let shader = |source| {
+            device.create_shader_module(&ShaderModuleDescriptor {
+                label: None,
+                source: make_spirv(source),
+                flags: ShaderFlags::empty(),
+            })
+        };
+        let vertex_module = fullscreen_triangle(device);
+        let fragment_module = shader(include_bytes!("shaders/chroma_cosited.frag.spv"));
+        let cosited_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("wgpu-mipmap-chroma-cosited-bg-layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStage::FRAGMENT,
+                ty: BindingType::Texture {
+                    view_dimension: TextureViewDimension::D2,
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&cosited_layout],
+            push_constant_ranges: &[],
+        });
+        let cosited_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("wgpu-mipmap-chroma-cosited-render-pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &vertex_module,
+                entry_point: "main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: FrontFace::Ccw,
+                cull_mode: CullMode::Back,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            fragment: Some(FragmentState {
+                module: &fragment_module,
+                entry_point: "main",
+                targets: &[CHROMA_PLANE_FORMAT.into()],
+            }),
+        });
+        Self {
+            planes,
+            cosited_layout,
+            cosited_pipeline,
+        }
+    }
+
+    /// Encodes commands to generate the mipmaps of a luma plane and its chroma plane.
+    ///
+    /// Expectations:
+    /// - `y_texture` has format [`LUMA_PLANE_FORMAT`] and `uv_texture` has format
+    ///   [`CHROMA_PLANE_FORMAT`].
+    /// - Both textures have the usage returned by [`YuvMipmapGenerator::required_usage`].
+    /// - `uv_siting` describes where the chroma samples of level 0 sit.
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        y_texture: &Texture,
+        y_texture_descriptor: &TextureDescriptor,
+        uv_texture: &Texture,
+        uv_texture_descriptor: &TextureDescriptor,
+        uv_siting: ChromaSiting,
+    ) -> Result<(), Error> {
+        if y_texture_descriptor.format != LUMA_PLANE_FORMAT {
+            return Err(Error::UnsupportedPlaneFormat(y_texture_descriptor.format));
+        }
+        self.generate_chroma(
+            device,
+            encoder,
+            uv_texture,
+            uv_texture_descriptor,
+            uv_siting,
+        )?;
+        self.planes
+            .generate(device, encoder, y_texture, y_texture_descriptor)
+    }
+
+    /// Encodes commands to generate the mipmaps of a chroma plane alone.
+    pub(crate) fn generate_chroma(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uv_texture: &Texture,
+        uv_texture_descriptor: &TextureDescriptor,
+        uv_siting: ChromaSiting,
+    ) -> Result<(), Error> {
+        if uv_texture_descriptor.format != CHROMA_PLANE_FORMAT {
+            return Err(Error::UnsupportedPlaneFormat(uv_texture_descriptor.format));
+        }
+        // Centered chroma downsamples like any other texture
+        if uv_siting == ChromaSiting::Center {
+            return self
+                .planes
+                .generate(device, encoder, uv_texture, uv_texture_descriptor);
+        }
+        if uv_texture_descriptor.dimension != TextureDimension::D2 {
+            return Err(Error::UnsupportedDimension(uv_texture_descriptor.dimension));
+        }
+        if !uv_texture_descriptor.usage.contains(Self::required_usage()) {
+            return Err(Error::UnsupportedUsage(uv_texture_descriptor.usage));
+        }
+        let mip_levels = GenerateOptions::default().mip_levels(uv_texture_descriptor)?;
+        for mip in mip_levels {
+            let src_view = create_mip_level_view(uv_texture, mip - 1);
+            let dst_view = create_mip_level_view(uv_texture, mip);
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &self.cosited_layout,
+                entries: &[BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&src_view),
+                }],
+            });
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: None,
+                color_attachments: &[RenderPassColorAttachmentDescriptor {
+                    attachment: &dst_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: self.planes.load_op(),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(&self.cosited_pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    // Adapts the chroma path to `MipmapGenerator` so it can be read back with the util helpers
+    struct ChromaPlane<'a>(&'a YuvMipmapGenerator, ChromaSiting);
+
+    impl MipmapGenerator for ChromaPlane<'_> {
+        fn generate_with_options(
+            &self,
+            device: &Device,
+            encoder: &mut CommandEncoder,
+            texture: &Texture,
+            texture_descriptor: &TextureDescriptor,
+            _options: &GenerateOptions,
+        ) -> Result<(), Error> {
+            self.0
+                .generate_chroma(device, encoder, texture, texture_descriptor, self.1)
+        }
+    }
+
+    fn chroma_descriptor(size: u32) -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format: CHROMA_PLANE_FORMAT,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: YuvMipmapGenerator::required_usage(),
+            label: None,
+        }
+    }
+
+    #[test]
+    fn unsupported_plane_format() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let y_descriptor = wgpu::TextureDescriptor {
+            format: TextureFormat::Rgba8Unorm,
+            ..chroma_descriptor(16)
+        };
+        let uv_descriptor = chroma_descriptor(8);
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let generator = YuvMipmapGenerator::new(&device);
+            let y_texture = device.create_texture(&y_descriptor);
+            let uv_texture = device.create_texture(&uv_descriptor);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let res = generator.generate(
+                &device,
+                &mut encoder,
+                &y_texture,
+                &y_descriptor,
+                &uv_texture,
+                &uv_descriptor,
+                ChromaSiting::Left,
+            );
+            assert!(res.err() == Some(Error::UnsupportedPlaneFormat(TextureFormat::Rgba8Unorm)));
+        });
+    }
+
+    // Returns the first 4 texels of level 1 of a chroma plane
+    async fn level1(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        generator: &YuvMipmapGenerator,
+        data: &[u8],
+        texture_descriptor: &wgpu::TextureDescriptor<'_>,
+        siting: ChromaSiting,
+    ) -> Vec<[u8; 2]> {
+        let mipmap_buffers = generate_and_copy_to_cpu(
+            device,
+            queue,
+            &ChromaPlane(generator, siting),
+            data,
+            texture_descriptor,
+        )
+        .await
+        .unwrap();
+        mipmap_buffers[1].as_slice::<[u8; 2]>()[..4].to_vec()
+    }
+
+    #[test]
+    fn cosited_chroma() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        // A single bright column at an odd x, which is not co-sited with any level 1 sample
+        let size = 16;
+        let data = (0..size * size)
+            .flat_map(|id| {
+                if id % size == 5 {
+                    vec![255, 255]
+                } else {
+                    vec![0, 0]
+                }
+            })
+            .collect::<Vec<_>>();
+        let texture_descriptor = chroma_descriptor(size);
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = YuvMipmapGenerator::new(&device);
+            // Co-sited samples at x = 4 and x = 6 each take a quarter of the column
+            let cosited = level1(
+                &device,
+                &queue,
+                &generator,
+                &data,
+                &texture_descriptor,
+                ChromaSiting::Left,
+            )
+            .await;
+            for (texel, expected) in cosited.iter().zip(&[0, 0, 64, 64]) {
+                assert!((texel[0] as i32 - expected).abs() <= 1);
+            }
+            // Centered samples average the pair x = 4, 5
+            let centered = level1(
+                &device,
+                &queue,
+                &generator,
+                &data,
+                &texture_descriptor,
+                ChromaSiting::Center,
+            )
+            .await;
+            for (texel, expected) in centered.iter().zip(&[0, 0, 128, 0]) {
+                assert!((texel[0] as i32 - expected).abs() <= 1);
+            }
+        });
+    }
+}
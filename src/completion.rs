@@ -0,0 +1,254 @@
+use std::{
+    future::Future,
+    ops::Range,
+    pin::Pin,
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+type Mapping = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+type Callback = Box<dyn FnOnce(Range<u32>) + Send>;
+
+#[derive(Debug)]
+struct Fence {
+    buffer: wgpu::Buffer,
+    levels: Range<u32>,
+}
+
+struct Pending {
+    fence: Fence,
+    // None until the encoder the fence was recorded into is submitted
+    mapping: Option<Mapping>,
+    callback: Callback,
+}
+
+/// Calls back on the CPU once levels recorded into an encoder have finished executing on the
+/// GPU, so streaming systems can mark each level resident as soon as it's complete rather than
+/// waiting for the whole texture.
+///
+/// wgpu 0.7 has no `Queue::on_submitted_work_done`, so completion is detected by recording a
+/// 4 byte copy into a fence buffer after the levels, and mapping it once the encoder is
+/// submitted: the mapping completes when the copy, and every command before it, has
+/// executed. Levels complete at the granularity of submissions. To be called back per level,
+/// record one level per encoder, e.g. with `StepBudget::Levels(1)`, and submit each.
+///
+/// ```no_run
+/// # use wgpu_mipmap::*;
+/// # fn example(device: &wgpu::Device, queue: &wgpu::Queue, generator: &RecommendedMipmapGenerator,
+/// #     texture: &wgpu::Texture, texture_descriptor: &wgpu::TextureDescriptor) -> Result<(), Error> {
+/// let mut task = ProgressiveMipmapTask::new(texture_descriptor, GenerateOptions::default())?;
+/// let mut completions = LevelCompletionTracker::new();
+/// while !task.is_finished() {
+///     let mut encoder = device.create_command_encoder(&Default::default());
+///     let levels = task.step(
+///         generator,
+///         device,
+///         &mut encoder,
+///         texture,
+///         texture_descriptor,
+///         StepBudget::Levels(1),
+///     )?;
+///     completions.record(device, &mut encoder, levels, |levels| {
+///         println!("levels {:?} are resident", levels);
+///     });
+///     queue.submit(std::iter::once(encoder.finish()));
+///     completions.submitted();
+/// }
+/// // Once per frame
+/// completions.poll(device);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct LevelCompletionTracker {
+    source: Option<wgpu::Buffer>,
+    pending: Vec<Pending>,
+}
+
+impl std::fmt::Debug for LevelCompletionTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LevelCompletionTracker")
+            .field(
+                "pending",
+                &self
+                    .pending
+                    .iter()
+                    .map(|pending| &pending.fence)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl LevelCompletionTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encodes a fence after the commands already in `encoder`, and registers `callback` to be
+    /// called with `levels` once they have executed. `levels` is only passed back to the
+    /// callback, e.g. the range returned by `ProgressiveMipmapTask::step`.
+    ///
+    /// Call [`LevelCompletionTracker::submitted`] after submitting `encoder`; fences that
+    /// were never submitted never complete.
+    pub fn record(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        levels: Range<u32>,
+        callback: impl FnOnce(Range<u32>) + Send + 'static,
+    ) {
+        let source = self.source.get_or_insert_with(|| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("wgpu-mipmap-fence-source"),
+                size: wgpu::COPY_BUFFER_ALIGNMENT,
+                usage: wgpu::BufferUsage::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("wgpu-mipmap-fence"),
+            size: wgpu::COPY_BUFFER_ALIGNMENT,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_buffer_to_buffer(source, 0, &buffer, 0, wgpu::COPY_BUFFER_ALIGNMENT);
+        self.pending.push(Pending {
+            fence: Fence { buffer, levels },
+            mapping: None,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Marks every fence recorded since the last call as submitted. Call it after submitting
+    /// the encoders passed to [`LevelCompletionTracker::record`]; a buffer can't be mapped
+    /// while a command buffer that uses it is waiting to be submitted.
+    pub fn submitted(&mut self) {
+        for pending in &mut self.pending {
+            if pending.mapping.is_none() {
+                let mapping = pending
+                    .fence
+                    .buffer
+                    .slice(..)
+                    .map_async(wgpu::MapMode::Read);
+                pending.mapping = Some(Box::pin(mapping));
+            }
+        }
+    }
+
+    /// Polls `device` without blocking, then calls the callbacks of the submitted levels that
+    /// have executed, in the order they were recorded. Returns the number of callbacks called.
+    pub fn poll(&mut self, device: &wgpu::Device) -> usize {
+        device.poll(wgpu::Maintain::Poll);
+        let waker = noop_waker();
+        let mut context = Context::from_waker(&waker);
+        let mut called = 0;
+        let mut index = 0;
+        while index < self.pending.len() {
+            let complete = match &mut self.pending[index].mapping {
+                // A failed mapping means the device was lost, so the levels will never complete
+                Some(mapping) => matches!(mapping.as_mut().poll(&mut context), Poll::Ready(Ok(()))),
+                None => false,
+            };
+            if complete {
+                let pending = self.pending.remove(index);
+                pending.fence.buffer.unmap();
+                (pending.callback)(pending.fence.levels);
+                called += 1;
+            } else {
+                index += 1;
+            }
+        }
+        called
+    }
+
+    /// Returns the number of recorded levels whose callback hasn't been called yet.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns true if every callback has been called.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Returns a waker that does nothing, for polling mappings that are driven by
+/// `Device::poll` rather than an executor.
+fn noop_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+    // Safety: the vtable functions ignore the data pointer
+    unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::ComputeMipmapGenerator;
+    use crate::core::*;
+    use crate::progressive::{ProgressiveMipmapTask, StepBudget};
+    use crate::util::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn callbacks_after_execution() {
+        if !adapter_available() {
+            return;
+        }
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 8,
+                height: 8,
+                depth: 1,
+            },
+            mip_level_count: 4,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage(),
+            label: None,
+        };
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = ComputeMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let texture = device.create_texture(&texture_descriptor);
+            let mut task =
+                ProgressiveMipmapTask::new(&texture_descriptor, GenerateOptions::default())
+                    .unwrap();
+            let completed = Arc::new(Mutex::new(Vec::new()));
+            let mut completions = LevelCompletionTracker::new();
+            while !task.is_finished() {
+                let mut encoder = device.create_command_encoder(&Default::default());
+                let levels = task
+                    .step(
+                        &generator,
+                        &device,
+                        &mut encoder,
+                        &texture,
+                        &texture_descriptor,
+                        StepBudget::Levels(1),
+                    )
+                    .unwrap();
+                let completed = completed.clone();
+                completions.record(&device, &mut encoder, levels, move |levels| {
+                    completed.lock().unwrap().push(levels)
+                });
+                // Nothing completes before it's submitted
+                assert_eq!(completions.poll(&device), 0);
+                queue.submit(std::iter::once(encoder.finish()));
+                completions.submitted();
+            }
+            assert_eq!(completions.len(), 3);
+            device.poll(wgpu::Maintain::Wait);
+            assert_eq!(completions.poll(&device), 3);
+            assert!(completions.is_empty());
+            assert_eq!(*completed.lock().unwrap(), vec![1..2, 2..3, 3..4]);
+        });
+    }
+}
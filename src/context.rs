@@ -0,0 +1,147 @@
+/*!
+Device setup for applications that only need a device to generate mipmaps with.
+
+```rust
+use wgpu_mipmap::{context::*, RecommendedMipmapGenerator};
+# futures::executor::block_on(async {
+let context = match Context::new(&ContextOptions::default()).await {
+    Ok(context) => context,
+    Err(_) => return,
+};
+let generator = RecommendedMipmapGenerator::new(&context.device);
+# });
+```
+*/
+use crate::{core::Error, trace::event, RecommendedMipmapGenerator};
+
+/// Returns the limits mipmap generation can make use of. See
+/// [`RecommendedMipmapGenerator::required_limits`].
+pub fn mipmap_limits() -> wgpu::Limits {
+    RecommendedMipmapGenerator::required_limits()
+}
+
+/// Selects the adapter and device a [`Context`] is created with.
+#[derive(Debug, Clone)]
+pub struct ContextOptions {
+    /// The backends to request an adapter from.
+    pub backends: wgpu::BackendBit,
+    pub power_preference: wgpu::PowerPreference,
+    /// Features the device must have. Creating the context fails if the adapter lacks any.
+    /// See [`RecommendedMipmapGenerator::required_features`].
+    pub features: wgpu::Features,
+    /// Limits to request. Limits beyond the adapter's are lowered to the adapter's.
+    pub limits: wgpu::Limits,
+}
+
+impl Default for ContextOptions {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::BackendBit::PRIMARY,
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            features: wgpu::Features::empty(),
+            limits: mipmap_limits(),
+        }
+    }
+}
+
+/// An adapter, and a device and queue created from it.
+#[derive(Debug)]
+pub struct Context {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+impl Context {
+    /// Requests an adapter and a device configured by `options`.
+    pub async fn new(options: &ContextOptions) -> Result<Self, Error> {
+        let instance = wgpu::Instance::new(options.backends);
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: options.power_preference,
+                compatible_surface: None,
+            })
+            .await
+            .ok_or(Error::NoAdapter)?;
+        let missing_features = options.features - adapter.features();
+        if !missing_features.is_empty() {
+            return Err(Error::UnsupportedFeatures(missing_features));
+        }
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: options.features,
+                    limits: clamp_limits(&options.limits, &adapter.limits()),
+                },
+                None,
+            )
+            .await
+            .map_err(|_| Error::RequestDeviceFailed)?;
+        event!(INFO, { adapter = adapter.get_info() }, "created context");
+        Ok(Self {
+            instance,
+            adapter,
+            device,
+            queue,
+        })
+    }
+
+    /// Returns true if the adapter is a software rasterizer, whose results may differ from
+    /// hardware by a rounding step.
+    pub fn is_software(&self) -> bool {
+        self.adapter.get_info().device_type == wgpu::DeviceType::Cpu
+    }
+}
+
+/// Returns `limits`, with every limit beyond `supported` lowered to it.
+fn clamp_limits(limits: &wgpu::Limits, supported: &wgpu::Limits) -> wgpu::Limits {
+    wgpu::Limits {
+        max_bind_groups: limits.max_bind_groups.min(supported.max_bind_groups),
+        max_dynamic_uniform_buffers_per_pipeline_layout: limits
+            .max_dynamic_uniform_buffers_per_pipeline_layout
+            .min(supported.max_dynamic_uniform_buffers_per_pipeline_layout),
+        max_dynamic_storage_buffers_per_pipeline_layout: limits
+            .max_dynamic_storage_buffers_per_pipeline_layout
+            .min(supported.max_dynamic_storage_buffers_per_pipeline_layout),
+        max_sampled_textures_per_shader_stage: limits
+            .max_sampled_textures_per_shader_stage
+            .min(supported.max_sampled_textures_per_shader_stage),
+        max_samplers_per_shader_stage: limits
+            .max_samplers_per_shader_stage
+            .min(supported.max_samplers_per_shader_stage),
+        max_storage_buffers_per_shader_stage: limits
+            .max_storage_buffers_per_shader_stage
+            .min(supported.max_storage_buffers_per_shader_stage),
+        max_storage_textures_per_shader_stage: limits
+            .max_storage_textures_per_shader_stage
+            .min(supported.max_storage_textures_per_shader_stage),
+        max_uniform_buffers_per_shader_stage: limits
+            .max_uniform_buffers_per_shader_stage
+            .min(supported.max_uniform_buffers_per_shader_stage),
+        max_uniform_buffer_binding_size: limits
+            .max_uniform_buffer_binding_size
+            .min(supported.max_uniform_buffer_binding_size),
+        max_push_constant_size: limits
+            .max_push_constant_size
+            .min(supported.max_push_constant_size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_are_clamped() {
+        let supported = wgpu::Limits {
+            max_storage_textures_per_shader_stage: 4,
+            max_bind_groups: 8,
+            ..Default::default()
+        };
+        let limits = clamp_limits(&mipmap_limits(), &supported);
+        assert_eq!(limits.max_storage_textures_per_shader_stage, 4);
+        assert_eq!(limits.max_bind_groups, mipmap_limits().max_bind_groups);
+    }
+}
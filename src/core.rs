@@ -1,9 +1,24 @@
+use crate::util::{get_mip_extent, level_bytes};
+use std::ops::Range;
 use thiserror::Error;
 
 /// MipmapGenerator describes types that can generate mipmaps for a texture.
+///
+/// The trait is object safe, and is implemented for references, `Box` and `Arc` of any
+/// generator, so generators can be stored as a [`BoxedMipmapGenerator`] or shared behind an
+/// `Arc<dyn MipmapGenerator + Send + Sync>`.
+///
+/// Generating overwrites every texel of each level it writes with a value computed from the
+/// level before it, whatever the level held before, so calling `generate` again after
+/// changing level 0 leaves nothing computed from its previous contents. Levels outside the
+/// range selected by [`GenerateOptions`], other array layers, and with a
+/// [`GenerateOptions::dirty_rect`], texels not computed from the rect, are left untouched.
 pub trait MipmapGenerator {
     /// Encodes commands to generate mipmaps for a texture.
     ///
+    /// A texture with a single mip level has no levels to generate, so every backend returns
+    /// `Ok(())` without encoding anything or validating the texture.
+    ///
     /// Expectations:
     /// - `texture_descriptor` should be the same descriptor used to create the `texture`.
     fn generate(
@@ -12,11 +27,463 @@ pub trait MipmapGenerator {
         encoder: &mut wgpu::CommandEncoder,
         texture: &wgpu::Texture,
         texture_descriptor: &wgpu::TextureDescriptor,
+    ) -> Result<(), Error> {
+        self.generate_with_options(
+            device,
+            encoder,
+            texture,
+            texture_descriptor,
+            &GenerateOptions::default(),
+        )
+    }
+
+    /// Encodes commands to generate all mip levels of a single array layer of a texture,
+    /// leaving the other layers untouched, e.g. after updating one slice of a texture array.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`.
+    fn generate_layer(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        layer: u32,
+    ) -> Result<(), Error> {
+        self.generate_with_options(
+            device,
+            encoder,
+            texture,
+            texture_descriptor,
+            &GenerateOptions {
+                array_layer: layer,
+                ..GenerateOptions::default()
+            },
+        )
+    }
+
+    /// Encodes commands to generate the mip levels of a texture selected by `options`.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`.
+    fn generate_with_options(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        options: &GenerateOptions,
     ) -> Result<(), Error>;
+
+    /// Returns the work `generate` would encode for a texture described by
+    /// `texture_descriptor`, without encoding anything.
+    fn estimate_cost(
+        &self,
+        texture_descriptor: &wgpu::TextureDescriptor,
+    ) -> Result<GenerationCost, Error> {
+        self.estimate_cost_with_options(texture_descriptor, &GenerateOptions::default())
+    }
+
+    /// Returns the work `generate_with_options` would encode for a texture described by
+    /// `texture_descriptor`, without encoding anything. The default estimate is one pass and
+    /// one draw or dispatch per level written.
+    fn estimate_cost_with_options(
+        &self,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<GenerationCost, Error> {
+        Ok(GenerationCost::per_level(
+            texture_descriptor,
+            options.mip_levels(texture_descriptor)?,
+        ))
+    }
+}
+
+/// A generator behind a trait object, e.g. for plugin systems that pick a backend at runtime
+/// and share it between threads. Every generator of this crate converts into one on native
+/// targets; on wasm, wgpu's types are neither `Send` nor `Sync`.
+pub type BoxedMipmapGenerator = Box<dyn MipmapGenerator + Send + Sync>;
+
+// Forwards every method, so the estimates of generators that override them are kept behind
+// references and smart pointers
+macro_rules! forward_mipmap_generator {
+    ($($ty:ty),*) => {$(
+        impl<G: MipmapGenerator + ?Sized> MipmapGenerator for $ty {
+            fn generate(
+                &self,
+                device: &wgpu::Device,
+                encoder: &mut wgpu::CommandEncoder,
+                texture: &wgpu::Texture,
+                texture_descriptor: &wgpu::TextureDescriptor,
+            ) -> Result<(), Error> {
+                (**self).generate(device, encoder, texture, texture_descriptor)
+            }
+
+            fn generate_layer(
+                &self,
+                device: &wgpu::Device,
+                encoder: &mut wgpu::CommandEncoder,
+                texture: &wgpu::Texture,
+                texture_descriptor: &wgpu::TextureDescriptor,
+                layer: u32,
+            ) -> Result<(), Error> {
+                (**self).generate_layer(device, encoder, texture, texture_descriptor, layer)
+            }
+
+            fn generate_with_options(
+                &self,
+                device: &wgpu::Device,
+                encoder: &mut wgpu::CommandEncoder,
+                texture: &wgpu::Texture,
+                texture_descriptor: &wgpu::TextureDescriptor,
+                options: &GenerateOptions,
+            ) -> Result<(), Error> {
+                (**self).generate_with_options(
+                    device,
+                    encoder,
+                    texture,
+                    texture_descriptor,
+                    options,
+                )
+            }
+
+            fn estimate_cost(
+                &self,
+                texture_descriptor: &wgpu::TextureDescriptor,
+            ) -> Result<GenerationCost, Error> {
+                (**self).estimate_cost(texture_descriptor)
+            }
+
+            fn estimate_cost_with_options(
+                &self,
+                texture_descriptor: &wgpu::TextureDescriptor,
+                options: &GenerateOptions,
+            ) -> Result<GenerationCost, Error> {
+                (**self).estimate_cost_with_options(texture_descriptor, options)
+            }
+        }
+    )*};
+}
+
+forward_mipmap_generator!(&G, Box<G>, std::sync::Arc<G>);
+
+/// The fields of a [`wgpu::TextureDescriptor`] mipmap generation uses, without the label
+/// whose lifetime the descriptor carries, so it can be stored next to the texture and passed
+/// around freely, e.g. across the boundary of a plugin system.
+///
+/// ```
+/// # use wgpu_mipmap::*;
+/// fn generate(
+///     generator: &BoxedMipmapGenerator,
+///     device: &wgpu::Device,
+///     encoder: &mut wgpu::CommandEncoder,
+///     texture: &wgpu::Texture,
+///     texture_descriptor: &MipTextureDescriptor,
+/// ) -> Result<(), Error> {
+///     generator.generate(device, encoder, texture, &texture_descriptor.to_descriptor())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MipTextureDescriptor {
+    pub size: wgpu::Extent3d,
+    pub mip_level_count: u32,
+    pub sample_count: u32,
+    pub dimension: wgpu::TextureDimension,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsage,
+}
+
+impl MipTextureDescriptor {
+    /// Returns the descriptor as a `wgpu::TextureDescriptor` without a label, to create the
+    /// texture with or pass to a [`MipmapGenerator`].
+    pub fn to_descriptor(&self) -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            label: None,
+            size: self.size,
+            mip_level_count: self.mip_level_count,
+            sample_count: self.sample_count,
+            dimension: self.dimension,
+            format: self.format,
+            usage: self.usage,
+        }
+    }
+}
+
+impl From<&wgpu::TextureDescriptor<'_>> for MipTextureDescriptor {
+    fn from(texture_descriptor: &wgpu::TextureDescriptor<'_>) -> Self {
+        Self {
+            size: texture_descriptor.size,
+            mip_level_count: texture_descriptor.mip_level_count,
+            sample_count: texture_descriptor.sample_count,
+            dimension: texture_descriptor.dimension,
+            format: texture_descriptor.format,
+            usage: texture_descriptor.usage,
+        }
+    }
+}
+
+/// The GPU work encoded to generate the mipmaps of one texture, for engines that budget
+/// texture mipping against other work in a frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GenerationCost {
+    /// The number of render and compute passes.
+    pub passes: u32,
+    /// The number of draw calls and compute dispatches.
+    pub dispatches: u32,
+    /// The number of bytes written to textures, including scratch textures and copies.
+    pub bytes_written: u64,
+}
+
+impl GenerationCost {
+    /// Returns the cost of writing `mip_levels` with one pass and one draw or dispatch each.
+    pub(crate) fn per_level(
+        texture_descriptor: &wgpu::TextureDescriptor,
+        mip_levels: Range<u32>,
+    ) -> Self {
+        let count = mip_levels.end - mip_levels.start;
+        Self {
+            passes: count,
+            dispatches: count,
+            bytes_written: level_bytes(texture_descriptor, mip_levels),
+        }
+    }
+}
+
+/// Selects which mip levels are generated.
+///
+/// New options may be added in minor releases, so the struct can't be built with a struct
+/// expression outside this crate. Start from `GenerateOptions::default()` and set the fields
+/// to change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct GenerateOptions {
+    /// The mip level treated as the source. Only the levels after it are written.
+    pub base_mip_level: u32,
+    /// Generation stops at the first level whose width or height is smaller than this,
+    /// e.g. 4 to leave out levels too small for block-compressed formats.
+    pub min_dimension: u32,
+    /// Writes at most this many levels after `base_mip_level`. `None` writes every remaining
+    /// level.
+    pub max_level_count: Option<u32>,
+    /// Lets `ComputeMipmapGenerator` write two mip levels per dispatch, halving the number
+    /// of passes. The intermediate level is averaged at full float precision before it is
+    /// stored, so results may differ from one level per pass by a rounding step.
+    pub two_levels_per_pass: bool,
+    /// Lets `ComputeMipmapGenerator` write the last (up to) 6 levels, starting from a level
+    /// no larger than 64x64, in a single dispatch. Like `two_levels_per_pass`, intermediate
+    /// levels are kept at full float precision. Ignored on devices created without
+    /// `ComputeMipmapGenerator::required_limits`.
+    pub tail_combine: bool,
+    /// Lets `RenderMipmapGenerator` draw the last levels, starting from a level no larger
+    /// than 64x64, into a scratch atlas in a single render pass, then copy each one into
+    /// place. This trades a copy per level for fewer render passes, which are costly on
+    /// tile-based GPUs. Atlas levels are box filtered straight from the level before the
+    /// first of them, so odd-sized levels may differ slightly. Ignored with filters other than
+    /// `MipFilter::Box`, with a `dirty_rect`, and for textures without `TextureUsage::COPY_DST`.
+    pub tail_atlas: bool,
+    /// How each texel of a mip level is computed from the level before it. Integer
+    /// formats use `MipFilter::Nearest` in place of `MipFilter::Box`.
+    pub filter: MipFilter,
+    /// The texels of `base_mip_level` that changed since the mipmaps were last generated.
+    /// `RenderMipmapGenerator` then only draws the texels of each level computed from them,
+    /// and leaves the rest of the level as it is, as do `CopyMipmapGenerator` and
+    /// `ScratchMipmapGenerator`, which generate whole levels in a temporary texture but only
+    /// copy those texels back. Other backends regenerate whole levels.
+    pub dirty_rect: Option<MipRect>,
+    /// The array layer whose mip levels are generated. The other layers are left untouched.
+    /// Only used by the compute, render and copy backends, and `RecommendedMipmapGenerator`.
+    pub array_layer: u32,
+    /// How `MipFilter::Box` rounds averages halfway between two 8 bit values, e.g. 127.5.
+    pub rounding: BoxRounding,
+}
+
+/// A rectangle of texels in a mip level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct MipRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl MipRect {
+    /// Returns the texels of the next level computed from texels in this rectangle of a
+    /// level of size `extent`. Odd-sized levels are downsampled with filters wider than 2x2,
+    /// so the rectangle is widened by a texel on each side along their odd dimensions.
+    pub fn next_level(&self, extent: &wgpu::Extent3d) -> Self {
+        let next = |start: u32, len: u32, size: u32| {
+            let next_size = (size / 2).max(1);
+            let end = (start + len).min(size);
+            if end <= start {
+                return (start.min(size) / 2, 0);
+            }
+            let (mut next_start, mut next_end) = (start / 2, end.div_ceil(2));
+            if size % 2 == 1 {
+                next_start = next_start.saturating_sub(1);
+                next_end += 1;
+            }
+            let next_end = next_end.min(next_size);
+            (
+                next_start.min(next_end),
+                next_end - next_start.min(next_end),
+            )
+        };
+        let (x, width) = next(self.x, self.width, extent.width);
+        let (y, height) = next(self.y, self.height, extent.height);
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns true if the rectangle contains no texels.
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+/// How the box filter quantizes the average of 8 bit unorm texels.
+///
+/// Devices round halfway values such as 127.5 up or down as they please when storing them, so
+/// the compute and render backends round the box filter's averages explicitly before storing
+/// them, and every device writes the same texels, e.g. for content hashing pipelines. This
+/// applies to `R8Unorm`, `Rg8Unorm`, `Rgba8Unorm` and `Bgra8Unorm` levels filtered one level
+/// per pass from even-sized levels. Other formats, including sRGB ones, odd-sized levels and
+/// `two_levels_per_pass`, `tail_combine` and `tail_atlas` levels are rounded by the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BoxRounding {
+    /// Rounds halves to the nearest even value, so 126.5 becomes 126 and 127.5 becomes 128.
+    /// Unlike rounding up, this doesn't brighten levels on average.
+    #[default]
+    HalfEven,
+    /// Rounds halves up, so 126.5 becomes 127 and 127.5 becomes 128.
+    HalfUp,
+}
+
+/// The filter used to downsample a mip level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MipFilter {
+    /// Averages each 2x2 block of texels.
+    #[default]
+    Box,
+    /// Takes the top-left texel of each 2x2 block, for data textures such as object
+    /// IDs or light indices whose texels must not be blended.
+    Nearest,
+    /// Takes one texel of each 2x2 block, picked by hashing the texel's position, its
+    /// mip level and `seed`. The same seed always picks the same texels. Unlike `Box`,
+    /// this preserves the value distribution of blue noise and dither textures.
+    Stochastic {
+        /// Selects a different, but still deterministic, set of texels.
+        seed: u32,
+    },
+    /// Detail-preserving image downscaling (DPID, Weber et al. 2016): averages each 2x2
+    /// block, giving more weight to texels that differ from a smoothed box filtered guidance
+    /// level, so small details survive in thumbnail-sized mips. Only supported by
+    /// `ComputeMipmapGenerator`, for `Rgba8Unorm`, `Bgra8Unorm`, `Rgba16Float` and
+    /// `Rgba32Float` textures.
+    Dpid,
+    /// Box filter that weights each source row by the cosine of its latitude, i.e. the solid
+    /// angle its texels cover in an equirectangular environment map, so the lower mips of
+    /// panoramas aren't biased toward the poles. Only supported for floating-point formats.
+    Equirectangular,
+    /// Takes the most frequent texel of each 2x2 block, for label textures such as shadow
+    /// map IDs or material IDs where the top-left texel may be an outlier. Ties are broken
+    /// in favor of the top-left, top-right, bottom-left, then bottom-right texel. Only
+    /// supported for integer formats.
+    Mode,
+    /// Takes the texel of each 2x2 block whose red and green channels form the longest
+    /// vector, for the velocity pyramids of TAA and motion blur, where averaging would
+    /// shorten the motion of thin moving objects. Ties are broken like `Mode`. Only supported
+    /// for `Rg16Float` and `Rg32Float`.
+    MaxMagnitude,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        Self {
+            base_mip_level: 0,
+            min_dimension: 1,
+            max_level_count: None,
+            two_levels_per_pass: false,
+            tail_combine: false,
+            tail_atlas: false,
+            filter: MipFilter::Box,
+            dirty_rect: None,
+            array_layer: 0,
+            rounding: BoxRounding::HalfEven,
+        }
+    }
 }
 
-/// An error that occurred during mipmap generation.
+impl GenerateOptions {
+    /// Returns the range of mip levels of a texture described by `texture_descriptor`
+    /// that are written when generating with these options. The range may be empty.
+    pub fn mip_levels(
+        &self,
+        texture_descriptor: &wgpu::TextureDescriptor,
+    ) -> Result<Range<u32>, Error> {
+        let mip_count = texture_descriptor.mip_level_count;
+        if self.base_mip_level >= mip_count {
+            return Err(Error::InvalidBaseMipLevel(self.base_mip_level));
+        }
+        if self.array_layer >= texture_descriptor.size.depth {
+            return Err(Error::InvalidArrayLayer(self.array_layer));
+        }
+        let start = self.base_mip_level + 1;
+        let end = (start..mip_count)
+            .find(|&mip| {
+                let extent = get_mip_extent(&texture_descriptor.size, mip);
+                extent.width < self.min_dimension || extent.height < self.min_dimension
+            })
+            .unwrap_or(mip_count);
+        let end = match self.max_level_count {
+            Some(max_level_count) => end.min(start + max_level_count),
+            None => end,
+        };
+        Ok(start..end)
+    }
+
+    /// Returns the rectangle of each level in [`GenerateOptions::mip_levels`] computed from
+    /// `dirty_rect`, or the whole level without one, i.e. the texels a backend honoring
+    /// `dirty_rect` writes. Levels below an empty rectangle are left out, as nothing in them
+    /// changes.
+    pub fn written_rects(
+        &self,
+        texture_descriptor: &wgpu::TextureDescriptor,
+    ) -> Result<Vec<(u32, MipRect)>, Error> {
+        let mip_levels = self.mip_levels(texture_descriptor)?;
+        let size = &texture_descriptor.size;
+        let mut dirty_rect = self.dirty_rect;
+        let mut rects = Vec::new();
+        for mip_level in mip_levels {
+            let extent = get_mip_extent(size, mip_level);
+            let rect = match &mut dirty_rect {
+                Some(rect) => {
+                    *rect = rect.next_level(&get_mip_extent(size, mip_level - 1));
+                    *rect
+                }
+                None => MipRect {
+                    x: 0,
+                    y: 0,
+                    width: extent.width,
+                    height: extent.height,
+                },
+            };
+            if rect.is_empty() {
+                break;
+            }
+            rects.push((mip_level, rect));
+        }
+        Ok(rects)
+    }
+}
+
+/// An error that occurred during mipmap generation. New variants may be added in minor
+/// releases.
 #[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Error {
     #[error("Unsupported texture usage `{0:?}`.\nYour texture usage must contain one of: 1. TextureUsage::STORAGE, 2. TextureUsage::OUTPUT_ATTACHMENT | TextureUsage::SAMPLED, 3. TextureUsage::COPY_SRC | TextureUsage::COPY_DST")]
     UnsupportedUsage(wgpu::TextureUsage),
@@ -30,4 +497,341 @@ pub enum Error {
     NpotTexture,
     #[error("Unknown texture format `{0:?}`.\nDid you mean to specify it in `MipmapGeneratorDescriptor::formats`?")]
     UnknownFormat(wgpu::TextureFormat),
+    #[error(
+        "Mismatched texture sizes. The source and destination textures must be the same size."
+    )]
+    ExtentMismatch,
+    #[error("Invalid base mip level `{0}`. The base mip level must be less than the texture's mip level count.")]
+    InvalidBaseMipLevel(u32),
+    #[error(
+        "Invalid array layer `{0}`. The array layer must be less than the texture's layer count."
+    )]
+    InvalidArrayLayer(u32),
+    #[error("Unsupported filter `{0:?}`. This generator does not implement it.")]
+    UnsupportedFilter(MipFilter),
+    #[error("Unsupported YUV plane format `{0:?}`. Luma planes must be `R8Unorm` and chroma planes `Rg8Unorm`.")]
+    UnsupportedPlaneFormat(wgpu::TextureFormat),
+    #[error(
+        "Texture size `{0:?}` is not a cubemap. Cubemaps are 2D textures with 6 square layers."
+    )]
+    NotACubemap(wgpu::Extent3d),
+    #[error("Incompatible storage view format `{1:?}` for a texture of format `{0:?}`.")]
+    IncompatibleViewFormat(wgpu::TextureFormat, wgpu::TextureFormat),
+    #[error("Failed to map a readback buffer.")]
+    ReadbackFailed,
+    #[error("Failed to find an adapter.")]
+    NoAdapter,
+    #[error("Unsupported features `{0:?}`. The adapter does not support them.")]
+    UnsupportedFeatures(wgpu::Features),
+    #[error("Failed to create a device.")]
+    RequestDeviceFailed,
+    #[error("The compute backend can't generate mipmaps for this texture: {0}\nStrict mode does not fall back to the render or copy backends.")]
+    FallbackRequired(Box<Error>),
+    #[error(
+        "Got `{0}` level views for a texture with `{1}` mip levels. Pass one view per mip level."
+    )]
+    LevelViewCount(usize, u32),
+    #[error("Unsupported storage texture format `{0:?}`. The device can't write it from a compute shader; try using the render or copy backend.")]
+    UnsupportedStorageFormat(wgpu::TextureFormat),
+    #[error("Mip level `{0}` is missing and no resident level can generate it. Mark the levels loaded with the texture resident.")]
+    MissingSourceLevel(u32),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(
+        width: u32,
+        height: u32,
+        mip_level_count: u32,
+    ) -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsage::STORAGE,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn written_rects() {
+        let descriptor = descriptor(16, 16, 5);
+        let whole = GenerateOptions {
+            max_level_count: Some(2),
+            ..GenerateOptions::default()
+        };
+        let rect = |x, y, width, height| MipRect {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            whole.written_rects(&descriptor),
+            Ok(vec![(1, rect(0, 0, 8, 8)), (2, rect(0, 0, 4, 4))])
+        );
+        let dirty = GenerateOptions {
+            dirty_rect: Some(rect(4, 0, 4, 2)),
+            ..GenerateOptions::default()
+        };
+        assert_eq!(
+            dirty.written_rects(&descriptor),
+            Ok(vec![
+                (1, rect(2, 0, 2, 1)),
+                (2, rect(1, 0, 1, 1)),
+                (3, rect(0, 0, 1, 1)),
+                (4, rect(0, 0, 1, 1)),
+            ])
+        );
+        let empty = GenerateOptions {
+            dirty_rect: Some(rect(4, 0, 0, 2)),
+            ..GenerateOptions::default()
+        };
+        assert_eq!(empty.written_rects(&descriptor), Ok(vec![]));
+    }
+
+    #[test]
+    fn default_mip_levels() {
+        let options = GenerateOptions::default();
+        assert_eq!(options.mip_levels(&descriptor(512, 512, 10)), Ok(1..10));
+        assert_eq!(options.mip_levels(&descriptor(512, 512, 1)), Ok(1..1));
+    }
+
+    #[test]
+    fn base_mip_level_and_min_dimension() {
+        let options = GenerateOptions {
+            base_mip_level: 2,
+            min_dimension: 4,
+            ..Default::default()
+        };
+        // levels 3..=7 are 64x64 down to 4x4
+        assert_eq!(options.mip_levels(&descriptor(512, 512, 10)), Ok(3..8));
+        // level 3 of a 512x64 texture is 64x8, level 5 is 16x2
+        assert_eq!(options.mip_levels(&descriptor(512, 64, 10)), Ok(3..5));
+    }
+
+    #[test]
+    fn rectangle_mip_levels() {
+        let options = GenerateOptions::default();
+        assert_eq!(options.mip_levels(&descriptor(4096, 16, 13)), Ok(1..13));
+        assert_eq!(options.mip_levels(&descriptor(3, 1025, 11)), Ok(1..11));
+        // The shorter dimension reaches the minimum first
+        let options = GenerateOptions {
+            min_dimension: 4,
+            ..Default::default()
+        };
+        assert_eq!(options.mip_levels(&descriptor(4096, 16, 13)), Ok(1..3));
+        assert_eq!(options.mip_levels(&descriptor(16, 4096, 13)), Ok(1..3));
+    }
+
+    #[test]
+    fn degenerate_mip_levels() {
+        // A dimension of 1 stays at 1, so every level of the longer dimension is generated
+        let options = GenerateOptions::default();
+        assert_eq!(options.mip_levels(&descriptor(1, 1024, 11)), Ok(1..11));
+        assert_eq!(options.mip_levels(&descriptor(1023, 1, 10)), Ok(1..10));
+        let extent = get_mip_extent(&descriptor(1, 1024, 11).size, 10);
+        assert_eq!((extent.width, extent.height), (1, 1));
+        let options = GenerateOptions {
+            min_dimension: 2,
+            ..Default::default()
+        };
+        assert_eq!(options.mip_levels(&descriptor(1, 1024, 11)), Ok(1..1));
+    }
+
+    #[test]
+    fn dirty_rect_next_level() {
+        let extent = |width, height| wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        };
+        let rect = |x, y, width, height| MipRect {
+            x,
+            y,
+            width,
+            height,
+        };
+        // even levels map 2x2 blocks to single texels
+        assert_eq!(
+            rect(3, 4, 2, 4).next_level(&extent(16, 16)),
+            rect(1, 2, 2, 2)
+        );
+        // odd dimensions are widened by a texel on each side, clamped to the level
+        assert_eq!(
+            rect(0, 4, 2, 1).next_level(&extent(5, 16)),
+            rect(0, 2, 2, 1)
+        );
+        assert_eq!(
+            rect(4, 0, 1, 2).next_level(&extent(5, 16)),
+            rect(1, 0, 1, 1)
+        );
+        // rectangles past the edge of the level are clamped
+        assert_eq!(
+            rect(6, 6, 100, 100).next_level(&extent(8, 8)),
+            rect(3, 3, 1, 1)
+        );
+        assert!(rect(2, 2, 0, 4).next_level(&extent(8, 8)).is_empty());
+    }
+
+    #[test]
+    fn per_level_cost() {
+        // levels 1..=9 are 256x256 down to 1x1, (4^9 - 1) / 3 texels of 4 bytes
+        assert_eq!(
+            GenerationCost::per_level(&descriptor(512, 512, 10), 1..10),
+            GenerationCost {
+                passes: 9,
+                dispatches: 9,
+                bytes_written: 4 * 87381,
+            }
+        );
+        assert_eq!(
+            GenerationCost::per_level(&descriptor(512, 512, 10), 1..1),
+            GenerationCost::default()
+        );
+    }
+
+    #[test]
+    fn max_level_count() {
+        let options = GenerateOptions {
+            base_mip_level: 2,
+            max_level_count: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(options.mip_levels(&descriptor(512, 512, 10)), Ok(3..6));
+        // min_dimension can still end the range first
+        let options = GenerateOptions {
+            min_dimension: 128,
+            ..options
+        };
+        assert_eq!(options.mip_levels(&descriptor(512, 512, 10)), Ok(3..3));
+    }
+
+    #[test]
+    fn invalid_base_mip_level() {
+        let options = GenerateOptions {
+            base_mip_level: 10,
+            ..Default::default()
+        };
+        assert_eq!(
+            options.mip_levels(&descriptor(512, 512, 10)),
+            Err(Error::InvalidBaseMipLevel(10))
+        );
+    }
+
+    // Random descriptors and options must never panic, and always select levels that
+    // exist and are no smaller than `min_dimension`
+    #[test]
+    fn random_mip_levels() {
+        let mut rng = crate::util::TestRng::new(0x5eed);
+        for _ in 0..10_000 {
+            let (width, height) = (rng.range(1..5000), rng.range(1..5000));
+            let mip_level_count = rng.range(1..16);
+            let max_level_count = rng.range(0..16);
+            let options = GenerateOptions {
+                base_mip_level: rng.range(0..16),
+                min_dimension: rng.range(0..64),
+                max_level_count: *rng.choose(&[None, Some(0), Some(1), Some(max_level_count)]),
+                ..Default::default()
+            };
+            let descriptor = descriptor(width, height, mip_level_count);
+            let levels = match options.mip_levels(&descriptor) {
+                Ok(levels) => levels,
+                Err(e) => {
+                    assert!(options.base_mip_level >= mip_level_count);
+                    assert_eq!(e, Error::InvalidBaseMipLevel(options.base_mip_level));
+                    continue;
+                }
+            };
+            assert_eq!(levels.start, options.base_mip_level + 1, "{:?}", options);
+            assert!(levels.end <= mip_level_count, "{:?}", options);
+            if let Some(max_level_count) = options.max_level_count {
+                assert!(levels.len() as u32 <= max_level_count, "{:?}", options);
+            }
+            for level in levels {
+                let extent = get_mip_extent(&descriptor.size, level);
+                assert!(extent.width >= options.min_dimension, "{:?}", options);
+                assert!(extent.height >= options.min_dimension, "{:?}", options);
+            }
+        }
+    }
+
+    // Estimates one pass for any texture, unlike the default estimate
+    struct OnePass;
+
+    impl MipmapGenerator for OnePass {
+        fn generate_with_options(
+            &self,
+            _device: &wgpu::Device,
+            _encoder: &mut wgpu::CommandEncoder,
+            _texture: &wgpu::Texture,
+            _texture_descriptor: &wgpu::TextureDescriptor,
+            _options: &GenerateOptions,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn estimate_cost_with_options(
+            &self,
+            _texture_descriptor: &wgpu::TextureDescriptor,
+            _options: &GenerateOptions,
+        ) -> Result<GenerationCost, Error> {
+            Ok(GenerationCost {
+                passes: 1,
+                ..GenerationCost::default()
+            })
+        }
+    }
+
+    #[test]
+    fn boxed_generators() {
+        fn assert_boxable<G: MipmapGenerator + Send + Sync + 'static>() {}
+        assert_boxable::<crate::RecommendedMipmapGenerator>();
+        assert_boxable::<crate::ComputeMipmapGenerator>();
+        assert_boxable::<crate::RenderMipmapGenerator>();
+        assert_boxable::<crate::ScratchMipmapGenerator>();
+        assert_boxable::<crate::PackedMipmapGenerator>();
+        assert_boxable::<BoxedMipmapGenerator>();
+        assert_boxable::<std::sync::Arc<dyn MipmapGenerator + Send + Sync>>();
+        // Overridden methods are forwarded, rather than replaced by the defaults
+        let descriptor = descriptor(16, 16, 5);
+        let expected = Ok(GenerationCost {
+            passes: 1,
+            ..GenerationCost::default()
+        });
+        let boxed: BoxedMipmapGenerator = Box::new(OnePass);
+        assert_eq!(boxed.estimate_cost(&descriptor), expected);
+        let shared: std::sync::Arc<dyn MipmapGenerator + Send + Sync> = boxed.into();
+        assert_eq!(shared.estimate_cost(&descriptor), expected);
+        let generators: Vec<&dyn MipmapGenerator> = vec![&shared, &OnePass];
+        for generator in generators {
+            assert_eq!(generator.estimate_cost(&descriptor), expected);
+        }
+    }
+
+    #[test]
+    fn texture_descriptor_without_label() {
+        let labeled = wgpu::TextureDescriptor {
+            label: Some("labeled"),
+            ..descriptor(300, 17, 9)
+        };
+        let descriptor = MipTextureDescriptor::from(&labeled);
+        assert_eq!(descriptor.size, labeled.size);
+        assert_eq!(descriptor.format, labeled.format);
+        assert_eq!(
+            descriptor.to_descriptor(),
+            wgpu::TextureDescriptor {
+                label: None,
+                ..labeled
+            }
+        );
+    }
 }
@@ -0,0 +1,607 @@
+use crate::backends::hash_bytes;
+use crate::core::*;
+use crate::trace::event;
+use crate::util::{readback_layout, MipBuffer, MipReadback};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const INDEX_FILE: &str = "index";
+const INDEX_HEADER: &str = "wgpu-mipmap-cache 1";
+const ENTRY_MAGIC: &[u8; 4] = b"WMMC";
+const ENTRY_VERSION: u32 = 2;
+// Seeds the hash of the base level stored in each entry, so it doesn't collide whenever keys do
+const CONTENT_SEED: u64 = 1;
+
+/// Identifies a mip chain in a [`MipChainCache`] by the content of its base level, the
+/// texture it belongs to and the options it's generated with.
+///
+/// The version of this crate is part of the key, as the levels it generates from the same base
+/// level may change between versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MipChainKey(pub u64);
+
+impl MipChainKey {
+    /// Returns the key of the levels generated from `base_level` with `options`.
+    ///
+    /// `base_level` holds the tightly packed texels of every array layer of level
+    /// `options.base_mip_level`. `options.array_layer` and `options.dirty_rect` are ignored,
+    /// as the whole chain of every layer is cached.
+    pub fn new(
+        base_level: &[u8],
+        texture_descriptor: &wgpu::TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Self {
+        let options = GenerateOptions {
+            array_layer: 0,
+            dirty_rect: None,
+            ..*options
+        };
+        let description = format!(
+            "{} {:?} {} {:?} {:?} {:?}",
+            env!("CARGO_PKG_VERSION"),
+            texture_descriptor.size,
+            texture_descriptor.mip_level_count,
+            texture_descriptor.dimension,
+            texture_descriptor.format,
+            options,
+        );
+        let content = hash_bytes(base_level, 0);
+        let description = hash_bytes(description.as_bytes(), 0);
+        Self(hash_bytes(bytemuck::cast_slice(&[content, description]), 0))
+    }
+
+    fn file_name(&self) -> String {
+        format!("{:016x}.mips", self.0)
+    }
+}
+
+/// A directory of generated mip chains, so editors re-importing the same assets reload their
+/// levels from disk instead of generating them again on the GPU.
+///
+/// Each chain is stored in its own file, named after its [`MipChainKey`], and an index file
+/// lists the chains and their size. The cache isn't safe to share between processes writing
+/// to it at the same time. Not available on wasm.
+///
+/// ```no_run
+/// # use wgpu_mipmap::*;
+/// # async fn example(device: &wgpu::Device, queue: &wgpu::Queue,
+/// #     generator: &RecommendedMipmapGenerator, texture: &wgpu::Texture,
+/// #     texture_descriptor: &wgpu::TextureDescriptor<'_>, level_0: &[u8]) -> Result<(), Error> {
+/// let mut cache = MipChainCache::open("target/mip-cache").expect("failed to open the cache");
+/// // Level 0 must already be written to the texture
+/// let loaded = cache
+///     .generate_or_load(device, queue, generator, texture, texture_descriptor, level_0,
+///         &GenerateOptions::default())
+///     .await?;
+/// println!("loaded from disk: {}", loaded);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MipChainCache {
+    dir: PathBuf,
+    // The size in bytes of each cached chain's file
+    index: HashMap<MipChainKey, u64>,
+}
+
+impl MipChainCache {
+    /// Returns the texture usage [`MipChainCache::generate_or_load`] requires, besides the
+    /// usage of the generator, to read generated levels back and write cached levels.
+    pub fn required_usage() -> wgpu::TextureUsage {
+        wgpu::TextureUsage::COPY_SRC | wgpu::TextureUsage::COPY_DST
+    }
+
+    /// Opens the cache stored in `dir`, creating the directory if it doesn't exist.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let index = match fs::read_to_string(dir.join(INDEX_FILE)) {
+            Ok(index) => parse_index(&index),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(error) => return Err(error),
+        };
+        Ok(Self { dir, index })
+    }
+
+    /// Returns the directory the cache is stored in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the number of cached chains.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns true if no chain is cached.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the size in bytes of every cached chain's file.
+    pub fn total_bytes(&self) -> u64 {
+        self.index.values().sum()
+    }
+
+    /// Returns true if the chain of `key` is cached.
+    pub fn contains(&self, key: MipChainKey) -> bool {
+        self.index.contains_key(&key)
+    }
+
+    /// Writes `levels` to the cache as the chain of `key`, generated from `base_level`,
+    /// replacing any chain cached for it.
+    ///
+    /// The length and a hash of `base_level`, independent of the key, are stored with the
+    /// levels, so [`MipChainCache::load`] can tell two base levels with the same key apart.
+    pub fn store(
+        &mut self,
+        key: MipChainKey,
+        base_level: &[u8],
+        levels: &[MipBuffer],
+    ) -> io::Result<()> {
+        let mut file = Vec::new();
+        file.extend_from_slice(ENTRY_MAGIC);
+        file.extend_from_slice(&ENTRY_VERSION.to_le_bytes());
+        file.extend_from_slice(&key.0.to_le_bytes());
+        file.extend_from_slice(&(base_level.len() as u64).to_le_bytes());
+        file.extend_from_slice(&hash_bytes(base_level, CONTENT_SEED).to_le_bytes());
+        file.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+        for level in levels {
+            let dims = &level.dimensions;
+            let header = [
+                level.level,
+                dims.width as u32,
+                dims.height as u32,
+                dims.layers as u32,
+                dims.bytes_per_channel as u32,
+            ];
+            for value in header.iter() {
+                file.extend_from_slice(&value.to_le_bytes());
+            }
+            file.extend_from_slice(&(level.buffer.len() as u64).to_le_bytes());
+            file.extend_from_slice(&level.buffer);
+        }
+        write_atomically(&self.dir.join(key.file_name()), &file)?;
+        self.index.insert(key, file.len() as u64);
+        self.write_index()
+    }
+
+    /// Reads the chain of `key`, or returns `None` if it isn't cached. Returns an error of kind
+    /// `InvalidData` if the file is corrupt, was stored for another `base_level` or its levels
+    /// don't fit `texture_descriptor`.
+    pub fn load(
+        &self,
+        key: MipChainKey,
+        base_level: &[u8],
+        texture_descriptor: &wgpu::TextureDescriptor,
+    ) -> io::Result<Option<Vec<MipBuffer>>> {
+        if !self.contains(key) {
+            return Ok(None);
+        }
+        let mut file = io::BufReader::new(fs::File::open(self.dir.join(key.file_name()))?);
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != ENTRY_MAGIC || read_u32(&mut file)? != ENTRY_VERSION {
+            return Err(invalid_data("not a cached mip chain"));
+        }
+        if read_u64(&mut file)? != key.0 {
+            return Err(invalid_data("the cached chain has another key"));
+        }
+        if read_u64(&mut file)? != base_level.len() as u64
+            || read_u64(&mut file)? != hash_bytes(base_level, CONTENT_SEED)
+        {
+            return Err(invalid_data("the cached chain has another base level"));
+        }
+        let (layout, _) = readback_layout(texture_descriptor);
+        let level_count = read_u32(&mut file)?;
+        let mut levels = Vec::with_capacity(level_count as usize);
+        for _ in 0..level_count {
+            let level = read_u32(&mut file)?;
+            let dimensions = match layout.get(level as usize) {
+                Some(expected) => expected.dimensions,
+                None => return Err(invalid_data("the cached level doesn't exist")),
+            };
+            let header = [
+                read_u32(&mut file)? as usize,
+                read_u32(&mut file)? as usize,
+                read_u32(&mut file)? as usize,
+                read_u32(&mut file)? as usize,
+            ];
+            let expected = [
+                dimensions.width,
+                dimensions.height,
+                dimensions.layers,
+                dimensions.bytes_per_channel,
+            ];
+            let len = read_u64(&mut file)? as usize;
+            let expected_len =
+                dimensions.unpadded_bytes_per_row * dimensions.height * dimensions.layers;
+            if header != expected || len != expected_len {
+                return Err(invalid_data("the cached level has another size"));
+            }
+            let mut buffer = vec![0; len];
+            file.read_exact(&mut buffer)?;
+            levels.push(MipBuffer {
+                buffer,
+                dimensions,
+                level,
+                format: texture_descriptor.format,
+            });
+        }
+        Ok(Some(levels))
+    }
+
+    /// Removes the chain of `key` from the cache, if it's cached.
+    pub fn remove(&mut self, key: MipChainKey) -> io::Result<()> {
+        if self.index.remove(&key).is_none() {
+            return Ok(());
+        }
+        match fs::remove_file(self.dir.join(key.file_name())) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+            _ => {}
+        }
+        self.write_index()
+    }
+
+    /// Fills the levels of `texture` generated from its base level with `options`: from the
+    /// cache if an identical `base_level` was generated before, or else with `generator`,
+    /// storing the generated levels in the cache. Returns true if the levels were loaded from
+    /// the cache.
+    ///
+    /// The cache never makes generation fail: chains that can't be read are generated again,
+    /// and chains that can't be written are only logged. Unlike `generate`, this submits its
+    /// own commands to `queue` and waits for generated levels to be read back.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`, which
+    ///   must have the usage of `generator` and [`MipChainCache::required_usage`], and an
+    ///   uncompressed color format.
+    /// - `base_level` holds the tightly packed texels of every array layer of level
+    ///   `options.base_mip_level`, which must already be written to `texture`.
+    /// - The levels of every array layer are generated, so `options.array_layer` is ignored.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn generate_or_load(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        generator: &dyn MipmapGenerator,
+        texture: &wgpu::Texture,
+        texture_descriptor: &wgpu::TextureDescriptor<'_>,
+        base_level: &[u8],
+        options: &GenerateOptions,
+    ) -> Result<bool, Error> {
+        if !texture_descriptor.usage.contains(Self::required_usage()) {
+            return Err(Error::UnsupportedUsage(texture_descriptor.usage));
+        }
+        let info = texture_descriptor.format.describe();
+        if info.block_dimensions != (1, 1) || info.sample_type == wgpu::TextureSampleType::Depth {
+            return Err(Error::UnsupportedFormat(texture_descriptor.format));
+        }
+        let mip_levels = options.mip_levels(texture_descriptor)?;
+        let key = MipChainKey::new(base_level, texture_descriptor, options);
+        let cached = self
+            .load(key, base_level, texture_descriptor)
+            .unwrap_or_else(|error| {
+                event!(WARN, { key = key.0, error = error }, "failed to read a cached mip chain");
+                None
+            });
+        if let Some(levels) = cached {
+            if levels
+                .iter()
+                .map(|level| level.level)
+                .eq(mip_levels.clone())
+            {
+                for level in &levels {
+                    write_level(queue, texture, level);
+                }
+                return Ok(true);
+            }
+        }
+        // The levels of each array layer are generated separately, and those of a 3D texture
+        // all at once
+        let layers = match texture_descriptor.dimension {
+            wgpu::TextureDimension::D2 => texture_descriptor.size.depth,
+            _ => 1,
+        };
+        let mut encoder = device.create_command_encoder(&Default::default());
+        for array_layer in 0..layers {
+            let layer_options = GenerateOptions {
+                array_layer,
+                dirty_rect: None,
+                ..*options
+            };
+            generator.generate_with_options(
+                device,
+                &mut encoder,
+                texture,
+                texture_descriptor,
+                &layer_options,
+            )?;
+        }
+        let readback = MipReadback::encode(device, &mut encoder, texture, texture_descriptor);
+        queue.submit(std::iter::once(encoder.finish()));
+        let read = readback.read();
+        device.poll(wgpu::Maintain::Wait);
+        let levels = read
+            .await?
+            .into_iter()
+            .filter(|level| mip_levels.contains(&level.level))
+            .collect::<Vec<_>>();
+        if let Err(error) = self.store(key, base_level, &levels) {
+            event!(WARN, { key = key.0, error = error }, "failed to cache a mip chain");
+        }
+        Ok(false)
+    }
+
+    fn write_index(&self) -> io::Result<()> {
+        let mut keys = self.index.iter().collect::<Vec<_>>();
+        keys.sort_by_key(|(key, _)| key.0);
+        let mut index = String::from(INDEX_HEADER);
+        index.push('\n');
+        for (key, bytes) in keys {
+            index.push_str(&format!("{:016x} {}\n", key.0, bytes));
+        }
+        write_atomically(&self.dir.join(INDEX_FILE), index.as_bytes())
+    }
+}
+
+/// Parses the index of a cache, skipping lines it can't read. An index written by another
+/// version of the cache is treated as empty.
+fn parse_index(index: &str) -> HashMap<MipChainKey, u64> {
+    let mut lines = index.lines();
+    if lines.next() != Some(INDEX_HEADER) {
+        return HashMap::new();
+    }
+    lines
+        .filter_map(|line| {
+            let (key, bytes) = line.split_once(' ')?;
+            let key = u64::from_str_radix(key, 16).ok()?;
+            Some((MipChainKey(key), bytes.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Writes `contents` to a temporary file renamed to `path`, so readers never see a partially
+/// written file.
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temporary = path.with_extension("tmp");
+    let mut file = fs::File::create(&temporary)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+fn write_level(queue: &wgpu::Queue, texture: &wgpu::Texture, level: &MipBuffer) {
+    let dims = &level.dimensions;
+    queue.write_texture(
+        wgpu::TextureCopyView {
+            texture,
+            mip_level: level.level,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        &level.buffer,
+        wgpu::TextureDataLayout {
+            offset: 0,
+            bytes_per_row: dims.unpadded_bytes_per_row as u32,
+            rows_per_image: dims.height as u32,
+        },
+        wgpu::Extent3d {
+            width: dims.width as u32,
+            height: dims.height as u32,
+            depth: dims.layers as u32,
+        },
+    );
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::ComputeMipmapGenerator;
+    use crate::util::*;
+
+    // A cache directory of its own for each test, removed when dropped
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("wgpu-mipmap-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn texture_descriptor() -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 8,
+                height: 8,
+                depth: 1,
+            },
+            mip_level_count: 4,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage() | MipChainCache::required_usage(),
+            label: None,
+        }
+    }
+
+    #[test]
+    fn keys() {
+        let descriptor = texture_descriptor();
+        let data = checkerboard_rgba8(8, 8, 2);
+        let options = GenerateOptions::default();
+        let key = MipChainKey::new(&data, &descriptor, &options);
+        assert_eq!(key, MipChainKey::new(&data, &descriptor, &options));
+        let mut changed = data.clone();
+        changed[5] ^= 1;
+        assert_ne!(key, MipChainKey::new(&changed, &descriptor, &options));
+        let nearest = GenerateOptions {
+            filter: MipFilter::Nearest,
+            ..options
+        };
+        assert_ne!(key, MipChainKey::new(&data, &descriptor, &nearest));
+        let srgb = wgpu::TextureDescriptor {
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            ..texture_descriptor()
+        };
+        assert_ne!(key, MipChainKey::new(&data, &srgb, &options));
+        // The whole chain is cached, whatever the layer or rectangle generated
+        let dirty = GenerateOptions {
+            dirty_rect: Some(MipRect::default()),
+            ..options
+        };
+        assert_eq!(key, MipChainKey::new(&data, &descriptor, &dirty));
+    }
+
+    #[test]
+    fn store_and_load() {
+        let dir = TestDir::new("store-and-load");
+        let descriptor = texture_descriptor();
+        let (layout, _) = readback_layout(&descriptor);
+        let levels = layout[1..]
+            .iter()
+            .enumerate()
+            .map(|(i, level)| MipBuffer {
+                buffer: vec![
+                    i as u8;
+                    level.dimensions.unpadded_bytes_per_row * level.dimensions.height
+                ],
+                dimensions: level.dimensions,
+                level: i as u32 + 1,
+                format: descriptor.format,
+            })
+            .collect::<Vec<_>>();
+        let base_level = checkerboard_rgba8(8, 8, 2);
+        let key = MipChainKey(42);
+        let mut cache = MipChainCache::open(&dir.0).unwrap();
+        assert!(cache.is_empty());
+        assert!(cache.load(key, &base_level, &descriptor).unwrap().is_none());
+        cache.store(key, &base_level, &levels).unwrap();
+        // The index is read back when the cache is opened again
+        let mut cache = MipChainCache::open(&dir.0).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert!(cache.total_bytes() > 0);
+        let loaded = cache.load(key, &base_level, &descriptor).unwrap().unwrap();
+        assert_eq!(loaded.len(), levels.len());
+        for (loaded, level) in loaded.iter().zip(&levels) {
+            assert_eq!(loaded.level, level.level);
+            assert_eq!(loaded.buffer, level.buffer);
+        }
+        // Levels of another size are rejected
+        let larger = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 16,
+                height: 16,
+                depth: 1,
+            },
+            ..texture_descriptor()
+        };
+        assert_eq!(
+            cache.load(key, &base_level, &larger).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        // So are chains stored for another base level with the same key
+        let mut changed = base_level.clone();
+        changed[5] ^= 1;
+        assert_eq!(
+            cache.load(key, &changed, &descriptor).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            cache
+                .load(key, &base_level[4..], &descriptor)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        cache.remove(key).unwrap();
+        assert!(!cache.contains(key));
+        assert!(MipChainCache::open(&dir.0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn loads_identical_base_levels() {
+        if !adapter_available() {
+            return;
+        }
+        let dir = TestDir::new("generate-or-load");
+        let descriptor = texture_descriptor();
+        let data = checkerboard_rgba8(8, 8, 2);
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator =
+                ComputeMipmapGenerator::new_with_format_hints(&device, &[descriptor.format]);
+            let mut cache = MipChainCache::open(&dir.0).unwrap();
+            let mut chains = Vec::new();
+            for _ in 0..2 {
+                let texture = device.create_texture(&descriptor);
+                queue.write_texture(
+                    wgpu::TextureCopyView {
+                        texture: &texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d::ZERO,
+                    },
+                    &data,
+                    wgpu::TextureDataLayout {
+                        offset: 0,
+                        bytes_per_row: 8 * 4,
+                        rows_per_image: 0,
+                    },
+                    descriptor.size,
+                );
+                let loaded = cache
+                    .generate_or_load(
+                        &device,
+                        &queue,
+                        &generator,
+                        &texture,
+                        &descriptor,
+                        &data,
+                        &GenerateOptions::default(),
+                    )
+                    .await
+                    .unwrap();
+                let mut encoder = device.create_command_encoder(&Default::default());
+                let readback = MipReadback::encode(&device, &mut encoder, &texture, &descriptor);
+                queue.submit(std::iter::once(encoder.finish()));
+                let read = readback.read();
+                device.poll(wgpu::Maintain::Wait);
+                chains.push((loaded, read.await.unwrap()));
+            }
+            assert!(!chains[0].0);
+            assert!(chains[1].0);
+            assert_eq!(cache.len(), 1);
+            for (generated, loaded) in chains[0].1.iter().zip(&chains[1].1) {
+                assert_eq!(generated.buffer, loaded.buffer, "level {}", generated.level);
+            }
+        });
+    }
+}
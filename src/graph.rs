@@ -0,0 +1,169 @@
+//! Adapters for frame graphs, which order passes and insert barriers from the resources
+//! each pass declares it reads and writes.
+//!
+//! A [`MipmapNode`] describes the generation of one texture's mipmaps as a pass that reads
+//! the levels before each generated level and writes the generated levels.
+use crate::core::*;
+use std::{ops::Range, sync::Arc};
+
+/// Whether a [`MipmapNode`] reads or writes a range of levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// A range of mip levels of a [`MipmapNode`]'s texture, and how the node accesses them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LevelAccess {
+    pub levels: Range<u32>,
+    pub access: Access,
+}
+
+/// A frame graph node that generates the mip levels of a texture selected by
+/// [`GenerateOptions`].
+#[derive(Debug)]
+pub struct MipmapNode {
+    texture: Arc<wgpu::Texture>,
+    texture_descriptor: wgpu::TextureDescriptor<'static>,
+    options: GenerateOptions,
+    levels: Range<u32>,
+}
+
+impl MipmapNode {
+    /// Creates a node generating the levels of `texture` selected by `options`.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`.
+    pub fn new(
+        texture: Arc<wgpu::Texture>,
+        texture_descriptor: wgpu::TextureDescriptor<'static>,
+        options: GenerateOptions,
+    ) -> Result<Self, Error> {
+        let levels = options.mip_levels(&texture_descriptor)?;
+        Ok(Self {
+            texture,
+            texture_descriptor,
+            options,
+            levels,
+        })
+    }
+
+    /// Returns the texture the node reads and writes.
+    pub fn texture(&self) -> &Arc<wgpu::Texture> {
+        &self.texture
+    }
+
+    /// Returns the descriptor of the node's texture.
+    pub fn texture_descriptor(&self) -> &wgpu::TextureDescriptor<'static> {
+        &self.texture_descriptor
+    }
+
+    /// Returns the levels the node reads: the base level, then every generated level but
+    /// the last.
+    pub fn reads(&self) -> Range<u32> {
+        if self.levels.is_empty() {
+            return self.levels.clone();
+        }
+        self.levels.start - 1..self.levels.end - 1
+    }
+
+    /// Returns the levels the node writes.
+    pub fn writes(&self) -> Range<u32> {
+        self.levels.clone()
+    }
+
+    /// Returns the node's reads and writes, for graphs that take a list of accesses. Levels
+    /// in both ranges are written before they are read.
+    pub fn accesses(&self) -> Vec<LevelAccess> {
+        if self.levels.is_empty() {
+            return Vec::new();
+        }
+        vec![
+            LevelAccess {
+                levels: self.reads(),
+                access: Access::Read,
+            },
+            LevelAccess {
+                levels: self.writes(),
+                access: Access::Write,
+            },
+        ]
+    }
+
+    /// Encodes the node's commands with `generator`.
+    pub fn run(
+        &self,
+        generator: &dyn MipmapGenerator,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> Result<(), Error> {
+        generator.generate_with_options(
+            device,
+            encoder,
+            &self.texture,
+            &self.texture_descriptor,
+            &self.options,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::RecommendedMipmapGenerator;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn descriptor() -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 256,
+                height: 256,
+                depth: 1,
+            },
+            mip_level_count: 9,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsage::STORAGE,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn declared_accesses() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let texture_descriptor = descriptor();
+            let texture = Arc::new(device.create_texture(&texture_descriptor));
+            let options = GenerateOptions {
+                base_mip_level: 2,
+                min_dimension: 4,
+                ..Default::default()
+            };
+            let node =
+                MipmapNode::new(texture.clone(), texture_descriptor.clone(), options).unwrap();
+            // levels 3..=6 are 32x32 down to 4x4
+            assert_eq!(node.reads(), 2..6);
+            assert_eq!(node.writes(), 3..7);
+            assert_eq!(node.accesses().len(), 2);
+            let options = GenerateOptions {
+                base_mip_level: 8,
+                ..Default::default()
+            };
+            let node = MipmapNode::new(texture, texture_descriptor, options).unwrap();
+            assert!(node.accesses().is_empty());
+            let generator = RecommendedMipmapGenerator::new(&device);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            assert!(node.run(&generator, &device, &mut encoder).is_ok());
+        });
+    }
+}
@@ -13,7 +13,7 @@ wgpu-mipmap = "0.1"
 Example usage:
 
 ```rust
-use wgpu_mipmap::*;
+use wgpu_mipmap::prelude::*;
 fn example(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), Error> {
     // create a recommended generator
     let generator = RecommendedMipmapGenerator::new(&device);
@@ -42,15 +42,60 @@ fn example(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), Error> {
 ```
 */
 mod backends;
+mod completion;
+pub mod context;
 mod core;
+#[cfg(all(feature = "unstable", not(target_arch = "wasm32")))]
+mod disk_cache;
+pub mod graph;
+#[cfg(feature = "unstable")]
+pub mod metrics;
+pub mod prelude;
+mod progressive;
+mod queue;
+mod residency;
+mod stats;
+pub mod testing;
+mod trace;
 
-#[doc(hidden)]
+#[cfg(feature = "unstable")]
 pub mod util;
+#[cfg(not(feature = "unstable"))]
+mod util;
+
+/// The mip chain layout math of the backends, from the dependency-free `wgpu-mipmap-core`
+/// crate, which offline tools can depend on directly.
+pub use wgpu_mipmap_core as layout;
 
 #[doc(inline)]
 pub use crate::backends::{
-    ComputeMipmapGenerator, CopyMipmapGenerator, RecommendedMipmapGenerator, RenderMipmapGenerator,
+    AtlasMipmapGenerator, BatchRecorder, BilateralMipmapGenerator, ChromaSiting, ColorConversion,
+    CompressedMipmapGenerator, ComputeMipmapGenerator, ComputeSynchronization, CopyMipmapGenerator,
+    DeviceBehavior, IrradianceMapGenerator, MaskedMipmapGenerator, MinMaxMipmapGenerator,
+    MipAtlasLayout, MipAtlasPacking, MomentMipmapGenerator, MomentWarp, PackedMipmapGenerator,
+    PipelineCache, PrefilteredEnvMapGenerator, PushPullGenerator, RecommendedMipmapGenerator,
+    RenderMipmapGenerator, SatGenerator, ScratchMipmapGenerator, SrgbStorageConversion,
+    StorageSupport, ToksvigRoughnessGenerator, UnormRounding, UnsupportedFormatReason,
+    UpsampleFilter, WorkgroupSize, YuvMipmapGenerator, CHROMA_PLANE_FORMAT, IRRADIANCE_MAP_FORMAT,
+    IRRADIANCE_MAP_SIZE, LINEAR_DEPTH_FORMAT, LUMA_PLANE_FORMAT, LUMINANCE_FORMAT, MASK_FORMAT,
+    MIN_MAX_FORMAT, PREFILTERED_ENV_MAP_FORMAT, ROUGHNESS_FORMAT, SAT_FORMAT,
 };
 
+#[cfg(feature = "unstable")]
+#[doc(inline)]
+pub use crate::backends::{MipDigest, MipDigestReadback, MipDigester};
+#[doc(inline)]
+pub use crate::completion::LevelCompletionTracker;
 #[doc(inline)]
 pub use crate::core::*;
+#[cfg(all(feature = "unstable", not(target_arch = "wasm32")))]
+#[doc(inline)]
+pub use crate::disk_cache::{MipChainCache, MipChainKey};
+#[doc(inline)]
+pub use crate::progressive::*;
+#[doc(inline)]
+pub use crate::queue::*;
+#[doc(inline)]
+pub use crate::residency::ResidentLevels;
+#[doc(inline)]
+pub use crate::stats::{Stats, StatsCollector};
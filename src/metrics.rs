@@ -0,0 +1,132 @@
+//! Image quality metrics for comparing mip levels read back with
+//! [`util::generate_and_copy_to_cpu`](crate::util::generate_and_copy_to_cpu).
+//!
+//! Both metrics interpret each byte of a [`MipBuffer`] as an 8-bit unorm channel,
+//! so they are meaningful for formats such as `Rgba8Unorm`, `Rgba8UnormSrgb` and `R8Unorm`.
+use crate::{core::Error, util::MipBuffer};
+
+const MAX_VALUE: f64 = 255.0;
+const SSIM_WINDOW: usize = 8;
+
+/// Returns the peak signal-to-noise ratio, in decibels, between two levels.
+/// Identical levels return `f64::INFINITY`.
+pub fn psnr(level_a: &MipBuffer, level_b: &MipBuffer) -> Result<f64, Error> {
+    check_dimensions(level_a, level_b)?;
+    let squared_error: f64 = level_a
+        .buffer
+        .iter()
+        .zip(level_b.buffer.iter())
+        .map(|(&a, &b)| {
+            let d = a as f64 - b as f64;
+            d * d
+        })
+        .sum();
+    let mse = squared_error / level_a.buffer.len().max(1) as f64;
+    if mse == 0.0 {
+        return Ok(f64::INFINITY);
+    }
+    Ok(10.0 * (MAX_VALUE * MAX_VALUE / mse).log10())
+}
+
+/// Returns the mean structural similarity index between two levels, in `[-1, 1]`.
+///
+/// The index is computed per channel over non-overlapping 8x8 windows (clamped to the
+/// level size) and averaged. Identical levels return `1.0`.
+pub fn ssim(level_a: &MipBuffer, level_b: &MipBuffer) -> Result<f64, Error> {
+    check_dimensions(level_a, level_b)?;
+    let c1 = (0.01 * MAX_VALUE).powi(2);
+    let c2 = (0.03 * MAX_VALUE).powi(2);
+    let dims = level_a.dimensions;
+    let channels = dims.bytes_per_channel;
+    let mut total = 0.0;
+    let mut windows = 0;
+    for wy in (0..dims.height).step_by(SSIM_WINDOW) {
+        for wx in (0..dims.width).step_by(SSIM_WINDOW) {
+            let y_end = (wy + SSIM_WINDOW).min(dims.height);
+            let x_end = (wx + SSIM_WINDOW).min(dims.width);
+            for c in 0..channels {
+                let texel = |buffer: &[u8], x: usize, y: usize| {
+                    buffer[y * dims.unpadded_bytes_per_row + x * channels + c] as f64
+                };
+                let n = ((y_end - wy) * (x_end - wx)) as f64;
+                let (mut sum_a, mut sum_b) = (0.0, 0.0);
+                for y in wy..y_end {
+                    for x in wx..x_end {
+                        sum_a += texel(&level_a.buffer, x, y);
+                        sum_b += texel(&level_b.buffer, x, y);
+                    }
+                }
+                let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+                let (mut var_a, mut var_b, mut covar) = (0.0, 0.0, 0.0);
+                for y in wy..y_end {
+                    for x in wx..x_end {
+                        let da = texel(&level_a.buffer, x, y) - mean_a;
+                        let db = texel(&level_b.buffer, x, y) - mean_b;
+                        var_a += da * da;
+                        var_b += db * db;
+                        covar += da * db;
+                    }
+                }
+                let (var_a, var_b, covar) = (var_a / n, var_b / n, covar / n);
+                total += ((2.0 * mean_a * mean_b + c1) * (2.0 * covar + c2))
+                    / ((mean_a * mean_a + mean_b * mean_b + c1) * (var_a + var_b + c2));
+                windows += 1;
+            }
+        }
+    }
+    Ok(total / windows.max(1) as f64)
+}
+
+fn check_dimensions(level_a: &MipBuffer, level_b: &MipBuffer) -> Result<(), Error> {
+    let (a, b) = (level_a.dimensions, level_b.dimensions);
+    if a.width != b.width || a.height != b.height || a.bytes_per_channel != b.bytes_per_channel {
+        return Err(Error::ExtentMismatch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::MipBufferDimensions;
+
+    fn level(width: usize, height: usize, buffer: Vec<u8>) -> MipBuffer {
+        MipBuffer {
+            buffer,
+            dimensions: MipBufferDimensions::new(width, height, 1),
+            level: 0,
+            format: wgpu::TextureFormat::R8Unorm,
+        }
+    }
+
+    #[test]
+    fn identical_levels() {
+        let a = level(16, 16, crate::util::checkerboard_r8(16, 16, 4));
+        assert_eq!(psnr(&a, &a).unwrap(), f64::INFINITY);
+        assert!((ssim(&a, &a).unwrap() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn psnr_of_constant_error() {
+        let a = level(4, 4, vec![100; 16]);
+        let b = level(4, 4, vec![110; 16]);
+        // mse = 100 -> 10 * log10(255^2 / 100)
+        let expected = 10.0 * (255.0f64 * 255.0 / 100.0).log10();
+        assert!((psnr(&a, &b).unwrap() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ssim_penalizes_structure() {
+        let a = level(16, 16, crate::util::checkerboard_r8(16, 16, 2));
+        let b = level(16, 16, vec![127; 256]);
+        assert!(ssim(&a, &b).unwrap() < 0.1);
+    }
+
+    #[test]
+    fn mismatched_dimensions() {
+        let a = level(4, 4, vec![0; 16]);
+        let b = level(2, 2, vec![0; 4]);
+        assert_eq!(psnr(&a, &b), Err(Error::ExtentMismatch));
+        assert_eq!(ssim(&a, &b), Err(Error::ExtentMismatch));
+    }
+}
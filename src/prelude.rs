@@ -0,0 +1,28 @@
+/*!
+The trait, generators, options and errors most applications need, for a single glob import.
+
+```rust
+use wgpu_mipmap::prelude::*;
+fn generate(
+    device: &wgpu::Device,
+    texture: &wgpu::Texture,
+    texture_descriptor: &wgpu::TextureDescriptor,
+) -> Result<wgpu::CommandBuffer, Error> {
+    let generator = RecommendedMipmapGenerator::new(device);
+    let mut encoder = device.create_command_encoder(&Default::default());
+    generator.generate(device, &mut encoder, texture, texture_descriptor)?;
+    Ok(encoder.finish())
+}
+```
+
+The prelude only exports stable APIs. The experimental ones, behind the `unstable` feature,
+are imported from the crate root.
+*/
+pub use crate::core::{
+    BoxRounding, BoxedMipmapGenerator, Error, GenerateOptions, GenerationCost, MipFilter, MipRect,
+    MipTextureDescriptor, MipmapGenerator,
+};
+pub use crate::{
+    ComputeMipmapGenerator, CopyMipmapGenerator, RecommendedMipmapGenerator, RenderMipmapGenerator,
+    ScratchMipmapGenerator,
+};
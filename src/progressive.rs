@@ -0,0 +1,256 @@
+use crate::core::*;
+use crate::util::{get_mip_extent, texel_count, MipExtent};
+use std::ops::Range;
+
+/// Limits how much work a single [`ProgressiveMipmapTask::step`] records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepBudget {
+    /// Records at most this many mip levels.
+    Levels(u32),
+    /// Records levels while the total number of texels written stays within this count.
+    /// The cost of a downsampling pass is roughly proportional to the texels it writes, so this
+    /// serves as a frame-time budget that doesn't depend on GPU timing queries. At least one
+    /// level is always recorded, so every step makes progress.
+    Texels(u64),
+}
+
+/// Spreads the mipmap generation of one texture over several calls, e.g. to keep the cost of
+/// streaming in a large texture below a per-frame budget.
+///
+/// Each call to [`ProgressiveMipmapTask::step`] records the next levels that fit in its
+/// budget and remembers where to resume. Every step reads the last level written by the step
+/// before it, so the encoders passed to successive steps must be submitted in the same order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgressiveMipmapTask {
+    options: GenerateOptions,
+    remaining: Range<u32>,
+}
+
+impl ProgressiveMipmapTask {
+    /// Creates a task that generates the levels of a texture described by
+    /// `texture_descriptor` selected by `options`.
+    pub fn new(
+        texture_descriptor: &wgpu::TextureDescriptor,
+        options: GenerateOptions,
+    ) -> Result<Self, Error> {
+        let remaining = options.mip_levels(texture_descriptor)?;
+        Ok(Self { options, remaining })
+    }
+
+    /// Returns the levels not yet recorded.
+    pub fn remaining_levels(&self) -> Range<u32> {
+        self.remaining.clone()
+    }
+
+    /// Returns true once every level has been recorded.
+    pub fn is_finished(&self) -> bool {
+        self.remaining.is_empty()
+    }
+
+    /// Encodes commands to generate the next levels that fit in `budget` with `generator`, and
+    /// returns the levels recorded. Returns an empty range once the task is finished.
+    ///
+    /// Expectations:
+    /// - `texture` and `texture_descriptor` are the same for every step, and match the
+    ///   descriptor the task was created with.
+    pub fn step(
+        &mut self,
+        generator: &dyn MipmapGenerator,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        budget: StepBudget,
+    ) -> Result<Range<u32>, Error> {
+        let levels = levels_within_budget(
+            &self.remaining,
+            &MipExtent::of(texture_descriptor).single_layer(),
+            budget,
+        );
+        if levels.is_empty() {
+            return Ok(levels);
+        }
+        generator.generate_with_options(
+            device,
+            encoder,
+            texture,
+            texture_descriptor,
+            &GenerateOptions {
+                base_mip_level: levels.start - 1,
+                max_level_count: Some(levels.end - levels.start),
+                ..self.options
+            },
+        )?;
+        // The next step starts from the last level written, so move the dirty rect there
+        self.options.dirty_rect = self.options.dirty_rect.map(|rect| {
+            (levels.start - 1..levels.end - 1).fold(rect, |rect, level| {
+                rect.next_level(&get_mip_extent(&texture_descriptor.size, level))
+            })
+        });
+        self.remaining.start = levels.end;
+        Ok(levels)
+    }
+}
+
+/// Returns the leading levels of `remaining` of a texture of size `size` that fit in
+/// `budget`.
+fn levels_within_budget(
+    remaining: &Range<u32>,
+    size: &MipExtent,
+    budget: StepBudget,
+) -> Range<u32> {
+    let end = match budget {
+        StepBudget::Levels(count) => remaining.end.min(remaining.start.saturating_add(count)),
+        StepBudget::Texels(texels) => {
+            let mut end = remaining.start;
+            let mut written = 0u64;
+            while end < remaining.end {
+                written += texel_count(size, end..end + 1);
+                if written > texels && end > remaining.start {
+                    break;
+                }
+                end += 1;
+            }
+            end
+        }
+    };
+    remaining.start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::RecommendedMipmapGenerator;
+    use crate::util::*;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn descriptor(size: u32, mip_level_count: u32) -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsage::STORAGE,
+            label: None,
+        }
+    }
+
+    /// Generates mipmaps by stepping a `ProgressiveMipmapTask` to completion in one encoder.
+    struct Progressive<'a> {
+        generator: &'a dyn MipmapGenerator,
+        budget: StepBudget,
+    }
+
+    impl MipmapGenerator for Progressive<'_> {
+        fn generate_with_options(
+            &self,
+            device: &wgpu::Device,
+            encoder: &mut wgpu::CommandEncoder,
+            texture: &wgpu::Texture,
+            texture_descriptor: &wgpu::TextureDescriptor,
+            options: &GenerateOptions,
+        ) -> Result<(), Error> {
+            let mut task = ProgressiveMipmapTask::new(texture_descriptor, *options)?;
+            while !task.is_finished() {
+                task.step(
+                    self.generator,
+                    device,
+                    encoder,
+                    texture,
+                    texture_descriptor,
+                    self.budget,
+                )?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn level_budget() {
+        let size = MipExtent::of(&descriptor(512, 10));
+        assert_eq!(
+            levels_within_budget(&(1..10), &size, StepBudget::Levels(3)),
+            1..4
+        );
+        assert_eq!(
+            levels_within_budget(&(8..10), &size, StepBudget::Levels(3)),
+            8..10
+        );
+        assert_eq!(
+            levels_within_budget(&(10..10), &size, StepBudget::Levels(3)),
+            10..10
+        );
+    }
+
+    #[test]
+    fn texel_budget() {
+        let size = MipExtent::of(&descriptor(512, 10));
+        // level 1 is 256x256, level 2 is 128x128
+        let budget = StepBudget::Texels(256 * 256 + 128 * 128);
+        assert_eq!(levels_within_budget(&(1..10), &size, budget), 1..3);
+        // every remaining level fits
+        assert_eq!(levels_within_budget(&(3..10), &size, budget), 3..10);
+        // a level larger than the budget is still recorded on its own
+        let budget = StepBudget::Texels(1);
+        assert_eq!(levels_within_budget(&(1..10), &size, budget), 1..2);
+    }
+
+    #[test]
+    fn task_tracks_remaining_levels() {
+        let descriptor = descriptor(512, 10);
+        let options = GenerateOptions {
+            min_dimension: 4,
+            ..Default::default()
+        };
+        let task = ProgressiveMipmapTask::new(&descriptor, options).unwrap();
+        assert_eq!(task.remaining_levels(), 1..8);
+        assert!(!task.is_finished());
+        let options = GenerateOptions {
+            base_mip_level: 10,
+            ..Default::default()
+        };
+        assert_eq!(
+            ProgressiveMipmapTask::new(&descriptor, options),
+            Err(Error::InvalidBaseMipLevel(10))
+        );
+    }
+
+    #[test]
+    fn matches_single_call() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        let descriptor = descriptor(256, 9);
+        let data = checkerboard_rgba8(256, 256, 8);
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = RecommendedMipmapGenerator::new(&device);
+            let expected =
+                generate_and_copy_to_cpu(&device, &queue, &generator, &data, &descriptor)
+                    .await
+                    .unwrap();
+            for budget in &[StepBudget::Levels(1), StepBudget::Texels(64 * 64)] {
+                let progressive = Progressive {
+                    generator: &generator,
+                    budget: *budget,
+                };
+                let actual =
+                    generate_and_copy_to_cpu(&device, &queue, &progressive, &data, &descriptor)
+                        .await
+                        .unwrap();
+                for (expected, actual) in expected.iter().zip(actual.iter()) {
+                    assert_eq!(expected.buffer, actual.buffer, "level {}", actual.level);
+                }
+            }
+        });
+    }
+}
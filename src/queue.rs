@@ -0,0 +1,292 @@
+use crate::core::*;
+use crate::progressive::{ProgressiveMipmapTask, StepBudget};
+use crate::util::{texel_count, MipExtent};
+use std::sync::Arc;
+
+/// Identifies a job enqueued in a [`MipJobQueue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MipJobId(u64);
+
+#[derive(Debug)]
+struct MipJob {
+    id: MipJobId,
+    priority: i32,
+    texture: Arc<wgpu::Texture>,
+    texture_descriptor: wgpu::TextureDescriptor<'static>,
+    task: ProgressiveMipmapTask,
+}
+
+/// Queues mipmap generation for streaming systems that upload more textures than they can
+/// afford to mip in one frame.
+///
+/// Jobs are recorded in priority order, highest first, and in the order they were enqueued
+/// among equal priorities. Each call to [`MipJobQueue::flush`] records as many levels as its
+/// [`StepBudget`] allows, so a large texture may be finished over several frames. A job can be
+/// cancelled until its last level is recorded.
+#[derive(Debug)]
+pub struct MipJobQueue<G: MipmapGenerator> {
+    generator: G,
+    jobs: Vec<MipJob>,
+    next_id: u64,
+}
+
+impl<G: MipmapGenerator> MipJobQueue<G> {
+    /// Creates an empty queue whose jobs are generated with `generator`.
+    pub fn new(generator: G) -> Self {
+        Self {
+            generator,
+            jobs: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Returns the generator used to record jobs.
+    pub fn generator(&self) -> &G {
+        &self.generator
+    }
+
+    /// Enqueues the generation of the levels of `texture` selected by `options`. Jobs with a
+    /// higher `priority` are recorded first.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`.
+    pub fn enqueue(
+        &mut self,
+        texture: Arc<wgpu::Texture>,
+        texture_descriptor: wgpu::TextureDescriptor<'static>,
+        options: GenerateOptions,
+        priority: i32,
+    ) -> Result<MipJobId, Error> {
+        let task = ProgressiveMipmapTask::new(&texture_descriptor, options)?;
+        let id = MipJobId(self.next_id);
+        self.next_id += 1;
+        let index = self
+            .jobs
+            .iter()
+            .position(|job| job.priority < priority)
+            .unwrap_or(self.jobs.len());
+        self.jobs.insert(
+            index,
+            MipJob {
+                id,
+                priority,
+                texture,
+                texture_descriptor,
+                task,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Removes a job from the queue. Levels already recorded by an earlier flush are left as
+    /// they are. Returns false if the job was already finished or cancelled.
+    pub fn cancel(&mut self, id: MipJobId) -> bool {
+        match self.jobs.iter().position(|job| job.id == id) {
+            Some(index) => {
+                self.jobs.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if the job has levels left to record.
+    pub fn contains(&self, id: MipJobId) -> bool {
+        self.jobs.iter().any(|job| job.id == id)
+    }
+
+    /// Returns the number of unfinished jobs.
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    /// Returns true if every job is finished or cancelled.
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Encodes commands for the queued jobs, in priority order, until `budget` is spent, and
+    /// returns the jobs that finished.
+    ///
+    /// The budget is shared by every job. With [`StepBudget::Texels`], the first job recorded
+    /// always makes progress, and later jobs are skipped while their next level doesn't fit in
+    /// what remains. Encoders passed to successive flushes must be submitted in order.
+    ///
+    /// If a job fails to generate, it is removed from the queue and the error is returned.
+    pub fn flush(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        budget: StepBudget,
+    ) -> Result<Vec<MipJobId>, Error> {
+        let mut budget = budget;
+        let mut recorded_any = false;
+        let mut finished = Vec::new();
+        let mut index = 0;
+        while index < self.jobs.len() {
+            if budget == StepBudget::Levels(0) {
+                break;
+            }
+            let job = &mut self.jobs[index];
+            if let StepBudget::Texels(texels) = budget {
+                let next = job.task.remaining_levels().start;
+                if recorded_any
+                    && texel_count(
+                        &MipExtent::of(&job.texture_descriptor).single_layer(),
+                        next..next + 1,
+                    ) > texels
+                {
+                    index += 1;
+                    continue;
+                }
+            }
+            let levels = match job.task.step(
+                &self.generator,
+                device,
+                encoder,
+                &job.texture,
+                &job.texture_descriptor,
+                budget,
+            ) {
+                Ok(levels) => levels,
+                Err(e) => {
+                    self.jobs.remove(index);
+                    return Err(e);
+                }
+            };
+            budget = match budget {
+                StepBudget::Levels(count) => StepBudget::Levels(count - levels.len() as u32),
+                StepBudget::Texels(texels) => {
+                    StepBudget::Texels(texels.saturating_sub(texel_count(
+                        &MipExtent::of(&job.texture_descriptor).single_layer(),
+                        levels,
+                    )))
+                }
+            };
+            recorded_any = true;
+            if job.task.is_finished() {
+                finished.push(self.jobs.remove(index).id);
+            } else {
+                index += 1;
+            }
+        }
+        Ok(finished)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::*;
+    use std::cell::RefCell;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn descriptor(size: u32) -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth: 1,
+            },
+            mip_level_count: 1 + (size as f32).log2() as u32,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsage::STORAGE,
+            label: None,
+        }
+    }
+
+    /// Records the size of each texture and the levels written, without encoding anything.
+    #[derive(Default)]
+    struct Recorder {
+        calls: RefCell<Vec<(u32, std::ops::Range<u32>)>>,
+    }
+
+    impl MipmapGenerator for Recorder {
+        fn generate_with_options(
+            &self,
+            _device: &wgpu::Device,
+            _encoder: &mut wgpu::CommandEncoder,
+            _texture: &wgpu::Texture,
+            texture_descriptor: &wgpu::TextureDescriptor,
+            options: &GenerateOptions,
+        ) -> Result<(), Error> {
+            let levels = options.mip_levels(texture_descriptor)?;
+            self.calls
+                .borrow_mut()
+                .push((texture_descriptor.size.width, levels));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn priority_and_cancellation() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let mut queue = MipJobQueue::new(Recorder::default());
+            let mut enqueue = |size: u32, priority: i32| {
+                let descriptor = descriptor(size);
+                let texture = Arc::new(device.create_texture(&descriptor));
+                queue
+                    .enqueue(texture, descriptor, Default::default(), priority)
+                    .unwrap()
+            };
+            let low = enqueue(16, 0);
+            let high = enqueue(32, 10);
+            let cancelled = enqueue(64, 5);
+            assert_eq!(queue.len(), 3);
+            assert!(queue.cancel(cancelled));
+            assert!(!queue.cancel(cancelled));
+
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let finished = queue
+                .flush(&device, &mut encoder, StepBudget::Levels(7))
+                .unwrap();
+            assert_eq!(finished, vec![high]);
+            assert!(queue.contains(low));
+            let finished = queue
+                .flush(&device, &mut encoder, StepBudget::Levels(7))
+                .unwrap();
+            assert_eq!(finished, vec![low]);
+            assert!(queue.is_empty());
+            assert_eq!(
+                *queue.generator().calls.borrow(),
+                vec![(32, 1..6), (16, 1..3), (16, 3..5)]
+            );
+        });
+    }
+
+    #[test]
+    fn shared_texel_budget() {
+        if !adapter_available() {
+            return;
+        }
+        init();
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, _queue) = wgpu_setup().await;
+            let mut queue = MipJobQueue::new(Recorder::default());
+            for size in &[64, 8] {
+                let descriptor = descriptor(*size);
+                let texture = Arc::new(device.create_texture(&descriptor));
+                queue
+                    .enqueue(texture, descriptor, Default::default(), 0)
+                    .unwrap();
+            }
+            let mut encoder = device.create_command_encoder(&Default::default());
+            // The 32x32 level of the first job doesn't fit, but is recorded anyway. The 4x4
+            // level of the second job doesn't fit in what remains, so it is skipped.
+            queue
+                .flush(&device, &mut encoder, StepBudget::Texels(16))
+                .unwrap();
+            assert_eq!(*queue.generator().calls.borrow(), vec![(64, 1..2)]);
+        });
+    }
+}
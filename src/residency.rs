@@ -0,0 +1,306 @@
+use crate::backends::{RenderMipmapGenerator, UpsampleFilter};
+use crate::core::*;
+use std::ops::Range;
+
+/// The mip levels of a texture that already hold valid data, e.g. levels a streaming system
+/// loaded from disk, so only the missing levels need to be generated.
+///
+/// [`ResidentLevels::generate_missing`] fills each run of missing levels from the resident
+/// level just before it, the nearest valid level that is larger, and leaves resident levels
+/// untouched.
+///
+/// ```no_run
+/// # use wgpu_mipmap::*;
+/// # fn example(device: &wgpu::Device, generator: &RecommendedMipmapGenerator,
+/// #     texture: &wgpu::Texture, texture_descriptor: &wgpu::TextureDescriptor) -> Result<(), Error> {
+/// // Levels 0 and 3 were loaded from disk
+/// let resident = ResidentLevels::new().with_level(0).with_level(3);
+/// let mut encoder = device.create_command_encoder(&Default::default());
+/// // Generates levels 1 and 2 from level 0, and levels 4 and up from level 3
+/// resident.generate_missing(generator, device, &mut encoder, texture, texture_descriptor)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ResidentLevels {
+    // Bit `n` is set if level `n` is resident. A 2D texture has at most 32 levels
+    mask: u32,
+}
+
+impl ResidentLevels {
+    /// Creates a set with no resident level.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the set with `level` marked resident as well.
+    pub fn with_level(mut self, level: u32) -> Self {
+        self.insert(level);
+        self
+    }
+
+    /// Returns the set with every level in `levels` marked resident as well.
+    pub fn with_levels(mut self, levels: Range<u32>) -> Self {
+        for level in levels {
+            self.insert(level);
+        }
+        self
+    }
+
+    /// Marks `level` resident. Levels of 32 and above don't exist, and are ignored.
+    pub fn insert(&mut self, level: u32) {
+        if let Some(bit) = 1u32.checked_shl(level) {
+            self.mask |= bit;
+        }
+    }
+
+    /// Marks `level` missing, e.g. once it's evicted.
+    pub fn remove(&mut self, level: u32) {
+        if let Some(bit) = 1u32.checked_shl(level) {
+            self.mask &= !bit;
+        }
+    }
+
+    /// Returns true if `level` is marked resident.
+    pub fn contains(&self, level: u32) -> bool {
+        1u32.checked_shl(level)
+            .is_some_and(|bit| self.mask & bit != 0)
+    }
+
+    /// Returns the runs of missing levels of a texture with `mip_level_count` levels, in
+    /// order. Each run is generated from the level before it, which is resident. Returns
+    /// `Error::MissingSourceLevel` if level 0 is missing, as no level can be generated from
+    /// a smaller one.
+    pub fn missing_levels(&self, mip_level_count: u32) -> Result<Vec<Range<u32>>, Error> {
+        if mip_level_count > 0 && !self.contains(0) {
+            return Err(Error::MissingSourceLevel(0));
+        }
+        let mut runs = Vec::new();
+        let mut level = 1;
+        while level < mip_level_count {
+            if self.contains(level) {
+                level += 1;
+                continue;
+            }
+            let start = level;
+            while level < mip_level_count && !self.contains(level) {
+                level += 1;
+            }
+            runs.push(start..level);
+        }
+        Ok(runs)
+    }
+
+    /// Encodes commands to generate the missing levels of `texture` with `generator`, and
+    /// returns the levels written.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`.
+    pub fn generate_missing(
+        &self,
+        generator: &dyn MipmapGenerator,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        texture_descriptor: &wgpu::TextureDescriptor,
+    ) -> Result<Vec<Range<u32>>, Error> {
+        self.generate_missing_with_options(
+            generator,
+            device,
+            encoder,
+            texture,
+            texture_descriptor,
+            &GenerateOptions::default(),
+        )
+    }
+
+    /// Like [`ResidentLevels::generate_missing`], generating each run of missing levels with
+    /// `options`. `options.base_mip_level` and `options.max_level_count` are replaced by the
+    /// bounds of each run, so `options.min_dimension` may still leave the smallest levels
+    /// missing. A `dirty_rect` isn't supported, since it describes a single source level.
+    pub fn generate_missing_with_options(
+        &self,
+        generator: &dyn MipmapGenerator,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        options: &GenerateOptions,
+    ) -> Result<Vec<Range<u32>>, Error> {
+        let mut written = Vec::new();
+        for run in self.missing_levels(texture_descriptor.mip_level_count)? {
+            let run_options = GenerateOptions {
+                base_mip_level: run.start - 1,
+                max_level_count: Some(run.end - run.start),
+                dirty_rect: None,
+                ..*options
+            };
+            let levels = run_options.mip_levels(texture_descriptor)?;
+            if levels.is_empty() {
+                continue;
+            }
+            generator.generate_with_options(
+                device,
+                encoder,
+                texture,
+                texture_descriptor,
+                &run_options,
+            )?;
+            written.push(levels);
+        }
+        Ok(written)
+    }
+
+    /// Encodes commands to fill the missing levels before the first resident level by
+    /// upsampling it with `generator`, and returns the levels written. This lets a texture
+    /// whose coarse levels are streamed in first be displayed right away, with placeholder
+    /// detail, until its larger levels load. Returns `Error::MissingSourceLevel` with the
+    /// last level if no level is resident.
+    ///
+    /// The upsampled levels aren't marked resident, so they're filled again once the real
+    /// data of the level after them loads.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn upsample_missing(
+        &self,
+        generator: &RenderMipmapGenerator,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        array_layer: u32,
+        filter: UpsampleFilter,
+    ) -> Result<Range<u32>, Error> {
+        let mip_level_count = texture_descriptor.mip_level_count;
+        let first_resident = (0..mip_level_count)
+            .find(|&level| self.contains(level))
+            .ok_or_else(|| Error::MissingSourceLevel(mip_level_count.saturating_sub(1)))?;
+        let levels = 0..first_resident;
+        if !levels.is_empty() {
+            generator.upsample(
+                device,
+                encoder,
+                texture,
+                texture_descriptor,
+                levels.clone(),
+                array_layer,
+                filter,
+            )?;
+        }
+        Ok(levels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::ComputeMipmapGenerator;
+    use crate::util::*;
+
+    #[test]
+    fn missing_runs() {
+        let resident = ResidentLevels::new().with_level(0).with_levels(3..5);
+        assert_eq!(resident.missing_levels(8), Ok(vec![1..3, 5..8]));
+        assert_eq!(resident.missing_levels(6), Ok(vec![1..3, 5..6]));
+        assert_eq!(resident.missing_levels(1), Ok(vec![]));
+        let full = ResidentLevels::new().with_levels(0..8);
+        assert_eq!(full.missing_levels(8), Ok(vec![]));
+        assert_eq!(
+            ResidentLevels::new().with_level(2).missing_levels(8),
+            Err(Error::MissingSourceLevel(0))
+        );
+        let mut resident = resident;
+        resident.remove(4);
+        resident.insert(40);
+        assert!(!resident.contains(4));
+        assert!(!resident.contains(40));
+        assert_eq!(resident.missing_levels(8), Ok(vec![1..3, 4..8]));
+    }
+
+    #[test]
+    fn generates_from_nearest_resident_level() {
+        if !adapter_available() {
+            return;
+        }
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 16,
+                height: 16,
+                depth: 1,
+            },
+            mip_level_count: 5,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: ComputeMipmapGenerator::required_usage()
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        let data = checkerboard_rgba8(16, 16, 2);
+        // Level 2 is loaded with a solid color no level 0 would produce
+        let level_2 = [200u8; 4 * 4 * 4];
+        futures::executor::block_on(async {
+            let (_instance, _adapter, device, queue) = wgpu_setup().await;
+            let generator = ComputeMipmapGenerator::new_with_format_hints(
+                &device,
+                &[texture_descriptor.format],
+            );
+            let texture = device.create_texture(&texture_descriptor);
+            for (level, data, width) in [(0, &data[..], 16), (2, &level_2[..], 4)] {
+                queue.write_texture(
+                    wgpu::TextureCopyView {
+                        texture: &texture,
+                        mip_level: level,
+                        origin: wgpu::Origin3d::ZERO,
+                    },
+                    data,
+                    wgpu::TextureDataLayout {
+                        offset: 0,
+                        bytes_per_row: width * 4,
+                        rows_per_image: 0,
+                    },
+                    wgpu::Extent3d {
+                        width,
+                        height: width,
+                        depth: 1,
+                    },
+                );
+            }
+            let resident = ResidentLevels::new().with_level(0).with_level(2);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let written = resident
+                .generate_missing(
+                    &generator,
+                    &device,
+                    &mut encoder,
+                    &texture,
+                    &texture_descriptor,
+                )
+                .unwrap();
+            assert_eq!(written, vec![1..2, 3..5]);
+            let readback =
+                MipReadback::encode(&device, &mut encoder, &texture, &texture_descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            let read = readback.read();
+            device.poll(wgpu::Maintain::Wait);
+            let levels = read.await.unwrap();
+            let expected =
+                generate_and_copy_to_cpu(&device, &queue, &generator, &data, &texture_descriptor)
+                    .await
+                    .unwrap();
+            assert_eq!(levels[0].buffer, expected[0].buffer);
+            assert_eq!(levels[1].buffer, expected[1].buffer);
+            // Level 2 is left as loaded, and the levels after it are generated from it
+            for level in &levels[2..] {
+                assert!(
+                    level.buffer.iter().all(|&b| b == 200),
+                    "level {}",
+                    level.level
+                );
+            }
+        });
+    }
+}
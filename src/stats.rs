@@ -0,0 +1,155 @@
+use crate::util::level_bytes;
+use std::{
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// A snapshot of the counters of a [`StatsCollector`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of textures, or array layers, whose mipmaps were generated.
+    pub textures: u64,
+    /// The number of mip levels written.
+    pub levels: u64,
+    /// The number of bytes in the mip levels written, excluding scratch textures.
+    pub bytes_written: u64,
+    /// The number of times a backend couldn't generate a texture and the next one was tried.
+    pub fallbacks: u64,
+    /// The CPU time spent encoding commands, including failed attempts. Always zero on wasm,
+    /// which has no monotonic clock in `std`.
+    pub encode_time: Duration,
+}
+
+/// Counts the work of the generators it's attached to, e.g. with
+/// `RecommendedMipmapGenerator::set_stats_collector`, for engine HUDs and telemetry.
+///
+/// The counters are atomic, so one collector can be shared between generators on several
+/// threads with an `Arc`. They only count commands encoded, not their execution on the GPU.
+#[derive(Debug, Default)]
+pub struct StatsCollector {
+    textures: AtomicU64,
+    levels: AtomicU64,
+    bytes_written: AtomicU64,
+    fallbacks: AtomicU64,
+    encode_nanos: AtomicU64,
+}
+
+impl StatsCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current value of every counter. Counters updated concurrently with the
+    /// call may be read before or after the update.
+    pub fn snapshot(&self) -> Stats {
+        Stats {
+            textures: self.textures.load(Ordering::Relaxed),
+            levels: self.levels.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            fallbacks: self.fallbacks.load(Ordering::Relaxed),
+            encode_time: Duration::from_nanos(self.encode_nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Returns the current value of every counter and sets them to zero, e.g. once per frame.
+    pub fn take(&self) -> Stats {
+        Stats {
+            textures: self.textures.swap(0, Ordering::Relaxed),
+            levels: self.levels.swap(0, Ordering::Relaxed),
+            bytes_written: self.bytes_written.swap(0, Ordering::Relaxed),
+            fallbacks: self.fallbacks.swap(0, Ordering::Relaxed),
+            encode_time: Duration::from_nanos(self.encode_nanos.swap(0, Ordering::Relaxed)),
+        }
+    }
+
+    /// Records `levels` of one layer of a texture described by `texture_descriptor` as
+    /// generated.
+    pub(crate) fn record_generated(
+        &self,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        levels: Range<u32>,
+    ) {
+        // Only one layer of a 2D array is written, while 3D textures are written whole
+        let bytes = level_bytes(texture_descriptor, levels.clone());
+        self.textures.fetch_add(1, Ordering::Relaxed);
+        self.levels
+            .fetch_add((levels.end - levels.start) as u64, Ordering::Relaxed);
+        self.bytes_written.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_fallbacks(&self, fallbacks: u64) {
+        self.fallbacks.fetch_add(fallbacks, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_encode_time(&self, encode_time: Duration) {
+        self.encode_nanos
+            .fetch_add(encode_time.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Measures the time until [`EncodeTimer::elapsed`] is called, or nothing on wasm.
+pub(crate) struct EncodeTimer {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+}
+
+impl EncodeTimer {
+    pub(crate) fn start() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.start.elapsed();
+        #[cfg(target_arch = "wasm32")]
+        return Duration::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(
+        dimension: wgpu::TextureDimension,
+        depth: u32,
+    ) -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: 8,
+                height: 8,
+                depth,
+            },
+            mip_level_count: 4,
+            sample_count: 1,
+            dimension,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsage::STORAGE,
+        }
+    }
+
+    #[test]
+    fn counters() {
+        let collector = StatsCollector::new();
+        collector.record_generated(&descriptor(wgpu::TextureDimension::D2, 6), 1..4);
+        collector.record_generated(&descriptor(wgpu::TextureDimension::D3, 4), 1..2);
+        collector.record_fallbacks(2);
+        collector.record_encode_time(Duration::from_micros(3));
+        let expected = Stats {
+            textures: 2,
+            levels: 4,
+            // One 2D layer of 4x4, 2x2 and 1x1, then a 4x4x2 level
+            bytes_written: (16 + 4 + 1) * 4 + 32 * 4,
+            fallbacks: 2,
+            encode_time: Duration::from_micros(3),
+        };
+        assert_eq!(collector.snapshot(), expected);
+        assert_eq!(collector.take(), expected);
+        assert_eq!(collector.snapshot(), Stats::default());
+    }
+}
@@ -0,0 +1,172 @@
+/*!
+Headless GPU setup for mipmap correctness tests.
+
+Machines without a GPU, such as most CI runners, can still run GPU tests with a software
+adapter like Mesa's lavapipe (Vulkan) or llvmpipe (GL). [`TestContext::new`] picks up such
+adapters like any other, and returns `None` when there is no adapter at all so tests can skip
+instead of panicking or hanging.
+
+The following environment variables configure the adapter:
+- `WGPU_MIPMAP_BACKEND`: a comma separated list of backends to request an adapter from:
+  `vulkan`, `metal`, `dx12`, `dx11` or `gl`. Defaults to the primary backends, then the
+  secondary ones.
+- `WGPU_MIPMAP_REQUIRE_ADAPTER`: when set, a missing adapter is an error instead of a reason
+  to skip, so a CI job can't pass by skipping every test.
+
+Rounding and srgb conversion are implementation-defined, so levels generated on different
+devices differ slightly. With the `unstable` feature, `assert_levels_match` compares levels
+against a reference with the tolerance of the device measured by
+[`TestContext::device_behavior`].
+
+```rust
+use wgpu_mipmap::{testing::TestContext, RecommendedMipmapGenerator};
+# futures::executor::block_on(async {
+let context = match TestContext::new().await {
+    Some(context) => context,
+    None => return,
+};
+let generator = RecommendedMipmapGenerator::new(&context.device);
+# });
+```
+*/
+#[cfg(feature = "unstable")]
+use crate::util::MipBuffer;
+use crate::{
+    backends::DeviceBehavior,
+    context::{Context, ContextOptions},
+    core::Error,
+    trace::event,
+};
+
+/// An adapter, device and queue to run tests with, created with the default
+/// [`ContextOptions`].
+#[derive(Debug)]
+pub struct TestContext {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+impl TestContext {
+    /// Requests an adapter from the backends selected by `WGPU_MIPMAP_BACKEND`, and a device
+    /// from it. Returns `None` if no backend has an adapter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no adapter is found and `WGPU_MIPMAP_REQUIRE_ADAPTER` is set, or if the
+    /// adapter fails to create a device.
+    pub async fn new() -> Option<Self> {
+        for backends in backends_from_env() {
+            let options = ContextOptions {
+                backends,
+                ..Default::default()
+            };
+            let context = match Context::new(&options).await {
+                Ok(context) => context,
+                Err(Error::NoAdapter) => continue,
+                Err(e) => panic!("{}", e),
+            };
+            context.device.on_uncaptured_error(|e| {
+                println!("Captured a wgpu error: {}", e);
+            });
+            return Some(Self {
+                instance: context.instance,
+                adapter: context.adapter,
+                device: context.device,
+                queue: context.queue,
+            });
+        }
+        assert!(
+            !Self::adapter_required(),
+            "Failed to find an appropiate adapter, and WGPU_MIPMAP_REQUIRE_ADAPTER is set"
+        );
+        None
+    }
+
+    /// Returns true if `WGPU_MIPMAP_REQUIRE_ADAPTER` is set.
+    pub fn adapter_required() -> bool {
+        std::env::var_os("WGPU_MIPMAP_REQUIRE_ADAPTER").is_some()
+    }
+
+    /// Returns true if the adapter is a software rasterizer. See [`Context::is_software`].
+    pub fn is_software(&self) -> bool {
+        self.adapter.get_info().device_type == wgpu::DeviceType::Cpu
+    }
+
+    /// Probes the implementation-defined behavior of the device, for
+    /// `assert_levels_match`. See [`DeviceBehavior::probe`].
+    pub async fn device_behavior(&self) -> Result<DeviceBehavior, Error> {
+        DeviceBehavior::probe(&self.adapter, &self.device, &self.queue).await
+    }
+}
+
+/// Asserts that every channel of every level in `actual` is within the tolerance `behavior`
+/// expects of the level with the same index in `reference`, a box filter that rounds to
+/// nearest. See [`DeviceBehavior::tolerance`].
+///
+/// # Panics
+///
+/// Panics if the levels differ by more than the tolerance, have different sizes, or have a
+/// format without 8 bit unorm channels.
+///
+/// Needs the `unstable` feature, as the shape of [`MipBuffer`] may still change.
+#[cfg(feature = "unstable")]
+pub fn assert_levels_match(
+    actual: &[MipBuffer],
+    reference: &[MipBuffer],
+    behavior: &DeviceBehavior,
+) {
+    assert_eq!(actual.len(), reference.len(), "level counts differ");
+    for (a, r) in actual.iter().zip(reference) {
+        let tolerance = behavior
+            .tolerance(a.format, a.level)
+            .unwrap_or_else(|| panic!("{:?} doesn't have 8 bit unorm channels", a.format));
+        assert_eq!(
+            a.buffer.len(),
+            r.buffer.len(),
+            "level {} sizes differ",
+            a.level
+        );
+        let max_difference = a
+            .buffer
+            .iter()
+            .zip(&r.buffer)
+            .map(|(&a, &r)| (a as i32 - r as i32).unsigned_abs())
+            .max()
+            .unwrap_or(0);
+        assert!(
+            max_difference <= tolerance as u32,
+            "level {} differs from the reference by {} steps, expected at most {} on a device with {:?}",
+            a.level,
+            max_difference,
+            tolerance,
+            behavior
+        );
+    }
+}
+
+/// Returns the backends to request an adapter from, in order.
+fn backends_from_env() -> Vec<wgpu::BackendBit> {
+    let names = match std::env::var("WGPU_MIPMAP_BACKEND") {
+        Ok(names) => names,
+        Err(_) => return vec![wgpu::BackendBit::PRIMARY, wgpu::BackendBit::SECONDARY],
+    };
+    let backends = names
+        .split(',')
+        .fold(wgpu::BackendBit::empty(), |backends, name| {
+            backends
+                | match name.trim().to_lowercase().as_str() {
+                    "vulkan" | "vk" => wgpu::BackendBit::VULKAN,
+                    "metal" | "mtl" => wgpu::BackendBit::METAL,
+                    "dx12" | "d3d12" => wgpu::BackendBit::DX12,
+                    "dx11" | "d3d11" => wgpu::BackendBit::DX11,
+                    "gl" | "opengl" | "gles" => wgpu::BackendBit::GL,
+                    name => {
+                        event!(WARN, { backend = name }, "unknown backend");
+                        wgpu::BackendBit::empty()
+                    }
+                }
+        });
+    vec![backends]
+}
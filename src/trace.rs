@@ -0,0 +1,73 @@
+//! Structured diagnostics. With the `tracing` feature, events and spans are emitted with
+//! `tracing` and carry their fields as structured data. Otherwise events are logged with
+//! `log`, with their fields appended to the message as `name=value`, and spans are skipped.
+
+/// Emits an event at `$level` (`ERROR`, `WARN`, `INFO`, `DEBUG` or `TRACE`) with the
+/// `Debug` representation of each field:
+///
+/// ```ignore
+/// event!(DEBUG, { format = format, backend = "compute" }, "falling back to {}", "render");
+/// ```
+#[cfg(feature = "tracing")]
+macro_rules! event {
+    ($level:ident, { $($field:ident = $value:expr),* $(,)? }, $($message:tt)+) => {
+        tracing::event!(tracing::Level::$level, $($field = ?$value,)* $($message)+)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($level:ident, { $($field:ident = $value:expr),* $(,)? }, $($message:tt)+) => {
+        log::log!(
+            $crate::trace::log_level!($level),
+            concat!("{}", $(" ", stringify!($field), "={:?}",)*),
+            format_args!($($message)+),
+            $($value),*
+        )
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! log_level {
+    (ERROR) => {
+        log::Level::Error
+    };
+    (WARN) => {
+        log::Level::Warn
+    };
+    (INFO) => {
+        log::Level::Info
+    };
+    (DEBUG) => {
+        log::Level::Debug
+    };
+    (TRACE) => {
+        log::Level::Trace
+    };
+}
+
+/// Enters a debug span named `$name` with the `Debug` representation of each field, and
+/// returns a guard that exits it when dropped.
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($name:literal, { $($field:ident = $value:expr),* $(,)? }) => {
+        tracing::debug_span!($name, $($field = ?$value),*).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($name:literal, { $($field:ident = $value:expr),* $(,)? }) => {{
+        $(let _ = &$value;)*
+        $crate::trace::NoSpan
+    }};
+}
+
+/// The guard returned by `span!` without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
+
+pub(crate) use event;
+#[cfg(not(feature = "tracing"))]
+pub(crate) use log_level;
+pub(crate) use span;
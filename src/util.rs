@@ -1,11 +1,101 @@
-/// utilities used throughout the project. Not part of the official API.
+//! Helpers to read mip levels back to the CPU, write them to KTX2 and DDS files, and create
+//! test patterns, along with utilities used throughout the project.
+//!
+//! Only public with the `unstable` feature: these APIs are experimental and may change in a
+//! patch release.
 use crate::core::*;
+use std::ops::Range;
 
 #[derive(Debug)]
 pub struct MipBuffer {
     pub buffer: Vec<u8>,
     pub dimensions: MipBufferDimensions,
     pub level: u32,
+    /// The format of the texture the level was read back from
+    // Only read through the unstable API
+    #[cfg_attr(not(feature = "unstable"), allow(dead_code))]
+    pub format: wgpu::TextureFormat,
+}
+
+#[cfg(feature = "unstable")]
+impl MipBuffer {
+    /// Returns the width of the level in texels.
+    pub fn width(&self) -> u32 {
+        self.dimensions.width as u32
+    }
+
+    /// Returns the height of the level in texels.
+    pub fn height(&self) -> u32 {
+        self.dimensions.height as u32
+    }
+
+    /// Returns the number of bytes per texel of the level's format.
+    pub fn bytes_per_texel(&self) -> usize {
+        self.dimensions.bytes_per_channel
+    }
+
+    /// Returns the number of array layers, or depth slices of a 3D texture, in the level.
+    pub fn layer_count(&self) -> usize {
+        self.dimensions.layers
+    }
+
+    /// Returns the tightly packed texel data of one array layer or depth slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `layer` is not less than [`MipBuffer::layer_count`].
+    pub fn layer(&self, layer: usize) -> &[u8] {
+        assert!(layer < self.layer_count(), "layer {} out of range", layer);
+        let layer_size = self.buffer.len() / self.layer_count();
+        &self.buffer[layer * layer_size..(layer + 1) * layer_size]
+    }
+
+    /// Returns the tightly packed texel data as a slice of `T`, e.g. `f32`
+    /// for `Rgba32Float` or `[u8; 4]` for `Rgba8Unorm`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is not aligned for `T` or its length is not a
+    /// multiple of the size of `T`.
+    pub fn as_slice<T: bytemuck::Pod>(&self) -> &[T] {
+        bytemuck::cast_slice(&self.buffer)
+    }
+
+    /// Converts the first layer of the level to an image, or returns `None` if the level's
+    /// format has no matching `image::DynamicImage` variant.
+    #[cfg(feature = "image")]
+    pub fn to_image(&self) -> Option<image::DynamicImage> {
+        use image::{DynamicImage, ImageBuffer};
+        use wgpu::TextureFormat;
+        let (width, height, buffer) = (self.width(), self.height(), self.layer(0).to_vec());
+        match self.format {
+            TextureFormat::R8Unorm => {
+                ImageBuffer::from_raw(width, height, buffer).map(DynamicImage::ImageLuma8)
+            }
+            TextureFormat::Rg8Unorm => {
+                ImageBuffer::from_raw(width, height, buffer).map(DynamicImage::ImageLumaA8)
+            }
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {
+                ImageBuffer::from_raw(width, height, buffer).map(DynamicImage::ImageRgba8)
+            }
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => {
+                ImageBuffer::from_raw(width, height, buffer).map(DynamicImage::ImageBgra8)
+            }
+            TextureFormat::R16Uint => ImageBuffer::from_raw(
+                width,
+                height,
+                bytemuck::cast_slice::<_, u16>(self.layer(0)).to_vec(),
+            )
+            .map(DynamicImage::ImageLuma16),
+            TextureFormat::Rgba16Uint => ImageBuffer::from_raw(
+                width,
+                height,
+                bytemuck::cast_slice::<_, u16>(self.layer(0)).to_vec(),
+            )
+            .map(DynamicImage::ImageRgba16),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -15,6 +105,9 @@ pub struct MipBufferDimensions {
     pub bytes_per_channel: usize,
     pub unpadded_bytes_per_row: usize,
     pub padded_bytes_per_row: usize,
+    /// The number of array layers, or depth slices of a 3D texture, stored one after the
+    /// other.
+    pub layers: usize,
 }
 
 impl MipBufferDimensions {
@@ -22,25 +115,59 @@ impl MipBufferDimensions {
         let width = width.max(1);
         let height = height.max(1);
         let unpadded_bytes_per_row = width * bytes_per_channel;
-        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
-        let padded_bytes_per_row_padding = (align - unpadded_bytes_per_row % align) % align;
-        let padded_bytes_per_row = unpadded_bytes_per_row + padded_bytes_per_row_padding;
+        let padded_bytes_per_row =
+            wgpu_mipmap_core::padded_bytes_per_row(unpadded_bytes_per_row as u32) as usize;
         Self {
             width,
             height,
             bytes_per_channel,
             unpadded_bytes_per_row,
             padded_bytes_per_row,
+            layers: 1,
+        }
+    }
+
+    /// Returns these dimensions with `layers` array layers or depth slices.
+    pub fn with_layers(self, layers: usize) -> Self {
+        Self {
+            layers: layers.max(1),
+            ..self
         }
     }
 }
 
+/// Uploads `data` to level 0 of a new texture described by `texture_descriptor`,
+/// generates its mipmaps with `generator` and reads every level back.
+///
+/// `texture_descriptor.usage` only needs the usage `generator` requires: the
+/// texture is created with `COPY_DST` and `COPY_SRC` added for the upload and
+/// readback, while `generator` still sees the caller's usage.
 pub async fn generate_and_copy_to_cpu(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     generator: &dyn MipmapGenerator,
     data: &[u8],
     texture_descriptor: &wgpu::TextureDescriptor<'_>,
+) -> Result<Vec<MipBuffer>, Error> {
+    generate_and_copy_to_cpu_with_options(
+        device,
+        queue,
+        generator,
+        data,
+        texture_descriptor,
+        &GenerateOptions::default(),
+    )
+    .await
+}
+
+/// Like [`generate_and_copy_to_cpu`], but generates with `options`.
+pub async fn generate_and_copy_to_cpu_with_options(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    generator: &dyn MipmapGenerator,
+    data: &[u8],
+    texture_descriptor: &wgpu::TextureDescriptor<'_>,
+    options: &GenerateOptions,
 ) -> Result<Vec<MipBuffer>, Error> {
     // Create a texture
     let buffer_dimensions = MipBufferDimensions::new(
@@ -48,7 +175,13 @@ pub async fn generate_and_copy_to_cpu(
         texture_descriptor.size.height as usize,
         format_bytes_per_channel(&texture_descriptor.format),
     );
-    let texture = device.create_texture(&texture_descriptor);
+    // The copy usages are only needed by this helper, so don't require callers to set them
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        usage: texture_descriptor.usage
+            | wgpu::TextureUsage::COPY_DST
+            | wgpu::TextureUsage::COPY_SRC,
+        ..texture_descriptor.clone()
+    });
     // Upload `data` to the texture
     queue.write_texture(
         wgpu::TextureCopyView {
@@ -56,7 +189,7 @@ pub async fn generate_and_copy_to_cpu(
             mip_level: 0,
             origin: wgpu::Origin3d::ZERO,
         },
-        &data,
+        data,
         wgpu::TextureDataLayout {
             offset: 0,
             bytes_per_row: buffer_dimensions.unpadded_bytes_per_row as u32,
@@ -70,92 +203,448 @@ pub async fn generate_and_copy_to_cpu(
     );
     let mut encoder =
         device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-    generator.generate(&device, &mut encoder, &texture, &texture_descriptor)?;
+    generator.generate_with_options(device, &mut encoder, &texture, texture_descriptor, options)?;
     // Copy all mipmap levels, including the base, to GPU buffers
-    let buffers = {
-        let mut buffers = Vec::new();
-        for i in 0..texture_descriptor.mip_level_count {
-            let mip_width = buffer_dimensions.width / 2usize.pow(i);
-            let mip_height = buffer_dimensions.height / 2usize.pow(i);
-            let mip_dimensions = MipBufferDimensions::new(
-                mip_width,
-                mip_height,
-                buffer_dimensions.bytes_per_channel,
-            );
-            let size = (mip_dimensions.height * mip_dimensions.padded_bytes_per_row) as u64;
-            let mip_texture_extent = wgpu::Extent3d {
-                width: mip_width as u32,
-                height: mip_height as u32,
-                depth: 1,
-            };
-            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
-                label: None,
-                size,
-                usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
-                mapped_at_creation: false,
-            });
+    let readback = MipReadback::encode(device, &mut encoder, &texture, texture_descriptor);
+    queue.submit(std::iter::once(encoder.finish()));
+    // Copy the GPU buffers to the CPU
+    let mip_buffers = readback.read();
+    // Poll the device in a blocking manner so that our future resolves.
+    // In an actual application, `device.poll(...)` should
+    // be called in an event loop or on another thread.
+    device.poll(wgpu::Maintain::Wait);
+    mip_buffers.await
+}
+
+/// Where a level of a texture is stored in a readback buffer, relative to the offset the
+/// levels were copied to.
+#[derive(Debug, Clone, Copy)]
+pub struct MipReadbackLevel {
+    pub offset: u64,
+    pub dimensions: MipBufferDimensions,
+}
+
+impl MipReadbackLevel {
+    /// Returns the level's tightly packed texels from the mapped range of the readback
+    /// buffer starting at the offset the levels were copied to.
+    pub fn unpad(&self, mapped: &[u8]) -> Vec<u8> {
+        let dims = &self.dimensions;
+        let start = self.offset as usize;
+        // The rows of the buffer are padded, so only extract what we need
+        let rows = dims.height * dims.layers;
+        let mut exact_buffer = Vec::with_capacity(dims.unpadded_bytes_per_row * rows);
+        for y in 0..rows {
+            let row_beg = start + y * dims.padded_bytes_per_row;
+            let row_end = row_beg + dims.unpadded_bytes_per_row;
+            exact_buffer.extend_from_slice(&mapped[row_beg..row_end]);
+        }
+        exact_buffer
+    }
+}
+
+/// Returns where each level of a texture described by `texture_descriptor` is stored by
+/// [`MipReadback::encode_into`], and the number of bytes they take in total.
+pub fn readback_layout(
+    texture_descriptor: &wgpu::TextureDescriptor,
+) -> (Vec<MipReadbackLevel>, u64) {
+    let extent = MipExtent::of(texture_descriptor);
+    let bytes_per_channel = format_bytes_per_channel(&texture_descriptor.format);
+    let mut offset = 0;
+    let levels = (0..texture_descriptor.mip_level_count)
+        .map(|i| {
+            // Array layers are kept by every level, while depth slices are halved
+            let mip = extent.mip(i);
+            let dimensions = MipBufferDimensions::new(
+                mip.width as usize,
+                mip.height as usize,
+                bytes_per_channel,
+            )
+            .with_layers(mip.depth_or_array_layers() as usize);
+            let level = MipReadbackLevel { offset, dimensions };
+            // Each row is padded to 256 bytes, so every level stays aligned for the next copy
+            offset +=
+                (dimensions.height * dimensions.layers * dimensions.padded_bytes_per_row) as u64;
+            level
+        })
+        .collect();
+    (levels, offset)
+}
+
+/// A GPU buffer holding a copy of every level of a texture, recorded by
+/// [`MipReadback::encode`] and read back to the CPU with [`MipReadback::read`].
+#[derive(Debug)]
+pub struct MipReadback {
+    buffer: wgpu::Buffer,
+    levels: Vec<MipReadbackLevel>,
+    format: wgpu::TextureFormat,
+}
+
+impl MipReadback {
+    /// Encodes commands to copy every level of `texture` to a new buffer.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`,
+    ///   which must have `TextureUsage::COPY_SRC`.
+    pub fn encode(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        texture_descriptor: &wgpu::TextureDescriptor,
+    ) -> Self {
+        let (_, size) = readback_layout(texture_descriptor);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("wgpu-mipmap-readback"),
+            size,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let levels = Self::encode_into(encoder, texture, texture_descriptor, &buffer, 0);
+        Self::from_levels(buffer, levels, texture_descriptor.format)
+    }
+
+    /// Wraps `buffer`, a `MAP_READ` buffer holding `levels` of `format` from offset 0.
+    pub(crate) fn from_levels(
+        buffer: wgpu::Buffer,
+        levels: Vec<MipReadbackLevel>,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        Self {
+            buffer,
+            levels,
+            format,
+        }
+    }
+
+    /// Encodes commands to copy every level of `texture` to `buffer`, starting at `offset`,
+    /// and returns where each level is stored relative to `offset`. Use this to read many
+    /// textures back through buffers you allocate and reuse.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`,
+    ///   which must have `TextureUsage::COPY_SRC`.
+    /// - `buffer` has `BufferUsage::COPY_DST` and room for the size returned by
+    ///   [`readback_layout`] after `offset`, which is a multiple of the format's texel size.
+    pub fn encode_into(
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        texture_descriptor: &wgpu::TextureDescriptor,
+        buffer: &wgpu::Buffer,
+        offset: u64,
+    ) -> Vec<MipReadbackLevel> {
+        let (levels, _) = readback_layout(texture_descriptor);
+        for (i, level) in levels.iter().enumerate() {
+            let dims = &level.dimensions;
             encoder.copy_texture_to_buffer(
                 wgpu::TextureCopyView {
-                    texture: &texture,
-                    mip_level: i,
+                    texture,
+                    mip_level: i as u32,
                     origin: wgpu::Origin3d::ZERO,
                 },
                 wgpu::BufferCopyView {
-                    buffer: &buffer,
+                    buffer,
                     layout: wgpu::TextureDataLayout {
-                        offset: 0,
-                        bytes_per_row: mip_dimensions.padded_bytes_per_row as u32,
-                        rows_per_image: 0,
+                        offset: offset + level.offset,
+                        bytes_per_row: dims.padded_bytes_per_row as u32,
+                        rows_per_image: dims.height as u32,
                     },
                 },
-                mip_texture_extent,
+                wgpu::Extent3d {
+                    width: dims.width as u32,
+                    height: dims.height as u32,
+                    depth: dims.layers as u32,
+                },
             );
-            buffers.push((buffer, mip_dimensions));
         }
-        buffers
-    };
-    queue.submit(std::iter::once(encoder.finish()));
-    // Copy the GPU buffers to the CPU
-    let mut mip_buffers: Vec<MipBuffer> = Vec::new();
-    for (level, (buffer, buffer_dimensions)) in buffers.iter().enumerate() {
-        // Note that we're not calling `.await` here.
-        let buffer_slice = buffer.slice(..);
-        let buffer_future = buffer_slice.map_async(wgpu::MapMode::Read);
-        // Poll the device in a blocking manner so that our future resolves.
-        // In an actual application, `device.poll(...)` should
-        // be called in an event loop or on another thread.
-        device.poll(wgpu::Maintain::Wait);
-        match buffer_future.await {
-            Err(e) => panic!("Unexpected failure: {}", e),
-            Ok(()) => {
-                let padded_buffer = buffer_slice.get_mapped_range();
-                // The buffer we get back is padded, so only extract what we need
-                let mut exact_buffer = Vec::with_capacity(
-                    buffer_dimensions.unpadded_bytes_per_row * buffer_dimensions.height,
-                );
-                for y in 0..buffer_dimensions.height {
-                    let row_beg = y * buffer_dimensions.padded_bytes_per_row;
-                    let row_end = row_beg + buffer_dimensions.unpadded_bytes_per_row;
-                    exact_buffer.extend_from_slice(&padded_buffer[row_beg..row_end]);
-                }
-                mip_buffers.push(MipBuffer {
-                    buffer: exact_buffer,
-                    dimensions: *buffer_dimensions,
+        levels
+    }
+
+    /// Maps the buffer and returns a future resolving to the levels once the copies
+    /// submitted with [`MipReadback::encode`] are complete.
+    ///
+    /// The future never blocks or polls the device. On the web it resolves on its own; on
+    /// native backends it resolves after `device.poll(...)` is called, e.g. from an event
+    /// loop or another thread, so it can be awaited on any executor.
+    pub fn read(&self) -> impl std::future::Future<Output = Result<Vec<MipBuffer>, Error>> + '_ {
+        // Request the mapping now, rather than when the future is first polled
+        let slice = self.buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        async move {
+            mapping.await.map_err(|_| Error::ReadbackFailed)?;
+            let mapped = slice.get_mapped_range();
+            let mip_buffers = self
+                .levels
+                .iter()
+                .enumerate()
+                .map(|(level, readback_level)| MipBuffer {
+                    buffer: readback_level.unpad(&mapped),
+                    dimensions: readback_level.dimensions,
                     level: level as u32,
-                });
-            }
+                    format: self.format,
+                })
+                .collect();
+            drop(mapped);
+            self.buffer.unmap();
+            Ok(mip_buffers)
         }
     }
-    Ok(mip_buffers)
 }
 
+/// A GPU buffer holding a copy of the last, 1x1, level of an `R16Float` luminance pyramid,
+/// i.e. the average luminance of its source, recorded by [`LuminanceReadback::encode`].
+#[cfg(feature = "unstable")]
+#[derive(Debug)]
+pub struct LuminanceReadback {
+    buffer: wgpu::Buffer,
+}
+
+#[cfg(feature = "unstable")]
+impl LuminanceReadback {
+    /// Encodes commands to copy the last level of `texture` to a new buffer.
+    ///
+    /// Expectations:
+    /// - `texture_descriptor` should be the same descriptor used to create the `texture`, an
+    ///   `R16Float` texture with `TextureUsage::COPY_SRC` whose last level is 1x1, such as the
+    ///   destination of `RenderMipmapGenerator::generate_luminance_pyramid`.
+    pub fn encode(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        texture_descriptor: &wgpu::TextureDescriptor,
+    ) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("wgpu-mipmap-luminance-readback"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::TextureCopyView {
+                texture,
+                mip_level: texture_descriptor.mip_level_count - 1,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::BufferCopyView {
+                buffer: &buffer,
+                layout: wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
+                    rows_per_image: 1,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth: 1,
+            },
+        );
+        Self { buffer }
+    }
+
+    /// Maps the buffer and returns a future resolving to the luminance once the copy
+    /// submitted with [`LuminanceReadback::encode`] is complete. Like [`MipReadback::read`],
+    /// the future never polls the device.
+    pub fn read(&self) -> impl std::future::Future<Output = Result<f32, Error>> + '_ {
+        let slice = self.buffer.slice(..2);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        async move {
+            mapping.await.map_err(|_| Error::ReadbackFailed)?;
+            let bits = {
+                let mapped = slice.get_mapped_range();
+                u16::from_le_bytes([mapped[0], mapped[1]])
+            };
+            self.buffer.unmap();
+            Ok(f16_to_f32(bits))
+        }
+    }
+}
+
+/// Converts the bits of an IEEE 754 half-precision float to an `f32`.
+#[cfg(feature = "unstable")]
+pub(crate) fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Concatenates levels read back from a texture in the order of a KTX2 file's level data:
+/// smallest level first, each aligned to the larger of its texel size and 4 bytes
+/// (`mipPadding`), with the layers or depth slices of each level one after the other.
+///
+/// Returns the data and the byte range of each level, indexed by level, for the KTX2 level
+/// index. Alignment is relative to the start of the data, which should itself be aligned.
+#[cfg(feature = "unstable")]
+pub fn pack_ktx2_levels(mip_buffers: &[MipBuffer]) -> (Vec<u8>, Vec<Range<usize>>) {
+    let mut data = Vec::new();
+    let mut ranges = vec![0..0; mip_buffers.len()];
+    for mip in mip_buffers.iter().rev() {
+        let alignment = mip.bytes_per_texel().max(4);
+        data.resize(data.len().div_ceil(alignment) * alignment, 0);
+        let start = data.len();
+        data.extend_from_slice(&mip.buffer);
+        ranges[mip.level as usize] = start..data.len();
+    }
+    (data, ranges)
+}
+
+/// Returns a KTX2 file holding `mip_buffers`, every level of a 2D texture of `format` that is
+/// `width` by `height` texels, indexed by level. `format` is `Rgba8Unorm`, `Bc7RgbaUnorm` or
+/// one of their sRGB variants; BC7 levels are the blocks read back by
+/// `CompressedMipmapGenerator::encode_levels`. Returns `Error::UnsupportedFormat` for any
+/// other format.
+///
+/// Only the first layer of each level is written, and the file has no supercompression.
+#[cfg(feature = "unstable")]
+pub fn ktx2_file(
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    mip_buffers: &[MipBuffer],
+) -> Result<Vec<u8>, Error> {
+    use wgpu::TextureFormat;
+    // KHR_DF_TRANSFER_LINEAR and KHR_DF_TRANSFER_SRGB
+    let transfer = |srgb| if srgb { 2 } else { 1 };
+    // A sample of the data format descriptor: its bit offset, bit length, channel type and
+    // qualifiers, and upper value
+    type Sample = (u32, u32, u32, u32);
+    // The Vulkan format, the data format descriptor's basic block and its samples
+    let (vk_format, basic, samples): (u32, [u32; 4], &[Sample]) = match format {
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {
+            let srgb = format == TextureFormat::Rgba8UnormSrgb;
+            let alpha = if srgb { 0x1f } else { 0x0f };
+            (
+                if srgb { 43 } else { 37 },
+                // KHR_DF_MODEL_RGBSDA, BT.709 primaries, 1x1x1 texels in 4 bytes
+                [1 | 1 << 8 | transfer(srgb) << 16, 0, 4, 0],
+                &[
+                    (0, 8, 0, 255),
+                    (8, 8, 1, 255),
+                    (16, 8, 2, 255),
+                    (24, 8, alpha, 255),
+                ],
+            )
+        }
+        TextureFormat::Bc7RgbaUnorm | TextureFormat::Bc7RgbaUnormSrgb => {
+            let srgb = format == TextureFormat::Bc7RgbaUnormSrgb;
+            (
+                if srgb { 146 } else { 145 },
+                // KHR_DF_MODEL_BC7, BT.709 primaries, 4x4x1 texels in 16 bytes
+                [134 | 1 << 8 | transfer(srgb) << 16, 3 | 3 << 8, 16, 0],
+                &[(0, 128, 0, u32::MAX)],
+            )
+        }
+        format => return Err(Error::UnsupportedFormat(format)),
+    };
+    let mut dfd = Vec::new();
+    let basic_size = 24 + 16 * samples.len() as u32;
+    for word in [4 + basic_size, 0, 2 | basic_size << 16]
+        .iter()
+        .chain(basic.iter())
+    {
+        dfd.extend_from_slice(&word.to_le_bytes());
+    }
+    for &(offset, length, channel, upper) in samples {
+        // Lengths, and the texel block dimensions above, are stored minus 1
+        for word in [offset | (length - 1) << 16 | channel << 24, 0, 0, upper].iter() {
+            dfd.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+    let mut kvd = Vec::new();
+    let writer = b"KTXwriter\0wgpu-mipmap\0";
+    kvd.extend_from_slice(&(writer.len() as u32).to_le_bytes());
+    kvd.extend_from_slice(writer);
+    kvd.resize(kvd.len().div_ceil(4) * 4, 0);
+
+    let level_count = mip_buffers.len();
+    let dfd_offset = 80 + 24 * level_count;
+    let kvd_offset = dfd_offset + dfd.len();
+    // Aligned to 16 bytes, a multiple of the texel block size of every format above
+    let data_offset = (kvd_offset + kvd.len()).div_ceil(16) * 16;
+    let first_layers = mip_buffers
+        .iter()
+        .map(|mip| MipBuffer {
+            buffer: mip.layer(0).to_vec(),
+            dimensions: mip.dimensions.with_layers(1),
+            level: mip.level,
+            format: mip.format,
+        })
+        .collect::<Vec<_>>();
+    let (data, ranges) = pack_ktx2_levels(&first_layers);
+
+    let mut file = vec![
+        0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+    ];
+    // vkFormat, typeSize, pixelWidth, pixelHeight, pixelDepth, layerCount, faceCount,
+    // levelCount and supercompressionScheme, then the offsets and lengths of the data format
+    // descriptor and key/value data
+    for word in [
+        vk_format,
+        1,
+        width,
+        height,
+        0,
+        0,
+        1,
+        level_count as u32,
+        0,
+        dfd_offset as u32,
+        dfd.len() as u32,
+        kvd_offset as u32,
+        kvd.len() as u32,
+    ]
+    .iter()
+    {
+        file.extend_from_slice(&word.to_le_bytes());
+    }
+    // There is no supercompression global data
+    file.extend_from_slice(&[0; 16]);
+    for range in &ranges {
+        let length = range.len() as u64;
+        for word in [(data_offset + range.start) as u64, length, length].iter() {
+            file.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+    file.extend_from_slice(&dfd);
+    file.extend_from_slice(&kvd);
+    file.resize(data_offset, 0);
+    file.extend_from_slice(&data);
+    Ok(file)
+}
+
+/// Concatenates levels read back from a texture in the order of a DDS file's surface data:
+/// for each array layer, every level from largest to smallest. The depth slices of a 3D
+/// texture's level stay together, and no padding is inserted.
+#[cfg(feature = "unstable")]
+pub fn pack_dds_levels(mip_buffers: &[MipBuffer], dimension: wgpu::TextureDimension) -> Vec<u8> {
+    let mut data = Vec::new();
+    if dimension == wgpu::TextureDimension::D3 {
+        for mip in mip_buffers {
+            data.extend_from_slice(&mip.buffer);
+        }
+        return data;
+    }
+    let layers = mip_buffers.first().map_or(1, |mip| mip.dimensions.layers);
+    for layer in 0..layers {
+        for mip in mip_buffers {
+            data.extend_from_slice(mip.layer(layer));
+        }
+    }
+    data
+}
+
+#[cfg(feature = "unstable")]
 pub fn checkerboard_r8(width: u32, height: u32, n: u32) -> Vec<u8> {
     use std::iter;
 
     (0..width * height)
         .flat_map(|id| {
             let x = id % width;
-            let y = id / height;
+            let y = id / width;
             let v = (((x / n + y / n) % 2) * 255) as u8;
             iter::once(v)
         })
@@ -163,13 +652,14 @@ pub fn checkerboard_r8(width: u32, height: u32, n: u32) -> Vec<u8> {
 }
 
 #[doc(hidden)]
+#[cfg(feature = "unstable")]
 pub fn checkerboard_rgba8(width: u32, height: u32, n: u32) -> Vec<u8> {
     use std::iter;
 
     (0..width * height)
         .flat_map(|id| {
             let x = id % width;
-            let y = id / height;
+            let y = id / width;
             let v = (((x / n + y / n) % 2) * 255) as u8;
             iter::once(v)
                 .chain(iter::once(v))
@@ -180,13 +670,14 @@ pub fn checkerboard_rgba8(width: u32, height: u32, n: u32) -> Vec<u8> {
 }
 
 #[doc(hidden)]
+#[cfg(feature = "unstable")]
 pub fn checkerboard_rgba32f(width: u32, height: u32, n: u32) -> Vec<f32> {
     use std::iter;
 
     (0..width * height)
         .flat_map(|id| {
             let x = id % width;
-            let y = id / height;
+            let y = id / width;
             let v = ((x / n + y / n) % 2) as f32;
             iter::once(v)
                 .chain(iter::once(v))
@@ -196,7 +687,7 @@ pub fn checkerboard_rgba32f(width: u32, height: u32, n: u32) -> Vec<f32> {
         .collect()
 }
 
-fn format_bytes_per_channel(format: &wgpu::TextureFormat) -> usize {
+pub(crate) fn format_bytes_per_channel(format: &wgpu::TextureFormat) -> usize {
     use wgpu::TextureFormat;
     match format {
         // 8 bit per channel
@@ -247,41 +738,550 @@ fn format_bytes_per_channel(format: &wgpu::TextureFormat) -> usize {
 #[doc(hidden)]
 #[allow(dead_code)]
 pub(crate) async fn wgpu_setup() -> (wgpu::Instance, wgpu::Adapter, wgpu::Device, wgpu::Queue) {
-    let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: None,
-        })
+    let context = crate::testing::TestContext::new()
         .await
         .expect("Failed to find an appropiate adapter");
-    // Create the logical device and command queue
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
+    (
+        context.instance,
+        context.adapter,
+        context.device,
+        context.queue,
+    )
+}
+
+/// Returns true if tests can get an adapter from [`wgpu_setup`]. GPU tests return early
+/// when this is false, so `cargo test` passes on machines without a GPU.
+#[cfg(test)]
+pub(crate) fn adapter_available() -> bool {
+    static AVAILABLE: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        let available = futures::executor::block_on(crate::testing::TestContext::new()).is_some();
+        if !available {
+            eprintln!("No adapter found, skipping GPU tests");
+        }
+        available
+    })
+}
+
+/// Asserts that the average value of every channel of every mip level of an
+/// 8 bit per channel texture is within `tolerance` of the base level's average.
+/// A downsampling filter that drops or double counts texels along either axis
+/// fails this check.
+#[allow(dead_code)]
+pub(crate) fn assert_levels_preserve_mean(mip_buffers: &[MipBuffer], tolerance: f32) {
+    let mean = |mip: &MipBuffer| {
+        let channels = mip.dimensions.bytes_per_channel;
+        let mut sums = vec![0.0f32; channels];
+        for texel in mip.buffer.chunks(channels) {
+            for (sum, value) in sums.iter_mut().zip(texel) {
+                *sum += *value as f32;
+            }
+        }
+        let count = (mip.dimensions.width * mip.dimensions.height) as f32;
+        sums.into_iter().map(|sum| sum / count).collect::<Vec<_>>()
+    };
+    let base = mean(&mip_buffers[0]);
+    for mip in &mip_buffers[1..] {
+        for (expected, actual) in base.iter().zip(mean(mip)) {
+            assert!(
+                (expected - actual).abs() <= tolerance,
+                "level {} ({}x{}) has mean {}, expected {}",
+                mip.level,
+                mip.dimensions.width,
+                mip.dimensions.height,
+                actual,
+                expected
+            );
+        }
+    }
+}
+
+/// Generates the mipmaps of a texture whose level 0 holds `first`, then overwrites level 0
+/// with `second` and generates them again in the same texture, and asserts that every level
+/// matches the mipmaps generated from `second` in a new texture, i.e. that nothing generated
+/// from `first` survives.
+#[cfg(test)]
+pub(crate) async fn assert_regeneration_overwrites(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    generator: &dyn MipmapGenerator,
+    first: &[u8],
+    second: &[u8],
+    texture_descriptor: &wgpu::TextureDescriptor<'_>,
+    options: &GenerateOptions,
+) {
+    let texture_descriptor = wgpu::TextureDescriptor {
+        usage: texture_descriptor.usage
+            | wgpu::TextureUsage::COPY_DST
+            | wgpu::TextureUsage::COPY_SRC,
+        ..texture_descriptor.clone()
+    };
+    let texture = device.create_texture(&texture_descriptor);
+    let bytes_per_row =
+        texture_descriptor.size.width * format_bytes_per_channel(&texture_descriptor.format) as u32;
+    let upload = |data: &[u8]| {
+        queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
             },
-            None,
-        )
-        .await
-        .expect("Failed to create device");
-    device.on_uncaptured_error(|e| {
-        println!("Captured a wgpu error: {}", e);
-    });
-    (instance, adapter, device, queue)
+            data,
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row,
+                rows_per_image: 0,
+            },
+            wgpu::Extent3d {
+                depth: 1,
+                ..texture_descriptor.size
+            },
+        );
+    };
+    upload(first);
+    let mut encoder = device.create_command_encoder(&Default::default());
+    generator
+        .generate_with_options(device, &mut encoder, &texture, &texture_descriptor, options)
+        .unwrap();
+    queue.submit(std::iter::once(encoder.finish()));
+    upload(second);
+    let mut encoder = device.create_command_encoder(&Default::default());
+    generator
+        .generate_with_options(device, &mut encoder, &texture, &texture_descriptor, options)
+        .unwrap();
+    let readback = MipReadback::encode(device, &mut encoder, &texture, &texture_descriptor);
+    queue.submit(std::iter::once(encoder.finish()));
+    let regenerated = readback.read();
+    device.poll(wgpu::Maintain::Wait);
+    let regenerated = regenerated.await.unwrap();
+    let expected = generate_and_copy_to_cpu_with_options(
+        device,
+        queue,
+        generator,
+        second,
+        &texture_descriptor,
+        options,
+    )
+    .await
+    .unwrap();
+    for (actual, expected) in regenerated.iter().zip(&expected) {
+        assert!(
+            actual.buffer == expected.buffer,
+            "level {} kept texels generated from the previous contents",
+            actual.level
+        );
+    }
+}
+
+/// Creates the uniform buffer read by the stochastic shaders when writing `level`.
+pub(crate) fn create_stochastic_params(
+    device: &wgpu::Device,
+    seed: u32,
+    level: u32,
+) -> wgpu::Buffer {
+    use wgpu::util::DeviceExt;
+    // Padded to 16 bytes, the size of the std140 block
+    let params = [seed, level, 0, 0];
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("wgpu-mipmap-stochastic-params"),
+        contents: bytemuck::cast_slice(&params),
+        usage: wgpu::BufferUsage::UNIFORM,
+    })
+}
+
+/// Maps `f` over `items` on scoped threads, one chunk of items per available core, and
+/// returns the results in the order of `items`. Without the `parallel` feature, or on wasm
+/// where threads can't be spawned, `items` are mapped on the calling thread.
+pub(crate) fn par_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        if threads > 1 && items.len() > 1 {
+            let chunk_size = items.len().div_ceil(threads);
+            let f = &f;
+            return std::thread::scope(|scope| {
+                let handles: Vec<_> = items
+                    .chunks(chunk_size)
+                    .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<_>>()))
+                    .collect();
+                handles
+                    .into_iter()
+                    .flat_map(|handle| {
+                        handle
+                            .join()
+                            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+                    })
+                    .collect()
+            });
+        }
+    }
+    items.iter().map(f).collect()
+}
+
+/// A small xorshift generator for property tests. The crate has no randomness
+/// dependency, and a fixed seed keeps failures reproducible.
+#[allow(dead_code)]
+pub(crate) struct TestRng(u64);
+
+#[allow(dead_code)]
+impl TestRng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub(crate) fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 32) as u32
+    }
+
+    /// Returns a value in `range`.
+    pub(crate) fn range(&mut self, range: Range<u32>) -> u32 {
+        range.start + self.next_u32() % (range.end - range.start)
+    }
+
+    pub(crate) fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.range(0..items.len() as u32) as usize]
+    }
+}
+
+/// The size of a texture or one of its mip levels that keeps array layers apart from the
+/// depth of a 3D texture. `wgpu::Extent3d::depth` holds either, depending on the texture's
+/// dimension, but each level only halves the depth of a 3D texture, and keeps every array
+/// layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MipExtent {
+    pub width: u32,
+    pub height: u32,
+    /// The depth of a 3D texture, or 1.
+    pub depth: u32,
+    /// The number of array layers of a 1D or 2D texture, or 1.
+    pub array_layers: u32,
+}
+
+impl MipExtent {
+    /// Returns the extent of a texture of size `size` and dimension `dimension`.
+    pub fn new(size: &wgpu::Extent3d, dimension: wgpu::TextureDimension) -> Self {
+        let (depth, array_layers) = match dimension {
+            wgpu::TextureDimension::D3 => (size.depth, 1),
+            _ => (1, size.depth),
+        };
+        Self {
+            width: size.width,
+            height: size.height,
+            depth,
+            array_layers,
+        }
+    }
+
+    /// Returns the extent of a texture described by `texture_descriptor`.
+    pub fn of(texture_descriptor: &wgpu::TextureDescriptor) -> Self {
+        Self::new(&texture_descriptor.size, texture_descriptor.dimension)
+    }
+
+    /// Returns the extent of mip level `level`, with the same number of array layers.
+    pub fn mip(&self, level: u32) -> Self {
+        let mip = wgpu_mipmap_core::Extent::new(self.width, self.height, self.depth).mip(level);
+        Self {
+            width: mip.width,
+            height: mip.height,
+            depth: mip.depth,
+            array_layers: self.array_layers,
+        }
+    }
+
+    /// Returns the extent of a single array layer, e.g. the layer `GenerateOptions` selects.
+    /// Every depth slice of a 3D texture is kept.
+    pub fn single_layer(&self) -> Self {
+        Self {
+            array_layers: 1,
+            ..*self
+        }
+    }
+
+    /// Returns the depth of a 3D texture, or the number of array layers of any other.
+    pub fn depth_or_array_layers(&self) -> u32 {
+        self.depth * self.array_layers
+    }
+
+    pub fn to_extent3d(self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth: self.depth_or_array_layers(),
+        }
+    }
+
+    /// Returns the number of texels in every layer and depth slice.
+    pub fn texel_count(&self) -> u64 {
+        self.width as u64 * self.height as u64 * self.depth_or_array_layers() as u64
+    }
 }
 
+/// Returns the size of mip level `level` of a 2D texture, or 2D texture array, of size
+/// `extent`. Array layers are never halved. See [`MipExtent`] for 3D textures.
 #[doc(hidden)]
 #[allow(dead_code)]
 pub(crate) fn get_mip_extent(extent: &wgpu::Extent3d, level: u32) -> wgpu::Extent3d {
-    let mip_width = ((extent.width as f32) / (2u32.pow(level) as f32)).floor() as u32;
-    let mip_height = ((extent.height as f32) / (2u32.pow(level) as f32)).floor() as u32;
-    let mip_depth = ((extent.depth as f32) / (2u32.pow(level) as f32)).floor() as u32;
-    wgpu::Extent3d {
-        width: mip_width.max(1),
-        height: mip_height.max(1),
-        depth: mip_depth.max(1),
+    MipExtent::new(extent, wgpu::TextureDimension::D2)
+        .mip(level)
+        .to_extent3d()
+}
+
+pub(crate) fn to_core_extent(extent: &wgpu::Extent3d) -> wgpu_mipmap_core::Extent {
+    wgpu_mipmap_core::Extent::new(extent.width, extent.height, extent.depth)
+}
+
+/// Returns the number of texels in `levels` of a texture of size `extent`.
+pub(crate) fn texel_count(extent: &MipExtent, levels: Range<u32>) -> u64 {
+    levels.map(|level| extent.mip(level).texel_count()).sum()
+}
+
+/// Returns the number of bytes generating `levels` of a texture described by
+/// `texture_descriptor` writes: every depth slice of a 3D texture, but a single array layer of
+/// a texture array, as `GenerateOptions::array_layer` selects.
+pub(crate) fn level_bytes(texture_descriptor: &wgpu::TextureDescriptor, levels: Range<u32>) -> u64 {
+    texel_count(&MipExtent::of(texture_descriptor).single_layer(), levels)
+        * format_bytes_per_channel(&texture_descriptor.format) as u64
+}
+
+#[doc(hidden)]
+pub(crate) fn create_mip_level_view(
+    texture: &wgpu::Texture,
+    base_mip_level: u32,
+) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: None,
+        format: None,
+        dimension: None,
+        aspect: wgpu::TextureAspect::All,
+        base_mip_level,
+        level_count: std::num::NonZeroU32::new(1),
+        array_layer_count: None,
+        base_array_layer: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_matches_wgpu() {
+        assert_eq!(
+            wgpu_mipmap_core::COPY_BYTES_PER_ROW_ALIGNMENT,
+            wgpu::COPY_BYTES_PER_ROW_ALIGNMENT
+        );
+    }
+
+    #[test]
+    fn f16_to_f32_decodes_halves() {
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    fn level(format: wgpu::TextureFormat, bytes_per_texel: usize, buffer: Vec<u8>) -> MipBuffer {
+        MipBuffer {
+            buffer,
+            dimensions: MipBufferDimensions::new(2, 1, bytes_per_texel),
+            level: 0,
+            format,
+        }
+    }
+
+    #[test]
+    fn mip_extent_layers() {
+        let size = wgpu::Extent3d {
+            width: 8,
+            height: 4,
+            depth: 6,
+        };
+        // Array layers are never halved, but the depth of a 3D texture is
+        let array = MipExtent::new(&size, wgpu::TextureDimension::D2);
+        assert_eq!((array.mip(2).depth, array.mip(2).array_layers), (1, 6));
+        assert_eq!(get_mip_extent(&size, 2).depth, 6);
+        let volume = MipExtent::new(&size, wgpu::TextureDimension::D3);
+        assert_eq!(volume.mip(1).depth_or_array_layers(), 3);
+        assert_eq!(volume.mip(4).to_extent3d().depth, 1);
+        // One layer of an array is generated, but every slice of a volume
+        assert_eq!(texel_count(&array.single_layer(), 1..3), 4 * 2 + 2);
+        assert_eq!(texel_count(&volume.single_layer(), 1..3), 4 * 2 * 3 + 2);
+    }
+
+    #[test]
+    fn readback_layout_offsets() {
+        let texture_descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 1,
+            },
+            mip_level_count: 3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        let (levels, size) = readback_layout(&texture_descriptor);
+        // every row is padded to 256 bytes
+        let offsets = levels.iter().map(|level| level.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 4 * 256, 6 * 256]);
+        assert_eq!(size, 7 * 256);
+        // level 1 is 2x2, its rows start 256 bytes apart
+        let mut mapped = vec![0u8; size as usize];
+        mapped[1024..1032].copy_from_slice(&[1; 8]);
+        mapped[1280..1288].copy_from_slice(&[2; 8]);
+        assert_eq!(levels[1].unpad(&mapped), [[1; 8], [2; 8]].concat());
+    }
+
+    #[test]
+    fn readback_layout_layers() {
+        let descriptor = |dimension| wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth: 4,
+            },
+            mip_level_count: 3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            dimension,
+            usage: wgpu::TextureUsage::COPY_SRC,
+            label: None,
+        };
+        let layers = |dimension| {
+            let (levels, _) = readback_layout(&descriptor(dimension));
+            levels
+                .iter()
+                .map(|level| level.dimensions.layers)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(layers(wgpu::TextureDimension::D2), vec![4, 4, 4]);
+        assert_eq!(layers(wgpu::TextureDimension::D3), vec![4, 2, 1]);
+        let (levels, size) = readback_layout(&descriptor(wgpu::TextureDimension::D2));
+        assert_eq!(levels[1].offset, 16 * 256);
+        assert_eq!(size, (16 + 8 + 4) * 256);
+    }
+
+    fn layered_level(level: u32, width: usize, layers: usize, value: u8) -> MipBuffer {
+        let dimensions = MipBufferDimensions::new(width, width, 2).with_layers(layers);
+        let layer_size = width * width * 2;
+        MipBuffer {
+            buffer: (0..layers)
+                .flat_map(|layer| vec![value + layer as u8; layer_size])
+                .collect(),
+            dimensions,
+            level,
+            format: wgpu::TextureFormat::Rg8Unorm,
+        }
+    }
+
+    #[test]
+    fn layer_access() {
+        let mip = layered_level(0, 2, 3, 10);
+        assert_eq!(mip.layer_count(), 3);
+        assert_eq!(mip.layer(2), &[12; 8][..]);
+    }
+
+    #[test]
+    fn ktx2_level_order() {
+        // levels of 8, 2 and 2 bytes
+        let mips = vec![
+            layered_level(0, 2, 1, 10),
+            layered_level(1, 1, 1, 20),
+            layered_level(2, 1, 1, 30),
+        ];
+        let (data, ranges) = pack_ktx2_levels(&mips);
+        // smallest first, each aligned to 4 bytes
+        assert_eq!(ranges, vec![8..16, 4..6, 0..2]);
+        assert_eq!(
+            data,
+            [vec![30, 30, 0, 0, 20, 20, 0, 0], vec![10; 8]].concat()
+        );
+    }
+
+    #[test]
+    fn ktx2_header() {
+        let mips = vec![layered_level(0, 2, 2, 10), layered_level(1, 1, 2, 20)];
+        let file = ktx2_file(4, 2, wgpu::TextureFormat::Rgba8UnormSrgb, &mips).unwrap();
+        let word = |offset: usize| {
+            u32::from_le_bytes([
+                file[offset],
+                file[offset + 1],
+                file[offset + 2],
+                file[offset + 3],
+            ])
+        };
+        assert_eq!(&file[..12], b"\xabKTX 20\xbb\r\n\x1a\n");
+        // vkFormat, width, height and levelCount
+        assert_eq!((word(12), word(20), word(24), word(40)), (43, 4, 2, 2));
+        // The data format descriptor follows the level index, and has 4 samples
+        assert_eq!((word(48), word(52)), (128, 4 + 24 + 4 * 16));
+        // Level 0 is stored after level 1, and both only keep their first layer
+        let level_0 = word(80) as usize;
+        let level_1 = word(104) as usize;
+        assert_eq!(level_1 % 16, 0);
+        assert_eq!((level_0 - level_1, word(88), word(112)), (4, 8, 2));
+        assert_eq!(&file[level_1..level_1 + 2], &[20; 2]);
+        assert_eq!(&file[level_0..], &[10; 8]);
+        assert_eq!(
+            ktx2_file(4, 2, wgpu::TextureFormat::R8Unorm, &mips),
+            Err(Error::UnsupportedFormat(wgpu::TextureFormat::R8Unorm))
+        );
+    }
+
+    #[test]
+    fn dds_layer_order() {
+        let mips = vec![layered_level(0, 2, 2, 10), layered_level(1, 1, 2, 20)];
+        let data = pack_dds_levels(&mips, wgpu::TextureDimension::D2);
+        // every level of layer 0, then every level of layer 1
+        assert_eq!(
+            data,
+            [vec![10; 8], vec![20; 2], vec![11; 8], vec![21; 2]].concat()
+        );
+    }
+
+    #[test]
+    fn typed_accessors() {
+        let values = [0.25f32, 0.5, 0.75, 1.0, 0.0, 0.125, 0.375, 1.0];
+        let mip = level(
+            wgpu::TextureFormat::Rgba32Float,
+            16,
+            bytemuck::cast_slice(&values).to_vec(),
+        );
+        assert_eq!((mip.width(), mip.height()), (2, 1));
+        assert_eq!(mip.bytes_per_texel(), 16);
+        assert_eq!(mip.as_slice::<f32>(), &values);
+        assert_eq!(mip.as_slice::<[f32; 4]>()[1], [0.0, 0.125, 0.375, 1.0]);
+    }
+
+    #[test]
+    fn par_map_preserves_order() {
+        let items: Vec<u32> = (0..37).collect();
+        let squares = par_map(&items, |i| i * i);
+        assert_eq!(squares, items.iter().map(|i| i * i).collect::<Vec<_>>());
+        assert!(par_map(&[] as &[u32], |i| *i).is_empty());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn to_image() {
+        let mip = level(
+            wgpu::TextureFormat::Rgba8Unorm,
+            4,
+            vec![1, 2, 3, 4, 5, 6, 7, 8],
+        );
+        let image = mip.to_image().unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.get_pixel(1, 0).0, [5, 6, 7, 8]);
+        let mip = level(wgpu::TextureFormat::Rgba32Float, 16, vec![0; 32]);
+        assert!(mip.to_image().is_none());
     }
 }
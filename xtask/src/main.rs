@@ -0,0 +1,70 @@
+//! Development tasks, run with `cargo xtask <task>`.
+//!
+//! - `srgb-matrix [backends]`: runs the `srgb_probe` example once per backend, `vulkan`,
+//!   `metal`, `dx12` and `gl` unless a comma separated list is given, and prints the srgb
+//!   storage conversion each adapter was probed with. Fails if the shader selected from the
+//!   probe generates a wrong level on any adapter. Backends without an adapter are skipped,
+//!   unless `WGPU_MIPMAP_REQUIRE_ADAPTER` is set.
+use std::{
+    env,
+    process::{self, Command},
+};
+
+const BACKENDS: &[&str] = &["vulkan", "metal", "dx12", "gl"];
+
+fn main() {
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        Some("srgb-matrix") => {
+            let backends: Vec<String> = match args.next() {
+                Some(list) => list.split(',').map(|b| b.trim().to_owned()).collect(),
+                None => BACKENDS.iter().map(|&b| b.to_owned()).collect(),
+            };
+            if !srgb_matrix(&backends) {
+                process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!("usage: cargo xtask srgb-matrix [vulkan,metal,dx12,gl]");
+            process::exit(2);
+        }
+    }
+}
+
+fn srgb_matrix(backends: &[String]) -> bool {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned());
+    // Build once so the per backend runs only print the probe results
+    let built = Command::new(&cargo)
+        .args(["build", "--quiet", "--example", "srgb_probe"])
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    if !built {
+        eprintln!("failed to build the srgb_probe example");
+        return false;
+    }
+    println!("requested\tbackend\tadapter\tconversion\tlevel 1\tresult");
+    let mut passed = true;
+    for backend in backends {
+        let output = Command::new(&cargo)
+            .args(["run", "--quiet", "--example", "srgb_probe"])
+            .env("WGPU_MIPMAP_BACKEND", backend)
+            .output();
+        match output {
+            Ok(output) => {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let line = stdout.lines().last().unwrap_or("-\t-\t-\t-\tcrashed");
+                println!("{}\t{}", backend, line);
+                if !output.status.success() {
+                    passed = false;
+                    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+                }
+            }
+            Err(e) => {
+                eprintln!("failed to run the srgb_probe example: {}", e);
+                passed = false;
+            }
+        }
+    }
+    passed
+}